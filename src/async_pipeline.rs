@@ -0,0 +1,143 @@
+pub mod async_pipeline {
+    use std::io::ErrorKind;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::OnceLock;
+
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    use crate::{base::base::DebugLevel, Error, Pipeline};
+
+    /// Lets tokio wait for the pipeline's fd without taking ownership of it.
+    pub(crate) struct PipelineFd(pub(crate) RawFd);
+
+    impl AsRawFd for PipelineFd {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0
+        }
+    }
+
+    impl PipelineFd {
+        pub(crate) fn readable(pipeline: &Pipeline) -> Result<AsyncFd<PipelineFd>, Error> {
+            Ok(AsyncFd::with_interest(
+                PipelineFd(pipeline.as_raw_fd()),
+                tokio::io::Interest::READABLE,
+            )?)
+        }
+    }
+
+    /// A step's end of the socket pair a session thread serves, or another
+    /// socket of its own, opened when the step is first used so that
+    /// templates and unused clones start nothing. A failed open is logged
+    /// once and reported as `NotConnected` from then on.
+    pub(crate) struct LazyStream<T = UnixStream>(OnceLock<Option<T>>);
+
+    impl<T> LazyStream<T> {
+        pub(crate) fn new() -> Self {
+            Self(OnceLock::new())
+        }
+
+        /// Returns the stream, opening it with `open` if this is the first use.
+        /// `what` names the stream in the log.
+        pub(crate) fn get<F>(
+            &self,
+            what: &str,
+            debug_level: DebugLevel,
+            open: F,
+        ) -> Result<&T, Error>
+        where
+            F: FnOnce() -> Result<T, Error>,
+        {
+            self.0
+                .get_or_init(|| match open() {
+                    Ok(stream) => Some(stream),
+                    Err(e) => {
+                        if debug_level > 0 {
+                            eprintln!("an error accured opening {}: {}", what, e);
+                        }
+                        None
+                    }
+                })
+                .as_ref()
+                .ok_or_else(|| Error::IoError(ErrorKind::NotConnected.into()))
+        }
+
+        /// Returns the stream if it was opened, without opening it.
+        pub(crate) fn opened(&self) -> Option<&T> {
+            self.0.get().and_then(Option::as_ref)
+        }
+    }
+
+    /// Steps write synchronously and may wait for their upstream, so let
+    /// the runtime move other tasks off this worker meanwhile.
+    pub(crate) fn write_pipeline(pipeline: &mut Pipeline, data: Vec<u8>) -> Result<(), Error> {
+        tokio::task::block_in_place(|| pipeline.write_pipeline(data))
+    }
+
+    /// Returns `None` once the pipeline has nothing more to give until its
+    /// fd becomes readable again.
+    pub(crate) fn read_pipeline(pipeline: &mut Pipeline) -> Result<Option<Vec<u8>>, Error> {
+        match pipeline.read_pipeline() {
+            Ok(data) if data.is_empty() => Ok(None),
+            Ok(data) => Ok(Some(data)),
+            Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Copies bytes between a client connection and its pipeline until the
+    /// pipeline ends, the client ending its half only half-closes the
+    /// pipeline.
+    pub(crate) async fn relay(
+        mut connection: TcpStream,
+        pipeline: Pipeline,
+        buffer_size: usize,
+    ) -> Result<(), Error> {
+        let (reader, writer) = connection.split();
+        relay_stream(reader, writer, pipeline, buffer_size).await
+    }
+
+    /// Like `relay`, for a client given as separate halves such as a QUIC
+    /// stream. The pipeline ending finishes the writer.
+    pub(crate) async fn relay_stream<R, W>(
+        mut reader: R,
+        mut writer: W,
+        mut pipeline: Pipeline,
+        buffer_size: usize,
+    ) -> Result<(), Error>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let pipeline_fd = PipelineFd::readable(&pipeline)?;
+        let mut buffer = vec![0u8; buffer_size];
+        let mut reader_done = false;
+        loop {
+            tokio::select! {
+                size = reader.read(&mut buffer), if !reader_done => {
+                    let size = size?;
+                    if size == 0 {
+                        reader_done = true;
+                        pipeline.close_forward()?;
+                        continue;
+                    }
+                    write_pipeline(&mut pipeline, buffer[0..size].to_vec())?;
+                }
+                guard = pipeline_fd.readable() => {
+                    let mut guard = guard?;
+                    match read_pipeline(&mut pipeline) {
+                        Ok(Some(data)) => writer.write_all(&data).await?,
+                        Ok(None) => guard.clear_ready(),
+                        Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                            writer.shutdown().await?;
+                            return Ok(());
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+}
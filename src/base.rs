@@ -1,6 +1,6 @@
 pub mod base {
     use std::{
-        collections::HashMap, error, fmt::{Display, Write}, future::IntoFuture, io, iter::Rev, net::AddrParseError, ops::{Deref, DerefMut}, os::fd::{AsRawFd, RawFd}, result, slice::{Iter, IterMut}
+        collections::HashMap, error, fmt::{Display, Write}, future::IntoFuture, io, iter::Rev, net::{AddrParseError, SocketAddr}, ops::{Deref, DerefMut}, os::fd::{AsRawFd, RawFd}, result, slice::{Iter, IterMut}
     };
 
     use cliparser::types::{CliParsed, CliSpec};
@@ -15,7 +15,8 @@ pub mod base {
         RequireOption(String),
         ParseIntError,
         AddrParseError(AddrParseError),
-        H2_error(h2::Error)
+        H2_error(h2::Error),
+        WsError(Box<tungstenite::Error>),
     }
 
     pub enum DebugLevel {
@@ -28,10 +29,38 @@ pub mod base {
     pub trait Step: Send + Sync + BoxedClone + AsRawFd {
         fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error>;
         fn process_data_backward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error>;
+
+        /// Tells the step no more forward data will come, so a step with an
+        /// upstream can half-close it and still read what comes back.
+        fn close_forward(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        /// Offers the step added behind this one. A step that carries the
+        /// rest of the pipeline itself (e.g. to share it between clients)
+        /// keeps it and returns `None`, the rest hand it back.
+        fn adopt_step(&mut self, step: Box<dyn Step>) -> Option<Box<dyn Step>> {
+            Some(step)
+        }
     }
 
     pub trait BoxedClone {
         fn bclone(&self) -> Box<dyn Step>;
+
+        /// Clones the step for one client connection. Steps that can make use
+        /// of what the entry learned about the client (e.g. a requested
+        /// destination) override this; the rest fall back to `bclone`.
+        fn bclone_with(&self, _info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            Ok(self.bclone())
+        }
+    }
+
+    /// What an entry knows about the client a pipeline is cloned for.
+    #[derive(Clone, Debug, Default)]
+    pub struct ConnectionInfo {
+        pub peer: Option<SocketAddr>,
+        pub local: Option<SocketAddr>,
+        pub destination: Option<(String, u16)>,
     }
 
     pub trait StepStatic: Clone {
@@ -78,6 +107,7 @@ pub mod base {
                 Error::ParseIntError => f.write_fmt(format_args!("{}", "Parse Int Error")),
                 Error::AddrParseError(e) => f.write_fmt(format_args!("{}", e)),
                 Error::H2_error(e) => f.write_fmt(format_args!("{}", e)),
+                Error::WsError(e) => f.write_fmt(format_args!("{}", e)),
             }
         }
     }
@@ -100,6 +130,19 @@ pub mod base {
         }
     }
 
+    impl From<tungstenite::Error> for Error {
+        fn from(value: tungstenite::Error) -> Self {
+            Error::WsError(Box::new(value))
+        }
+    }
+
+    impl From<ssh2::Error> for Error {
+        fn from(value: ssh2::Error) -> Self {
+            // keeps libssh2's EAGAIN recognizable as WouldBlock
+            Error::IoError(value.into())
+        }
+    }
+
     impl From<i32> for DebugLevel {
         fn from(value: i32) -> Self {
             match value {
@@ -143,7 +186,13 @@ pub mod base {
         }
 
         pub fn add_step(&mut self, step: Box<dyn Step>) {
-            self.steps.push(step);
+            let step = match self.steps.last_mut() {
+                Some(last) => last.adopt_step(step),
+                None => Some(step),
+            };
+            if let Some(step) = step {
+                self.steps.push(step);
+            }
         }
     }
 
@@ -180,6 +229,14 @@ pub mod base {
     }
 
     impl Pipeline {
+        pub fn clone_with(&self, info: &ConnectionInfo) -> Result<Pipeline, Error> {
+            let mut result: Pipeline = Pipeline { steps: Vec::new() };
+            for step in self.iter() {
+                result.steps.push(step.bclone_with(info)?)
+            }
+            Ok(result)
+        }
+
         pub fn iter_forwad(&mut self) -> IterMut<Box<dyn Step>> {
             self.iter_mut()
         }
@@ -204,6 +261,13 @@ pub mod base {
             buffer.clear();
             Ok(())
         }
+
+        pub fn close_forward(&mut self) -> Result<(), Error> {
+            for step in self.iter_forwad() {
+                step.close_forward()?;
+            }
+            Ok(())
+        }
     }
 
     impl AsRawFd for Pipeline {
@@ -0,0 +1,293 @@
+pub mod connect {
+    use std::sync::Arc;
+
+    use base64::Engine;
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::async_pipeline::async_pipeline::{relay, write_pipeline};
+    use crate::http::http::read_head;
+    use crate::{base::base::DebugLevel, ConnectionInfo, Entry, EntryStatic, Error, Pipeline};
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const CONNECT_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "connect-entry-address",
+        "--connect-ea",
+        "(ConnectEntry) Http proxy listen address",
+    );
+    const CONNECT_ENTRY_PORT: (&str, &str, &str) = (
+        "connect-entry-port",
+        "--connect-ep",
+        "(ConnectEntry) Http proxy listen port",
+    );
+    const CONNECT_ENTRY_AUTH: (&str, &str, &str) = (
+        "connect-entry-auth",
+        "--connect-eauth",
+        "(ConnectEntry) Credentials as \"user:password\" clients must send in Proxy-Authorization",
+    );
+
+    /// A standard http proxy: clients open tunnels with `CONNECT host:port`
+    /// and the requested destination is handed to the pipeline, where a
+    /// `TcpStep` dials it in place of its configured endpoint.
+    pub struct ConnectEntry {
+        address: String,
+        port: u16,
+        /// Expected `user:password` of the `Proxy-Authorization` header,
+        /// `None` accepts every client.
+        authorization: Option<String>,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for ConnectEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(self.serve())
+        }
+    }
+
+    impl ConnectEntry {
+        async fn serve(&mut self) -> Result<(), Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let server = TcpListener::bind(addr).await?;
+
+            loop {
+                let (connection, peer) = server.accept().await?;
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("new client: {}", peer);
+                    }
+                }
+
+                let pipeline_template = self.pipeline_template.clone();
+                let authorization = self.authorization.clone();
+                let debug_level = self.debug_level;
+                let buffer_size = self.buffer_size;
+                tokio::spawn(async move {
+                    let info = ConnectionInfo {
+                        peer: Some(peer),
+                        local: connection.local_addr().ok(),
+                        destination: None,
+                    };
+                    if let Err(e) = ConnectEntry::serve_client(
+                        connection,
+                        info,
+                        pipeline_template,
+                        authorization,
+                        buffer_size,
+                    )
+                    .await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving {}: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        }
+
+        async fn serve_client(
+            mut connection: TcpStream,
+            mut info: ConnectionInfo,
+            pipeline_template: Arc<Pipeline>,
+            authorization: Option<String>,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let (head, leftover) = match read_head(&mut connection).await? {
+                Some(head) => head,
+                None => return Ok(()),
+            };
+            let request = match ConnectRequest::parse(&head) {
+                Ok(request) => request,
+                Err(status) => return ConnectEntry::reject(&mut connection, status).await,
+            };
+            if let Some(authorization) = authorization {
+                if request.authorization.as_deref() != Some(authorization.as_str()) {
+                    return ConnectEntry::reject(&mut connection, Status::ProxyAuthRequired).await;
+                }
+            }
+
+            info.destination = Some(request.destination);
+            let mut pipeline =
+                match tokio::task::block_in_place(|| pipeline_template.clone_with(&info)) {
+                    Ok(pipeline) => pipeline,
+                    Err(e) => {
+                        ConnectEntry::reject(&mut connection, Status::BadGateway).await?;
+                        return Err(e);
+                    }
+                };
+            connection
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await?;
+
+            if !leftover.is_empty() {
+                write_pipeline(&mut pipeline, leftover)?;
+            }
+            relay(connection, pipeline, buffer_size).await
+        }
+
+        async fn reject(connection: &mut TcpStream, status: Status) -> Result<(), Error> {
+            let response = match status {
+                Status::BadRequest => "HTTP/1.1 400 Bad Request\r\n",
+                Status::ProxyAuthRequired => {
+                    "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Basic realm=\"kproxy\"\r\n"
+                }
+                Status::MethodNotAllowed => "HTTP/1.1 405 Method Not Allowed\r\nAllow: CONNECT\r\n",
+                Status::BadGateway => "HTTP/1.1 502 Bad Gateway\r\n",
+            };
+            connection.write_all(response.as_bytes()).await?;
+            connection
+                .write_all(b"Content-Length: 0\r\nConnection: close\r\n\r\n")
+                .await?;
+            Ok(())
+        }
+    }
+
+    enum Status {
+        BadRequest,
+        ProxyAuthRequired,
+        MethodNotAllowed,
+        BadGateway,
+    }
+
+    struct ConnectRequest {
+        destination: (String, u16),
+        /// Decoded `user:password` of a Basic `Proxy-Authorization` header.
+        authorization: Option<String>,
+    }
+
+    impl ConnectRequest {
+        fn parse(head: &[u8]) -> Result<ConnectRequest, Status> {
+            let mut headers = [httparse::EMPTY_HEADER; 64];
+            let mut request = httparse::Request::new(&mut headers);
+            match request.parse(head) {
+                Ok(httparse::Status::Complete(_)) => {}
+                _ => return Err(Status::BadRequest),
+            }
+            if request.method != Some("CONNECT") {
+                return Err(Status::MethodNotAllowed);
+            }
+            let destination = match request.path.and_then(parse_authority) {
+                Some(destination) => destination,
+                None => return Err(Status::BadRequest),
+            };
+            let authorization = request
+                .headers
+                .iter()
+                .find(|header| header.name.eq_ignore_ascii_case("proxy-authorization"))
+                .and_then(|header| basic_credentials(&String::from_utf8_lossy(header.value)));
+
+            Ok(ConnectRequest {
+                destination,
+                authorization,
+            })
+        }
+    }
+
+    /// Decodes the credentials of a Basic authorization, whose scheme is
+    /// case-insensitive and may be followed by any amount of whitespace.
+    fn basic_credentials(value: &str) -> Option<String> {
+        let (scheme, token) = value.trim().split_once(char::is_whitespace)?;
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let credentials = base64::engine::general_purpose::STANDARD
+            .decode(token.trim())
+            .ok()?;
+        String::from_utf8(credentials).ok()
+    }
+
+    /// Splits a `host:port` request target, ipv6 hosts come in brackets.
+    pub(crate) fn parse_authority(authority: &str) -> Option<(String, u16)> {
+        let (host, port) = authority.rsplit_once(':')?;
+        let port = str::parse::<u16>(port).ok()?;
+        let host = match host.strip_prefix('[') {
+            Some(host) => host.strip_suffix(']')?,
+            None if host.contains(':') => return None,
+            None => host,
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some((host.to_string(), port))
+    }
+
+    impl EntryStatic<ConnectEntry> for ConnectEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<ConnectEntry, Error> {
+            let address = match args.argument_values.get(CONNECT_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(CONNECT_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(CONNECT_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(CONNECT_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+            let authorization = args
+                .argument_values
+                .get(CONNECT_ENTRY_AUTH.0)
+                .map(|credentials| credentials[0].clone());
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(ConnectEntry {
+                address,
+                port,
+                authorization,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: CONNECT_ENTRY_ADDRESS.0.to_string(),
+                key: vec![CONNECT_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(CONNECT_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: CONNECT_ENTRY_PORT.0.to_string(),
+                key: vec![CONNECT_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("8080".to_string()),
+                help: Some(ArgumentHelp::Text(CONNECT_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: CONNECT_ENTRY_AUTH.0.to_string(),
+                key: vec![CONNECT_ENTRY_AUTH.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(CONNECT_ENTRY_AUTH.2.to_string())),
+            });
+            argument
+        }
+    }
+}
@@ -46,6 +46,18 @@ pub mod multi_key_map {
                 value: Box::into_raw(boxed),
             }
         }
+
+        /// Drops the value.
+        ///
+        /// # Safety
+        /// A `Ref` is a shared raw pointer: every copy points at the same
+        /// value and none of them owns it. The caller must call `free` once
+        /// per value, only after removing every copy from wherever it is kept
+        /// (e.g. both tokens of a client in a `MultiMap`), and must not use
+        /// any copy afterwards. A value that is never freed is leaked.
+        pub unsafe fn free(self) {
+            drop(Box::from_raw(self.value));
+        }
     }
 
     pub struct MultiMap<K, V>
@@ -108,5 +120,9 @@ pub mod multi_key_map {
         pub fn get_mut(&mut self, key: &K)-> Option<&mut V> {
             self.map.get_mut(key)
         }
+
+        pub fn remove(&mut self, key: &K) -> Option<V> {
+            self.map.remove(key)
+        }
     }
 }
@@ -0,0 +1,1472 @@
+pub mod dns {
+    use std::collections::HashMap;
+    use std::io::{ErrorKind, Write};
+    use std::net::{Shutdown, SocketAddr};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use data_encoding::BASE32_DNSSEC;
+    use mio::net::UdpSocket;
+    use mio::unix::SourceFd;
+    use mio::{Events, Interest, Poll, Token};
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    use crate::async_pipeline::async_pipeline::LazyStream;
+    use crate::mux::mux::serve_stream;
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const DNS_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "dns-entry-address",
+        "--dns-ea",
+        "(DnsEntry) Dns Entry listen address",
+    );
+    const DNS_ENTRY_PORT: (&str, &str, &str) = (
+        "dns-entry-port",
+        "--dns-ep",
+        "(DnsEntry) Dns Entry listen port",
+    );
+    const DNS_STEP_ADDRESS: (&str, &str, &str) = (
+        "dns-step-address",
+        "--dns-sa",
+        "(DnsStep) Dns Step address of the resolver",
+    );
+    const DNS_STEP_PORT: (&str, &str, &str) = (
+        "dns-step-port",
+        "--dns-sp",
+        "(DnsStep) Dns Step port of the resolver",
+    );
+    const DNS_TYPE: (&str, &str, &str) = (
+        "dns-type",
+        "--dns-type",
+        "(DnsStep) Record type asked for, carrying the data back: txt, null or cname",
+    );
+    const DNS_RATE: (&str, &str, &str) = (
+        "dns-rate",
+        "--dns-rate",
+        "(DnsStep) Most queries sent per second, 0 for no limit",
+    );
+    const DNS_POLL: (&str, &str, &str) = (
+        "dns-poll",
+        "--dns-poll",
+        "(DnsStep) Longest wait in milliseconds between queries asking for data while idle",
+    );
+
+    const DNS_DOMAIN: (&str, &str, &str) = (
+        "dns-domain",
+        "--dns-domain",
+        "(DnsEntry, DnsStep) Domain the dns entry is the authoritative server of",
+    );
+    const DNS_TIMEOUT: (&str, &str, &str) = (
+        "dns-timeout",
+        "--dns-timeout",
+        "(DnsEntry, DnsStep) Seconds of silence after which the other side is given up",
+    );
+
+    // a query name carries the session id, flags, the sequence number of the
+    // upstream chunk, the downstream chunk wanted next and a nonce keeping
+    // caches out of the way, followed by the chunk
+    const QUERY_HEADER_SIZE: usize = 15;
+    // an answer carries a status, the upstream chunk expected next, the
+    // sequence number and flags of its downstream chunk, followed by the chunk
+    const ANSWER_HEADER_SIZE: usize = 10;
+    const FIN: u8 = 1;
+    /// Set on answers when more downstream data waits.
+    const MORE: u8 = 2;
+    const STATUS_OK: u8 = 0;
+    const STATUS_UNKNOWN: u8 = 1;
+
+    const HEADER_SIZE: usize = 12;
+    const MAX_NAME: usize = 253;
+    const MAX_LABEL: usize = 63;
+    /// Plain DNS over UDP, without EDNS.
+    const MAX_MESSAGE: usize = 512;
+    const TYPE_CNAME: u16 = 5;
+    const TYPE_NULL: u16 = 10;
+    const TYPE_TXT: u16 = 16;
+    const CLASS_IN: u16 = 1;
+    const RCODE_REFUSED: u16 = 5;
+
+    const SOCKET: Token = Token(0);
+    const LOCAL: Token = Token(1);
+    const FIRST_SESSION: usize = 1;
+
+    // a query unanswered for a few round trips is sent again
+    const INITIAL_RETRY: Duration = Duration::from_millis(1_000);
+    const MIN_RETRY: Duration = Duration::from_millis(200);
+    const MAX_RETRY: Duration = Duration::from_millis(5_000);
+    const MIN_POLL: Duration = Duration::from_millis(50);
+    const SWEEP_INTERVAL: Duration = Duration::from_millis(100);
+    /// Data read from either end and not yet carried, per session.
+    const MAX_BUFFERED: usize = 64 * 1024;
+
+    #[derive(Clone)]
+    struct DnsConfig {
+        /// Lowercase, without the trailing dot.
+        domain: String,
+        timeout: Duration,
+    }
+
+    fn parse_config(args: &CliParsed) -> Result<DnsConfig, Error> {
+        let domain = match args.argument_values.get(DNS_DOMAIN.0) {
+            Some(domain) => domain[0].clone(),
+            None => return Err(Error::RequireOption(DNS_DOMAIN.0.to_string())),
+        };
+        let timeout = match args.argument_values.get(DNS_TIMEOUT.0) {
+            Some(timeout) => timeout[0].clone(),
+            None => return Err(Error::RequireOption(DNS_TIMEOUT.0.to_string())),
+        };
+
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        let labels_valid = domain
+            .split('.')
+            .all(|label| !label.is_empty() && label.len() <= MAX_LABEL);
+        if !labels_valid || query_capacity(&domain) == 0 {
+            return Err(Error::Msg(format!("unusable dns domain: {}", domain)));
+        }
+        let timeout = match str::parse::<u64>(timeout.as_str()) {
+            Ok(timeout) => Duration::from_secs(timeout),
+            Err(_) => return Err(Error::ParseIntError),
+        };
+
+        Ok(DnsConfig { domain, timeout })
+    }
+
+    fn read_u16(bytes: &[u8]) -> u16 {
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+
+    fn read_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    /// Bytes whose base32 form fits `room` characters of a name, counting
+    /// the dot after every label.
+    fn name_capacity(room: usize) -> usize {
+        let chars = room - room.div_ceil(MAX_LABEL + 1);
+        chars * 5 / 8
+    }
+
+    /// Chunk bytes one query carries under `domain`.
+    fn query_capacity(domain: &str) -> usize {
+        name_capacity(MAX_NAME.saturating_sub(domain.len() + 1)).saturating_sub(QUERY_HEADER_SIZE)
+    }
+
+    fn encode_name(data: &[u8], domain: &str) -> String {
+        let encoded = BASE32_DNSSEC.encode(data);
+        let mut name = String::with_capacity(MAX_NAME);
+        for label in encoded.as_bytes().chunks(MAX_LABEL) {
+            // base32 is ascii
+            name.push_str(std::str::from_utf8(label).unwrap_or_default());
+            name.push('.');
+        }
+        name.push_str(domain);
+        name
+    }
+
+    /// The data in the labels of `name` before `domain`, in whatever case a
+    /// resolver chose to send them.
+    fn decode_name(name: &str, domain: &str) -> Option<Vec<u8>> {
+        let name = name.to_ascii_lowercase();
+        let labels = name.strip_suffix(domain)?.strip_suffix('.')?;
+        BASE32_DNSSEC
+            .decode(labels.replace('.', "").as_bytes())
+            .ok()
+    }
+
+    fn within(name: &str, domain: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        name == domain
+            || name
+                .strip_suffix(domain)
+                .is_some_and(|labels| labels.ends_with('.'))
+    }
+
+    /// Reads the name at `offset`, following compression, and returns it
+    /// with the offset after it.
+    fn read_name(message: &[u8], mut offset: usize) -> Option<(String, usize)> {
+        let mut name = String::new();
+        let mut end = None;
+        let mut jumps = 0;
+        loop {
+            let size = *message.get(offset)? as usize;
+            if size & 0xc0 == 0xc0 {
+                let pointer = ((size & 0x3f) << 8) | *message.get(offset + 1)? as usize;
+                end.get_or_insert(offset + 2);
+                jumps += 1;
+                if jumps > 16 {
+                    return None;
+                }
+                offset = pointer;
+                continue;
+            }
+            if size == 0 {
+                return Some((name, end.unwrap_or(offset + 1)));
+            }
+            if size > MAX_LABEL {
+                return None;
+            }
+            let label = message.get(offset + 1..offset + 1 + size)?;
+            if !name.is_empty() {
+                name.push('.');
+            }
+            name.push_str(&String::from_utf8_lossy(label));
+            offset += 1 + size;
+        }
+    }
+
+    fn write_name(message: &mut Vec<u8>, name: &str) {
+        for label in name.split('.') {
+            message.push(label.len() as u8);
+            message.extend_from_slice(label.as_bytes());
+        }
+        message.push(0);
+    }
+
+    struct Question {
+        id: u16,
+        flags: u16,
+        name: String,
+        qtype: u16,
+        /// End of the question section, which the answer repeats as is.
+        end: usize,
+    }
+
+    fn read_question(message: &[u8]) -> Option<Question> {
+        if message.len() < HEADER_SIZE || read_u16(&message[4..]) != 1 {
+            return None;
+        }
+        let flags = read_u16(&message[2..]);
+        if flags & 0x8000 != 0 {
+            return None;
+        }
+        let (name, offset) = read_name(message, HEADER_SIZE)?;
+        let qtype = read_u16(message.get(offset..offset + 4)?);
+        Some(Question {
+            id: read_u16(message),
+            flags,
+            name,
+            qtype,
+            end: offset + 4,
+        })
+    }
+
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut message = Vec::with_capacity(MAX_MESSAGE);
+        message.extend_from_slice(&id.to_be_bytes());
+        // recursion desired, the resolver is what we talk to
+        message.extend_from_slice(&0x0100u16.to_be_bytes());
+        message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        write_name(&mut message, name);
+        message.extend_from_slice(&qtype.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+        message
+    }
+
+    fn answer(query: &[u8], question: &Question, rcode: u16, rdata: Option<&[u8]>) -> Vec<u8> {
+        let mut message = Vec::with_capacity(MAX_MESSAGE);
+        message.extend_from_slice(&question.id.to_be_bytes());
+        // an authoritative response, keeping the recursion desired bit
+        let flags = 0x8400 | (question.flags & 0x0100) | rcode;
+        message.extend_from_slice(&flags.to_be_bytes());
+        message.extend_from_slice(&1u16.to_be_bytes());
+        message.extend_from_slice(&(rdata.is_some() as u16).to_be_bytes());
+        message.extend_from_slice(&[0, 0, 0, 0]);
+        message.extend_from_slice(&query[HEADER_SIZE..question.end]);
+        if let Some(rdata) = rdata {
+            // the name is the question's, and a ttl of 0 keeps it out of caches
+            message.extend_from_slice(&[0xc0, HEADER_SIZE as u8]);
+            message.extend_from_slice(&question.qtype.to_be_bytes());
+            message.extend_from_slice(&CLASS_IN.to_be_bytes());
+            message.extend_from_slice(&0u32.to_be_bytes());
+            message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            message.extend_from_slice(rdata);
+        }
+        message
+    }
+
+    /// Answer payload bytes fitting a record of `qtype` in a message whose
+    /// question ends at `question_end`.
+    fn answer_capacity(qtype: u16, question_end: usize, domain: &str) -> usize {
+        // the record's name pointer, type, class, ttl and length
+        let room = MAX_MESSAGE.saturating_sub(question_end + 12);
+        match qtype {
+            // character strings of up to 255 bytes, each after its length
+            TYPE_TXT => room - room.div_ceil(256),
+            TYPE_NULL => room,
+            _ => name_capacity(
+                room.saturating_sub(domain.len() + 2)
+                    .min(MAX_NAME - domain.len() - 1),
+            ),
+        }
+    }
+
+    fn to_rdata(qtype: u16, payload: &[u8], domain: &str) -> Vec<u8> {
+        let mut rdata = Vec::with_capacity(MAX_MESSAGE);
+        match qtype {
+            TYPE_TXT => {
+                for string in payload.chunks(255) {
+                    rdata.push(string.len() as u8);
+                    rdata.extend_from_slice(string);
+                }
+            }
+            TYPE_NULL => rdata.extend_from_slice(payload),
+            _ => write_name(&mut rdata, &encode_name(payload, domain)),
+        }
+        rdata
+    }
+
+    /// The payload of the first record of `qtype` in a response to query
+    /// `id`.
+    fn read_answer(message: &[u8], id: u16, qtype: u16, domain: &str) -> Option<Vec<u8>> {
+        if message.len() < HEADER_SIZE || read_u16(message) != id {
+            return None;
+        }
+        let flags = read_u16(&message[2..]);
+        if flags & 0x8000 == 0 || flags & 0x000f != 0 {
+            return None;
+        }
+        let mut offset = HEADER_SIZE;
+        for _ in 0..read_u16(&message[4..]) {
+            offset = read_name(message, offset)?.1 + 4;
+        }
+        for _ in 0..read_u16(&message[6..]) {
+            offset = read_name(message, offset)?.1;
+            let fields = message.get(offset..offset + 10)?;
+            let size = read_u16(&fields[8..]) as usize;
+            let start = offset + 10;
+            let rdata = message.get(start..start + size)?;
+            offset = start + size;
+            if read_u16(fields) != qtype {
+                continue;
+            }
+            return match qtype {
+                TYPE_TXT => {
+                    let mut payload = Vec::with_capacity(size);
+                    let mut rest = rdata;
+                    while let Some((&length, tail)) = rest.split_first() {
+                        payload.extend_from_slice(tail.get(..length as usize)?);
+                        rest = &tail[length as usize..];
+                    }
+                    Some(payload)
+                }
+                TYPE_NULL => Some(rdata.to_vec()),
+                _ => decode_name(&read_name(message, start)?.0, domain),
+            };
+        }
+        None
+    }
+
+    fn random_bytes<const N: usize>(random: &SystemRandom) -> Result<[u8; N], Error> {
+        let mut bytes = [0u8; N];
+        random
+            .fill(&mut bytes)
+            .map_err(|_| Error::Msg("no randomness available for a query".to_string()))?;
+        Ok(bytes)
+    }
+
+    /// One client's session with the entry, answering its queries.
+    struct DnsSession {
+        id: u32,
+        local: UnixStream,
+        /// The resolver the last query came through.
+        peer: SocketAddr,
+        readable: bool,
+        up_next: u32,
+        to_local: Vec<u8>,
+        remote_fin: bool,
+        write_closed: bool,
+        from_local: Vec<u8>,
+        local_eof: bool,
+        down_next: u32,
+        /// The downstream chunk last cut, answered again until acknowledged.
+        chunk: Option<(u32, Vec<u8>, u8)>,
+        fin_cut: bool,
+        fin_acked: bool,
+        last_heard: Instant,
+    }
+
+    impl DnsSession {
+        fn flush(&mut self) -> Result<(), Error> {
+            while !self.to_local.is_empty() {
+                match self.local.write(&self.to_local) {
+                    Ok(size) => {
+                        self.to_local.drain(..size);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+            if self.remote_fin && self.to_local.is_empty() && !self.write_closed {
+                let _ = self.local.shutdown(Shutdown::Write);
+                self.write_closed = true;
+            }
+            Ok(())
+        }
+
+        /// Takes the upstream chunk of a query and returns the answer
+        /// payload, holding at most `capacity` bytes.
+        fn exchange(
+            &mut self,
+            query: &[u8],
+            capacity: usize,
+            buffer_size: usize,
+        ) -> Result<Vec<u8>, Error> {
+            let flags = query[4];
+            let up_seq = read_u32(&query[5..]);
+            let down_seq = read_u32(&query[9..]);
+            let data = &query[QUERY_HEADER_SIZE..];
+            self.last_heard = Instant::now();
+
+            self.flush()?;
+            let carries = !data.is_empty() || flags & FIN != 0;
+            if carries && up_seq == self.up_next && self.to_local.is_empty() && !self.remote_fin {
+                self.to_local.extend_from_slice(data);
+                self.remote_fin = flags & FIN != 0;
+                self.up_next = self.up_next.wrapping_add(1);
+                self.flush()?;
+            }
+
+            if let Some((seq, _, chunk_flags)) = &self.chunk {
+                if down_seq == seq.wrapping_add(1) {
+                    self.fin_acked = chunk_flags & FIN != 0;
+                    self.chunk = None;
+                }
+            }
+            while self.readable && !self.local_eof && self.from_local.len() < MAX_BUFFERED {
+                match read_available(&mut self.local, buffer_size) {
+                    Ok(data) => self.from_local.extend_from_slice(&data),
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                        self.readable = false
+                    }
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                        self.local_eof = true
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            let capacity = capacity.saturating_sub(ANSWER_HEADER_SIZE);
+            let waiting = !self.from_local.is_empty() || (self.local_eof && !self.fin_cut);
+            if self.chunk.is_none() && down_seq == self.down_next && waiting && capacity > 0 {
+                let size = self.from_local.len().min(capacity);
+                let data: Vec<u8> = self.from_local.drain(..size).collect();
+                let mut chunk_flags = 0;
+                if self.local_eof && self.from_local.is_empty() {
+                    chunk_flags |= FIN;
+                    self.fin_cut = true;
+                }
+                self.chunk = Some((self.down_next, data, chunk_flags));
+                self.down_next = self.down_next.wrapping_add(1);
+            }
+
+            let mut payload = vec![STATUS_OK];
+            payload.extend_from_slice(&self.up_next.to_be_bytes());
+            payload.extend_from_slice(&down_seq.to_be_bytes());
+            let more =
+                !self.from_local.is_empty() || self.readable || (self.local_eof && !self.fin_cut);
+            let more = if more { MORE } else { 0 };
+            match &self.chunk {
+                Some((seq, data, chunk_flags)) if *seq == down_seq && data.len() <= capacity => {
+                    payload.push(chunk_flags | more);
+                    payload.extend_from_slice(data);
+                }
+                _ => payload.push(more),
+            }
+            Ok(payload)
+        }
+
+        fn finished(&self) -> bool {
+            self.write_closed && self.fin_acked
+        }
+    }
+
+    type OnOpen = Box<dyn FnMut(UnixStream, SocketAddr) + Send>;
+
+    /// Answers the queries for the domain, running a session for every
+    /// client on its own thread.
+    struct DnsServer {
+        socket: UdpSocket,
+        poll: Poll,
+        sessions: HashMap<Token, DnsSession>,
+        ids: HashMap<u32, Token>,
+        next_token: usize,
+        on_open: OnOpen,
+        config: DnsConfig,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl DnsServer {
+        fn run(&mut self) -> Result<(), Error> {
+            self.poll
+                .registry()
+                .register(&mut self.socket, SOCKET, Interest::READABLE)?;
+            let mut events = Events::with_capacity(1024);
+            let mut buffer = vec![0u8; u16::MAX as usize];
+            loop {
+                if let Err(e) = self.poll.poll(&mut events, Some(SWEEP_INTERVAL)) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+
+                for event in events.iter() {
+                    match event.token() {
+                        SOCKET => self.receive(&mut buffer)?,
+                        token => {
+                            if let Some(session) = self.sessions.get_mut(&token) {
+                                session.readable |= event.is_readable();
+                            }
+                        }
+                    }
+                }
+
+                self.sweep();
+            }
+        }
+
+        fn receive(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+            loop {
+                let (size, peer) = match self.socket.recv_from(buffer) {
+                    Ok(received) => received,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(e) => return Err(Error::IoError(e)),
+                };
+                let message = &buffer[..size];
+                let question = match read_question(message) {
+                    Some(question) => question,
+                    None => continue,
+                };
+                let response = self.respond(message, &question, peer)?;
+                // a lost answer is asked for again
+                let _ = self.socket.send_to(&response, peer);
+            }
+        }
+
+        fn respond(
+            &mut self,
+            message: &[u8],
+            question: &Question,
+            peer: SocketAddr,
+        ) -> Result<Vec<u8>, Error> {
+            let domain = self.config.domain.clone();
+            let domain = domain.as_str();
+            if !within(&question.name, domain) {
+                return Ok(answer(message, question, RCODE_REFUSED, None));
+            }
+            // anything else under the domain, like the probes of a resolver
+            // minimising its queries, exists without records
+            let query = match decode_name(&question.name, domain) {
+                Some(query) if query.len() >= QUERY_HEADER_SIZE => query,
+                _ => return Ok(answer(message, question, 0, None)),
+            };
+            if ![TYPE_TXT, TYPE_NULL, TYPE_CNAME].contains(&question.qtype) {
+                return Ok(answer(message, question, 0, None));
+            }
+
+            let id = read_u32(&query);
+            let token = match self.ids.get(&id) {
+                Some(token) => *token,
+                // only the first query of a session opens it
+                None if read_u32(&query[5..]) == 0 && read_u32(&query[9..]) == 0 => {
+                    self.accept(id, peer)?
+                }
+                None => {
+                    let mut payload = vec![STATUS_UNKNOWN];
+                    payload.extend_from_slice(&[0; ANSWER_HEADER_SIZE - 1]);
+                    let rdata = to_rdata(question.qtype, &payload, domain);
+                    return Ok(answer(message, question, 0, Some(&rdata)));
+                }
+            };
+
+            let capacity = answer_capacity(question.qtype, question.end, domain);
+            let session = match self.sessions.get_mut(&token) {
+                Some(session) => session,
+                None => return Ok(answer(message, question, 0, None)),
+            };
+            session.peer = peer;
+            let payload = match session.exchange(&query, capacity, self.buffer_size) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured on session {:08x}: {}", id, e);
+                    }
+                    self.close(token);
+                    return Ok(answer(message, question, 0, None));
+                }
+            };
+            let rdata = to_rdata(question.qtype, &payload, domain);
+            Ok(answer(message, question, 0, Some(&rdata)))
+        }
+
+        fn accept(&mut self, id: u32, peer: SocketAddr) -> Result<Token, Error> {
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            self.poll.registry().register(
+                &mut SourceFd(&inner.as_raw_fd()),
+                token,
+                Interest::READABLE,
+            )?;
+            self.sessions.insert(
+                token,
+                DnsSession {
+                    id,
+                    local: inner,
+                    peer,
+                    readable: true,
+                    up_next: 0,
+                    to_local: Vec::new(),
+                    remote_fin: false,
+                    write_closed: false,
+                    from_local: Vec::new(),
+                    local_eof: false,
+                    down_next: 0,
+                    chunk: None,
+                    fin_cut: false,
+                    fin_acked: false,
+                    last_heard: Instant::now(),
+                },
+            );
+            self.ids.insert(id, token);
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("new client: {:08x} via {}", id, peer);
+                }
+            }
+
+            (self.on_open)(outer, peer);
+            Ok(token)
+        }
+
+        /// Writes what upstream data is left and drops the sessions that
+        /// ended or went silent.
+        fn sweep(&mut self) {
+            let mut over = Vec::new();
+            for (token, session) in self.sessions.iter_mut() {
+                let result = session.flush();
+                if let Err(e) = &result {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured on session {:08x}: {}", session.id, e);
+                    }
+                }
+                if result.is_err()
+                    || session.finished()
+                    || session.last_heard.elapsed() >= self.config.timeout
+                {
+                    over.push(*token);
+                }
+            }
+            for token in over {
+                self.close(token);
+            }
+        }
+
+        fn close(&mut self, token: Token) {
+            if let Some(session) = self.sessions.remove(&token) {
+                self.ids.remove(&session.id);
+                let _ = self
+                    .poll
+                    .registry()
+                    .deregister(&mut SourceFd(&session.local.as_raw_fd()));
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("client {:08x} closed", session.id);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The step's end of a session, sending one query at a time through the
+    /// resolver and polling for downstream data when it has none to send.
+    struct DnsClient {
+        socket: UdpSocket,
+        local: UnixStream,
+        config: DnsConfig,
+        record_type: u16,
+        /// Shortest time between two queries.
+        gap: Duration,
+        /// Longest time between two queries.
+        poll: Duration,
+        id: u32,
+        random: SystemRandom,
+        capacity: usize,
+        readable: bool,
+        from_local: Vec<u8>,
+        local_eof: bool,
+        up_seq: u32,
+        /// The upstream chunk numbered `up_seq`, sent until acknowledged.
+        chunk: Option<(Vec<u8>, u8)>,
+        fin_cut: bool,
+        down_seq: u32,
+        to_local: Vec<u8>,
+        remote_fin: bool,
+        write_closed: bool,
+        /// The dns id of the query waiting for its answer, and when it went.
+        in_flight: Option<(u16, Instant)>,
+        rtt: Duration,
+        retry: Duration,
+        next_query: Instant,
+        /// When the rate allows the next query.
+        earliest: Instant,
+        idle: Duration,
+        last_heard: Instant,
+        buffer_size: usize,
+    }
+
+    impl DnsClient {
+        fn run(&mut self) -> Result<(), Error> {
+            let mut poll = Poll::new()?;
+            poll.registry()
+                .register(&mut self.socket, SOCKET, Interest::READABLE)?;
+            poll.registry().register(
+                &mut SourceFd(&self.local.as_raw_fd()),
+                LOCAL,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            let mut events = Events::with_capacity(16);
+            let mut buffer = vec![0u8; u16::MAX as usize];
+            loop {
+                let wait = match self.in_flight {
+                    Some((_, sent)) => sent + self.retry,
+                    None => self.next_query.max(self.earliest),
+                };
+                let wait = wait.saturating_duration_since(Instant::now());
+                if let Err(e) = poll.poll(&mut events, Some(wait)) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+                for event in events.iter() {
+                    match event.token() {
+                        SOCKET => self.receive(&mut buffer)?,
+                        _ => self.readable |= event.is_readable(),
+                    }
+                }
+
+                if self.pump()? {
+                    return Ok(());
+                }
+            }
+        }
+
+        /// Moves data between `local` and the queries, returning whether the
+        /// session is over.
+        fn pump(&mut self) -> Result<bool, Error> {
+            let now = Instant::now();
+            if now.duration_since(self.last_heard) >= self.config.timeout {
+                return Err(Error::Msg("resolver stopped answering".to_string()));
+            }
+
+            while self.readable && !self.local_eof && self.from_local.len() < MAX_BUFFERED {
+                match read_available(&mut self.local, self.buffer_size) {
+                    Ok(data) => self.from_local.extend_from_slice(&data),
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                        self.readable = false
+                    }
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                        self.local_eof = true
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            while !self.to_local.is_empty() {
+                match self.local.write(&self.to_local) {
+                    Ok(size) => {
+                        self.to_local.drain(..size);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+            let done = self.fin_cut && self.chunk.is_none();
+            if self.remote_fin && self.to_local.is_empty() && (done || !self.write_closed) {
+                if done {
+                    // one last query acknowledging the end, sent before our
+                    // side sees it and perhaps exits, an entry not hearing it
+                    // lets the session time out
+                    self.send()?;
+                }
+                let _ = self.local.shutdown(Shutdown::Write);
+                self.write_closed = true;
+                if done {
+                    return Ok(true);
+                }
+            }
+
+            if self.chunk.is_none()
+                && (!self.from_local.is_empty() || self.local_eof && !self.fin_cut)
+            {
+                let size = self.from_local.len().min(self.capacity);
+                let data: Vec<u8> = self.from_local.drain(..size).collect();
+                let mut flags = 0;
+                if self.local_eof && self.from_local.is_empty() {
+                    flags |= FIN;
+                    self.fin_cut = true;
+                }
+                self.chunk = Some((data, flags));
+                // data to send cuts an idle wait short
+                self.idle = Duration::ZERO;
+                self.next_query = self.next_query.min(now);
+            }
+
+            let due = match self.in_flight {
+                Some((_, sent)) if now.duration_since(sent) >= self.retry => {
+                    self.retry = (self.retry * 2).min(MAX_RETRY);
+                    true
+                }
+                Some(_) => false,
+                None => now >= self.next_query,
+            };
+            if due && now >= self.earliest {
+                self.send()?;
+            }
+            Ok(false)
+        }
+
+        fn send(&mut self) -> Result<(), Error> {
+            let [id_high, id_low, nonce_high, nonce_low] = random_bytes::<4>(&self.random)?;
+            let mut payload = Vec::with_capacity(QUERY_HEADER_SIZE + self.capacity);
+            payload.extend_from_slice(&self.id.to_be_bytes());
+            payload.push(self.chunk.as_ref().map(|(_, flags)| *flags).unwrap_or(0));
+            payload.extend_from_slice(&self.up_seq.to_be_bytes());
+            payload.extend_from_slice(&self.down_seq.to_be_bytes());
+            payload.extend_from_slice(&[nonce_high, nonce_low]);
+            if let Some((data, _)) = &self.chunk {
+                payload.extend_from_slice(data);
+            }
+
+            let id = u16::from_be_bytes([id_high, id_low]);
+            let message = query(
+                id,
+                &encode_name(&payload, &self.config.domain),
+                self.record_type,
+            );
+            match self.socket.send(&message) {
+                Ok(_) => {}
+                // lost like any other query, and sent again
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) if e.kind() == ErrorKind::ConnectionRefused => {}
+                Err(e) => return Err(Error::IoError(e)),
+            }
+            let now = Instant::now();
+            self.in_flight = Some((id, now));
+            self.earliest = now + self.gap;
+            Ok(())
+        }
+
+        fn receive(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+            loop {
+                let size = match self.socket.recv(buffer) {
+                    Ok(size) => size,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(e) if e.kind() == ErrorKind::ConnectionRefused => continue,
+                    Err(e) => return Err(Error::IoError(e)),
+                };
+                let id = match self.in_flight {
+                    Some((id, _)) => id,
+                    None => continue,
+                };
+                let payload =
+                    match read_answer(&buffer[..size], id, self.record_type, &self.config.domain) {
+                        Some(payload) if payload.len() >= ANSWER_HEADER_SIZE => payload,
+                        _ => continue,
+                    };
+                self.answered(&payload)?;
+            }
+        }
+
+        fn answered(&mut self, payload: &[u8]) -> Result<(), Error> {
+            if payload[0] == STATUS_UNKNOWN {
+                return Err(Error::Msg("entry does not know the session".to_string()));
+            }
+            let now = Instant::now();
+            if let Some((_, sent)) = self.in_flight.take() {
+                // every query has its own id, so the answer is to this one
+                let rtt = now.duration_since(sent);
+                self.rtt = (self.rtt * 7 + rtt) / 8;
+                self.retry = (self.rtt * 4).clamp(MIN_RETRY, MAX_RETRY);
+            }
+            self.last_heard = now;
+
+            let mut active = false;
+            if self.chunk.is_some() && read_u32(&payload[1..]) == self.up_seq.wrapping_add(1) {
+                self.chunk = None;
+                self.up_seq = self.up_seq.wrapping_add(1);
+                active = true;
+            }
+            let flags = payload[9];
+            let data = &payload[ANSWER_HEADER_SIZE..];
+            let carries = !data.is_empty() || flags & FIN != 0;
+            if carries
+                && read_u32(&payload[5..]) == self.down_seq
+                && !self.remote_fin
+                && self.to_local.len() < MAX_BUFFERED
+            {
+                self.to_local.extend_from_slice(data);
+                self.remote_fin = flags & FIN != 0;
+                self.down_seq = self.down_seq.wrapping_add(1);
+                active = true;
+            }
+
+            // queries follow each other as fast as allowed while there is
+            // data either way, and slow down to `poll` while there is none
+            if active || flags & MORE != 0 || self.chunk.is_some() {
+                self.idle = Duration::ZERO;
+            } else {
+                self.idle = (self.idle * 2).max(MIN_POLL).min(self.poll);
+            }
+            self.next_query = now + self.idle;
+            Ok(())
+        }
+    }
+
+    /// Answers the queries of `DnsStep` clients as the authoritative server
+    /// of the domain, and serves each with its own copy of the pipeline.
+    pub struct DnsEntry {
+        address: String,
+        port: u16,
+        config: DnsConfig,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for DnsEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let socket = UdpSocket::bind(addr)?;
+            let local = socket.local_addr()?;
+
+            let handle = runtime.handle().clone();
+            let pipeline_template = self.pipeline_template.clone();
+            let debug_level = self.debug_level;
+            let buffer_size = self.buffer_size;
+            let on_open: OnOpen = Box::new(move |stream, peer| {
+                let info = ConnectionInfo {
+                    peer: Some(peer),
+                    local: Some(local),
+                    destination: None,
+                };
+                let pipeline_template = pipeline_template.clone();
+                handle.spawn(async move {
+                    if let Err(e) = serve_stream(stream, info, pipeline_template, buffer_size).await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving session via {}: {}", peer, e);
+                        }
+                    }
+                });
+            });
+
+            DnsServer {
+                socket,
+                poll: Poll::new()?,
+                sessions: HashMap::new(),
+                ids: HashMap::new(),
+                next_token: FIRST_SESSION,
+                on_open,
+                config: self.config.clone(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+            .run()
+        }
+    }
+
+    impl EntryStatic<DnsEntry> for DnsEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<DnsEntry, Error> {
+            let address = match args.argument_values.get(DNS_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(DNS_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(DNS_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(DNS_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(DnsEntry {
+                address,
+                port,
+                config: parse_config(&args)?,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: DNS_ENTRY_ADDRESS.0.to_string(),
+                key: vec![DNS_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DNS_ENTRY_PORT.0.to_string(),
+                key: vec![DNS_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("53".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DNS_DOMAIN.0.to_string(),
+                key: vec![DNS_DOMAIN.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(DNS_DOMAIN.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DNS_TIMEOUT.0.to_string(),
+                key: vec![DNS_TIMEOUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("60".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_TIMEOUT.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Carries its client through a resolver to a `DnsEntry`, inside the
+    /// names of the queries and the records answering them.
+    pub struct DnsStep {
+        address: String,
+        port: u16,
+        config: DnsConfig,
+        record_type: u16,
+        gap: Duration,
+        poll: Duration,
+        /// The local end of the session and its query loop.
+        stream: LazyStream,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl DnsStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let resolver = create_socket_addr(self.address.as_str(), self.port)?;
+            let bind = match resolver {
+                SocketAddr::V4(_) => "0.0.0.0:0",
+                SocketAddr::V6(_) => "[::]:0",
+            };
+            let socket = UdpSocket::bind(bind.parse()?)?;
+            socket.connect(resolver)?;
+            let random = SystemRandom::new();
+            let id = u32::from_be_bytes(random_bytes::<4>(&random)?);
+
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let now = Instant::now();
+            let mut client = DnsClient {
+                socket,
+                local: inner,
+                capacity: query_capacity(&self.config.domain),
+                config: self.config.clone(),
+                record_type: self.record_type,
+                gap: self.gap,
+                poll: self.poll,
+                id,
+                random,
+                readable: true,
+                from_local: Vec::new(),
+                local_eof: false,
+                up_seq: 0,
+                chunk: None,
+                fin_cut: false,
+                down_seq: 0,
+                to_local: Vec::new(),
+                remote_fin: false,
+                write_closed: false,
+                in_flight: None,
+                rtt: INITIAL_RETRY / 4,
+                retry: INITIAL_RETRY,
+                next_query: now,
+                earliest: now,
+                idle: Duration::ZERO,
+                last_heard: now,
+                buffer_size: self.buffer_size,
+            };
+
+            let debug_level = self.debug_level;
+            thread::spawn(move || {
+                if let Err(e) = client.run() {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on dns session: {}", e);
+                    }
+                }
+            });
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("dns session", self.debug_level, || self.open())
+        }
+    }
+
+    impl Step for DnsStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_all(&mut self.stream()?, data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.stream()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for DnsStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl StepStatic for DnsStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(DNS_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(DNS_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(DNS_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(DNS_STEP_PORT.0.to_string())),
+            };
+            let record_type = match args.argument_values.get(DNS_TYPE.0) {
+                Some(record_type) => record_type[0].clone(),
+                None => return Err(Error::RequireOption(DNS_TYPE.0.to_string())),
+            };
+            let rate = match args.argument_values.get(DNS_RATE.0) {
+                Some(rate) => rate[0].clone(),
+                None => return Err(Error::RequireOption(DNS_RATE.0.to_string())),
+            };
+            let poll = match args.argument_values.get(DNS_POLL.0) {
+                Some(poll) => poll[0].clone(),
+                None => return Err(Error::RequireOption(DNS_POLL.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let record_type = match record_type.to_ascii_lowercase().as_str() {
+                "txt" => TYPE_TXT,
+                "null" => TYPE_NULL,
+                "cname" => TYPE_CNAME,
+                _ => {
+                    return Err(Error::Msg(format!(
+                        "unknown dns record type: {}",
+                        record_type
+                    )))
+                }
+            };
+            let gap = match str::parse::<u64>(rate.as_str()) {
+                Ok(0) => Duration::ZERO,
+                Ok(rate) => Duration::from_micros(1_000_000 / rate),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let poll = match str::parse::<u64>(poll.as_str()) {
+                Ok(poll) => Duration::from_millis(poll),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Self {
+                address,
+                port,
+                config: parse_config(&args)?,
+                record_type,
+                gap,
+                poll,
+                stream: LazyStream::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: DNS_STEP_ADDRESS.0.to_string(),
+                key: vec![DNS_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DNS_STEP_PORT.0.to_string(),
+                key: vec![DNS_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("53".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_STEP_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DNS_TYPE.0.to_string(),
+                key: vec![DNS_TYPE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("txt".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_TYPE.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DNS_RATE.0.to_string(),
+                key: vec![DNS_RATE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("50".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_RATE.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DNS_POLL.0.to_string(),
+                key: vec![DNS_POLL.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("1000".to_string()),
+                help: Some(ArgumentHelp::Text(DNS_POLL.2.to_string())),
+            });
+            // the domain and timeout are shared with DnsEntry, which adds them
+            argument
+        }
+    }
+
+    impl Clone for DnsStep {
+        fn clone(&self) -> Self {
+            Self {
+                address: self.address.clone(),
+                port: self.port,
+                config: self.config.clone(),
+                record_type: self.record_type,
+                gap: self.gap,
+                poll: self.poll,
+                stream: LazyStream::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for DnsStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::io::Read;
+        use std::net::UdpSocket as StdUdpSocket;
+
+        use super::*;
+
+        const DOMAIN: &str = "t.example.com";
+
+        fn config() -> DnsConfig {
+            DnsConfig {
+                domain: DOMAIN.to_string(),
+                timeout: Duration::from_secs(10),
+            }
+        }
+
+        #[test]
+        fn full_query_fits_a_name() {
+            for domain in [DOMAIN, "a.b", &"x".repeat(60)] {
+                let data: Vec<u8> = (0..QUERY_HEADER_SIZE + query_capacity(domain))
+                    .map(|n| n as u8)
+                    .collect();
+                let name = encode_name(&data, domain);
+                assert!(name.len() <= MAX_NAME, "{} for {}", name.len(), domain);
+                assert!(name.split('.').all(|label| label.len() <= MAX_LABEL));
+                assert_eq!(decode_name(&name, domain), Some(data));
+            }
+        }
+
+        #[test]
+        fn names_decode_in_any_case() {
+            let name = encode_name(b"mixed case", DOMAIN);
+            // resolvers may randomise the case of the names they pass on
+            let name: String = name
+                .chars()
+                .enumerate()
+                .map(|(n, c)| match n % 2 {
+                    0 => c.to_ascii_uppercase(),
+                    _ => c,
+                })
+                .collect();
+            assert_eq!(decode_name(&name, DOMAIN), Some(b"mixed case".to_vec()));
+            assert_eq!(decode_name("abc.other.com", DOMAIN), None);
+        }
+
+        #[test]
+        fn within_domain() {
+            assert!(within(DOMAIN, DOMAIN));
+            assert!(within("ABC.T.Example.com", DOMAIN));
+            assert!(!within("abct.example.com", DOMAIN));
+            assert!(!within("example.com", DOMAIN));
+        }
+
+        #[test]
+        fn answers_carry_a_full_payload() {
+            for qtype in [TYPE_TXT, TYPE_NULL, TYPE_CNAME] {
+                let name = encode_name(&vec![7u8; query_capacity(DOMAIN)], DOMAIN);
+                let message = query(0x1234, &name, qtype);
+                let question = read_question(&message).unwrap();
+                assert_eq!(question.name, name);
+                assert_eq!(question.qtype, qtype);
+
+                let capacity = answer_capacity(qtype, question.end, DOMAIN);
+                let payload: Vec<u8> = (0..capacity).map(|n| n as u8).collect();
+                let rdata = to_rdata(qtype, &payload, DOMAIN);
+                let response = answer(&message, &question, 0, Some(&rdata));
+                assert!(
+                    response.len() <= MAX_MESSAGE,
+                    "{} for {}",
+                    response.len(),
+                    qtype
+                );
+                assert_eq!(read_answer(&response, 0x1234, qtype, DOMAIN), Some(payload));
+                assert_eq!(read_answer(&response, 0x4321, qtype, DOMAIN), None);
+            }
+        }
+
+        /// Passes queries from the step to the entry and answers back, like
+        /// a recursive resolver, changing the case of the names it asks for.
+        fn resolver(server: SocketAddr) -> SocketAddr {
+            let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+            let address = socket.local_addr().unwrap();
+            thread::spawn(move || {
+                let mut buffer = [0u8; MAX_MESSAGE];
+                let mut client = None;
+                loop {
+                    let (size, peer) = socket.recv_from(&mut buffer).unwrap();
+                    let message = &mut buffer[..size];
+                    if peer == server {
+                        if let Some(client) = client {
+                            socket.send_to(message, client).unwrap();
+                        }
+                        continue;
+                    }
+                    client = Some(peer);
+                    for byte in message[HEADER_SIZE..].iter_mut().step_by(3) {
+                        byte.make_ascii_uppercase();
+                    }
+                    socket.send_to(message, server).unwrap();
+                }
+            });
+            address
+        }
+
+        /// Runs an entry whose sessions echo what they read.
+        fn echo_server() -> SocketAddr {
+            let socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let address = socket.local_addr().unwrap();
+            let on_open: OnOpen = Box::new(|mut stream, _| {
+                thread::spawn(move || {
+                    stream.set_nonblocking(false).unwrap();
+                    let mut data = Vec::new();
+                    stream.read_to_end(&mut data).unwrap();
+                    stream.write_all(&data).unwrap();
+                    stream.shutdown(Shutdown::Write).unwrap();
+                });
+            });
+            let mut server = DnsServer {
+                socket,
+                poll: Poll::new().unwrap(),
+                sessions: HashMap::new(),
+                ids: HashMap::new(),
+                next_token: FIRST_SESSION,
+                on_open,
+                config: config(),
+                debug_level: DebugLevel::None,
+                buffer_size: 4096,
+            };
+            thread::spawn(move || server.run());
+            address
+        }
+
+        #[test]
+        fn session_through_a_resolver() {
+            let resolver = resolver(echo_server());
+            let data: Vec<u8> = (0..4000).map(|n| (n % 251) as u8).collect();
+            for record_type in [TYPE_TXT, TYPE_NULL, TYPE_CNAME] {
+                let step = DnsStep {
+                    address: resolver.ip().to_string(),
+                    port: resolver.port(),
+                    config: config(),
+                    record_type,
+                    gap: Duration::ZERO,
+                    poll: MIN_POLL,
+                    stream: LazyStream::new(),
+                    debug_level: DebugLevel::None,
+                    buffer_size: 4096,
+                };
+                let mut stream = step.open().unwrap();
+                stream.set_nonblocking(false).unwrap();
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(20)))
+                    .unwrap();
+                stream.write_all(&data).unwrap();
+                stream.shutdown(Shutdown::Write).unwrap();
+                let mut echoed = Vec::new();
+                stream.read_to_end(&mut echoed).unwrap();
+                assert_eq!(echoed, data, "record type {}", record_type);
+            }
+        }
+    }
+}
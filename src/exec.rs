@@ -0,0 +1,757 @@
+pub mod exec {
+    use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+    use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::process::{Child, Command, ExitStatus, Stdio};
+    use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+
+    use crate::create_socket_addr;
+    use crate::tcp::tcp::{read_available, TcpEntry, WRITE_TIMEOUT};
+    use crate::BUFFER_SIZE;
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+
+    const EXEC_STEP_COMMAND: (&str, &str, &str) = (
+        "exec-step-command",
+        "--exec-scmd",
+        "(ExecStep) Program to spawn",
+    );
+    const EXEC_STEP_ARG: (&str, &str, &str) = (
+        "exec-step-arg",
+        "--exec-sarg",
+        "(ExecStep) Argument of the program, may be repeated",
+    );
+    const EXEC_STEP_ENV: (&str, &str, &str) = (
+        "exec-step-env",
+        "--exec-senv",
+        "(ExecStep) Environment variable of the program as NAME=value, may be repeated",
+    );
+    const EXEC_STEP_MODE: (&str, &str, &str) = (
+        "exec-step-mode",
+        "--exec-smode",
+        "(ExecStep) \"connection\" spawns a process per client, \"shared\" feeds every client to one process",
+    );
+
+    const EXEC_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "exec-entry-address",
+        "--exec-ea",
+        "(ExecEntry) Exec Entry listen address",
+    );
+    const EXEC_ENTRY_PORT: (&str, &str, &str) = (
+        "exec-entry-port",
+        "--exec-ep",
+        "(ExecEntry) Exec Entry listen port",
+    );
+    const EXEC_ENTRY_COMMAND: (&str, &str, &str) = (
+        "exec-entry-command",
+        "--exec-ecmd",
+        "(ExecEntry) Program to spawn for every client",
+    );
+    const EXEC_ENTRY_ARG: (&str, &str, &str) = (
+        "exec-entry-arg",
+        "--exec-earg",
+        "(ExecEntry) Argument of the program, may be repeated",
+    );
+    const EXEC_ENTRY_ENV: (&str, &str, &str) = (
+        "exec-entry-env",
+        "--exec-eenv",
+        "(ExecEntry) Environment variable of the program as NAME=value, may be repeated",
+    );
+
+    /// How long a process may take to exit after its stdin was closed before
+    /// it is killed.
+    const EXIT_GRACE: Duration = Duration::from_secs(5);
+    const EXIT_POLL: Duration = Duration::from_millis(50);
+    /// Writes queued for a process's stdin before its clients have to wait.
+    const STDIN_QUEUE: usize = 64;
+    const STDIN_POLL: Duration = Duration::from_millis(10);
+    /// Milliseconds a client may leave the output of a process unread before
+    /// it is disconnected, so one stalled client cannot block the others.
+    const SUBSCRIBER_TIMEOUT: i32 = 1000;
+
+    /// Program, arguments and environment of the processes to spawn.
+    #[derive(Clone)]
+    pub(crate) struct ExecCommand {
+        pub(crate) program: String,
+        pub(crate) args: Vec<String>,
+        pub(crate) env: Vec<(String, String)>,
+    }
+
+    impl ExecCommand {
+        pub(crate) fn command(&self) -> Command {
+            let mut command = Command::new(&self.program);
+            command.args(&self.args);
+            command.envs(self.env.iter().map(|(name, value)| (name, value)));
+            command
+        }
+
+        /// Variables describing the client in the UCSPI convention followed by
+        /// inetd-like servers.
+        pub(crate) fn connection_env(info: &ConnectionInfo) -> Vec<(String, String)> {
+            let mut env = vec![("PROTO".to_string(), "TCP".to_string())];
+            if let Some(peer) = info.peer {
+                env.push(("TCPREMOTEIP".to_string(), peer.ip().to_string()));
+                env.push(("TCPREMOTEPORT".to_string(), peer.port().to_string()));
+            }
+            if let Some(local) = info.local {
+                env.push(("TCPLOCALIP".to_string(), local.ip().to_string()));
+                env.push(("TCPLOCALPORT".to_string(), local.port().to_string()));
+            }
+            env
+        }
+
+        pub(crate) fn parse_env(
+            values: Option<&Vec<String>>,
+        ) -> Result<Vec<(String, String)>, Error> {
+            let mut env = Vec::new();
+            for value in values.into_iter().flatten() {
+                match value.split_once('=') {
+                    Some((name, value)) => env.push((name.to_string(), value.to_string())),
+                    None => {
+                        return Err(Error::Msg(format!(
+                            "invalid environment variable: {}",
+                            value
+                        )))
+                    }
+                }
+            }
+            Ok(env)
+        }
+    }
+
+    /// Logs each line the process writes to `stderr`.
+    pub(crate) fn log_stderr<R: Read + Send + 'static>(
+        stderr: R,
+        program: String,
+        debug_level: DebugLevel,
+    ) {
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines() {
+                match line {
+                    Ok(line) => {
+                        if debug_level > 0 {
+                            eprintln!("{}: {}", program, line);
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+    }
+
+    /// Waits for the process to exit, killing it once `deadline` has passed.
+    pub(crate) fn reap(child: &Mutex<Child>, deadline: Option<Instant>) -> io::Result<ExitStatus> {
+        loop {
+            let mut child = child.lock().unwrap();
+            match child.try_wait()? {
+                Some(status) => return Ok(status),
+                None => {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        let _ = child.kill();
+                    }
+                }
+            }
+            drop(child);
+            thread::sleep(EXIT_POLL);
+        }
+    }
+
+    pub(crate) fn log_exit(program: &str, status: io::Result<ExitStatus>, debug_level: DebugLevel) {
+        match status {
+            Ok(status) => {
+                if debug_level >= 2 {
+                    eprintln!("{} exited: {}", program, status);
+                }
+            }
+            Err(e) => {
+                if debug_level > 0 {
+                    eprintln!("an error accured waiting for {}: {}", program, e);
+                }
+            }
+        }
+    }
+
+    /// Writes `data` to a subscriber, giving up on it if it stays full for
+    /// longer than `timeout` milliseconds (-1 waits forever).
+    pub(crate) fn deliver(subscriber: &mut UnixStream, mut data: &[u8], timeout: i32) -> bool {
+        while !data.is_empty() {
+            match subscriber.write(data) {
+                Ok(size) => data = &data[size..],
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    let mut fd = libc::pollfd {
+                        fd: subscriber.as_raw_fd(),
+                        events: libc::POLLOUT,
+                        revents: 0,
+                    };
+                    if unsafe { libc::poll(&mut fd, 1, timeout) } <= 0 {
+                        return false;
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum ExecMode {
+        Connection,
+        Shared,
+    }
+
+    /// A running process. Its stdout is copied to every subscribed socket,
+    /// a single one unless the process is shared between clients.
+    struct ExecProcess {
+        child: Arc<Mutex<Child>>,
+        /// Queue of the thread writing stdin, so that a process busy writing
+        /// its stdout never blocks the entry that has to read it.
+        stdin: Mutex<Option<SyncSender<Vec<u8>>>>,
+        subscribers: Arc<Mutex<Vec<UnixStream>>>,
+    }
+
+    impl ExecProcess {
+        fn spawn(
+            command: &ExecCommand,
+            subscriber_timeout: i32,
+            debug_level: DebugLevel,
+        ) -> Result<ExecProcess, Error> {
+            let mut child = command
+                .command()
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            let mut stdin = child.stdin.take().unwrap();
+            let mut stdout = child.stdout.take().unwrap();
+            log_stderr(
+                child.stderr.take().unwrap(),
+                command.program.clone(),
+                debug_level,
+            );
+
+            {
+                // debug
+                if debug_level >= 2 {
+                    eprintln!("spawned {} ({})", command.program, child.id());
+                }
+            }
+
+            let (sender, receiver) = sync_channel::<Vec<u8>>(STDIN_QUEUE);
+            thread::spawn(move || {
+                // ends once the step is dropped, closing stdin
+                for data in receiver {
+                    if stdin.write_all(&data).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let child = Arc::new(Mutex::new(child));
+            let subscribers: Arc<Mutex<Vec<UnixStream>>> = Arc::new(Mutex::new(Vec::new()));
+            {
+                let child = child.clone();
+                let subscribers = subscribers.clone();
+                let program = command.program.clone();
+                thread::spawn(move || {
+                    let mut buffer = vec![0u8; 64 * 1024];
+                    loop {
+                        let size = match stdout.read(&mut buffer) {
+                            Ok(0) | Err(_) => break,
+                            Ok(size) => size,
+                        };
+                        // subscribers that went away or stopped reading are dropped
+                        subscribers.lock().unwrap().retain_mut(|subscriber| {
+                            deliver(subscriber, &buffer[0..size], subscriber_timeout)
+                        });
+                    }
+                    // closing the sockets tells the steps the process is gone
+                    subscribers.lock().unwrap().clear();
+                    log_exit(&program, reap(&child, None), debug_level);
+                });
+            }
+
+            Ok(ExecProcess {
+                child,
+                stdin: Mutex::new(Some(sender)),
+                subscribers,
+            })
+        }
+
+        /// Returns the local end of a socket receiving the process's stdout.
+        fn subscribe(&self) -> Result<UnixStream, Error> {
+            let (local, remote) = UnixStream::pair()?;
+            local.set_nonblocking(true)?;
+            remote.set_nonblocking(true)?;
+            self.subscribers.lock().unwrap().push(remote);
+            Ok(local)
+        }
+
+        /// Queues `data` for stdin, waiting up to `WRITE_TIMEOUT` while the
+        /// process is behind on reading it.
+        fn write(&self, mut data: Vec<u8>) -> Result<(), Error> {
+            let stdin = self.stdin.lock().unwrap();
+            let stdin = match stdin.as_ref() {
+                Some(stdin) => stdin,
+                None => return Err(Error::IoError(ErrorKind::BrokenPipe.into())),
+            };
+            let deadline = Instant::now() + WRITE_TIMEOUT;
+            loop {
+                match stdin.try_send(data) {
+                    Ok(()) => return Ok(()),
+                    Err(TrySendError::Full(_)) if Instant::now() >= deadline => {
+                        return Err(Error::IoError(ErrorKind::TimedOut.into()))
+                    }
+                    Err(TrySendError::Full(unsent)) => {
+                        data = unsent;
+                        thread::sleep(STDIN_POLL);
+                    }
+                    Err(TrySendError::Disconnected(_)) => {
+                        return Err(Error::IoError(ErrorKind::BrokenPipe.into()))
+                    }
+                }
+            }
+        }
+
+        /// Closes stdin once the writer thread has passed on what is queued.
+        fn close_stdin(&self) {
+            self.stdin.lock().unwrap().take();
+        }
+
+        fn has_exited(&self) -> bool {
+            !matches!(self.child.lock().unwrap().try_wait(), Ok(None))
+        }
+    }
+
+    impl Drop for ExecProcess {
+        /// Closes stdin so the process can finish on its own, then kills it if
+        /// it does not exit in time. Its exit is logged by the stdout thread.
+        fn drop(&mut self) {
+            self.close_stdin();
+            let child = self.child.clone();
+            thread::spawn(move || {
+                let _ = reap(&child, Some(Instant::now() + EXIT_GRACE));
+            });
+        }
+    }
+
+    /// Pipes pipeline traffic through a spawned process: forward data is
+    /// written to its stdin and its stdout is returned backward.
+    pub struct ExecStep {
+        command: ExecCommand,
+        mode: ExecMode,
+        /// The process of shared mode, respawned once it has exited.
+        shared: Arc<Mutex<Option<Arc<ExecProcess>>>>,
+        /// The process and the socket receiving its stdout, spawned or joined
+        /// when the step is first used, `None` if that failed.
+        running: OnceLock<Option<(Arc<ExecProcess>, UnixStream)>>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl ExecStep {
+        fn process(&self) -> Result<Arc<ExecProcess>, Error> {
+            if self.mode == ExecMode::Connection {
+                // the only subscriber is this step, it may stall as long as
+                // its entry applies backpressure
+                return Ok(Arc::new(ExecProcess::spawn(
+                    &self.command,
+                    -1,
+                    self.debug_level,
+                )?));
+            }
+            let mut shared = self.shared.lock().unwrap();
+            match shared.as_ref() {
+                Some(process) if !process.has_exited() => Ok(process.clone()),
+                _ => {
+                    let process = Arc::new(ExecProcess::spawn(
+                        &self.command,
+                        SUBSCRIBER_TIMEOUT,
+                        self.debug_level,
+                    )?);
+                    *shared = Some(process.clone());
+                    Ok(process)
+                }
+            }
+        }
+
+        fn try_start(&self) -> Result<(Arc<ExecProcess>, UnixStream), Error> {
+            let process = self.process()?;
+            let output = process.subscribe()?;
+            Ok((process, output))
+        }
+
+        /// Spawns or joins a process on first use, leaving the step without
+        /// one on failure so that the entry drops the client instead of
+        /// exiting.
+        fn running(&self) -> Option<&(Arc<ExecProcess>, UnixStream)> {
+            self.running
+                .get_or_init(|| match self.try_start() {
+                    Ok(running) => Some(running),
+                    Err(e) => {
+                        if self.debug_level > 0 {
+                            eprintln!("an error accured spawning {}: {}", self.command.program, e);
+                        }
+                        None
+                    }
+                })
+                .as_ref()
+        }
+    }
+
+    impl Step for ExecStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            match self.running() {
+                Some((process, _)) => process.write(data.clone())?,
+                None => return Err(Error::IoError(ErrorKind::NotConnected.into())),
+            }
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            self.running();
+            match self.running.get_mut().and_then(Option::as_mut) {
+                Some((_, output)) => read_available(output, self.buffer_size),
+                None => Err(Error::IoError(ErrorKind::NotConnected.into())),
+            }
+        }
+
+        /// Closes stdin of a per-connection process, a shared one keeps
+        /// serving the other clients.
+        fn close_forward(&mut self) -> Result<(), Error> {
+            if let (ExecMode::Connection, Some(Some((process, _)))) =
+                (self.mode, self.running.get())
+            {
+                process.close_stdin();
+            }
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for ExecStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        /// Passes the client's addresses to a per-connection process in its
+        /// environment, a shared process may already serve other clients.
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            let mut result = self.clone();
+            if self.mode == ExecMode::Connection {
+                result.command.env.extend(ExecCommand::connection_env(info));
+            }
+            result.running = OnceLock::from(Some(result.try_start()?));
+            Ok(Box::new(result))
+        }
+    }
+
+    impl Clone for ExecStep {
+        /// The clone spawns its own process, or joins the shared one, once it
+        /// is used.
+        fn clone(&self) -> Self {
+            Self {
+                command: self.command.clone(),
+                mode: self.mode,
+                shared: self.shared.clone(),
+                running: OnceLock::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for ExecStep {
+        fn as_raw_fd(&self) -> RawFd {
+            match self.running() {
+                Some((_, output)) => output.as_raw_fd(),
+                None => -1,
+            }
+        }
+    }
+
+    impl StepStatic for ExecStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let program = match args.argument_values.get(EXEC_STEP_COMMAND.0) {
+                Some(program) => program[0].clone(),
+                None => return Err(Error::RequireOption(EXEC_STEP_COMMAND.0.to_string())),
+            };
+            let mode = match args.argument_values.get(EXEC_STEP_MODE.0) {
+                Some(mode) => match mode[0].as_str() {
+                    "connection" => ExecMode::Connection,
+                    "shared" => ExecMode::Shared,
+                    other => return Err(Error::Msg(format!("unknown exec mode: {}", other))),
+                },
+                None => return Err(Error::RequireOption(EXEC_STEP_MODE.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let command = ExecCommand {
+                program,
+                args: args
+                    .argument_values
+                    .get(EXEC_STEP_ARG.0)
+                    .cloned()
+                    .unwrap_or_default(),
+                env: ExecCommand::parse_env(args.argument_values.get(EXEC_STEP_ENV.0))?,
+            };
+
+            Ok(Self {
+                command,
+                mode,
+                shared: Arc::new(Mutex::new(None)),
+                running: OnceLock::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: EXEC_STEP_COMMAND.0.to_string(),
+                key: vec![EXEC_STEP_COMMAND.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(EXEC_STEP_COMMAND.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: EXEC_STEP_ARG.0.to_string(),
+                key: vec![EXEC_STEP_ARG.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Multiple,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(EXEC_STEP_ARG.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: EXEC_STEP_ENV.0.to_string(),
+                key: vec![EXEC_STEP_ENV.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Multiple,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(EXEC_STEP_ENV.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: EXEC_STEP_MODE.0.to_string(),
+                key: vec![EXEC_STEP_MODE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("connection".to_string()),
+                help: Some(ArgumentHelp::Text(EXEC_STEP_MODE.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Inetd-like listener spawning the program for every client with the
+    /// connection as its stdin and stdout, and the client's addresses in
+    /// its environment. Given steps, the traffic passes through them and
+    /// the program is spawned by an `ExecStep` at the end of the pipeline.
+    pub struct ExecEntry {
+        address: String,
+        port: u16,
+        command: ExecCommand,
+        /// Serves the clients when there are steps between them and the
+        /// program.
+        through: Option<TcpEntry>,
+        debug_level: DebugLevel,
+    }
+
+    impl Entry for ExecEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            if let Some(entry) = self.through.as_mut() {
+                return entry.listen();
+            }
+
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let server = std::net::TcpListener::bind(addr)?;
+
+            loop {
+                let (connection, peer) = server.accept()?;
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("new client: {}", peer);
+                    }
+                }
+
+                let info = ConnectionInfo {
+                    peer: Some(peer),
+                    local: connection.local_addr().ok(),
+                    destination: None,
+                };
+                if let Err(e) = self.spawn(connection, &info) {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured spawning {}: {}", self.command.program, e);
+                    }
+                }
+            }
+        }
+    }
+
+    impl ExecEntry {
+        fn spawn(
+            &self,
+            connection: std::net::TcpStream,
+            info: &ConnectionInfo,
+        ) -> Result<(), Error> {
+            let stdin = connection.try_clone()?;
+            let mut child = self
+                .command
+                .command()
+                .envs(ExecCommand::connection_env(info))
+                .stdin(Stdio::from(OwnedFd::from(stdin)))
+                .stdout(Stdio::from(OwnedFd::from(connection)))
+                .stderr(Stdio::piped())
+                .spawn()?;
+            log_stderr(
+                child.stderr.take().unwrap(),
+                self.command.program.clone(),
+                self.debug_level,
+            );
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    eprintln!("spawned {} ({})", self.command.program, child.id());
+                }
+            }
+
+            let program = self.command.program.clone();
+            let debug_level = self.debug_level;
+            thread::spawn(move || log_exit(&program, child.wait(), debug_level));
+            Ok(())
+        }
+    }
+
+    impl EntryStatic<ExecEntry> for ExecEntry {
+        fn new(
+            args: CliParsed,
+            mut pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<ExecEntry, Error> {
+            let address = match args.argument_values.get(EXEC_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(EXEC_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(EXEC_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(EXEC_ENTRY_PORT.0.to_string())),
+            };
+            let program = match args.argument_values.get(EXEC_ENTRY_COMMAND.0) {
+                Some(program) => program[0].clone(),
+                None => return Err(Error::RequireOption(EXEC_ENTRY_COMMAND.0.to_string())),
+            };
+
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let command = ExecCommand {
+                program,
+                args: args
+                    .argument_values
+                    .get(EXEC_ENTRY_ARG.0)
+                    .cloned()
+                    .unwrap_or_default(),
+                env: ExecCommand::parse_env(args.argument_values.get(EXEC_ENTRY_ENV.0))?,
+            };
+            let through = if pipeline.is_empty() {
+                None
+            } else {
+                pipeline.add_step(Box::new(ExecStep {
+                    command: command.clone(),
+                    mode: ExecMode::Connection,
+                    shared: Arc::new(Mutex::new(None)),
+                    running: OnceLock::new(),
+                    debug_level,
+                    buffer_size,
+                }));
+                Some(TcpEntry::with_pipeline(
+                    address.clone(),
+                    port,
+                    pipeline,
+                    buffer_size,
+                    debug_level,
+                ))
+            };
+
+            Ok(ExecEntry {
+                address,
+                port,
+                command,
+                through,
+                debug_level,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: EXEC_ENTRY_ADDRESS.0.to_string(),
+                key: vec![EXEC_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(EXEC_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: EXEC_ENTRY_PORT.0.to_string(),
+                key: vec![EXEC_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("8000".to_string()),
+                help: Some(ArgumentHelp::Text(EXEC_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: EXEC_ENTRY_COMMAND.0.to_string(),
+                key: vec![EXEC_ENTRY_COMMAND.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(EXEC_ENTRY_COMMAND.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: EXEC_ENTRY_ARG.0.to_string(),
+                key: vec![EXEC_ENTRY_ARG.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Multiple,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(EXEC_ENTRY_ARG.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: EXEC_ENTRY_ENV.0.to_string(),
+                key: vec![EXEC_ENTRY_ENV.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Multiple,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(EXEC_ENTRY_ENV.2.to_string())),
+            });
+            argument
+        }
+    }
+}
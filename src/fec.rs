@@ -0,0 +1,1273 @@
+pub mod fec {
+    use std::collections::{BTreeMap, HashMap};
+    use std::io::{ErrorKind, Write};
+    use std::net::{Shutdown, SocketAddr};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::net::UdpSocket;
+    use mio::unix::SourceFd;
+    use mio::{Events, Interest, Poll, Token};
+    use reed_solomon_erasure::galois_8::ReedSolomon;
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    use crate::async_pipeline::async_pipeline::LazyStream;
+    use crate::mux::mux::serve_stream;
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const FEC_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "fec-entry-address",
+        "--fec-ea",
+        "(FecEntry) Fec Entry listen address",
+    );
+    const FEC_ENTRY_PORT: (&str, &str, &str) = (
+        "fec-entry-port",
+        "--fec-ep",
+        "(FecEntry) Fec Entry listen port",
+    );
+    const FEC_STEP_ADDRESS: (&str, &str, &str) = (
+        "fec-step-address",
+        "--fec-sa",
+        "(FecStep) Fec Step address of the fec entry",
+    );
+    const FEC_STEP_PORT: (&str, &str, &str) = (
+        "fec-step-port",
+        "--fec-sp",
+        "(FecStep) Fec Step port of the fec entry",
+    );
+
+    const FEC_DATA: (&str, &str, &str) = (
+        "fec-data",
+        "--fec-data",
+        "(FecEntry, FecStep) Packets in a group protected by the same parity",
+    );
+    const FEC_PARITY: (&str, &str, &str) = (
+        "fec-parity",
+        "--fec-parity",
+        "(FecEntry, FecStep) Parity packets sent per group, as many lost packets of a group are rebuilt",
+    );
+    const FEC_MTU: (&str, &str, &str) = (
+        "fec-mtu",
+        "--fec-mtu",
+        "(FecEntry, FecStep) Largest datagram sent",
+    );
+    const FEC_DELAY: (&str, &str, &str) = (
+        "fec-delay",
+        "--fec-delay",
+        "(FecEntry, FecStep) Milliseconds a group waits for more packets before its parity is sent",
+    );
+    const FEC_TIMEOUT: (&str, &str, &str) = (
+        "fec-timeout",
+        "--fec-timeout",
+        "(FecEntry, FecStep) Seconds of silence after which the peer is given up",
+    );
+    const FEC_LOSS: (&str, &str, &str) = (
+        "fec-loss",
+        "--fec-loss",
+        "(FecEntry, FecStep) Percent of outgoing datagrams to drop, for testing",
+    );
+
+    // every datagram starts with the session id and a kind, DATA and PARITY go
+    // on with their group, their index in it and, for PARITY, how many data
+    // packets the group got
+    const HEADER_SIZE: usize = 5;
+    const SHARD_HEADER_SIZE: usize = 6;
+    const DATA: u8 = 0;
+    const PARITY: u8 = 1;
+    const PING: u8 = 2;
+    const CLOSE: u8 = 3;
+    /// Each data shard starts with the length of its payload, so that shards
+    /// padded for the code give it back unchanged.
+    const LENGTH_SIZE: usize = 2;
+
+    const SOCKET: Token = Token(0);
+    const FIRST_SESSION: usize = 1;
+
+    const TICK: Duration = Duration::from_millis(5);
+    const KEEPALIVE_INTERVAL: u32 = 5_000;
+    /// How much later than its parity was due a group may still complete.
+    const REORDER_ALLOWANCE: u32 = 50;
+    /// CLOSE is not acknowledged, so it goes out a few times.
+    const CLOSE_REPEATS: u32 = 3;
+    /// A finished session stays around so that late datagrams do not open a
+    /// new one.
+    const LINGER: u32 = 3_000;
+    /// Bytes held for a local side that stopped reading, beyond which arriving
+    /// packets are dropped like late ones.
+    const MAX_PENDING: usize = 4 << 20;
+
+    #[derive(Clone, Copy)]
+    struct FecConfig {
+        data: usize,
+        parity: usize,
+        mtu: usize,
+        delay: u32,
+        timeout: u32,
+        loss: u32,
+    }
+
+    impl FecConfig {
+        fn max_payload(&self) -> usize {
+            self.mtu - HEADER_SIZE - SHARD_HEADER_SIZE - LENGTH_SIZE
+        }
+    }
+
+    fn parse_config(args: &CliParsed) -> Result<FecConfig, Error> {
+        let data = match args.argument_values.get(FEC_DATA.0) {
+            Some(data) => data[0].clone(),
+            None => return Err(Error::RequireOption(FEC_DATA.0.to_string())),
+        };
+        let parity = match args.argument_values.get(FEC_PARITY.0) {
+            Some(parity) => parity[0].clone(),
+            None => return Err(Error::RequireOption(FEC_PARITY.0.to_string())),
+        };
+        let mtu = match args.argument_values.get(FEC_MTU.0) {
+            Some(mtu) => mtu[0].clone(),
+            None => return Err(Error::RequireOption(FEC_MTU.0.to_string())),
+        };
+        let delay = match args.argument_values.get(FEC_DELAY.0) {
+            Some(delay) => delay[0].clone(),
+            None => return Err(Error::RequireOption(FEC_DELAY.0.to_string())),
+        };
+        let timeout = match args.argument_values.get(FEC_TIMEOUT.0) {
+            Some(timeout) => timeout[0].clone(),
+            None => return Err(Error::RequireOption(FEC_TIMEOUT.0.to_string())),
+        };
+        let loss = match args.argument_values.get(FEC_LOSS.0) {
+            Some(loss) => loss[0].clone(),
+            None => return Err(Error::RequireOption(FEC_LOSS.0.to_string())),
+        };
+
+        let data = match str::parse::<usize>(data.as_str()) {
+            Ok(data) if data >= 1 => data,
+            _ => return Err(Error::ParseIntError),
+        };
+        let parity = match str::parse::<usize>(parity.as_str()) {
+            Ok(parity) if parity >= 1 => parity,
+            _ => return Err(Error::ParseIntError),
+        };
+        // the code works over bytes, so a group has at most 256 packets
+        if data + parity > u8::MAX as usize + 1 {
+            return Err(Error::Msg(format!(
+                "a group of {} data and {} parity packets is larger than 256",
+                data, parity
+            )));
+        }
+        let mtu = match str::parse::<usize>(mtu.as_str()) {
+            Ok(mtu) if mtu > HEADER_SIZE + SHARD_HEADER_SIZE + LENGTH_SIZE => mtu,
+            _ => return Err(Error::ParseIntError),
+        };
+        let delay = match str::parse::<u32>(delay.as_str()) {
+            Ok(delay) => delay,
+            Err(_) => return Err(Error::ParseIntError),
+        };
+        let timeout = match str::parse::<u32>(timeout.as_str()) {
+            Ok(timeout) => timeout.saturating_mul(1000),
+            Err(_) => return Err(Error::ParseIntError),
+        };
+        let loss = match str::parse::<u32>(loss.as_str()) {
+            Ok(loss) if loss <= 100 => loss,
+            _ => return Err(Error::ParseIntError),
+        };
+
+        Ok(FecConfig {
+            data,
+            parity,
+            mtu,
+            delay,
+            timeout,
+            loss,
+        })
+    }
+
+    fn packet_header(id: u32, kind: u8) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&id.to_be_bytes());
+        packet.push(kind);
+        packet
+    }
+
+    fn shard_packet(id: u32, kind: u8, group: u32, index: usize, sent: usize) -> Vec<u8> {
+        let mut packet = packet_header(id, kind);
+        packet.extend_from_slice(&group.to_be_bytes());
+        packet.push(index as u8);
+        packet.push(sent as u8);
+        packet
+    }
+
+    fn read_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    /// Cuts outgoing packets into groups and adds parity to every group once
+    /// it is full or has waited long enough.
+    struct Encoder {
+        group: u32,
+        /// Data shards of the current group, each a length and a payload.
+        shards: Vec<Vec<u8>>,
+        started: u32,
+    }
+
+    impl Encoder {
+        fn push(
+            &mut self,
+            id: u32,
+            payload: &[u8],
+            now: u32,
+            config: &FecConfig,
+            code: &ReedSolomon,
+            output: &mut Vec<Vec<u8>>,
+        ) -> Result<(), Error> {
+            if self.shards.is_empty() {
+                self.started = now;
+            }
+            let mut shard = (payload.len() as u16).to_be_bytes().to_vec();
+            shard.extend_from_slice(payload);
+            let mut packet = shard_packet(id, DATA, self.group, self.shards.len(), 0);
+            packet.extend_from_slice(&shard);
+            output.push(packet);
+            self.shards.push(shard);
+            if self.shards.len() == config.data {
+                self.seal(id, config, code, output)?;
+            }
+            Ok(())
+        }
+
+        /// Sends the parity of a group that waited for more packets too long.
+        fn tick(
+            &mut self,
+            id: u32,
+            now: u32,
+            config: &FecConfig,
+            code: &ReedSolomon,
+            output: &mut Vec<Vec<u8>>,
+        ) -> Result<(), Error> {
+            if !self.shards.is_empty() && now.saturating_sub(self.started) >= config.delay {
+                self.seal(id, config, code, output)?;
+            }
+            Ok(())
+        }
+
+        /// Sends the parity of the current group and starts the next one. The
+        /// packets a group did not get count as empty ones nobody sends.
+        fn seal(
+            &mut self,
+            id: u32,
+            config: &FecConfig,
+            code: &ReedSolomon,
+            output: &mut Vec<Vec<u8>>,
+        ) -> Result<(), Error> {
+            if self.shards.is_empty() {
+                return Ok(());
+            }
+            let sent = self.shards.len();
+            let size = self.shards.iter().map(Vec::len).max().unwrap_or_default();
+            let mut shards = std::mem::take(&mut self.shards);
+            shards.resize(config.data + config.parity, Vec::new());
+            for shard in shards.iter_mut() {
+                shard.resize(size, 0);
+            }
+            code.encode(&mut shards)
+                .map_err(|e| Error::Msg(format!("parity failed: {}", e)))?;
+            for (index, shard) in shards[config.data..].iter().enumerate() {
+                let mut packet = shard_packet(id, PARITY, self.group, index, sent);
+                packet.extend_from_slice(shard);
+                output.push(packet);
+            }
+            self.group = self.group.wrapping_add(1);
+            Ok(())
+        }
+    }
+
+    struct Group {
+        shards: Vec<Option<Vec<u8>>>,
+        /// How many data packets the sender put in the group, known once a
+        /// parity packet arrived.
+        sent: Option<usize>,
+        /// The next data packet to hand on.
+        delivered: usize,
+        first_seen: u32,
+    }
+
+    /// Puts groups back together, rebuilding what parity allows, and hands
+    /// their packets on in order.
+    struct Decoder {
+        config: FecConfig,
+        next_group: u32,
+        groups: BTreeMap<u32, Group>,
+        recovered: u64,
+        unrecoverable: u64,
+    }
+
+    impl Decoder {
+        /// Takes a DATA or PARITY packet, `body` being what follows the
+        /// packet header.
+        fn input(
+            &mut self,
+            kind: u8,
+            body: &[u8],
+            now: u32,
+            code: &ReedSolomon,
+            out: &mut Vec<u8>,
+        ) {
+            let config = self.config;
+            let (group, index, sent) = (read_u32(body), body[4] as usize, body[5] as usize);
+            let shard = &body[SHARD_HEADER_SIZE..];
+            let (slot, sent) = match kind {
+                DATA if index < config.data => (index, None),
+                PARITY if index < config.parity && (1..=config.data).contains(&sent) => {
+                    (config.data + index, Some(sent))
+                }
+                _ => return,
+            };
+            // old groups were handed on already, far ones are garbage
+            let ahead = group.wrapping_sub(self.next_group);
+            if ahead > u32::MAX / 2 || ahead as usize > 64 * config.data {
+                return;
+            }
+
+            let entry = self.groups.entry(group).or_insert_with(|| Group {
+                shards: vec![None; config.data + config.parity],
+                sent: None,
+                delivered: 0,
+                first_seen: now,
+            });
+            if entry.shards[slot].is_none() {
+                entry.shards[slot] = Some(shard.to_vec());
+            }
+            if let (Some(sent), None) = (sent, entry.sent) {
+                entry.sent = Some(sent);
+                for shard in entry.shards[sent..config.data].iter_mut() {
+                    *shard = Some(Vec::new());
+                }
+            }
+
+            if let Some(sent) = entry.sent {
+                let missing = entry.shards[..sent].iter().any(Option::is_none);
+                let present = entry.shards.iter().filter(|shard| shard.is_some()).count();
+                if missing && present >= config.data {
+                    let size = entry.shards[config.data..]
+                        .iter()
+                        .flatten()
+                        .map(Vec::len)
+                        .max()
+                        .unwrap_or_default();
+                    for shard in entry.shards.iter_mut().flatten() {
+                        shard.resize(size, 0);
+                    }
+                    if code.reconstruct_data(&mut entry.shards).is_ok() {
+                        self.recovered += 1;
+                    }
+                }
+            }
+            self.deliver(out);
+        }
+
+        /// Hands on the packets in order for as long as none is missing.
+        fn deliver(&mut self, out: &mut Vec<u8>) {
+            let config = self.config;
+            while let Some(group) = self.groups.get_mut(&self.next_group) {
+                let last = group.sent.unwrap_or(config.data);
+                while group.delivered < last {
+                    match &group.shards[group.delivered] {
+                        Some(shard) => push_payload(shard, out),
+                        None => break,
+                    }
+                    group.delivered += 1;
+                }
+                if group.delivered < last {
+                    return;
+                }
+                self.groups.remove(&self.next_group);
+                self.next_group = self.next_group.wrapping_add(1);
+            }
+        }
+
+        /// Gives up on the groups that should have completed by now, handing
+        /// on what arrived of them. `flush` gives up on all of them.
+        fn expire(&mut self, now: u32, flush: bool, out: &mut Vec<u8>) {
+            let config = self.config;
+            let hold = config.delay + REORDER_ALLOWANCE;
+            while let Some((&number, group)) = self.groups.first_key_value() {
+                if !flush && now.saturating_sub(group.first_seen) < hold {
+                    return;
+                }
+                // groups of which nothing arrived at all
+                let skipped = number.wrapping_sub(self.next_group);
+                self.unrecoverable += skipped as u64;
+                self.next_group = number;
+
+                let mut group = match self.groups.remove(&number) {
+                    Some(group) => group,
+                    None => return,
+                };
+                let last = match group.sent {
+                    Some(sent) => sent,
+                    None => group.shards[..config.data]
+                        .iter()
+                        .rposition(Option::is_some)
+                        .map_or(0, |index| index + 1),
+                };
+                if group.shards[group.delivered..last]
+                    .iter()
+                    .any(Option::is_none)
+                {
+                    self.unrecoverable += 1;
+                }
+                for shard in group.shards[group.delivered..last].iter().flatten() {
+                    push_payload(shard, out);
+                }
+                group.delivered = last;
+                self.next_group = self.next_group.wrapping_add(1);
+                self.deliver(out);
+            }
+        }
+    }
+
+    fn push_payload(shard: &[u8], out: &mut Vec<u8>) {
+        if shard.len() < LENGTH_SIZE || out.len() >= MAX_PENDING {
+            return;
+        }
+        let length = u16::from_be_bytes([shard[0], shard[1]]) as usize;
+        if let Some(payload) = shard.get(LENGTH_SIZE..LENGTH_SIZE + length) {
+            out.extend_from_slice(payload);
+        }
+    }
+
+    /// A session and the client or upstream holding the other end of `local`.
+    struct FecSession {
+        id: u32,
+        local: UnixStream,
+        peer: SocketAddr,
+        encoder: Encoder,
+        decoder: Decoder,
+        delivered: Vec<u8>,
+        output: Vec<Vec<u8>>,
+        readable: bool,
+        /// CLOSE datagrams still to send once `local` ended.
+        closes_left: Option<u32>,
+        remote_closed: bool,
+        write_closed: bool,
+        last_heard: u32,
+        last_sent: u32,
+        finished_at: Option<u32>,
+    }
+
+    impl FecSession {
+        fn new(id: u32, local: UnixStream, peer: SocketAddr, config: FecConfig, now: u32) -> Self {
+            Self {
+                id,
+                local,
+                peer,
+                encoder: Encoder {
+                    group: 0,
+                    shards: Vec::new(),
+                    started: now,
+                },
+                decoder: Decoder {
+                    config,
+                    next_group: 0,
+                    groups: BTreeMap::new(),
+                    recovered: 0,
+                    unrecoverable: 0,
+                },
+                delivered: Vec::new(),
+                output: Vec::new(),
+                readable: true,
+                closes_left: None,
+                remote_closed: false,
+                write_closed: false,
+                last_heard: now,
+                last_sent: now,
+                finished_at: None,
+            }
+        }
+
+        fn input(&mut self, packet: &[u8], now: u32, code: &ReedSolomon) {
+            self.last_heard = now;
+            match packet[4] {
+                kind @ (DATA | PARITY) if packet.len() >= HEADER_SIZE + SHARD_HEADER_SIZE => {
+                    let body = &packet[HEADER_SIZE..];
+                    self.decoder
+                        .input(kind, body, now, code, &mut self.delivered);
+                }
+                CLOSE if !self.remote_closed => {
+                    self.remote_closed = true;
+                    self.decoder.expire(now, true, &mut self.delivered);
+                }
+                _ => {}
+            }
+        }
+
+        /// Moves data between `local` and the peer, returning whether the
+        /// session is over.
+        fn pump(
+            &mut self,
+            now: u32,
+            config: &FecConfig,
+            code: &ReedSolomon,
+            buffer_size: usize,
+            transmit: &mut dyn FnMut(&[u8], SocketAddr),
+        ) -> Result<bool, Error> {
+            if now.saturating_sub(self.last_heard) >= config.timeout {
+                return Err(Error::Msg("peer stopped answering".to_string()));
+            }
+
+            if self.finished_at.is_none() {
+                while self.readable && self.closes_left.is_none() {
+                    match read_available(&mut self.local, buffer_size) {
+                        Ok(data) => {
+                            for payload in data.chunks(config.max_payload()) {
+                                self.encoder.push(
+                                    self.id,
+                                    payload,
+                                    now,
+                                    config,
+                                    code,
+                                    &mut self.output,
+                                )?;
+                            }
+                        }
+                        Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                            self.readable = false
+                        }
+                        Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                            self.encoder.seal(self.id, config, code, &mut self.output)?;
+                            self.closes_left = Some(CLOSE_REPEATS);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                self.encoder
+                    .tick(self.id, now, config, code, &mut self.output)?;
+
+                self.decoder.expire(now, false, &mut self.delivered);
+                while !self.delivered.is_empty() {
+                    match self.local.write(&self.delivered) {
+                        Ok(size) => {
+                            self.delivered.drain(..size);
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(Error::IoError(e)),
+                    }
+                }
+                if self.remote_closed && self.delivered.is_empty() && !self.write_closed {
+                    let _ = self.local.shutdown(Shutdown::Write);
+                    self.write_closed = true;
+                }
+            }
+
+            if let Some(left) = self.closes_left.as_mut() {
+                if *left > 0 {
+                    self.output.push(packet_header(self.id, CLOSE));
+                    *left -= 1;
+                }
+            }
+            if self.output.is_empty() && now.saturating_sub(self.last_sent) >= KEEPALIVE_INTERVAL {
+                self.output.push(packet_header(self.id, PING));
+            }
+            for packet in self.output.drain(..) {
+                transmit(&packet, self.peer);
+                self.last_sent = now;
+            }
+
+            if self.closes_left == Some(0) && self.write_closed && self.finished_at.is_none() {
+                self.finished_at = Some(now);
+            }
+            Ok(matches!(self.finished_at, Some(at) if now - at >= LINGER))
+        }
+
+        fn report(&self) -> String {
+            format!(
+                "{} groups recovered, {} unrecoverable",
+                self.decoder.recovered, self.decoder.unrecoverable
+            )
+        }
+    }
+
+    type OnOpen = Box<dyn FnMut(UnixStream, SocketAddr) + Send>;
+
+    /// Runs the sessions of one UDP socket on its own thread. An entry's
+    /// endpoint opens a session for every new peer through `on_open`, a
+    /// step's runs the one it was given and ends with it.
+    struct FecEndpoint {
+        socket: UdpSocket,
+        poll: Poll,
+        sessions: HashMap<Token, FecSession>,
+        peers: HashMap<(SocketAddr, u32), Token>,
+        next_token: usize,
+        on_open: Option<OnOpen>,
+        config: FecConfig,
+        code: ReedSolomon,
+        started: Instant,
+        /// State of the generator deciding which datagrams to drop.
+        random: u64,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl FecEndpoint {
+        fn new(
+            socket: UdpSocket,
+            on_open: Option<OnOpen>,
+            config: FecConfig,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) -> Result<Self, Error> {
+            let code = ReedSolomon::new(config.data, config.parity)
+                .map_err(|e| Error::Msg(format!("no code for these groups: {}", e)))?;
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or_default();
+            Ok(Self {
+                socket,
+                poll: Poll::new()?,
+                sessions: HashMap::new(),
+                peers: HashMap::new(),
+                next_token: FIRST_SESSION,
+                on_open,
+                config,
+                code,
+                started: Instant::now(),
+                random: seed | 1,
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn now(&self) -> u32 {
+            self.started.elapsed().as_millis() as u32
+        }
+
+        fn add_session(
+            &mut self,
+            local: UnixStream,
+            peer: SocketAddr,
+            id: u32,
+        ) -> Result<Token, Error> {
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            self.poll.registry().register(
+                &mut SourceFd(&local.as_raw_fd()),
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            let session = FecSession::new(id, local, peer, self.config, self.now());
+            self.sessions.insert(token, session);
+            self.peers.insert((peer, id), token);
+            Ok(token)
+        }
+
+        fn run(&mut self) -> Result<(), Error> {
+            self.poll
+                .registry()
+                .register(&mut self.socket, SOCKET, Interest::READABLE)?;
+            let mut events = Events::with_capacity(1024);
+            let mut buffer = vec![0u8; u16::MAX as usize];
+            loop {
+                if let Err(e) = self.poll.poll(&mut events, Some(TICK)) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+
+                for event in events.iter() {
+                    match event.token() {
+                        SOCKET => self.receive(&mut buffer)?,
+                        token => {
+                            if let Some(session) = self.sessions.get_mut(&token) {
+                                session.readable |= event.is_readable();
+                            }
+                        }
+                    }
+                }
+
+                self.pump();
+                if self.on_open.is_none() && self.sessions.is_empty() {
+                    return Ok(());
+                }
+            }
+        }
+
+        fn receive(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+            loop {
+                let (size, peer) = match self.socket.recv_from(buffer) {
+                    Ok(received) => received,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(e) => return Err(Error::IoError(e)),
+                };
+                if size < HEADER_SIZE {
+                    continue;
+                }
+                let packet = &buffer[..size];
+                let id = read_u32(packet);
+                let token = match self.peers.get(&(peer, id)) {
+                    Some(token) => *token,
+                    None => match self.accept(packet, peer, id)? {
+                        Some(token) => token,
+                        None => continue,
+                    },
+                };
+                let now = self.now();
+                if let Some(session) = self.sessions.get_mut(&token) {
+                    session.input(packet, now, &self.code);
+                }
+            }
+        }
+
+        /// Opens a session for the first PING or group of a new peer, and
+        /// tells peers of a session that is gone to stop.
+        fn accept(
+            &mut self,
+            packet: &[u8],
+            peer: SocketAddr,
+            id: u32,
+        ) -> Result<Option<Token>, Error> {
+            if self.on_open.is_none() {
+                return Ok(None);
+            }
+            let kind = packet[4];
+            let opening = kind == PING
+                || (kind == DATA || kind == PARITY)
+                    && packet.len() >= HEADER_SIZE + SHARD_HEADER_SIZE
+                    && read_u32(&packet[HEADER_SIZE..]) == 0;
+            if !opening {
+                if kind != CLOSE {
+                    let _ = self.socket.send_to(&packet_header(id, CLOSE), peer);
+                }
+                return Ok(None);
+            }
+
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let token = self.add_session(inner, peer, id)?;
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("new client: {}", peer);
+                }
+            }
+
+            if let Some(on_open) = self.on_open.as_mut() {
+                on_open(outer, peer);
+            }
+            Ok(Some(token))
+        }
+
+        fn pump(&mut self) {
+            let now = self.now();
+            let Self {
+                socket,
+                sessions,
+                random,
+                config,
+                code,
+                debug_level,
+                buffer_size,
+                ..
+            } = self;
+            let mut transmit = |packet: &[u8], peer: SocketAddr| {
+                // xorshift, good enough to pick datagrams to lose
+                *random ^= *random << 13;
+                *random ^= *random >> 7;
+                *random ^= *random << 17;
+                if (*random % 100) < config.loss as u64 {
+                    return;
+                }
+                // a full socket buffer is just more loss to recover from
+                let _ = socket.send_to(packet, peer);
+            };
+
+            let mut over = Vec::new();
+            for (token, session) in sessions.iter_mut() {
+                match session.pump(now, config, code, *buffer_size, &mut transmit) {
+                    Ok(true) => over.push(*token),
+                    Ok(false) => {}
+                    Err(e) => {
+                        if *debug_level > 0 {
+                            eprintln!("an error accured on session of {}: {}", session.peer, e);
+                        }
+                        transmit(&packet_header(session.id, CLOSE), session.peer);
+                        over.push(*token);
+                    }
+                }
+            }
+
+            for token in over {
+                if let Some(session) = self.sessions.remove(&token) {
+                    self.peers.remove(&(session.peer, session.id));
+                    let _ = self
+                        .poll
+                        .registry()
+                        .deregister(&mut SourceFd(&session.local.as_raw_fd()));
+
+                    {
+                        // debug
+                        // a step may share stdout with the data it carries
+                        if self.debug_level >= 2 && self.on_open.is_some() {
+                            println!("client {} closed, {}", session.peer, session.report());
+                        } else if self.debug_level >= 2 {
+                            eprintln!("fec session ended, {}", session.report());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Accepts `FecStep` sessions over UDP and serves each with its own copy
+    /// of the pipeline. Lost packets are rebuilt from parity or skipped, never
+    /// sent again, which suits traffic that prefers a gap to a stall.
+    pub struct FecEntry {
+        address: String,
+        port: u16,
+        config: FecConfig,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for FecEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let socket = UdpSocket::bind(addr)?;
+            let local = socket.local_addr()?;
+
+            let handle = runtime.handle().clone();
+            let pipeline_template = self.pipeline_template.clone();
+            let debug_level = self.debug_level;
+            let buffer_size = self.buffer_size;
+            let on_open: OnOpen = Box::new(move |stream, peer| {
+                let info = ConnectionInfo {
+                    peer: Some(peer),
+                    local: Some(local),
+                    destination: None,
+                };
+                let pipeline_template = pipeline_template.clone();
+                handle.spawn(async move {
+                    if let Err(e) = serve_stream(stream, info, pipeline_template, buffer_size).await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving session of {}: {}", peer, e);
+                        }
+                    }
+                });
+            });
+
+            FecEndpoint::new(
+                socket,
+                Some(on_open),
+                self.config,
+                self.debug_level,
+                self.buffer_size,
+            )?
+            .run()
+        }
+    }
+
+    impl EntryStatic<FecEntry> for FecEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<FecEntry, Error> {
+            let address = match args.argument_values.get(FEC_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(FEC_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(FEC_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(FEC_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(FecEntry {
+                address,
+                port,
+                config: parse_config(&args)?,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: FEC_ENTRY_ADDRESS.0.to_string(),
+                key: vec![FEC_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_ENTRY_PORT.0.to_string(),
+                key: vec![FEC_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("4100".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_DATA.0.to_string(),
+                key: vec![FEC_DATA.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("10".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_DATA.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_PARITY.0.to_string(),
+                key: vec![FEC_PARITY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("3".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_PARITY.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_MTU.0.to_string(),
+                key: vec![FEC_MTU.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("1400".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_MTU.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_DELAY.0.to_string(),
+                key: vec![FEC_DELAY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("20".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_DELAY.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_TIMEOUT.0.to_string(),
+                key: vec![FEC_TIMEOUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("30".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_TIMEOUT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_LOSS.0.to_string(),
+                key: vec![FEC_LOSS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_LOSS.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Carries its client as one session of parity-protected datagrams to a
+    /// `FecEntry`.
+    pub struct FecStep {
+        address: String,
+        port: u16,
+        config: FecConfig,
+        /// The local end of this client's session.
+        stream: LazyStream,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl FecStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let peer = create_socket_addr(self.address.as_str(), self.port)?;
+            let bind = match peer {
+                SocketAddr::V4(_) => "0.0.0.0:0",
+                SocketAddr::V6(_) => "[::]:0",
+            };
+            let socket = UdpSocket::bind(bind.parse()?)?;
+            let mut id = [0u8; 4];
+            SystemRandom::new()
+                .fill(&mut id)
+                .map_err(|_| Error::Msg("no randomness available for a session".to_string()))?;
+
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let mut endpoint = FecEndpoint::new(
+                socket,
+                None,
+                self.config,
+                self.debug_level,
+                self.buffer_size,
+            )?;
+            let id = u32::from_be_bytes(id);
+            let token = endpoint.add_session(inner, peer, id)?;
+            // let the entry open the session even if the client speaks last
+            if let Some(session) = endpoint.sessions.get_mut(&token) {
+                session.output.push(packet_header(id, PING));
+            }
+
+            let debug_level = self.debug_level;
+            thread::spawn(move || {
+                if let Err(e) = endpoint.run() {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on fec session: {}", e);
+                    }
+                }
+            });
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("fec session", self.debug_level, || self.open())
+        }
+    }
+
+    impl Step for FecStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_all(&mut self.stream()?, data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.stream()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for FecStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl StepStatic for FecStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(FEC_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(FEC_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(FEC_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(FEC_STEP_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Self {
+                address,
+                port,
+                config: parse_config(&args)?,
+                stream: LazyStream::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: FEC_STEP_ADDRESS.0.to_string(),
+                key: vec![FEC_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FEC_STEP_PORT.0.to_string(),
+                key: vec![FEC_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("4100".to_string()),
+                help: Some(ArgumentHelp::Text(FEC_STEP_PORT.2.to_string())),
+            });
+            // the session options are shared with FecEntry, which adds them
+            argument
+        }
+    }
+
+    impl Clone for FecStep {
+        fn clone(&self) -> Self {
+            Self {
+                address: self.address.clone(),
+                port: self.port,
+                config: self.config,
+                stream: LazyStream::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for FecStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const CONFIG: FecConfig = FecConfig {
+            data: 4,
+            parity: 2,
+            mtu: 1400,
+            delay: 20,
+            timeout: 30_000,
+            loss: 0,
+        };
+
+        fn code() -> ReedSolomon {
+            ReedSolomon::new(CONFIG.data, CONFIG.parity).unwrap()
+        }
+
+        fn decoder() -> Decoder {
+            Decoder {
+                config: CONFIG,
+                next_group: 0,
+                groups: BTreeMap::new(),
+                recovered: 0,
+                unrecoverable: 0,
+            }
+        }
+
+        /// Encodes `payloads` of different sizes, sealing the last group by
+        /// its delay, and returns the packets that went out.
+        fn encode(payloads: &[Vec<u8>]) -> Vec<Vec<u8>> {
+            let code = code();
+            let mut encoder = Encoder {
+                group: 0,
+                shards: Vec::new(),
+                started: 0,
+            };
+            let mut output = Vec::new();
+            for payload in payloads {
+                encoder
+                    .push(1, payload, 0, &CONFIG, &code, &mut output)
+                    .unwrap();
+            }
+            encoder
+                .tick(1, CONFIG.delay, &CONFIG, &code, &mut output)
+                .unwrap();
+            output
+        }
+
+        fn decode(decoder: &mut Decoder, packets: &[Vec<u8>]) -> Vec<u8> {
+            let code = code();
+            let mut out = Vec::new();
+            for packet in packets {
+                decoder.input(packet[4], &packet[HEADER_SIZE..], 0, &code, &mut out);
+            }
+            out
+        }
+
+        fn payloads(count: usize) -> Vec<Vec<u8>> {
+            (0..count).map(|n| vec![n as u8; 10 + n * 7]).collect()
+        }
+
+        #[test]
+        fn groups_get_their_parity() {
+            // two full groups and one of two packets, each with two parity
+            let packets = encode(&payloads(10));
+            assert_eq!(packets.len(), 10 + 3 * CONFIG.parity);
+            let kinds: Vec<u8> = packets.iter().map(|packet| packet[4]).collect();
+            assert_eq!(&kinds[..6], [DATA, DATA, DATA, DATA, PARITY, PARITY]);
+            // a sealed partial group tells how many data packets it got
+            assert_eq!(packets.last().unwrap()[HEADER_SIZE + 5], 2);
+        }
+
+        #[test]
+        fn lossless_stream_is_unchanged() {
+            let payloads = payloads(10);
+            let mut decoder = decoder();
+            assert_eq!(decode(&mut decoder, &encode(&payloads)), payloads.concat());
+            assert_eq!(decoder.recovered, 0);
+            assert!(decoder.groups.is_empty());
+        }
+
+        #[test]
+        fn lost_data_is_recovered() {
+            let payloads = payloads(10);
+            let packets = encode(&payloads);
+            // drop two data packets of each full group and one of the last
+            let lost = [0, 3, 7, 8, 13];
+            let kept: Vec<Vec<u8>> = packets
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !lost.contains(index))
+                .map(|(_, packet)| packet.clone())
+                .collect();
+            let mut decoder = decoder();
+            assert_eq!(decode(&mut decoder, &kept), payloads.concat());
+            assert_eq!(decoder.recovered, 3);
+            assert_eq!(decoder.unrecoverable, 0);
+        }
+
+        #[test]
+        fn reordered_packets_are_delivered_in_order() {
+            let payloads = payloads(8);
+            let mut packets = encode(&payloads);
+            packets.reverse();
+            let mut decoder = decoder();
+            assert_eq!(decode(&mut decoder, &packets), payloads.concat());
+        }
+
+        #[test]
+        fn too_much_loss_is_handed_on_when_expired() {
+            let payloads = payloads(4);
+            let packets = encode(&payloads);
+            // three data packets lost against two parity
+            let kept = [packets[1].clone(), packets[4].clone(), packets[5].clone()];
+            let mut decoder = decoder();
+            assert!(decode(&mut decoder, &kept).is_empty());
+
+            let mut out = Vec::new();
+            decoder.expire(CONFIG.delay + REORDER_ALLOWANCE, false, &mut out);
+            assert_eq!(out, payloads[1]);
+            assert_eq!(decoder.unrecoverable, 1);
+            assert!(decoder.groups.is_empty());
+        }
+    }
+}
@@ -0,0 +1,487 @@
+pub mod file {
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{self, ErrorKind, Write};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::thread;
+    use std::time::Duration;
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::{unix::SourceFd, Events, Interest, Poll, Token};
+
+    use crate::tcp::tcp::read_available;
+    use crate::{
+        base::base::DebugLevel, BoxedClone, Entry, EntryStatic, Error, Pipeline, Step, StepStatic,
+        BUFFER_SIZE,
+    };
+
+    const FILE_ENTRY_INPUT: (&str, &str, &str) = (
+        "file-entry-input",
+        "--file-ein",
+        "(FileEntry) File or named pipe streamed into the pipeline",
+    );
+    const FILE_ENTRY_OUTPUT: (&str, &str, &str) = (
+        "file-entry-output",
+        "--file-eout",
+        "(FileEntry) File or named pipe the pipeline output is appended to",
+    );
+    const FILE_ENTRY_WAIT: (&str, &str, &str) = (
+        "file-entry-wait",
+        "--file-ewait",
+        "(FileEntry) Seconds without pipeline output to wait after the input ended, 0 waits until the pipeline closes",
+    );
+
+    const FILE_STEP_INPUT: (&str, &str, &str) = (
+        "file-step-input",
+        "--file-sin",
+        "(FileStep) File or named pipe returned backward, read once per client",
+    );
+    const FILE_STEP_OUTPUT: (&str, &str, &str) = (
+        "file-step-output",
+        "--file-sout",
+        "(FileStep) File or named pipe forward data is appended to",
+    );
+    const FILE_STEP_ROTATE: (&str, &str, &str) = (
+        "file-step-rotate",
+        "--file-srotate",
+        "(FileStep) Rotate the output file once it would grow beyond this many bytes, 0 never rotates",
+    );
+    const FILE_STEP_KEEP: (&str, &str, &str) = (
+        "file-step-keep",
+        "--file-skeep",
+        "(FileStep) Number of rotated output files to keep",
+    );
+
+    const INPUT_TOKEN: Token = Token(0);
+    const PIPELINE_TOKEN: Token = Token(1);
+
+    /// Streams `path` into the returned socket from a thread, so the file can
+    /// be polled: epoll refuses regular files, and opening a named pipe blocks
+    /// until it has a writer. The socket reaches EOF with the file.
+    fn read_file(path: &PathBuf, debug_level: DebugLevel) -> Result<UnixStream, Error> {
+        // report missing files now, the open itself may block
+        fs::metadata(path)?;
+        let (local, mut remote) = UnixStream::pair()?;
+        local.set_nonblocking(true)?;
+        let path = path.clone();
+        thread::spawn(move || {
+            let result = File::open(&path).and_then(|mut file| io::copy(&mut file, &mut remote));
+            if let Err(e) = result {
+                if debug_level > 0 {
+                    eprintln!("an error accured reading {}: {}", path.display(), e);
+                }
+            }
+        });
+        Ok(local)
+    }
+
+    /// Appends to a file, rotating it to `path.1`, `path.2`, ... once it
+    /// would grow beyond `rotate` bytes.
+    struct FileSink {
+        path: PathBuf,
+        file: File,
+        written: u64,
+        rotate: u64,
+        keep: usize,
+    }
+
+    impl FileSink {
+        fn open(path: PathBuf, rotate: u64, keep: usize) -> Result<FileSink, Error> {
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let metadata = file.metadata()?;
+            Ok(FileSink {
+                path,
+                written: metadata.len(),
+                // named pipes are never rotated
+                rotate: match metadata.file_type().is_fifo() {
+                    true => 0,
+                    false => rotate,
+                },
+                file,
+                keep,
+            })
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+            if self.rotate > 0 && self.written > 0 && self.written + data.len() as u64 > self.rotate
+            {
+                self.rotate()?;
+            }
+            self.file.write_all(data)?;
+            self.written += data.len() as u64;
+            Ok(())
+        }
+
+        fn rotate(&mut self) -> Result<(), Error> {
+            let rotated = |index: usize| {
+                let mut path = self.path.clone().into_os_string();
+                path.push(format!(".{}", index));
+                PathBuf::from(path)
+            };
+            if self.keep == 0 {
+                fs::remove_file(&self.path)?;
+            } else {
+                for index in (1..self.keep).rev() {
+                    if rotated(index).exists() {
+                        fs::rename(rotated(index), rotated(index + 1))?;
+                    }
+                }
+                fs::rename(&self.path, rotated(1))?;
+            }
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+            self.written = 0;
+            Ok(())
+        }
+    }
+
+    /// Streams a file or named pipe into the pipeline and appends what comes
+    /// back to another path, e.g. to replay a captured payload to an upstream
+    /// and capture its response.
+    pub struct FileEntry {
+        input: PathBuf,
+        output: PathBuf,
+        wait: Option<Duration>,
+        pipeline: Pipeline,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl Entry for FileEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let mut input = Some(read_file(&self.input, self.debug_level)?);
+            let mut output = FileSink::open(self.output.clone(), 0, 0)?;
+
+            let mut poll = Poll::new()?;
+            let mut events = Events::with_capacity(128);
+            let fd = input.as_ref().unwrap().as_raw_fd();
+            poll.registry()
+                .register(&mut SourceFd(&fd), INPUT_TOKEN, Interest::READABLE)?;
+            poll.registry()
+                .register(&mut self.pipeline, PIPELINE_TOKEN, Interest::READABLE)?;
+
+            loop {
+                // once the input has ended, stop when the pipeline goes quiet
+                let timeout = match input {
+                    Some(_) => None,
+                    None => self.wait,
+                };
+                poll.poll(&mut events, timeout)?;
+                if events.is_empty() && timeout.is_some() {
+                    return Ok(());
+                }
+                for event in events.iter() {
+                    match event.token() {
+                        // read until told to wait, an fd that delivered its
+                        // last data and its end together gets no further event
+                        INPUT_TOKEN => {
+                            while let Some(stream) = input.as_mut() {
+                                match read_available(stream, self.buffer_size) {
+                                    Ok(data) => self.pipeline.write_pipeline(data)?,
+                                    Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                                        break
+                                    }
+                                    Err(Error::IoError(e))
+                                        if e.kind() == ErrorKind::ConnectionAborted =>
+                                    {
+                                        {
+                                            // debug
+                                            if self.debug_level >= 2 {
+                                                println!("{} ended", self.input.display());
+                                            }
+                                        }
+                                        poll.registry().deregister(&mut SourceFd(&fd))?;
+                                        input = None;
+                                        self.pipeline.close_forward()?;
+                                    }
+                                    Err(e) => return Err(e),
+                                }
+                            }
+                        }
+                        PIPELINE_TOKEN => loop {
+                            match self.pipeline.read_pipeline() {
+                                Ok(data) => output.write(&data)?,
+                                Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                                    break
+                                }
+                                Err(Error::IoError(e))
+                                    if e.kind() == ErrorKind::ConnectionAborted =>
+                                {
+                                    return Ok(())
+                                }
+                                Err(e) => return Err(e),
+                            }
+                        },
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }
+    }
+
+    impl EntryStatic<FileEntry> for FileEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<FileEntry, Error> {
+            let input = match args.argument_values.get(FILE_ENTRY_INPUT.0) {
+                Some(input) => PathBuf::from(&input[0]),
+                None => return Err(Error::RequireOption(FILE_ENTRY_INPUT.0.to_string())),
+            };
+            let output = match args.argument_values.get(FILE_ENTRY_OUTPUT.0) {
+                Some(output) => PathBuf::from(&output[0]),
+                None => return Err(Error::RequireOption(FILE_ENTRY_OUTPUT.0.to_string())),
+            };
+            let wait = match args.argument_values.get(FILE_ENTRY_WAIT.0) {
+                Some(wait) => wait[0].clone(),
+                None => return Err(Error::RequireOption(FILE_ENTRY_WAIT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let wait = match str::parse::<u64>(wait.as_str()) {
+                Ok(0) => None,
+                Ok(wait) => Some(Duration::from_secs(wait)),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(FileEntry {
+                input,
+                output,
+                wait,
+                pipeline,
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: FILE_ENTRY_INPUT.0.to_string(),
+                key: vec![FILE_ENTRY_INPUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(FILE_ENTRY_INPUT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FILE_ENTRY_OUTPUT.0.to_string(),
+                key: vec![FILE_ENTRY_OUTPUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(FILE_ENTRY_OUTPUT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FILE_ENTRY_WAIT.0.to_string(),
+                key: vec![FILE_ENTRY_WAIT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0".to_string()),
+                help: Some(ArgumentHelp::Text(FILE_ENTRY_WAIT.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Appends forward data to a file and returns the content of another
+    /// file or named pipe backward. Clones share the output file and each
+    /// read the input from its start.
+    pub struct FileStep {
+        input: Option<PathBuf>,
+        /// Opened when the step is first used, so that only steps serving a
+        /// client read from the input. `None` if opening failed.
+        input_stream: OnceLock<Option<InputStream>>,
+        output: Option<Arc<Mutex<FileSink>>>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    struct InputStream {
+        /// Reads the input, or stays idle when there is none.
+        stream: UnixStream,
+        /// Keeps an idle `stream` from reaching EOF.
+        _idle: Option<UnixStream>,
+    }
+
+    impl FileStep {
+        fn open_input(&self) -> Result<InputStream, Error> {
+            match &self.input {
+                Some(path) => Ok(InputStream {
+                    stream: read_file(path, self.debug_level)?,
+                    _idle: None,
+                }),
+                None => {
+                    let (local, remote) = UnixStream::pair()?;
+                    local.set_nonblocking(true)?;
+                    Ok(InputStream {
+                        stream: local,
+                        _idle: Some(remote),
+                    })
+                }
+            }
+        }
+
+        fn input_stream(&self) -> Option<&UnixStream> {
+            self.input_stream
+                .get_or_init(|| match self.open_input() {
+                    Ok(input_stream) => Some(input_stream),
+                    Err(e) => {
+                        if self.debug_level > 0 {
+                            eprintln!("an error accured opening input: {}", e);
+                        }
+                        None
+                    }
+                })
+                .as_ref()
+                .map(|input_stream| &input_stream.stream)
+        }
+    }
+
+    impl Step for FileStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            if let Some(output) = &self.output {
+                output.lock().unwrap().write(data)?;
+            }
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            match self.input_stream() {
+                Some(mut input_stream) => read_available(&mut input_stream, self.buffer_size),
+                None => Err(Error::IoError(ErrorKind::NotConnected.into())),
+            }
+        }
+    }
+
+    impl BoxedClone for FileStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl Clone for FileStep {
+        fn clone(&self) -> Self {
+            Self {
+                input: self.input.clone(),
+                input_stream: OnceLock::new(),
+                output: self.output.clone(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for FileStep {
+        fn as_raw_fd(&self) -> RawFd {
+            match self.input_stream() {
+                Some(input_stream) => input_stream.as_raw_fd(),
+                None => -1,
+            }
+        }
+    }
+
+    impl StepStatic for FileStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let input = args
+                .argument_values
+                .get(FILE_STEP_INPUT.0)
+                .map(|input| PathBuf::from(&input[0]));
+            if let Some(input) = &input {
+                // report a missing input now rather than for every client
+                fs::metadata(input)?;
+            }
+            let rotate = match args.argument_values.get(FILE_STEP_ROTATE.0) {
+                Some(rotate) => rotate[0].clone(),
+                None => return Err(Error::RequireOption(FILE_STEP_ROTATE.0.to_string())),
+            };
+            let keep = match args.argument_values.get(FILE_STEP_KEEP.0) {
+                Some(keep) => keep[0].clone(),
+                None => return Err(Error::RequireOption(FILE_STEP_KEEP.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let rotate = match str::parse::<u64>(rotate.as_str()) {
+                Ok(rotate) => rotate,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let keep = match str::parse::<usize>(keep.as_str()) {
+                Ok(keep) => keep,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let output = match args.argument_values.get(FILE_STEP_OUTPUT.0) {
+                Some(output) => Some(Arc::new(Mutex::new(FileSink::open(
+                    PathBuf::from(&output[0]),
+                    rotate,
+                    keep,
+                )?))),
+                None => None,
+            };
+            Ok(FileStep {
+                input,
+                input_stream: OnceLock::new(),
+                output,
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: FILE_STEP_INPUT.0.to_string(),
+                key: vec![FILE_STEP_INPUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(FILE_STEP_INPUT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FILE_STEP_OUTPUT.0.to_string(),
+                key: vec![FILE_STEP_OUTPUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(FILE_STEP_OUTPUT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FILE_STEP_ROTATE.0.to_string(),
+                key: vec![FILE_STEP_ROTATE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0".to_string()),
+                help: Some(ArgumentHelp::Text(FILE_STEP_ROTATE.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: FILE_STEP_KEEP.0.to_string(),
+                key: vec![FILE_STEP_KEEP.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("5".to_string()),
+                help: Some(ArgumentHelp::Text(FILE_STEP_KEEP.2.to_string())),
+            });
+            argument
+        }
+    }
+}
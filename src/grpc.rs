@@ -0,0 +1,820 @@
+pub mod grpc {
+    use std::future::poll_fn;
+    use std::io::ErrorKind;
+    use std::net::Shutdown;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use h2::client::SendRequest;
+    use h2::server::{self, SendResponse};
+    use h2::{RecvStream, SendStream};
+    use hyper::body::Bytes;
+    use hyper::header::{HeaderMap, HeaderValue};
+    use hyper::{Method, Request, Response, StatusCode};
+    use lazy_static::lazy_static;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::runtime::Runtime;
+
+    use crate::async_pipeline::async_pipeline::{
+        read_pipeline, write_pipeline, LazyStream, PipelineFd,
+    };
+    use crate::http::http::{h2_connection, HttpEntry, SendEvent, H2_WINDOW_SIZE};
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const GRPC_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "grpc-entry-address",
+        "--grpc-ea",
+        "(GrpcEntry) Grpc Entry listen address",
+    );
+    const GRPC_ENTRY_PORT: (&str, &str, &str) = (
+        "grpc-entry-port",
+        "--grpc-ep",
+        "(GrpcEntry) Grpc Entry listen port",
+    );
+
+    const GRPC_STEP_ADDRESS: (&str, &str, &str) = (
+        "grpc-step-address",
+        "--grpc-sa",
+        "(GrpcStep) Grpc step endpoint address",
+    );
+    const GRPC_STEP_PORT: (&str, &str, &str) = (
+        "grpc-step-port",
+        "--grpc-sp",
+        "(GrpcStep) Grpc step endpoint port",
+    );
+    const GRPC_STEP_AUTHORITY: (&str, &str, &str) = (
+        "grpc-step-authority",
+        "--grpc-sauthority",
+        "(GrpcStep) Authority of the calls, the endpoint address and port by default",
+    );
+    const GRPC_STEP_HEADER: (&str, &str, &str) = (
+        "grpc-step-header",
+        "--grpc-sheader",
+        "(GrpcStep) Extra call metadata as \"Name: value\", may be repeated",
+    );
+
+    /// The one method served, a bidirectional streaming `Stream` of the
+    /// `Tunnel` service.
+    const GRPC_PATH: &str = "/Tunnel/Stream";
+    const GRPC_CONTENT_TYPE: &str = "application/grpc";
+    /// Compressed flag and big-endian length in front of every message.
+    const MESSAGE_HEADER_SIZE: usize = 5;
+    /// The receive limit gRPC implementations apply by default.
+    const MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+    const GRPC_OK: u32 = 0;
+    const GRPC_CANCELLED: u32 = 1;
+    const GRPC_UNIMPLEMENTED: u32 = 12;
+    const GRPC_INTERNAL: u32 = 13;
+    const GRPC_UNAVAILABLE: u32 = 14;
+
+    /// Frames every chunk as a gRPC message, splitting chunks larger than a
+    /// peer accepts.
+    fn encode_messages(data: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(data.len() + MESSAGE_HEADER_SIZE);
+        for chunk in data.chunks(MAX_MESSAGE_SIZE) {
+            encoded.push(0);
+            encoded.extend_from_slice(&(chunk.len() as u32).to_be_bytes());
+            encoded.extend_from_slice(chunk);
+        }
+        encoded
+    }
+
+    /// Reassembles messages from DATA frames, which may split or join them
+    /// anywhere.
+    #[derive(Default)]
+    struct MessageDecoder {
+        pending: Vec<u8>,
+    }
+
+    impl MessageDecoder {
+        fn push(&mut self, data: &[u8]) {
+            self.pending.extend_from_slice(data);
+        }
+
+        /// Nothing is sent compressed since no `grpc-encoding` is ever
+        /// negotiated, a compressed message is a protocol error.
+        fn next(&mut self) -> Result<Option<Vec<u8>>, Error> {
+            if self.pending.len() < MESSAGE_HEADER_SIZE {
+                return Ok(None);
+            }
+            if self.pending[0] != 0 {
+                return Err(Error::Msg(
+                    "compressed messages are not supported".to_string(),
+                ));
+            }
+            let size = u32::from_be_bytes(self.pending[1..5].try_into().unwrap()) as usize;
+            if size > MAX_MESSAGE_SIZE {
+                return Err(Error::Msg(format!(
+                    "message of {} bytes is too large",
+                    size
+                )));
+            }
+            if self.pending.len() < MESSAGE_HEADER_SIZE + size {
+                return Ok(None);
+            }
+            let message = self.pending[MESSAGE_HEADER_SIZE..MESSAGE_HEADER_SIZE + size].to_vec();
+            self.pending.drain(0..MESSAGE_HEADER_SIZE + size);
+            Ok(Some(message))
+        }
+
+        fn is_empty(&self) -> bool {
+            self.pending.is_empty()
+        }
+    }
+
+    /// `grpc-message` is percent-encoded so any text fits in a header value.
+    fn encode_grpc_message(message: &str) -> String {
+        let mut encoded = String::new();
+        for byte in message.bytes() {
+            if (0x20..0x7f).contains(&byte) && byte != b'%' {
+                encoded.push(byte as char);
+            } else {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        encoded
+    }
+
+    fn decode_grpc_message(message: &[u8]) -> String {
+        let mut decoded = Vec::new();
+        let mut i = 0;
+        while i < message.len() {
+            let escaped = match message.get(i + 1..i + 3) {
+                Some(hex) if message[i] == b'%' => std::str::from_utf8(hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                _ => None,
+            };
+            match escaped {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 3;
+                }
+                None => {
+                    decoded.push(message[i]);
+                    i += 1;
+                }
+            }
+        }
+        String::from_utf8_lossy(&decoded).into_owned()
+    }
+
+    /// Trailers ending a call, or the headers of a call ended before it
+    /// started.
+    fn status_headers(headers: &mut HeaderMap, code: u32, message: &str) {
+        headers.insert("grpc-status", HeaderValue::from(code));
+        if !message.is_empty() {
+            if let Ok(message) = HeaderValue::from_str(&encode_grpc_message(message)) {
+                headers.insert("grpc-message", message);
+            }
+        }
+    }
+
+    /// Reads the outcome of a call from its trailers.
+    fn call_status(headers: &HeaderMap) -> Result<(), Error> {
+        let code = match headers
+            .get("grpc-status")
+            .and_then(|code| code.to_str().ok())
+            .and_then(|code| code.parse::<u32>().ok())
+        {
+            Some(code) => code,
+            None => return Err(Error::Msg("call ended without grpc-status".to_string())),
+        };
+        if code == GRPC_OK {
+            return Ok(());
+        }
+        let message = headers
+            .get("grpc-message")
+            .map(|message| decode_grpc_message(message.as_bytes()))
+            .unwrap_or_default();
+        Err(Error::Msg(format!(
+            "call failed with grpc-status {}: {}",
+            code, message
+        )))
+    }
+
+    /// Why a call ended other than with OK, reported in its trailers.
+    struct Status {
+        code: u32,
+        error: Error,
+    }
+
+    impl From<Error> for Status {
+        fn from(error: Error) -> Status {
+            Status {
+                code: GRPC_UNAVAILABLE,
+                error,
+            }
+        }
+    }
+
+    impl From<h2::Error> for Status {
+        fn from(error: h2::Error) -> Status {
+            Status::from(Error::from(error))
+        }
+    }
+
+    impl From<std::io::Error> for Status {
+        fn from(error: std::io::Error) -> Status {
+            Status::from(Error::from(error))
+        }
+    }
+
+    /// Serves the `Tunnel/Stream` method over h2 with prior knowledge, each
+    /// call carrying one client: request messages are fed to the pipeline and
+    /// its output returned as response messages.
+    pub struct GrpcEntry {
+        address: String,
+        port: u16,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for GrpcEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(self.serve())
+        }
+    }
+
+    impl GrpcEntry {
+        async fn serve(&mut self) -> Result<(), Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let server = TcpListener::bind(addr).await?;
+
+            loop {
+                let (connection, peer) = server.accept().await?;
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("new client: {}", peer);
+                    }
+                }
+
+                let info = ConnectionInfo {
+                    peer: Some(peer),
+                    local: connection.local_addr().ok(),
+                    destination: None,
+                };
+                let pipeline_template = self.pipeline_template.clone();
+                let debug_level = self.debug_level;
+                let buffer_size = self.buffer_size;
+                tokio::spawn(async move {
+                    if let Err(e) = GrpcEntry::serve_connection(
+                        connection,
+                        info,
+                        pipeline_template,
+                        debug_level,
+                        buffer_size,
+                    )
+                    .await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving client {}: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        }
+
+        /// Like `HttpEntry::serve_h2`, but calls to anything other than the
+        /// tunnel method are answered with a gRPC status instead of reaching
+        /// the pipeline.
+        async fn serve_connection(
+            connection: TcpStream,
+            info: ConnectionInfo,
+            pipeline_template: Arc<Pipeline>,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let mut connection = server::Builder::new()
+                .initial_window_size(H2_WINDOW_SIZE)
+                .initial_connection_window_size(H2_WINDOW_SIZE)
+                .handshake(connection)
+                .await?;
+            while let Some(stream) = connection.accept().await {
+                let (request, mut response) = stream?;
+                let stream_id = response.stream_id();
+
+                {
+                    // debug
+                    if debug_level >= 2 {
+                        println!("new call {:?}: {}", stream_id, request.uri());
+                    }
+                }
+
+                let is_grpc = request
+                    .headers()
+                    .get("content-type")
+                    .and_then(|content_type| content_type.to_str().ok())
+                    .is_some_and(|content_type| content_type.starts_with(GRPC_CONTENT_TYPE));
+                if request.method() != Method::POST || !is_grpc {
+                    let mut reply = Response::new(());
+                    *reply.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+                    response.send_response(reply, true)?;
+                    continue;
+                }
+                if request.uri().path() != GRPC_PATH {
+                    let message = format!("unknown method {}", request.uri().path());
+                    response.send_response(GrpcEntry::reply(GRPC_UNIMPLEMENTED, &message), true)?;
+                    continue;
+                }
+
+                let pipeline = match tokio::task::block_in_place(|| {
+                    pipeline_template.clone_with(&info)
+                }) {
+                    Ok(pipeline) => pipeline,
+                    Err(e) => {
+                        if debug_level > 0 {
+                            eprintln!("an error accured cloning pipeline: {}", e);
+                        }
+                        let message = e.to_string();
+                        response
+                            .send_response(GrpcEntry::reply(GRPC_UNAVAILABLE, &message), true)?;
+                        continue;
+                    }
+                };
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        GrpcEntry::serve_call(request, response, pipeline, buffer_size).await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving call {:?}: {}", stream_id, e);
+                        }
+                    }
+                });
+            }
+            Ok(())
+        }
+
+        /// Headers of a response, ending the call right away when given a
+        /// status other than OK.
+        fn reply(code: u32, message: &str) -> Response<()> {
+            let mut reply = Response::new(());
+            reply
+                .headers_mut()
+                .insert("content-type", HeaderValue::from_static(GRPC_CONTENT_TYPE));
+            if code != GRPC_OK {
+                status_headers(reply.headers_mut(), code, message);
+            }
+            reply
+        }
+
+        async fn serve_call(
+            mut request: Request<RecvStream>,
+            mut response: SendResponse<Bytes>,
+            mut pipeline: Pipeline,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let mut send = response.send_response(GrpcEntry::reply(GRPC_OK, ""), false)?;
+            let result =
+                GrpcEntry::relay_call(request.body_mut(), &mut send, &mut pipeline, buffer_size)
+                    .await;
+
+            let mut trailers = HeaderMap::new();
+            match result {
+                Ok(()) => {
+                    status_headers(&mut trailers, GRPC_OK, "");
+                    send.send_trailers(trailers)?;
+                    Ok(())
+                }
+                Err(status) => {
+                    status_headers(&mut trailers, status.code, &status.error.to_string());
+                    // fails when the client has reset the stream
+                    let _ = send.send_trailers(trailers);
+                    Err(status.error)
+                }
+            }
+        }
+
+        /// Like `HttpEntry::serve_h2_stream`, with the request and response
+        /// bodies framed as messages. The end of the request half-closes the
+        /// pipeline, the call ends once the pipeline is done and everything it
+        /// returned has been sent.
+        async fn relay_call(
+            body: &mut RecvStream,
+            send: &mut SendStream<Bytes>,
+            pipeline: &mut Pipeline,
+            buffer_size: usize,
+        ) -> Result<(), Status> {
+            let pipeline_fd = PipelineFd::readable(pipeline)?;
+            let mut body_done = false;
+            let mut decoder = MessageDecoder::default();
+            let mut outgoing: Vec<u8> = Vec::new();
+            let mut pipeline_end = None;
+
+            loop {
+                if outgoing.is_empty() {
+                    if let Some(end) = pipeline_end {
+                        return end;
+                    }
+                }
+
+                tokio::select! {
+                    data = body.data(), if !body_done => {
+                        match data {
+                            Some(data) => {
+                                let data = data?;
+                                decoder.push(&data);
+                                while let Some(message) = decoder.next().map_err(|error| Status {
+                                    code: GRPC_INTERNAL,
+                                    error,
+                                })? {
+                                    write_pipeline(pipeline, message)?;
+                                }
+                                body.flow_control().release_capacity(data.len())?;
+                            }
+                            None => {
+                                if !decoder.is_empty() {
+                                    return Err(Status {
+                                        code: GRPC_INTERNAL,
+                                        error: Error::Msg("request ended inside a message".to_string()),
+                                    });
+                                }
+                                body_done = true;
+                                pipeline.close_forward()?;
+                            }
+                        }
+                    }
+                    event = poll_fn(|cx| HttpEntry::poll_send(send, !outgoing.is_empty(), cx)) => {
+                        match event? {
+                            SendEvent::Capacity(capacity) => {
+                                let size = capacity.min(outgoing.len());
+                                let data: Vec<u8> = outgoing.drain(0..size).collect();
+                                send.send_data(Bytes::from(data), false)?;
+                                send.reserve_capacity(outgoing.len());
+                            }
+                            SendEvent::Reset(reason) => {
+                                return Err(Status {
+                                    code: GRPC_CANCELLED,
+                                    error: Error::Msg(format!("stream reset by client: {:?}", reason)),
+                                });
+                            }
+                        }
+                    }
+                    guard = pipeline_fd.readable(), if pipeline_end.is_none() && outgoing.len() < buffer_size => {
+                        let mut guard = guard?;
+                        match read_pipeline(pipeline) {
+                            Ok(Some(data)) => {
+                                outgoing.extend(encode_messages(&data));
+                                send.reserve_capacity(outgoing.len());
+                            }
+                            Ok(None) => guard.clear_ready(),
+                            Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                                pipeline_end = Some(Ok(()));
+                            }
+                            Err(e) => pipeline_end = Some(Err(Status::from(e))),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    impl EntryStatic<GrpcEntry> for GrpcEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: crate::Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<GrpcEntry, Error> {
+            let address = match args.argument_values.get(GRPC_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(GRPC_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(GRPC_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(GRPC_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(GrpcEntry {
+                address,
+                port,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: GRPC_ENTRY_ADDRESS.0.to_string(),
+                key: vec![GRPC_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(GRPC_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: GRPC_ENTRY_PORT.0.to_string(),
+                key: vec![GRPC_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("50051".to_string()),
+                help: Some(ArgumentHelp::Text(GRPC_ENTRY_PORT.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    // drives the calls of every GrpcStep, the steps themselves are called
+    // synchronously by their entry
+    lazy_static! {
+        static ref RUNTIME: Runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+    }
+
+    struct GrpcTarget {
+        address: String,
+        port: u16,
+        authority: String,
+        headers: Vec<(String, String)>,
+        h2_connection: tokio::sync::Mutex<Option<SendRequest<Bytes>>>,
+    }
+
+    /// Carries its client as one `Tunnel/Stream` call to a `GrpcEntry`, or
+    /// anything gRPC-aware in front of one. The calls of all clones share an
+    /// h2 connection.
+    pub struct GrpcStep {
+        target: Arc<GrpcTarget>,
+        /// The local end of this client's call.
+        stream: LazyStream,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl GrpcStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let target = self.target.clone();
+            let debug_level = self.debug_level;
+            let buffer_size = self.buffer_size;
+            RUNTIME.spawn(async move {
+                if let Err(e) = run_call(target, inner, buffer_size).await {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on grpc call: {}", e);
+                    }
+                }
+            });
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("grpc call", self.debug_level, || self.open())
+        }
+    }
+
+    /// Sends what the step is given as request messages and returns the
+    /// response messages, the call ending with the trailers the entry sends
+    /// once its pipeline is done.
+    async fn run_call(
+        target: Arc<GrpcTarget>,
+        tunnel_end: UnixStream,
+        buffer_size: usize,
+    ) -> Result<(), Error> {
+        let tunnel_end = tokio::net::UnixStream::from_std(tunnel_end)?;
+        let (mut reader, mut writer) = tunnel_end.into_split();
+        let mut send_request =
+            h2_connection(&target.h2_connection, target.address.as_str(), target.port).await?;
+
+        let mut request = Request::post(format!("http://{}{}", target.authority, GRPC_PATH))
+            .header("content-type", GRPC_CONTENT_TYPE)
+            .header("te", "trailers");
+        for (name, value) in target.headers.iter() {
+            request = request.header(name, value);
+        }
+        let request = match request.body(()) {
+            Ok(request) => request,
+            Err(e) => return Err(Error::Msg(format!("invalid request: {}", e))),
+        };
+        let (response, mut send) = send_request.send_request(request, false)?;
+
+        let forward = async {
+            let mut buffer = vec![0u8; buffer_size];
+            loop {
+                let size = reader.read(&mut buffer).await?;
+                if size == 0 {
+                    send.send_data(Bytes::new(), true)?;
+                    return Ok::<(), Error>(());
+                }
+                let mut data = Bytes::from(encode_messages(&buffer[0..size]));
+                while !data.is_empty() {
+                    send.reserve_capacity(data.len());
+                    let capacity = match poll_fn(|cx| send.poll_capacity(cx)).await {
+                        Some(capacity) => capacity?,
+                        None => return Err(Error::Msg("stream closed".to_string())),
+                    };
+                    let chunk = data.split_to(capacity.min(data.len()));
+                    send.send_data(chunk, false)?;
+                }
+            }
+        };
+
+        let backward = async {
+            let response = response.await?;
+            if response.status() != StatusCode::OK {
+                return Err(Error::Msg(format!(
+                    "unexpected response status {}",
+                    response.status()
+                )));
+            }
+            // a call refused outright has its status in the headers
+            if response.headers().contains_key("grpc-status") {
+                return call_status(response.headers());
+            }
+
+            let mut body = response.into_body();
+            let mut decoder = MessageDecoder::default();
+            while let Some(data) = body.data().await {
+                let data = data?;
+                decoder.push(&data);
+                while let Some(message) = decoder.next()? {
+                    writer.write_all(&message).await?;
+                }
+                body.flow_control().release_capacity(data.len())?;
+            }
+            if !decoder.is_empty() {
+                return Err(Error::Msg("response ended inside a message".to_string()));
+            }
+            match body.trailers().await? {
+                Some(trailers) => call_status(&trailers)?,
+                None => return Err(Error::Msg("call ended without trailers".to_string())),
+            }
+            writer.shutdown().await?;
+            Ok::<(), Error>(())
+        };
+
+        // the call is over once the entry has ended it, whatever the client
+        // still had to send
+        tokio::pin!(forward, backward);
+        tokio::select! {
+            result = &mut forward => {
+                result?;
+                backward.await
+            }
+            result = &mut backward => result,
+        }
+    }
+
+    impl Step for GrpcStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_all(&mut self.stream()?, data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.stream()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for GrpcStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl StepStatic for GrpcStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(GRPC_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(GRPC_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(GRPC_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(GRPC_STEP_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let mut headers = Vec::new();
+            if let Some(values) = args.argument_values.get(GRPC_STEP_HEADER.0) {
+                for header in values {
+                    match header.split_once(':') {
+                        Some((name, value)) => {
+                            headers.push((name.trim().to_string(), value.trim().to_string()))
+                        }
+                        None => return Err(Error::Msg(format!("invalid header: {}", header))),
+                    }
+                }
+            }
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let authority = match args.argument_values.get(GRPC_STEP_AUTHORITY.0) {
+                Some(authority) => authority[0].clone(),
+                None => format!("{}:{}", address, port),
+            };
+
+            Ok(Self {
+                target: Arc::new(GrpcTarget {
+                    address,
+                    port,
+                    authority,
+                    headers,
+                    h2_connection: tokio::sync::Mutex::new(None),
+                }),
+                stream: LazyStream::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: GRPC_STEP_ADDRESS.0.to_string(),
+                key: vec![GRPC_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(GRPC_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: GRPC_STEP_PORT.0.to_string(),
+                key: vec![GRPC_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("50051".to_string()),
+                help: Some(ArgumentHelp::Text(GRPC_STEP_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: GRPC_STEP_AUTHORITY.0.to_string(),
+                key: vec![GRPC_STEP_AUTHORITY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(GRPC_STEP_AUTHORITY.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: GRPC_STEP_HEADER.0.to_string(),
+                key: vec![GRPC_STEP_HEADER.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Multiple,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(GRPC_STEP_HEADER.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    impl Clone for GrpcStep {
+        fn clone(&self) -> Self {
+            Self {
+                target: self.target.clone(),
+                stream: LazyStream::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for GrpcStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+}
@@ -1,27 +1,36 @@
 pub mod http {
-    use async_std::task::block_on;
     use cliparser::types::{
         Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
     };
     use hyper::body::Bytes;
-    use hyper::server::conn;
     use hyper::{Request, Response};
-    use id_pool::IdPool;
-    use mio::unix::SourceFd;
-    // use mio::net::{TcpListener, TcpStream};
-    use mio::{Events, Interest, Poll, Token};
-    use std::io::{ErrorKind, Read, Write};
-    use std::os::fd::{AsRawFd, FromRawFd};
-    use std::{result, string, vec};
+    use std::future::poll_fn;
+    use std::io::{self, ErrorKind, Read};
+    use std::net::SocketAddr;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, OnceLock};
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, Interest};
     use tokio::net::{TcpListener, TcpStream};
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc::{channel, Receiver, Sender};
 
+    use h2::client::{self, SendRequest};
     use h2::server::{self, SendResponse};
-    use h2::RecvStream;
+    use h2::{Reason, RecvStream, SendStream};
 
+    use lazy_static::lazy_static;
+
+    use crate::async_pipeline::async_pipeline::{read_pipeline, write_pipeline, PipelineFd};
+    use crate::proxy_protocol::proxy_protocol::{self, MAX_HEADER_SIZE};
+    use crate::tcp::tcp::WRITE_TIMEOUT;
     use crate::{
-        base::base::DebugLevel, BoxedClone, Entry, EntryStatic, Error, Pipeline, Step, StepStatic,
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
     };
-    use crate::{create_socket_addr, MultiMap, Ref, BUFFER_SIZE};
+    use crate::{create_socket_addr, BUFFER_SIZE};
 
     const HTTP_ENTRY_ADDRESS: (&str, &str, &str) = (
         "http-entry-address",
@@ -33,173 +42,499 @@ pub mod http {
         "--http-ep",
         "(HttpEntry) Http Entry listen port",
     );
+    const HTTP_ENTRY_PROXY: (&str, &str, &str) = (
+        "http-entry-proxy",
+        "--http-eproxy",
+        "(HttpEntry) Expect a PROXY protocol v1 or v2 header from every client, e.g. behind a load balancer",
+    );
 
     const HTTP_STEP_ADDRESS: (&str, &str, &str) = (
         "http-step-address",
         "--http-sa",
-        "(HttpEntry) Http step endpoint address",
+        "(HttpStep) Http step endpoint address",
     );
     const HTTP_STEP_PORT: (&str, &str, &str) = (
         "http-step-port",
         "--http-sp",
-        "(HttpEntry) Http step endpoint port",
+        "(HttpStep) Http step endpoint port",
+    );
+    const HTTP_STEP_PATH: (&str, &str, &str) = (
+        "http-step-path",
+        "--http-spath",
+        "(HttpStep) Path of the tunnel request",
+    );
+    const HTTP_STEP_HEADER: (&str, &str, &str) = (
+        "http-step-header",
+        "--http-sheader",
+        "(HttpStep) Extra request header as \"Name: value\", may be repeated",
+    );
+    const HTTP_STEP_VERSION: (&str, &str, &str) = (
+        "http-step-version",
+        "--http-sversion",
+        "(HttpStep) Http version of the tunnel request, 2 (prior knowledge) or 1.1",
     );
 
-    const SERVER_TOKEN: Token = Token(0);
+    const H2_PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+    const MAX_HEAD_SIZE: usize = 64 * 1024;
+    /// How long a client may take to send what comes before its requests.
+    const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+    pub(crate) const H2_WINDOW_SIZE: u32 = 1024 * 1024;
 
     pub struct HttpEntry {
         address: String,
         port: u16,
         debug_level: DebugLevel,
-        pipeline_template: Pipeline,
-        connections: MultiMap<Token, Ref<TcpEntryContext>>,
+        pipeline_template: Arc<Pipeline>,
+        proxy_protocol: bool,
         buffer_size: usize,
     }
 
-    struct TcpEntryContext {
-        connection: server::Connection<TcpStream, Bytes>,
-        request: Request<RecvStream>,
-        response: SendResponse<Bytes>,
-        pipeline: Pipeline,
-        connection_buf: Vec<u8>,
-        pipeline_buf: Vec<u8>,
+    enum Protocol {
+        Http1,
+        H2,
+    }
+
+    pub(crate) enum SendEvent {
+        Capacity(usize),
+        Reset(Reason),
     }
 
     impl Entry for HttpEntry {
         fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(self.serve())
+        }
+    }
+
+    impl HttpEntry {
+        async fn serve(&mut self) -> Result<(), Error> {
             let addr = create_socket_addr(self.address.as_str(), self.port)?;
-            let mut server = block_on(TcpListener::bind(addr))?;
+            let server = TcpListener::bind(addr).await?;
 
-            let mut poll = Poll::new()?;
-            let mut events = Events::with_capacity(128);
-            let fd = server.as_raw_fd();
-            let mut fd = SourceFd(&fd);
-            poll.registry()
-                .register(&mut fd, SERVER_TOKEN, Interest::READABLE)?;
-            let mut connection_counter = 0;
+            loop {
+                let (connection, peer) = server.accept().await?;
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("new client: {}", peer);
+                    }
+                }
 
+                let pipeline_template = self.pipeline_template.clone();
+                let proxy_protocol = self.proxy_protocol;
+                let debug_level = self.debug_level;
+                let buffer_size = self.buffer_size;
+                tokio::spawn(async move {
+                    if let Err(e) = HttpEntry::serve_connection(
+                        connection,
+                        peer,
+                        proxy_protocol,
+                        pipeline_template,
+                        debug_level,
+                        buffer_size,
+                    )
+                    .await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving client {}: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        }
+
+        async fn serve_connection(
+            mut connection: TcpStream,
+            peer: SocketAddr,
+            proxy_protocol: bool,
+            pipeline_template: Arc<Pipeline>,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let mut info = ConnectionInfo {
+                peer: Some(peer),
+                local: connection.local_addr().ok(),
+                destination: None,
+            };
+            if proxy_protocol {
+                let header = tokio::time::timeout(
+                    HEADER_TIMEOUT,
+                    HttpEntry::read_proxy_header(&mut connection),
+                )
+                .await
+                .map_err(|_| Error::Msg("PROXY protocol header timed out".to_string()))?;
+                if let Some((source, destination)) = header? {
+                    info.peer = Some(source);
+                    info.local = Some(destination);
+                }
+
+                {
+                    // debug
+                    if debug_level >= 2 {
+                        println!("client {} via {}", info.peer.unwrap(), peer);
+                    }
+                }
+            }
+
+            let protocol =
+                tokio::time::timeout(HEADER_TIMEOUT, HttpEntry::detect_protocol(&connection))
+                    .await
+                    .map_err(|_| Error::Msg("first request timed out".to_string()))?;
+            match protocol? {
+                Protocol::H2 => {
+                    HttpEntry::serve_h2(
+                        connection,
+                        info,
+                        pipeline_template,
+                        debug_level,
+                        buffer_size,
+                    )
+                    .await
+                }
+                Protocol::Http1 => {
+                    let pipeline =
+                        tokio::task::block_in_place(|| pipeline_template.clone_with(&info))?;
+                    HttpEntry::serve_http1(connection, pipeline, buffer_size).await
+                }
+            }
+        }
+
+        /// Consumes the PROXY protocol header and nothing after it, returning
+        /// the client and the address it connected to if the header has them.
+        async fn read_proxy_header(
+            connection: &mut TcpStream,
+        ) -> Result<Option<(SocketAddr, SocketAddr)>, Error> {
+            let mut buffer = vec![0u8; 512];
+            let mut seen = 0;
             loop {
-                poll.poll(&mut events, None)?;
-                for event in events.iter() {
-                    match event.token() {
-                        SERVER_TOKEN => {
-                            let connection = block_on(server.accept())?;
-                            let fd = connection.0.as_raw_fd();
-                            let mut handshacked_connection =
-                                block_on(server::handshake(connection.0)).unwrap();
-                            let (request, response) =
-                                block_on(handshacked_connection.accept()).unwrap()?;
-
-                            let mut client = Ref::new(TcpEntryContext {
-                                connection: handshacked_connection,
-                                request: request,
-                                response: response,
-                                pipeline: self.pipeline_template.clone(),
-                                connection_buf: vec![0u8; 0],
-                                pipeline_buf: vec![0u8; 0],
-                            });
-
-                            {
-                                // debug
-                                if self.debug_level >= 2 {
-                                    println!("new client: {}", connection.1);
-                                }
+                let size = HttpEntry::peek_more(connection, &mut buffer, seen).await?;
+                if size == 0 {
+                    return Err(Error::IoError(ErrorKind::UnexpectedEof.into()));
+                }
+                match proxy_protocol::parse(&buffer[0..size])? {
+                    Some(header) => {
+                        buffer.resize(header.size, 0);
+                        connection.read_exact(&mut buffer).await?;
+                        return Ok(header.addresses);
+                    }
+                    None if size == buffer.len() => {
+                        if size >= MAX_HEADER_SIZE {
+                            return Err(Error::Msg("PROXY protocol header too long".to_string()));
+                        }
+                        buffer.resize((size * 2).min(MAX_HEADER_SIZE), 0);
+                    }
+                    // like the h2 preface, wait for the rest of it
+                    None => {}
+                }
+                seen = size;
+            }
+        }
+
+        /// Peeks at the first bytes of the connection: clients speaking h2 with
+        /// prior knowledge start with the connection preface, anything else is
+        /// treated as an HTTP/1.x request line.
+        async fn detect_protocol(connection: &TcpStream) -> Result<Protocol, Error> {
+            let mut buffer = [0u8; H2_PREFACE.len()];
+            let mut seen = 0;
+            loop {
+                let size = HttpEntry::peek_more(connection, &mut buffer, seen).await?;
+                if size == 0 {
+                    return Err(Error::IoError(ErrorKind::UnexpectedEof.into()));
+                }
+                if buffer[0..size] != H2_PREFACE[0..size] {
+                    return Ok(Protocol::Http1);
+                }
+                if size == H2_PREFACE.len() {
+                    return Ok(Protocol::H2);
+                }
+                // a partial preface is also a valid start of a "PRI" request line,
+                // so wait for the rest of it
+                seen = size;
+            }
+        }
+
+        /// Peeks into `buffer` once the client sent more than the `seen`
+        /// bytes peeked before, or closed the connection. Peeking consumes
+        /// nothing, so readiness is only cleared when nothing new came.
+        async fn peek_more(
+            connection: &TcpStream,
+            buffer: &mut [u8],
+            seen: usize,
+        ) -> Result<usize, Error> {
+            loop {
+                connection.readable().await?;
+                let peeked = connection.try_io(Interest::READABLE, || {
+                    let size = unsafe {
+                        libc::recv(
+                            connection.as_raw_fd(),
+                            buffer.as_mut_ptr() as *mut libc::c_void,
+                            buffer.len(),
+                            libc::MSG_PEEK,
+                        )
+                    };
+                    match size {
+                        size if size < 0 => Err(io::Error::last_os_error()),
+                        0 => Ok(0),
+                        size if size as usize > seen => Ok(size as usize),
+                        _ => Err(ErrorKind::WouldBlock.into()),
+                    }
+                });
+                match peeked {
+                    Ok(size) => return Ok(size),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+        }
+
+        /// Accepting streams is what drives the connection, so this keeps
+        /// polling it until the client goes away or sends GOAWAY. Every stream
+        /// is served by its own task with its own copy of the pipeline.
+        async fn serve_h2(
+            connection: TcpStream,
+            info: ConnectionInfo,
+            pipeline_template: Arc<Pipeline>,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let mut connection = server::Builder::new()
+                .initial_window_size(H2_WINDOW_SIZE)
+                .initial_connection_window_size(H2_WINDOW_SIZE)
+                .handshake(connection)
+                .await?;
+            while let Some(stream) = connection.accept().await {
+                let (request, mut response) = stream?;
+                let stream_id = response.stream_id();
+
+                {
+                    // debug
+                    if debug_level >= 2 {
+                        println!("new stream {:?}: {}", stream_id, request.uri());
+                    }
+                }
+
+                let pipeline =
+                    match tokio::task::block_in_place(|| pipeline_template.clone_with(&info)) {
+                        Ok(pipeline) => pipeline,
+                        Err(e) => {
+                            if debug_level > 0 {
+                                eprintln!("an error accured cloning pipeline: {}", e);
                             }
+                            response.send_reset(Reason::REFUSED_STREAM);
+                            continue;
+                        }
+                    };
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        HttpEntry::serve_h2_stream(request, response, pipeline, buffer_size).await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving stream {:?}: {}", stream_id, e);
+                        }
+                    }
+                });
+            }
+            Ok(())
+        }
+
+        /// Streams the request body into the pipeline and the pipeline output
+        /// back as DATA frames until either side is done. Response data waits
+        /// for send capacity, and receive capacity is released as soon as the
+        /// request data has been handed to the pipeline.
+        async fn serve_h2_stream(
+            mut request: Request<RecvStream>,
+            mut response: SendResponse<Bytes>,
+            mut pipeline: Pipeline,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let pipeline_fd = PipelineFd::readable(&pipeline)?;
+            let mut send = response.send_response(Response::new(()), false)?;
+            let body = request.body_mut();
+            let mut body_done = false;
+            let mut outgoing: Vec<u8> = Vec::new();
+            // the pipeline ending is reported once its output was sent
+            let mut pipeline_end = None;
+
+            loop {
+                if outgoing.is_empty() {
+                    if let Some(end) = pipeline_end {
+                        send.send_data(Bytes::new(), true)?;
+                        return end;
+                    }
+                }
 
-                            connection_counter += 1;
-                            let token = Token(connection_counter);
-                            let mut fd = SourceFd(&fd);
-                            poll.registry()
-                                .register(&mut fd, token, Interest::READABLE | Interest::WRITABLE)
-                                .unwrap();
-
-                            connection_counter += 1;
-                            let pipeline_token = Token(connection_counter);
-                            let pfd = client.pipeline.as_raw_fd();
-                            let mut pfd = SourceFd(&pfd);
-                            poll.registry()
-                                .register(
-                                    &mut pfd,
-                                    pipeline_token,
-                                    Interest::READABLE | Interest::WRITABLE,
-                                )
-                                .unwrap();
-
-                            self.connections.insert(token, client.clone());
-                            self.connections.insert(pipeline_token, client);
-                            // drop(connection);
+                tokio::select! {
+                    data = body.data(), if !body_done => {
+                        match data {
+                            Some(data) => {
+                                let data = data?;
+                                let size = data.len();
+                                write_pipeline(&mut pipeline, data.to_vec())?;
+                                body.flow_control().release_capacity(size)?;
+                            }
+                            None => {
+                                body_done = true;
+                                pipeline.close_forward()?;
+                            }
                         }
-                        other => {
-                            let client = match self.connections.get_mut(&other) {
-                                Some(client) => client,
-                                None => {
-                                    if self.debug_level > 0 {
-                                        eprintln!("No client found with this token {}", other.0);
-                                    }
-                                    continue;
-                                }
-                            };
-
-                            match other.0 % 2 {
-                                0 => {
-                                    // pipeline has io event
-                                    if event.is_readable() {
-                                        if let Err(e) = HttpEntry::read_pipeline(client) {
-                                            match e {
-                                                Error::IoError(e) => {
-                                                    if e.kind() == ErrorKind::WouldBlock {
-                                                        continue;
-                                                    }
-                                                }
-                                                _ => {
-                                                    if self.debug_level > 0 {
-                                                        eprintln!(
-                                                            "an error accured reading pipeline: {}",
-                                                            e
-                                                        );
-                                                    }
-                                                }
-                                            }
-                                        }
-
-                                        if let Err(e) = HttpEntry::write_client(client) {
-                                            if self.debug_level > 0 {
-                                                eprintln!("an error accured writing client: {}", e);
-                                            }
-                                        }
-                                    }
+                    }
+                    event = poll_fn(|cx| HttpEntry::poll_send(&mut send, !outgoing.is_empty(), cx)) => {
+                        match event? {
+                            SendEvent::Capacity(capacity) => {
+                                let size = capacity.min(outgoing.len());
+                                let data: Vec<u8> = outgoing.drain(0..size).collect();
+                                send.send_data(Bytes::from(data), false)?;
+                                send.reserve_capacity(outgoing.len());
+                            }
+                            SendEvent::Reset(reason) => {
+                                return Err(Error::Msg(format!("stream reset by client: {:?}", reason)));
+                            }
+                        }
+                    }
+                    guard = pipeline_fd.readable(), if pipeline_end.is_none() && outgoing.len() < buffer_size => {
+                        let mut guard = guard?;
+                        match read_pipeline(&mut pipeline) {
+                            Ok(Some(data)) => {
+                                outgoing.extend(data);
+                                send.reserve_capacity(outgoing.len());
+                            }
+                            Ok(None) => guard.clear_ready(),
+                            Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                                pipeline_end = Some(Ok(()));
+                            }
+                            Err(e) => pipeline_end = Some(Err(e)),
+                        }
+                    }
+                }
+            }
+        }
+
+        /// Hands the body bytes in `input` to the pipeline and half-closes it
+        /// once the body is complete. Bytes after the end of the body belong
+        /// to requests that cannot be answered while the response is still
+        /// streaming, they are dropped.
+        fn forward_body(
+            pipeline: &mut Pipeline,
+            decoder: &mut BodyDecoder,
+            input: &[u8],
+            body_done: &mut bool,
+        ) -> Result<(), Error> {
+            if *body_done {
+                return Ok(());
+            }
+            let data = decoder.decode(input)?;
+            if !data.is_empty() {
+                write_pipeline(pipeline, data)?;
+            }
+            if decoder.is_done() {
+                *body_done = true;
+                pipeline.close_forward()?;
+            }
+            Ok(())
+        }
+
+        /// Waits for the client to reset the stream or, when there is data
+        /// waiting to be sent, for the stream to be assigned send capacity.
+        pub(crate) fn poll_send(
+            send: &mut SendStream<Bytes>,
+            has_outgoing: bool,
+            cx: &mut Context,
+        ) -> Poll<Result<SendEvent, Error>> {
+            if let Poll::Ready(reason) = send.poll_reset(cx) {
+                return Poll::Ready(Ok(SendEvent::Reset(reason?)));
+            }
+            if !has_outgoing {
+                return Poll::Pending;
+            }
+            match send.poll_capacity(cx) {
+                Poll::Ready(Some(capacity)) => Poll::Ready(Ok(SendEvent::Capacity(capacity?))),
+                Poll::Ready(None) => Poll::Ready(Err(Error::Msg("stream closed".to_string()))),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+
+        async fn serve_http1(
+            mut connection: TcpStream,
+            mut pipeline: Pipeline,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let (head, leftover) = match read_head(&mut connection).await? {
+                Some(request) => request,
+                None => return Ok(()),
+            };
+            let head = match RequestHead::parse(&head) {
+                Ok(head) => head,
+                Err(e) => {
+                    connection
+                        .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\nContent-Length: 0\r\n\r\n")
+                        .await?;
+                    return Err(e);
+                }
+            };
+
+            if head.expect_continue {
+                connection
+                    .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                    .await?;
+            }
+            // HTTP/1.0 clients do not understand chunked responses, the end of
+            // their response is signalled by closing the connection instead
+            let chunked_response = head.version > 0;
+            if chunked_response {
+                connection
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nTransfer-Encoding: chunked\r\n\r\n")
+                    .await?;
+            } else {
+                connection
+                    .write_all(b"HTTP/1.0 200 OK\r\nContent-Type: application/octet-stream\r\nConnection: close\r\n\r\n")
+                    .await?;
+            }
+
+            let pipeline_fd = PipelineFd::readable(&pipeline)?;
+            let mut decoder = head.body;
+            let mut body_done = false;
+            HttpEntry::forward_body(&mut pipeline, &mut decoder, &leftover, &mut body_done)?;
+
+            let (mut reader, mut writer) = connection.split();
+            let mut buffer = vec![0u8; buffer_size];
+            loop {
+                tokio::select! {
+                    size = reader.read(&mut buffer) => {
+                        let size = size?;
+                        if size == 0 {
+                            return Ok(());
+                        }
+                        HttpEntry::forward_body(
+                            &mut pipeline,
+                            &mut decoder,
+                            &buffer[0..size],
+                            &mut body_done,
+                        )?;
+                    }
+                    guard = pipeline_fd.readable() => {
+                        let mut guard = guard?;
+                        match read_pipeline(&mut pipeline) {
+                            Ok(Some(data)) => {
+                                if chunked_response {
+                                    writer.write_all(format!("{:x}\r\n", data.len()).as_bytes()).await?;
+                                    writer.write_all(&data).await?;
+                                    writer.write_all(b"\r\n").await?;
+                                } else {
+                                    writer.write_all(&data).await?;
                                 }
-                                1 => {
-                                    // client has io event
-                                    if event.is_readable() {
-                                        if let Err(e) =
-                                            HttpEntry::read_client(self.buffer_size, client)
-                                        {
-                                            if self.debug_level > 0 {
-                                                eprintln!("an error accured reading client: {}", e);
-                                            }
-                                        }
-
-                                        if let Err(e) = HttpEntry::write_pipeline(client) {
-                                            match e {
-                                                Error::IoError(e) => {
-                                                    if e.kind() == ErrorKind::WouldBlock {
-                                                        continue;
-                                                    }
-                                                }
-                                                _ => {
-                                                    if self.debug_level > 0 {
-                                                        eprintln!(
-                                                            "an error accured writing pipeline: {}",
-                                                            e
-                                                        );
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
+                            }
+                            Ok(None) => guard.clear_ready(),
+                            Err(e) => {
+                                if chunked_response {
+                                    writer.write_all(b"0\r\n\r\n").await?;
                                 }
-                                _ => unreachable!(),
+                                return match e {
+                                    Error::IoError(e) if e.kind() == ErrorKind::ConnectionAborted => Ok(()),
+                                    e => Err(e),
+                                };
                             }
                         }
                     }
@@ -208,41 +543,222 @@ pub mod http {
         }
     }
 
-    impl HttpEntry {
-        fn read_client(buffer_size: usize, client: &mut TcpEntryContext) -> Result<(), Error> {
-            let body = client.request.body_mut();
-            match block_on(body.data()) {
-                Some(data) => {
-                    client.connection_buf.extend(data?.iter());
+    /// Reads until the end of a request or response head, returning the head
+    /// and any body bytes that were received along with it.
+    pub(crate) async fn read_head<R: AsyncRead + Unpin>(
+        connection: &mut R,
+    ) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error> {
+        let mut head = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let size = connection.read(&mut buffer).await?;
+            if size == 0 {
+                return Ok(None);
+            }
+            let searched = head.len().saturating_sub(3);
+            head.extend_from_slice(&buffer[0..size]);
+            if let Some(end) = head[searched..].windows(4).position(|w| w == b"\r\n\r\n") {
+                let body = head.split_off(searched + end + 4);
+                return Ok(Some((head, body)));
+            }
+            if head.len() > MAX_HEAD_SIZE {
+                return Err(Error::Msg("http head too large".to_string()));
+            }
+        }
+    }
+
+    struct RequestHead {
+        version: u8,
+        expect_continue: bool,
+        body: BodyDecoder,
+    }
+
+    impl RequestHead {
+        fn parse(head: &[u8]) -> Result<RequestHead, Error> {
+            let mut headers = [httparse::EMPTY_HEADER; 64];
+            let mut request = httparse::Request::new(&mut headers);
+            match request.parse(head) {
+                Ok(httparse::Status::Complete(_)) => {}
+                Ok(httparse::Status::Partial) => {
+                    return Err(Error::Msg("incomplete request head".to_string()))
                 }
-                None => {}
+                Err(e) => return Err(Error::Msg(format!("invalid request head: {}", e))),
             }
-            Ok(())
+
+            let expect_continue = request.headers.iter().any(|header| {
+                header.name.eq_ignore_ascii_case("expect")
+                    && String::from_utf8_lossy(header.value)
+                        .trim()
+                        .eq_ignore_ascii_case("100-continue")
+            });
+            // a request without framing headers has no body
+            let body = BodyDecoder::from_headers(request.headers, BodyDecoder::Length(0))?;
+
+            Ok(RequestHead {
+                version: request.version.unwrap_or(1),
+                expect_continue,
+                body,
+            })
         }
+    }
 
-        fn read_pipeline(client: &mut TcpEntryContext) -> Result<(), Error> {
-            client.pipeline_buf.extend(client.pipeline.read_pipeline()?);
-            Ok(())
+    pub(crate) struct ResponseHead {
+        pub(crate) status: u16,
+        /// The server closes the connection after this response.
+        pub(crate) close: bool,
+        pub(crate) body: BodyDecoder,
+    }
+
+    impl ResponseHead {
+        pub(crate) fn parse(head: &[u8]) -> Result<ResponseHead, Error> {
+            let mut headers = [httparse::EMPTY_HEADER; 64];
+            let mut response = httparse::Response::new(&mut headers);
+            match response.parse(head) {
+                Ok(httparse::Status::Complete(_)) => {}
+                Ok(httparse::Status::Partial) => {
+                    return Err(Error::Msg("incomplete response head".to_string()))
+                }
+                Err(e) => return Err(Error::Msg(format!("invalid response head: {}", e))),
+            }
+
+            let close = response.version == Some(0)
+                || response.headers.iter().any(|header| {
+                    header.name.eq_ignore_ascii_case("connection")
+                        && String::from_utf8_lossy(header.value)
+                            .trim()
+                            .eq_ignore_ascii_case("close")
+                });
+            // a response without framing headers lasts until the connection closes
+            let body = BodyDecoder::from_headers(response.headers, BodyDecoder::UntilClose)?;
+
+            Ok(ResponseHead {
+                status: response.code.unwrap_or(0),
+                close,
+                body,
+            })
         }
+    }
+
+    pub(crate) enum ChunkState {
+        Size,
+        Data(u64),
+        DataEnd,
+        Trailer,
+        Done,
+    }
 
-        fn write_client(client: &mut TcpEntryContext) -> Result<(), Error> {
-            if client.pipeline_buf.len() > 0 {
-                let response = Response::new(());
-                let mut send = client.response.send_response(response, true)?;
-                send.send_data(Bytes::from_iter(client.pipeline_buf.clone()), false)?;
-                client.pipeline_buf.clear();
+    /// Strips the HTTP/1.1 framing from a request or response body.
+    pub(crate) enum BodyDecoder {
+        Length(u64),
+        Chunked { state: ChunkState, pending: Vec<u8> },
+        UntilClose,
+    }
+
+    impl BodyDecoder {
+        pub(crate) fn from_headers(
+            headers: &[httparse::Header],
+            unframed: BodyDecoder,
+        ) -> Result<BodyDecoder, Error> {
+            let mut chunked = false;
+            let mut content_length = None;
+            for header in headers.iter() {
+                let value = String::from_utf8_lossy(header.value);
+                if header.name.eq_ignore_ascii_case("transfer-encoding") {
+                    chunked = value
+                        .rsplit(',')
+                        .next()
+                        .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+                } else if header.name.eq_ignore_ascii_case("content-length") {
+                    match str::parse::<u64>(value.trim()) {
+                        Ok(length) => content_length = Some(length),
+                        Err(_) => return Err(Error::ParseIntError),
+                    }
+                }
+            }
+
+            if chunked {
+                Ok(BodyDecoder::Chunked {
+                    state: ChunkState::Size,
+                    pending: Vec::new(),
+                })
+            } else {
+                Ok(content_length.map_or(unframed, BodyDecoder::Length))
             }
-            Ok(())
         }
 
-        fn write_pipeline(client: &mut TcpEntryContext) -> Result<(), Error> {
-            if client.connection_buf.len() > 0 {
-                client
-                    .pipeline
-                    .write_pipeline(client.connection_buf.clone())?;
-                client.connection_buf.clear();
+        pub(crate) fn is_done(&self) -> bool {
+            match self {
+                BodyDecoder::Length(remaining) => *remaining == 0,
+                BodyDecoder::Chunked { state, .. } => matches!(state, ChunkState::Done),
+                BodyDecoder::UntilClose => false,
+            }
+        }
+
+        pub(crate) fn decode(&mut self, input: &[u8]) -> Result<Vec<u8>, Error> {
+            match self {
+                BodyDecoder::UntilClose => Ok(input.to_vec()),
+                BodyDecoder::Length(remaining) => {
+                    let size = (*remaining).min(input.len() as u64) as usize;
+                    *remaining -= size as u64;
+                    Ok(input[0..size].to_vec())
+                }
+                BodyDecoder::Chunked { state, pending } => {
+                    pending.extend_from_slice(input);
+                    let mut data = Vec::new();
+                    loop {
+                        match state {
+                            ChunkState::Size | ChunkState::Trailer => {
+                                let end = match pending.windows(2).position(|w| w == b"\r\n") {
+                                    Some(end) => end,
+                                    None if pending.len() > MAX_HEAD_SIZE => {
+                                        return Err(Error::Msg("invalid chunk".to_string()))
+                                    }
+                                    None => break,
+                                };
+                                let line: Vec<u8> = pending.drain(0..end + 2).take(end).collect();
+                                if let ChunkState::Trailer = state {
+                                    if line.is_empty() {
+                                        *state = ChunkState::Done;
+                                    }
+                                    continue;
+                                }
+                                let line = String::from_utf8_lossy(&line);
+                                let size = line.split(';').next().unwrap_or("").trim();
+                                *state = match u64::from_str_radix(size, 16) {
+                                    Ok(0) => ChunkState::Trailer,
+                                    Ok(size) => ChunkState::Data(size),
+                                    Err(_) => return Err(Error::ParseIntError),
+                                };
+                            }
+                            ChunkState::Data(remaining) => {
+                                if pending.is_empty() {
+                                    break;
+                                }
+                                let size = (*remaining).min(pending.len() as u64) as usize;
+                                data.extend(pending.drain(0..size));
+                                *remaining -= size as u64;
+                                if *remaining == 0 {
+                                    *state = ChunkState::DataEnd;
+                                }
+                            }
+                            ChunkState::DataEnd => {
+                                if pending.len() < 2 {
+                                    break;
+                                }
+                                if pending.drain(0..2).as_slice() != b"\r\n" {
+                                    return Err(Error::Msg("invalid chunk".to_string()));
+                                }
+                                *state = ChunkState::Size;
+                            }
+                            ChunkState::Done => {
+                                pending.clear();
+                                break;
+                            }
+                        }
+                    }
+                    Ok(data)
+                }
             }
-            Ok(())
         }
     }
 
@@ -278,8 +794,8 @@ pub mod http {
                 address,
                 port,
                 debug_level,
-                pipeline_template: pipeline,
-                connections: MultiMap::new(),
+                pipeline_template: Arc::new(pipeline),
+                proxy_protocol: args.arguments.contains(HTTP_ENTRY_PROXY.0),
                 buffer_size,
             })
         }
@@ -301,117 +817,520 @@ pub mod http {
                 default_value: Some("80".to_string()),
                 help: Some(ArgumentHelp::Text(HTTP_ENTRY_PORT.2.to_string())),
             });
+            argument = argument.add_argument(Argument {
+                name: HTTP_ENTRY_PROXY.0.to_string(),
+                key: vec![HTTP_ENTRY_PROXY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::None,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(HTTP_ENTRY_PROXY.2.to_string())),
+            });
             argument
         }
     }
 
-    // pub struct TcpStep {
-    //     address: String,
-    //     port: u16,
-    //     connection: TcpStream,
-    //     debug_level: DebugLevel,
-    //     buffer_size: usize,
-    // }
-
-    // impl TcpStep {}
-
-    // impl Step for TcpStep {
-    //     fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
-    //         self.connection.write(&data)?;
-    //         Ok(data.clone())
-    //     }
-
-    //     fn process_data_backward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
-    //         let mut read_buffer = vec![0u8; self.buffer_size];
-    //         let read_size = self.connection.read(&mut read_buffer)?;
-    //         Ok(read_buffer[0..read_size].to_vec())
-    //     }
-    // }
-
-    // impl BoxedClone for TcpStep {
-    //     fn bclone(&self) -> Box<dyn Step> {
-    //         Box::new(Self {
-    //             address: self.address.clone(),
-    //             port: self.port.clone(),
-    //             connection: unsafe { TcpStream::from_raw_fd(self.connection.as_raw_fd()) },
-    //             debug_level: self.debug_level,
-    //             buffer_size: self.buffer_size,
-    //         })
-    //     }
-    // }
-
-    // impl StepStatic for TcpStep {
-    //     fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
-    //         let address = match args.argument_values.get(TCP_STEP_ADDRESS.0) {
-    //             Some(address) => address[0].clone(),
-    //             None => return Err(Error::RequireOption(TCP_STEP_ADDRESS.0.to_string())),
-    //         };
-    //         let port = match args.argument_values.get(TCP_STEP_PORT.0) {
-    //             Some(port) => port[0].clone(),
-    //             None => return Err(Error::RequireOption(TCP_STEP_PORT.0.to_string())),
-    //         };
-    //         let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
-    //             Some(buffer_size) => buffer_size[0].clone(),
-    //             None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
-    //         };
-
-    //         let port = match str::parse::<u16>(port.as_str()) {
-    //             Ok(port) => port,
-    //             Err(e) => return Err(Error::ParseIntError),
-    //         };
-    //         let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
-    //             Ok(buffer_size) => buffer_size,
-    //             Err(e) => return Err(Error::ParseIntError),
-    //         };
-
-    //         let addr = create_socket_addr(address.as_str(), port)?;
-    //         let connection = TcpStream::connect(addr)?;
-
-    //         Ok(Self {
-    //             address: address,
-    //             port: port,
-    //             connection: connection,
-    //             debug_level: debug_level,
-    //             buffer_size: buffer_size,
-    //         })
-    //     }
-
-    //     fn get_cmd(mut argument: CliSpec) -> CliSpec {
-    //         argument = argument.add_argument(Argument {
-    //             name: TCP_STEP_ADDRESS.0.to_string(),
-    //             key: vec![TCP_STEP_ADDRESS.1.to_string()],
-    //             argument_occurrence: ArgumentOccurrence::Single,
-    //             value_type: ArgumentValueType::Single,
-    //             default_value: Some("127.0.0.1".to_string()),
-    //             help: Some(ArgumentHelp::Text(TCP_STEP_ADDRESS.2.to_string())),
-    //         });
-    //         argument = argument.add_argument(Argument {
-    //             name: TCP_STEP_PORT.0.to_string(),
-    //             key: vec![TCP_STEP_PORT.1.to_string()],
-    //             argument_occurrence: ArgumentOccurrence::Single,
-    //             value_type: ArgumentValueType::Single,
-    //             default_value: Some("80".to_string()),
-    //             help: Some(ArgumentHelp::Text(TCP_STEP_PORT.2.to_string())),
-    //         });
-    //         argument
-    //     }
-    // }
-
-    // impl Clone for TcpStep {
-    //     fn clone(&self) -> Self {
-    //         Self {
-    //             address: self.address.clone(),
-    //             port: self.port.clone(),
-    //             connection: unsafe { TcpStream::from_raw_fd(self.connection.as_raw_fd()) },
-    //             debug_level: self.debug_level,
-    //             buffer_size: self.buffer_size,
-    //         }
-    //     }
-    // }
-
-    // impl AsRawFd for TcpStep {
-    //     fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
-    //         self.connection.as_raw_fd()
-    //     }
-    // }
+    /// Request body chunks queued for the tunnel before the step waits.
+    const REQUEST_BODY_CHUNKS: usize = 16;
+
+    // drives the connections of every HttpStep, the steps themselves are
+    // called synchronously by their entry
+    lazy_static! {
+        static ref RUNTIME: Runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum HttpVersion {
+        Http1,
+        H2,
+    }
+
+    /// Tunnels pipeline bytes through a streaming POST: forward data becomes
+    /// the request body and the response body is returned backward. Every
+    /// clone opens its own request, h2 requests share one connection.
+    pub struct HttpStep {
+        target: HttpTarget,
+        /// Sent when the step is first used, `None` if that failed.
+        request: OnceLock<Option<HttpRequest>>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    /// The step's end of a request that a tunnel task is running.
+    struct HttpRequest {
+        /// Dropped to end the request body.
+        body: Option<Sender<Vec<u8>>>,
+        response_body: UnixStream,
+    }
+
+    #[derive(Clone)]
+    struct HttpTarget {
+        address: String,
+        port: u16,
+        path: String,
+        headers: Vec<(String, String)>,
+        version: HttpVersion,
+        h2_connection: Arc<tokio::sync::Mutex<Option<SendRequest<Bytes>>>>,
+    }
+
+    impl HttpStep {
+        fn open(target: &HttpTarget, debug_level: DebugLevel) -> Result<HttpRequest, Error> {
+            let (response_body, tunnel_end) = UnixStream::pair()?;
+            response_body.set_nonblocking(true)?;
+            tunnel_end.set_nonblocking(true)?;
+            let (body, receiver) = channel(REQUEST_BODY_CHUNKS);
+
+            let tunnel = HttpTunnel {
+                target: target.clone(),
+                request_body: receiver,
+            };
+            let version = target.version;
+            RUNTIME.spawn(async move {
+                let result = match version {
+                    HttpVersion::H2 => tunnel.run_h2(tunnel_end).await,
+                    HttpVersion::Http1 => tunnel.run_http1(tunnel_end).await,
+                };
+                if let Err(e) = result {
+                    if debug_level > 0 {
+                        eprintln!("an error accured in http tunnel: {}", e);
+                    }
+                }
+            });
+
+            Ok(HttpRequest {
+                body: Some(body),
+                response_body,
+            })
+        }
+
+        fn opened(&self) -> Option<&HttpRequest> {
+            self.request
+                .get_or_init(|| match HttpStep::open(&self.target, self.debug_level) {
+                    Ok(request) => Some(request),
+                    Err(e) => {
+                        if self.debug_level > 0 {
+                            eprintln!("an error accured opening http request: {}", e);
+                        }
+                        None
+                    }
+                })
+                .as_ref()
+        }
+
+        fn request(&mut self) -> Result<&mut HttpRequest, Error> {
+            self.opened();
+            self.request
+                .get_mut()
+                .and_then(Option::as_mut)
+                .ok_or_else(|| Error::IoError(ErrorKind::NotConnected.into()))
+        }
+    }
+
+    struct HttpTunnel {
+        target: HttpTarget,
+        request_body: Receiver<Vec<u8>>,
+    }
+
+    /// Returns a handle to the shared h2 connection, dialing a new one when
+    /// there is none yet or the previous one has gone away.
+    pub(crate) async fn h2_connection(
+        shared: &tokio::sync::Mutex<Option<SendRequest<Bytes>>>,
+        address: &str,
+        port: u16,
+    ) -> Result<SendRequest<Bytes>, Error> {
+        let mut h2_connection = shared.lock().await;
+        if let Some(send_request) = h2_connection.clone() {
+            if let Ok(send_request) = send_request.ready().await {
+                return Ok(send_request);
+            }
+        }
+
+        let addr = create_socket_addr(address, port)?;
+        let connection = TcpStream::connect(addr).await?;
+        let (send_request, connection) = client::Builder::new()
+            .initial_window_size(H2_WINDOW_SIZE)
+            .initial_connection_window_size(H2_WINDOW_SIZE)
+            .handshake(connection)
+            .await?;
+        tokio::spawn(connection);
+        *h2_connection = Some(send_request.clone());
+        Ok(send_request.ready().await?)
+    }
+
+    impl HttpTunnel {
+        async fn run_h2(mut self, tunnel_end: UnixStream) -> Result<(), Error> {
+            let mut tunnel_end = tokio::net::UnixStream::from_std(tunnel_end)?;
+            let mut send_request = h2_connection(
+                &self.target.h2_connection,
+                self.target.address.as_str(),
+                self.target.port,
+            )
+            .await?;
+
+            let mut request = Request::post(format!(
+                "http://{}:{}{}",
+                self.target.address, self.target.port, self.target.path
+            ));
+            for (name, value) in self.target.headers.iter() {
+                request = request.header(name, value);
+            }
+            let request = match request.body(()) {
+                Ok(request) => request,
+                Err(e) => return Err(Error::Msg(format!("invalid request: {}", e))),
+            };
+            let (response, mut send) = send_request.send_request(request, false)?;
+
+            let forward = async {
+                while let Some(data) = self.request_body.recv().await {
+                    let mut data = Bytes::from(data);
+                    while !data.is_empty() {
+                        send.reserve_capacity(data.len());
+                        let capacity = match poll_fn(|cx| send.poll_capacity(cx)).await {
+                            Some(capacity) => capacity?,
+                            None => return Err(Error::Msg("stream closed".to_string())),
+                        };
+                        let chunk = data.split_to(capacity.min(data.len()));
+                        send.send_data(chunk, false)?;
+                    }
+                }
+                // the step was closed or dropped
+                send.send_data(Bytes::new(), true)?;
+                Ok::<(), Error>(())
+            };
+
+            let backward = async {
+                let response = response.await?;
+                if !response.status().is_success() {
+                    return Err(Error::Msg(format!(
+                        "unexpected response status {}",
+                        response.status()
+                    )));
+                }
+                let mut body = response.into_body();
+                while let Some(data) = body.data().await {
+                    let data = data?;
+                    tunnel_end.write_all(&data).await?;
+                    body.flow_control().release_capacity(data.len())?;
+                }
+                Ok::<(), Error>(())
+            };
+
+            tokio::try_join!(forward, backward)?;
+            Ok(())
+        }
+
+        async fn run_http1(mut self, tunnel_end: UnixStream) -> Result<(), Error> {
+            let mut tunnel_end = tokio::net::UnixStream::from_std(tunnel_end)?;
+            let addr = create_socket_addr(self.target.address.as_str(), self.target.port)?;
+            let mut connection = TcpStream::connect(addr).await?;
+
+            let mut head = format!(
+                "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/octet-stream\r\nTransfer-Encoding: chunked\r\n",
+                self.target.path, self.target.address, self.target.port
+            );
+            for (name, value) in self.target.headers.iter() {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str("\r\n");
+            connection.write_all(head.as_bytes()).await?;
+
+            let (mut reader, mut writer) = connection.split();
+            let forward = async {
+                while let Some(data) = self.request_body.recv().await {
+                    if !data.is_empty() {
+                        writer
+                            .write_all(format!("{:x}\r\n", data.len()).as_bytes())
+                            .await?;
+                        writer.write_all(&data).await?;
+                        writer.write_all(b"\r\n").await?;
+                    }
+                }
+                // the step was closed or dropped
+                writer.write_all(b"0\r\n\r\n").await?;
+                Ok::<(), Error>(())
+            };
+
+            let backward = async {
+                let (head, leftover) = match read_head(&mut reader).await? {
+                    Some(response) => response,
+                    None => return Err(Error::IoError(ErrorKind::UnexpectedEof.into())),
+                };
+                let head = ResponseHead::parse(&head)?;
+                if !(200..300).contains(&head.status) {
+                    return Err(Error::Msg(format!(
+                        "unexpected response status {}",
+                        head.status
+                    )));
+                }
+
+                let mut decoder = head.body;
+                tunnel_end.write_all(&decoder.decode(&leftover)?).await?;
+                let mut buffer = vec![0u8; 16 * 1024];
+                while !decoder.is_done() {
+                    let size = reader.read(&mut buffer).await?;
+                    if size == 0 {
+                        break;
+                    }
+                    tunnel_end
+                        .write_all(&decoder.decode(&buffer[0..size])?)
+                        .await?;
+                }
+                Ok::<(), Error>(())
+            };
+
+            tokio::try_join!(forward, backward)?;
+            Ok(())
+        }
+    }
+
+    impl Step for HttpStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            let body = match &self.request()?.body {
+                Some(body) => body,
+                None => return Err(Error::IoError(ErrorKind::BrokenPipe.into())),
+            };
+            // waits while the tunnel is behind on sending the body
+            let sent = RUNTIME.block_on(async {
+                tokio::time::timeout(WRITE_TIMEOUT, body.send(data.clone())).await
+            });
+            match sent {
+                Ok(Ok(())) => Ok(data.clone()),
+                Ok(Err(_)) => Err(Error::IoError(ErrorKind::BrokenPipe.into())),
+                Err(_) => Err(Error::IoError(ErrorKind::TimedOut.into())),
+            }
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            let mut read_buffer = vec![0u8; self.buffer_size];
+            let response_body = &mut self.request()?.response_body;
+            let mut result = Vec::new();
+            loop {
+                match response_body.read(&mut read_buffer) {
+                    // the tunnel task has finished
+                    Ok(0) if result.is_empty() => {
+                        return Err(Error::IoError(ErrorKind::ConnectionAborted.into()))
+                    }
+                    Ok(0) => break,
+                    Ok(read_size) => result.extend_from_slice(&read_buffer[0..read_size]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock && !result.is_empty() => break,
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+            Ok(result)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.request()?.body = None;
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for HttpStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl StepStatic for HttpStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(HTTP_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(HTTP_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(HTTP_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(HTTP_STEP_PORT.0.to_string())),
+            };
+            let path = match args.argument_values.get(HTTP_STEP_PATH.0) {
+                Some(path) => path[0].clone(),
+                None => return Err(Error::RequireOption(HTTP_STEP_PATH.0.to_string())),
+            };
+            let version = match args.argument_values.get(HTTP_STEP_VERSION.0) {
+                Some(version) => version[0].clone(),
+                None => return Err(Error::RequireOption(HTTP_STEP_VERSION.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let mut headers = Vec::new();
+            if let Some(values) = args.argument_values.get(HTTP_STEP_HEADER.0) {
+                for header in values {
+                    match header.split_once(':') {
+                        Some((name, value)) => {
+                            headers.push((name.trim().to_string(), value.trim().to_string()))
+                        }
+                        None => return Err(Error::Msg(format!("invalid header: {}", header))),
+                    }
+                }
+            }
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let version = match version.as_str() {
+                "2" => HttpVersion::H2,
+                "1.1" => HttpVersion::Http1,
+                other => return Err(Error::Msg(format!("unsupported http version: {}", other))),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let target = HttpTarget {
+                address,
+                port,
+                path,
+                headers,
+                version,
+                h2_connection: Arc::new(tokio::sync::Mutex::new(None)),
+            };
+            Ok(Self {
+                target,
+                request: OnceLock::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: HTTP_STEP_ADDRESS.0.to_string(),
+                key: vec![HTTP_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(HTTP_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: HTTP_STEP_PORT.0.to_string(),
+                key: vec![HTTP_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("80".to_string()),
+                help: Some(ArgumentHelp::Text(HTTP_STEP_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: HTTP_STEP_PATH.0.to_string(),
+                key: vec![HTTP_STEP_PATH.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("/".to_string()),
+                help: Some(ArgumentHelp::Text(HTTP_STEP_PATH.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: HTTP_STEP_HEADER.0.to_string(),
+                key: vec![HTTP_STEP_HEADER.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Multiple,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(HTTP_STEP_HEADER.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: HTTP_STEP_VERSION.0.to_string(),
+                key: vec![HTTP_STEP_VERSION.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("2".to_string()),
+                help: Some(ArgumentHelp::Text(HTTP_STEP_VERSION.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    impl Clone for HttpStep {
+        fn clone(&self) -> Self {
+            Self {
+                target: self.target.clone(),
+                request: OnceLock::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for HttpStep {
+        fn as_raw_fd(&self) -> RawFd {
+            match self.opened() {
+                Some(request) => request.response_body.as_raw_fd(),
+                None => -1,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn chunked() -> BodyDecoder {
+            BodyDecoder::Chunked {
+                state: ChunkState::Size,
+                pending: Vec::new(),
+            }
+        }
+
+        #[test]
+        fn length_body_stops_at_content_length() {
+            let mut decoder = BodyDecoder::Length(5);
+            assert_eq!(decoder.decode(b"hel").unwrap(), b"hel");
+            assert!(!decoder.is_done());
+            assert_eq!(decoder.decode(b"lo GET /").unwrap(), b"lo");
+            assert!(decoder.is_done());
+        }
+
+        #[test]
+        fn chunked_body_is_unframed() {
+            let mut decoder = chunked();
+            let data = decoder
+                .decode(b"5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n")
+                .unwrap();
+            assert_eq!(data, b"hello world");
+            assert!(decoder.is_done());
+        }
+
+        #[test]
+        fn chunked_body_split_at_every_byte() {
+            let body = b"3\r\nabc\r\na\r\n0123456789\r\n0\r\nTrailer: x\r\n\r\n";
+            let mut decoder = chunked();
+            let mut data = Vec::new();
+            for byte in body.iter() {
+                assert!(!decoder.is_done());
+                data.extend(decoder.decode(&[*byte]).unwrap());
+            }
+            assert_eq!(data, b"abc0123456789");
+            assert!(decoder.is_done());
+        }
+
+        #[test]
+        fn chunked_body_rejects_bad_framing() {
+            assert!(chunked().decode(b"zz\r\n").is_err());
+            assert!(chunked().decode(b"3\r\nabcXY").is_err());
+            assert!(chunked().decode(&vec![b'1'; MAX_HEAD_SIZE + 1]).is_err());
+        }
+
+        #[test]
+        fn framing_is_taken_from_headers() {
+            let headers = [httparse::Header {
+                name: "Transfer-Encoding",
+                value: b"gzip, chunked",
+            }];
+            let decoder = BodyDecoder::from_headers(&headers, BodyDecoder::UntilClose).unwrap();
+            assert!(matches!(decoder, BodyDecoder::Chunked { .. }));
+
+            let headers = [httparse::Header {
+                name: "content-length",
+                value: b" 12 ",
+            }];
+            let decoder = BodyDecoder::from_headers(&headers, BodyDecoder::UntilClose).unwrap();
+            assert!(matches!(decoder, BodyDecoder::Length(12)));
+
+            let decoder = BodyDecoder::from_headers(&[], BodyDecoder::Length(0)).unwrap();
+            assert!(decoder.is_done());
+        }
+    }
 }
@@ -0,0 +1,1214 @@
+pub mod kcp {
+    use std::collections::{BTreeMap, HashMap, VecDeque};
+    use std::io::{ErrorKind, Write};
+    use std::mem;
+    use std::net::{Shutdown, SocketAddr};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::net::UdpSocket;
+    use mio::unix::SourceFd;
+    use mio::{Events, Interest, Poll, Token};
+    use ring::rand::{SecureRandom, SystemRandom};
+
+    use crate::async_pipeline::async_pipeline::LazyStream;
+    use crate::mux::mux::serve_stream;
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const KCP_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "kcp-entry-address",
+        "--kcp-ea",
+        "(KcpEntry) Kcp Entry listen address",
+    );
+    const KCP_ENTRY_PORT: (&str, &str, &str) = (
+        "kcp-entry-port",
+        "--kcp-ep",
+        "(KcpEntry) Kcp Entry listen port",
+    );
+    const KCP_STEP_ADDRESS: (&str, &str, &str) = (
+        "kcp-step-address",
+        "--kcp-sa",
+        "(KcpStep) Kcp Step address of the kcp entry",
+    );
+    const KCP_STEP_PORT: (&str, &str, &str) = (
+        "kcp-step-port",
+        "--kcp-sp",
+        "(KcpStep) Kcp Step port of the kcp entry",
+    );
+
+    const KCP_MODE: (&str, &str, &str) = (
+        "kcp-mode",
+        "--kcp-mode",
+        "(KcpEntry, KcpStep) How eagerly lost segments are sent again: normal, fast or turbo (ignores congestion)",
+    );
+    const KCP_WINDOW: (&str, &str, &str) = (
+        "kcp-window",
+        "--kcp-window",
+        "(KcpEntry, KcpStep) Segments in flight and waiting to be read, each way",
+    );
+    const KCP_MTU: (&str, &str, &str) = (
+        "kcp-mtu",
+        "--kcp-mtu",
+        "(KcpEntry, KcpStep) Largest datagram sent",
+    );
+    const KCP_TIMEOUT: (&str, &str, &str) = (
+        "kcp-timeout",
+        "--kcp-timeout",
+        "(KcpEntry, KcpStep) Seconds of silence after which the peer is given up",
+    );
+    const KCP_LOSS: (&str, &str, &str) = (
+        "kcp-loss",
+        "--kcp-loss",
+        "(KcpEntry, KcpStep) Percent of outgoing datagrams to drop, for testing",
+    );
+
+    // every datagram starts with the session's conversation id, a command,
+    // the sender's receive window and the next segment it expects
+    const HEADER_SIZE: usize = 11;
+    // PUSH and FIN go on with their sequence number and send time, ACK with
+    // such pairs for every segment it acknowledges
+    const SEGMENT_HEADER_SIZE: usize = 8;
+    const PUSH: u8 = 0;
+    const FIN: u8 = 1;
+    const ACK: u8 = 2;
+    const PING: u8 = 3;
+    const CLOSE: u8 = 4;
+
+    const SOCKET: Token = Token(0);
+    const FIRST_SESSION: usize = 1;
+
+    const INITIAL_RTO: u32 = 200;
+    const MAX_RTO: u32 = 60_000;
+    /// A segment sent this many times without an ack means the link is gone.
+    const DEAD_LINK: u32 = 20;
+    const PROBE_INTERVAL: u32 = 1_000;
+    const KEEPALIVE_INTERVAL: u32 = 5_000;
+    /// A finished session stays around to answer retransmissions of the end.
+    const LINGER: u32 = 3_000;
+
+    #[derive(Clone, Copy)]
+    struct KcpConfig {
+        /// Milliseconds between flushes when nothing else happens.
+        interval: u32,
+        min_rto: u32,
+        /// Grow a segment's timeout by half on every resend instead of
+        /// doubling it.
+        gentle_backoff: bool,
+        /// Resend a segment once this many later ones were acknowledged,
+        /// 0 waits for its timeout.
+        fast_resend: u32,
+        congestion: bool,
+        window: u32,
+        mtu: usize,
+        timeout: u32,
+        loss: u32,
+    }
+
+    fn parse_config(args: &CliParsed) -> Result<KcpConfig, Error> {
+        let mode = match args.argument_values.get(KCP_MODE.0) {
+            Some(mode) => mode[0].clone(),
+            None => return Err(Error::RequireOption(KCP_MODE.0.to_string())),
+        };
+        let window = match args.argument_values.get(KCP_WINDOW.0) {
+            Some(window) => window[0].clone(),
+            None => return Err(Error::RequireOption(KCP_WINDOW.0.to_string())),
+        };
+        let mtu = match args.argument_values.get(KCP_MTU.0) {
+            Some(mtu) => mtu[0].clone(),
+            None => return Err(Error::RequireOption(KCP_MTU.0.to_string())),
+        };
+        let timeout = match args.argument_values.get(KCP_TIMEOUT.0) {
+            Some(timeout) => timeout[0].clone(),
+            None => return Err(Error::RequireOption(KCP_TIMEOUT.0.to_string())),
+        };
+        let loss = match args.argument_values.get(KCP_LOSS.0) {
+            Some(loss) => loss[0].clone(),
+            None => return Err(Error::RequireOption(KCP_LOSS.0.to_string())),
+        };
+
+        let (interval, min_rto, gentle_backoff, fast_resend, congestion) = match mode.as_str() {
+            "normal" => (40, 100, false, 0, true),
+            "fast" => (20, 30, true, 2, true),
+            "turbo" => (10, 30, true, 2, false),
+            _ => return Err(Error::Msg(format!("unknown kcp mode: {}", mode))),
+        };
+        let window = match str::parse::<u32>(window.as_str()) {
+            Ok(window) if (1..=u16::MAX as u32).contains(&window) => window,
+            _ => return Err(Error::ParseIntError),
+        };
+        let mtu = match str::parse::<usize>(mtu.as_str()) {
+            Ok(mtu) if mtu > HEADER_SIZE + SEGMENT_HEADER_SIZE => mtu,
+            _ => return Err(Error::ParseIntError),
+        };
+        let timeout = match str::parse::<u32>(timeout.as_str()) {
+            Ok(timeout) => timeout.saturating_mul(1000),
+            Err(_) => return Err(Error::ParseIntError),
+        };
+        let loss = match str::parse::<u32>(loss.as_str()) {
+            Ok(loss) if loss <= 100 => loss,
+            _ => return Err(Error::ParseIntError),
+        };
+
+        Ok(KcpConfig {
+            interval,
+            min_rto,
+            gentle_backoff,
+            fast_resend,
+            congestion,
+            window,
+            mtu,
+            timeout,
+            loss,
+        })
+    }
+
+    fn packet_header(conv: u32, cmd: u8, window: u32, una: u32) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&conv.to_be_bytes());
+        packet.push(cmd);
+        packet.extend_from_slice(&(window.min(u16::MAX as u32) as u16).to_be_bytes());
+        packet.extend_from_slice(&una.to_be_bytes());
+        packet
+    }
+
+    fn read_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    struct Segment {
+        sn: u32,
+        fin: bool,
+        data: Vec<u8>,
+        /// When it was last sent, echoed back by its ack.
+        ts: u32,
+        resend_at: u32,
+        rto: u32,
+        transmits: u32,
+        /// Later segments acknowledged since it was last sent.
+        fast_acks: u32,
+    }
+
+    impl Segment {
+        fn new(data: Vec<u8>, fin: bool) -> Self {
+            Self {
+                sn: 0,
+                fin,
+                data,
+                ts: 0,
+                resend_at: 0,
+                rto: 0,
+                transmits: 0,
+                fast_acks: 0,
+            }
+        }
+    }
+
+    /// The retransmission state of one session, fed datagrams and time and
+    /// leaving the datagrams to send in `output`.
+    struct Arq {
+        conv: u32,
+        config: KcpConfig,
+        mss: usize,
+        send_queue: VecDeque<Segment>,
+        in_flight: BTreeMap<u32, Segment>,
+        snd_una: u32,
+        snd_nxt: u32,
+        fin_queued: bool,
+        remote_window: u32,
+        cwnd: u32,
+        ssthresh: u32,
+        /// Bytes the window grew by, `cwnd` follows it in whole segments.
+        incr: u32,
+        srtt: u32,
+        rttvar: u32,
+        rto: u32,
+        rcv_nxt: u32,
+        /// Segments that arrived ahead of `rcv_nxt`, a FIN has no data.
+        rcv_buf: BTreeMap<u32, Option<Vec<u8>>>,
+        /// In order, waiting to be read.
+        delivered: Vec<u8>,
+        remote_fin: bool,
+        acks: Vec<(u32, u32)>,
+        reply: bool,
+        ping_at: u32,
+        last_heard: u32,
+        reset: bool,
+        dead: bool,
+        output: Vec<Vec<u8>>,
+    }
+
+    impl Arq {
+        fn new(conv: u32, config: KcpConfig, now: u32) -> Self {
+            let mss = config.mtu - HEADER_SIZE - SEGMENT_HEADER_SIZE;
+            Self {
+                conv,
+                config,
+                mss,
+                send_queue: VecDeque::new(),
+                in_flight: BTreeMap::new(),
+                snd_una: 0,
+                snd_nxt: 0,
+                fin_queued: false,
+                remote_window: config.window,
+                cwnd: 1,
+                ssthresh: config.window,
+                incr: mss as u32,
+                srtt: 0,
+                rttvar: 0,
+                rto: INITIAL_RTO,
+                rcv_nxt: 0,
+                rcv_buf: BTreeMap::new(),
+                delivered: Vec::new(),
+                remote_fin: false,
+                acks: Vec::new(),
+                reply: false,
+                ping_at: now,
+                last_heard: now,
+                reset: false,
+                dead: false,
+                output: Vec::new(),
+            }
+        }
+
+        fn send(&mut self, data: &[u8]) {
+            for chunk in data.chunks(self.mss) {
+                self.send_queue
+                    .push_back(Segment::new(chunk.to_vec(), false));
+            }
+        }
+
+        fn close(&mut self) {
+            self.fin_queued = true;
+            self.send_queue.push_back(Segment::new(Vec::new(), true));
+        }
+
+        fn finished(&self) -> bool {
+            self.fin_queued
+                && self.send_queue.is_empty()
+                && self.in_flight.is_empty()
+                && self.remote_fin
+        }
+
+        fn receive_window(&self) -> u32 {
+            let waiting = self.rcv_buf.len() + self.delivered.len().div_ceil(self.mss);
+            self.config.window.saturating_sub(waiting as u32)
+        }
+
+        fn header(&self, cmd: u8) -> Vec<u8> {
+            packet_header(self.conv, cmd, self.receive_window(), self.rcv_nxt)
+        }
+
+        fn input(&mut self, packet: &[u8], now: u32) {
+            if packet.len() < HEADER_SIZE {
+                return;
+            }
+            let cmd = packet[4];
+            let body = &packet[HEADER_SIZE..];
+            self.last_heard = now;
+            self.remote_window = u16::from_be_bytes([packet[5], packet[6]]) as u32;
+            let una = self.snd_una;
+            self.acknowledge_until(read_u32(&packet[7..]));
+
+            match cmd {
+                PUSH | FIN if body.len() >= SEGMENT_HEADER_SIZE => {
+                    let sn = read_u32(body);
+                    // Segments beyond the window are dropped unacknowledged so
+                    // that the peer sends them again later.
+                    if sn >= self.rcv_nxt + self.config.window {
+                        return;
+                    }
+                    self.acks.push((sn, read_u32(&body[4..])));
+                    if !self.remote_fin && sn >= self.rcv_nxt {
+                        self.rcv_buf.entry(sn).or_insert_with(|| match cmd {
+                            PUSH => Some(body[SEGMENT_HEADER_SIZE..].to_vec()),
+                            _ => None,
+                        });
+                        while let Some(segment) = self.rcv_buf.remove(&self.rcv_nxt) {
+                            self.rcv_nxt += 1;
+                            match segment {
+                                Some(data) => self.delivered.extend_from_slice(&data),
+                                None => {
+                                    self.remote_fin = true;
+                                    self.rcv_buf.clear();
+                                }
+                            }
+                        }
+                    }
+                }
+                ACK => {
+                    let mut newest = None;
+                    for pair in body.chunks_exact(SEGMENT_HEADER_SIZE) {
+                        let sn = read_u32(pair);
+                        let ts = read_u32(&pair[4..]);
+                        if now >= ts {
+                            self.update_rtt(now - ts);
+                        }
+                        if self.in_flight.remove(&sn).is_some() {
+                            newest = newest.max(Some(sn));
+                        }
+                    }
+                    if let Some(newest) = newest {
+                        for segment in self.in_flight.range_mut(..newest).map(|(_, s)| s) {
+                            segment.fast_acks += 1;
+                        }
+                    }
+                    self.acknowledge_until(0);
+                }
+                PING => self.reply = true,
+                CLOSE => self.reset = true,
+                _ => {}
+            }
+
+            if self.config.congestion && self.snd_una > una {
+                self.grow_window();
+            }
+        }
+
+        /// Drops what the peer has up to `una` and moves `snd_una` to the
+        /// oldest segment still in flight.
+        fn acknowledge_until(&mut self, una: u32) {
+            while let Some((&sn, _)) = self.in_flight.first_key_value() {
+                if sn >= una {
+                    break;
+                }
+                self.in_flight.pop_first();
+            }
+            self.snd_una = match self.in_flight.first_key_value() {
+                Some((&sn, _)) => sn,
+                None => self.snd_nxt,
+            };
+        }
+
+        fn update_rtt(&mut self, rtt: u32) {
+            if self.srtt == 0 {
+                self.srtt = rtt.max(1);
+                self.rttvar = rtt / 2;
+            } else {
+                let delta = rtt.abs_diff(self.srtt);
+                self.rttvar = (3 * self.rttvar + delta) / 4;
+                self.srtt = ((7 * self.srtt + rtt) / 8).max(1);
+            }
+            self.rto = (self.srtt + self.config.interval.max(4 * self.rttvar))
+                .clamp(self.config.min_rto, MAX_RTO);
+        }
+
+        /// Slow start below `ssthresh`, then about one segment per window.
+        fn grow_window(&mut self) {
+            if self.cwnd >= self.remote_window {
+                return;
+            }
+            let mss = self.mss as u32;
+            if self.cwnd < self.ssthresh {
+                self.cwnd += 1;
+                self.incr += mss;
+            } else {
+                self.incr = self.incr.max(mss);
+                self.incr += mss * mss / self.incr + mss / 16;
+                if (self.cwnd + 1) * mss <= self.incr {
+                    self.cwnd = self.incr.div_ceil(mss);
+                }
+            }
+            if self.cwnd > self.remote_window.max(1) {
+                self.cwnd = self.remote_window.max(1);
+                self.incr = self.cwnd * mss;
+            }
+        }
+
+        fn flush(&mut self, now: u32) {
+            let mut packet = self.header(ACK);
+            for (sn, ts) in mem::take(&mut self.acks) {
+                if packet.len() + SEGMENT_HEADER_SIZE > self.config.mtu {
+                    self.output
+                        .push(mem::replace(&mut packet, self.header(ACK)));
+                }
+                packet.extend_from_slice(&sn.to_be_bytes());
+                packet.extend_from_slice(&ts.to_be_bytes());
+            }
+            if packet.len() > HEADER_SIZE || self.reply {
+                self.output.push(packet);
+                self.reply = false;
+            }
+
+            // probe a closed window, and make an idle peer answer
+            let idle = now.saturating_sub(self.last_heard) >= KEEPALIVE_INTERVAL;
+            if (self.remote_window == 0 || idle) && now >= self.ping_at {
+                self.output.push(self.header(PING));
+                self.ping_at = now + PROBE_INTERVAL;
+            }
+
+            let mut window = self.config.window.min(self.remote_window);
+            if self.config.congestion {
+                window = window.min(self.cwnd);
+            }
+            while self.snd_nxt < self.snd_una + window {
+                let Some(mut segment) = self.send_queue.pop_front() else {
+                    break;
+                };
+                segment.sn = self.snd_nxt;
+                self.snd_nxt += 1;
+                self.in_flight.insert(segment.sn, segment);
+            }
+
+            let (wnd, una) = (self.receive_window(), self.rcv_nxt);
+            let (mut lost, mut resent) = (false, false);
+            for segment in self.in_flight.values_mut() {
+                if segment.transmits == 0 {
+                    segment.rto = self.rto;
+                } else if now >= segment.resend_at {
+                    lost = true;
+                    segment.rto += match self.config.gentle_backoff {
+                        true => segment.rto / 2,
+                        false => segment.rto.max(self.rto),
+                    };
+                    segment.rto = segment.rto.min(MAX_RTO);
+                } else if self.config.fast_resend > 0
+                    && segment.fast_acks >= self.config.fast_resend
+                {
+                    resent = true;
+                } else {
+                    continue;
+                }
+
+                segment.transmits += 1;
+                segment.fast_acks = 0;
+                segment.ts = now;
+                segment.resend_at = now + segment.rto;
+                let cmd = if segment.fin { FIN } else { PUSH };
+                let mut packet = packet_header(self.conv, cmd, wnd, una);
+                packet.extend_from_slice(&segment.sn.to_be_bytes());
+                packet.extend_from_slice(&segment.ts.to_be_bytes());
+                packet.extend_from_slice(&segment.data);
+                self.output.push(packet);
+                if segment.transmits >= DEAD_LINK {
+                    self.dead = true;
+                }
+            }
+
+            if self.config.congestion {
+                if resent {
+                    self.ssthresh = ((self.snd_nxt - self.snd_una) / 2).max(2);
+                    self.cwnd = self.ssthresh + self.config.fast_resend;
+                    self.incr = self.cwnd * self.mss as u32;
+                }
+                if lost {
+                    self.ssthresh = (self.cwnd / 2).max(2);
+                    self.cwnd = 1;
+                    self.incr = self.mss as u32;
+                }
+            }
+        }
+    }
+
+    /// A session and the client or upstream holding the other end of `local`.
+    struct KcpSession {
+        arq: Arq,
+        local: UnixStream,
+        peer: SocketAddr,
+        readable: bool,
+        write_closed: bool,
+        finished_at: Option<u32>,
+    }
+
+    impl KcpSession {
+        /// Moves data between `local` and the peer, returning whether the
+        /// session is over.
+        fn pump(
+            &mut self,
+            now: u32,
+            buffer_size: usize,
+            transmit: &mut dyn FnMut(&[u8], SocketAddr),
+        ) -> Result<bool, Error> {
+            if self.arq.reset {
+                return Err(Error::Msg("peer closed the session".to_string()));
+            }
+            if self.arq.dead || now.saturating_sub(self.arq.last_heard) >= self.arq.config.timeout {
+                return Err(Error::Msg("peer stopped answering".to_string()));
+            }
+
+            if self.finished_at.is_none() {
+                let window = self.arq.config.window as usize;
+                while self.readable && !self.arq.fin_queued && self.arq.send_queue.len() < window {
+                    match read_available(&mut self.local, buffer_size) {
+                        Ok(data) => self.arq.send(&data),
+                        Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                            self.readable = false
+                        }
+                        Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                            self.arq.close()
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                while !self.arq.delivered.is_empty() {
+                    match self.local.write(&self.arq.delivered) {
+                        Ok(size) => {
+                            self.arq.delivered.drain(..size);
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(Error::IoError(e)),
+                    }
+                }
+            }
+
+            self.arq.flush(now);
+            for packet in self.arq.output.drain(..) {
+                transmit(&packet, self.peer);
+            }
+
+            // only once the peer was told its FIN arrived, our side may see
+            // the end and let the process exit
+            if self.arq.remote_fin && self.arq.delivered.is_empty() && !self.write_closed {
+                let _ = self.local.shutdown(Shutdown::Write);
+                self.write_closed = true;
+            }
+            if self.arq.finished() && self.write_closed && self.finished_at.is_none() {
+                self.finished_at = Some(now);
+            }
+            Ok(matches!(self.finished_at, Some(at) if now - at >= LINGER))
+        }
+    }
+
+    type OnOpen = Box<dyn FnMut(UnixStream, SocketAddr) + Send>;
+
+    /// Runs the sessions of one UDP socket on its own thread. An entry's
+    /// endpoint opens a session for every new peer through `on_open`, a
+    /// step's runs the one it was given and ends with it.
+    struct KcpEndpoint {
+        socket: UdpSocket,
+        poll: Poll,
+        sessions: HashMap<Token, KcpSession>,
+        peers: HashMap<(SocketAddr, u32), Token>,
+        next_token: usize,
+        on_open: Option<OnOpen>,
+        config: KcpConfig,
+        started: Instant,
+        /// State of the generator deciding which datagrams to drop.
+        random: u64,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl KcpEndpoint {
+        fn new(
+            socket: UdpSocket,
+            on_open: Option<OnOpen>,
+            config: KcpConfig,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) -> Result<Self, Error> {
+            let seed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or_default();
+            Ok(Self {
+                socket,
+                poll: Poll::new()?,
+                sessions: HashMap::new(),
+                peers: HashMap::new(),
+                next_token: FIRST_SESSION,
+                on_open,
+                config,
+                started: Instant::now(),
+                random: seed | 1,
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn now(&self) -> u32 {
+            self.started.elapsed().as_millis() as u32
+        }
+
+        fn add_session(
+            &mut self,
+            local: UnixStream,
+            peer: SocketAddr,
+            conv: u32,
+        ) -> Result<Token, Error> {
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            self.poll.registry().register(
+                &mut SourceFd(&local.as_raw_fd()),
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            let session = KcpSession {
+                arq: Arq::new(conv, self.config, self.now()),
+                local,
+                peer,
+                readable: true,
+                write_closed: false,
+                finished_at: None,
+            };
+            self.sessions.insert(token, session);
+            self.peers.insert((peer, conv), token);
+            Ok(token)
+        }
+
+        fn run(&mut self) -> Result<(), Error> {
+            self.poll
+                .registry()
+                .register(&mut self.socket, SOCKET, Interest::READABLE)?;
+            let mut events = Events::with_capacity(1024);
+            let mut buffer = vec![0u8; u16::MAX as usize];
+            let interval = std::time::Duration::from_millis(self.config.interval as u64);
+            loop {
+                if let Err(e) = self.poll.poll(&mut events, Some(interval)) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+
+                for event in events.iter() {
+                    match event.token() {
+                        SOCKET => self.receive(&mut buffer)?,
+                        token => {
+                            if let Some(session) = self.sessions.get_mut(&token) {
+                                session.readable |= event.is_readable();
+                            }
+                        }
+                    }
+                }
+
+                self.pump();
+                if self.on_open.is_none() && self.sessions.is_empty() {
+                    return Ok(());
+                }
+            }
+        }
+
+        fn receive(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+            loop {
+                let (size, peer) = match self.socket.recv_from(buffer) {
+                    Ok(received) => received,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                    Err(e) => return Err(Error::IoError(e)),
+                };
+                if size < HEADER_SIZE {
+                    continue;
+                }
+                let packet = &buffer[..size];
+                let conv = read_u32(packet);
+                let token = match self.peers.get(&(peer, conv)) {
+                    Some(token) => *token,
+                    None => match self.accept(packet, peer, conv)? {
+                        Some(token) => token,
+                        None => continue,
+                    },
+                };
+                let now = self.now();
+                if let Some(session) = self.sessions.get_mut(&token) {
+                    session.arq.input(packet, now);
+                }
+            }
+        }
+
+        /// Opens a session for the first segments of a new peer, and tells
+        /// peers of a session that is gone to stop.
+        fn accept(
+            &mut self,
+            packet: &[u8],
+            peer: SocketAddr,
+            conv: u32,
+        ) -> Result<Option<Token>, Error> {
+            if self.on_open.is_none() {
+                return Ok(None);
+            }
+            let cmd = packet[4];
+            let opening = (cmd == PUSH || cmd == FIN)
+                && packet.len() >= HEADER_SIZE + SEGMENT_HEADER_SIZE
+                && read_u32(&packet[HEADER_SIZE..]) < self.config.window;
+            if !opening {
+                if cmd != CLOSE {
+                    let _ = self.socket.send_to(&packet_header(conv, CLOSE, 0, 0), peer);
+                }
+                return Ok(None);
+            }
+
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let token = self.add_session(inner, peer, conv)?;
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("new client: {}", peer);
+                }
+            }
+
+            if let Some(on_open) = self.on_open.as_mut() {
+                on_open(outer, peer);
+            }
+            Ok(Some(token))
+        }
+
+        fn pump(&mut self) {
+            let now = self.now();
+            let Self {
+                socket,
+                sessions,
+                random,
+                config,
+                debug_level,
+                buffer_size,
+                ..
+            } = self;
+            let mut transmit = |packet: &[u8], peer: SocketAddr| {
+                // xorshift, good enough to pick datagrams to lose
+                *random ^= *random << 13;
+                *random ^= *random >> 7;
+                *random ^= *random << 17;
+                if (*random % 100) < config.loss as u64 {
+                    return;
+                }
+                // a full socket buffer is just more loss to recover from
+                let _ = socket.send_to(packet, peer);
+            };
+
+            let mut over = Vec::new();
+            for (token, session) in sessions.iter_mut() {
+                match session.pump(now, *buffer_size, &mut transmit) {
+                    Ok(true) => over.push(*token),
+                    Ok(false) => {}
+                    Err(e) => {
+                        if *debug_level > 0 {
+                            eprintln!("an error accured on session of {}: {}", session.peer, e);
+                        }
+                        if !session.arq.reset {
+                            transmit(&packet_header(session.arq.conv, CLOSE, 0, 0), session.peer);
+                        }
+                        over.push(*token);
+                    }
+                }
+            }
+
+            for token in over {
+                if let Some(session) = self.sessions.remove(&token) {
+                    self.peers.remove(&(session.peer, session.arq.conv));
+                    let _ = self
+                        .poll
+                        .registry()
+                        .deregister(&mut SourceFd(&session.local.as_raw_fd()));
+
+                    {
+                        // debug
+                        if self.debug_level >= 2 {
+                            eprintln!("client {} closed", session.peer);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Accepts `KcpStep` sessions over UDP and serves each with its own copy
+    /// of the pipeline.
+    pub struct KcpEntry {
+        address: String,
+        port: u16,
+        config: KcpConfig,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for KcpEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let socket = UdpSocket::bind(addr)?;
+            let local = socket.local_addr()?;
+
+            let handle = runtime.handle().clone();
+            let pipeline_template = self.pipeline_template.clone();
+            let debug_level = self.debug_level;
+            let buffer_size = self.buffer_size;
+            let on_open: OnOpen = Box::new(move |stream, peer| {
+                let info = ConnectionInfo {
+                    peer: Some(peer),
+                    local: Some(local),
+                    destination: None,
+                };
+                let pipeline_template = pipeline_template.clone();
+                handle.spawn(async move {
+                    if let Err(e) = serve_stream(stream, info, pipeline_template, buffer_size).await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving session of {}: {}", peer, e);
+                        }
+                    }
+                });
+            });
+
+            KcpEndpoint::new(
+                socket,
+                Some(on_open),
+                self.config,
+                self.debug_level,
+                self.buffer_size,
+            )?
+            .run()
+        }
+    }
+
+    impl EntryStatic<KcpEntry> for KcpEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<KcpEntry, Error> {
+            let address = match args.argument_values.get(KCP_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(KCP_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(KCP_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(KCP_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(KcpEntry {
+                address,
+                port,
+                config: parse_config(&args)?,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: KCP_ENTRY_ADDRESS.0.to_string(),
+                key: vec![KCP_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: KCP_ENTRY_PORT.0.to_string(),
+                key: vec![KCP_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("4000".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: KCP_MODE.0.to_string(),
+                key: vec![KCP_MODE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("fast".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_MODE.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: KCP_WINDOW.0.to_string(),
+                key: vec![KCP_WINDOW.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("256".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_WINDOW.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: KCP_MTU.0.to_string(),
+                key: vec![KCP_MTU.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("1400".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_MTU.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: KCP_TIMEOUT.0.to_string(),
+                key: vec![KCP_TIMEOUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("30".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_TIMEOUT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: KCP_LOSS.0.to_string(),
+                key: vec![KCP_LOSS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_LOSS.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Carries its client as one session over UDP to a `KcpEntry`.
+    pub struct KcpStep {
+        address: String,
+        port: u16,
+        config: KcpConfig,
+        /// The local end of this client's session and its own socket.
+        stream: LazyStream,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl KcpStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let peer = create_socket_addr(self.address.as_str(), self.port)?;
+            let bind = match peer {
+                SocketAddr::V4(_) => "0.0.0.0:0",
+                SocketAddr::V6(_) => "[::]:0",
+            };
+            let socket = UdpSocket::bind(bind.parse()?)?;
+            let mut conv = [0u8; 4];
+            SystemRandom::new()
+                .fill(&mut conv)
+                .map_err(|_| Error::Msg("no randomness available for a session".to_string()))?;
+
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let mut endpoint = KcpEndpoint::new(
+                socket,
+                None,
+                self.config,
+                self.debug_level,
+                self.buffer_size,
+            )?;
+            endpoint.add_session(inner, peer, u32::from_be_bytes(conv))?;
+
+            let debug_level = self.debug_level;
+            thread::spawn(move || {
+                if let Err(e) = endpoint.run() {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on kcp session: {}", e);
+                    }
+                }
+            });
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("kcp session", self.debug_level, || self.open())
+        }
+    }
+
+    impl Step for KcpStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_all(&mut self.stream()?, data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.stream()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for KcpStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl StepStatic for KcpStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(KCP_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(KCP_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(KCP_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(KCP_STEP_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Self {
+                address,
+                port,
+                config: parse_config(&args)?,
+                stream: LazyStream::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: KCP_STEP_ADDRESS.0.to_string(),
+                key: vec![KCP_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: KCP_STEP_PORT.0.to_string(),
+                key: vec![KCP_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("4000".to_string()),
+                help: Some(ArgumentHelp::Text(KCP_STEP_PORT.2.to_string())),
+            });
+            // the session options are shared with KcpEntry, which adds them
+            argument
+        }
+    }
+
+    impl Clone for KcpStep {
+        fn clone(&self) -> Self {
+            Self {
+                address: self.address.clone(),
+                port: self.port,
+                config: self.config,
+                stream: LazyStream::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for KcpStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const CONFIG: KcpConfig = KcpConfig {
+            interval: 10,
+            min_rto: 30,
+            gentle_backoff: true,
+            fast_resend: 2,
+            congestion: true,
+            window: 128,
+            mtu: 1400,
+            timeout: 30_000,
+            loss: 0,
+        };
+
+        /// Moves everything `from` sent to `to`, dropping `loss` percent.
+        fn carry(from: &mut Arq, to: &mut Arq, loss: u64, random: &mut u64, now: u32) {
+            for packet in mem::take(&mut from.output) {
+                *random ^= *random << 13;
+                *random ^= *random >> 7;
+                *random ^= *random << 17;
+                if *random % 100 < loss {
+                    continue;
+                }
+                to.input(&packet, now);
+            }
+        }
+
+        /// Sends `data` both ways between two sessions that close once it is
+        /// queued, and returns what each side read.
+        fn transfer(data: &[u8], loss: u64) -> (Vec<u8>, Vec<u8>) {
+            let (mut a, mut b) = (Arq::new(1, CONFIG, 0), Arq::new(1, CONFIG, 0));
+            a.send(data);
+            a.close();
+            b.send(data);
+            b.close();
+            let (mut read_a, mut read_b) = (Vec::new(), Vec::new());
+            let mut random = 0x2545_f491_4f6c_dd1d;
+            let mut now = 0;
+            while !(a.finished() && b.finished()) {
+                assert!(now < 600_000, "transfer stalled");
+                assert!(!a.dead && !b.dead);
+                a.flush(now);
+                b.flush(now);
+                carry(&mut a, &mut b, loss, &mut random, now);
+                carry(&mut b, &mut a, loss, &mut random, now);
+                read_a.append(&mut a.delivered);
+                read_b.append(&mut b.delivered);
+                now += CONFIG.interval;
+            }
+            (read_a, read_b)
+        }
+
+        fn data(size: usize) -> Vec<u8> {
+            (0..size).map(|n| (n % 251) as u8).collect()
+        }
+
+        #[test]
+        fn lossless_transfer() {
+            let data = data(256 * 1024);
+            let (read_a, read_b) = transfer(&data, 0);
+            assert_eq!(read_a, data);
+            assert_eq!(read_b, data);
+        }
+
+        #[test]
+        fn transfer_with_loss() {
+            let data = data(256 * 1024);
+            let (read_a, read_b) = transfer(&data, 20);
+            assert_eq!(read_a, data);
+            assert_eq!(read_b, data);
+        }
+
+        #[test]
+        fn reordered_segments_are_delivered_once_in_order() {
+            let config = KcpConfig {
+                congestion: false,
+                ..CONFIG
+            };
+            let (mut a, mut b) = (Arq::new(1, config, 0), Arq::new(1, config, 0));
+            let data = data(4 * a.mss);
+            a.send(&data);
+            a.flush(0);
+            let packets = mem::take(&mut a.output);
+            assert_eq!(packets.len(), 4);
+
+            // backwards and twice over
+            for packet in packets.iter().rev().chain(packets.iter()) {
+                b.input(packet, 0);
+            }
+            assert_eq!(b.delivered, data);
+            assert!(b.rcv_buf.is_empty());
+            assert_eq!(b.rcv_nxt, 4);
+        }
+    }
+}
@@ -13,7 +13,7 @@ use std::{
 };
 
 pub use base::base::{
-    BoxedClone, DebugLevel, Entry, EntryStatic, Error, Pipeline, Step, StepStatic,
+    BoxedClone, ConnectionInfo, DebugLevel, Entry, EntryStatic, Error, Pipeline, Step, StepStatic,
 };
 
 mod stdio;
@@ -36,5 +36,60 @@ pub fn create_socket_addr(address: &str, port: u16) -> Result<SocketAddr, Error>
     }
 }
 
+mod async_pipeline;
+
+mod proxy_protocol;
+
 mod http;
-pub use http::http::HttpEntry;
+pub use http::http::{HttpEntry, HttpStep};
+
+mod ws;
+pub use ws::ws::{WsEntry, WsStep};
+
+mod connect;
+pub use connect::connect::ConnectEntry;
+
+mod socks5;
+pub use socks5::socks5::Socks5Entry;
+
+mod proxy;
+pub use proxy::proxy::{HttpConnectStep, Socks5Step};
+
+mod exec;
+pub use exec::exec::{ExecEntry, ExecStep};
+
+mod file;
+pub use file::file::{FileEntry, FileStep};
+
+mod quic;
+pub use quic::quic::{QuicEntry, QuicStep};
+
+mod mux;
+pub use mux::mux::{DemuxEntry, MuxStep};
+
+mod reverse;
+pub use reverse::reverse::{AgentEntry, RelayEntry};
+
+mod resume;
+pub use resume::resume::{ResumeEntry, ResumeStep};
+
+mod kcp;
+pub use kcp::kcp::{KcpEntry, KcpStep};
+
+mod fec;
+pub use fec::fec::{FecEntry, FecStep};
+
+mod udp;
+pub use udp::udp::{Tcp2UdpStep, Udp2TcpStep, UdpEntry};
+
+mod dns;
+pub use dns::dns::{DnsEntry, DnsStep};
+
+mod longpoll;
+pub use longpoll::longpoll::{LongPollEntry, LongPollStep};
+
+mod grpc;
+pub use grpc::grpc::{GrpcEntry, GrpcStep};
+
+mod ssh;
+pub use ssh::ssh::SshStep;
@@ -0,0 +1,1021 @@
+pub mod longpoll {
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::net::{Shutdown, SocketAddr};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use lazy_static::lazy_static;
+    use ring::rand::{SecureRandom, SystemRandom};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+    use tokio::sync::oneshot;
+    use tokio::time::{sleep, sleep_until, timeout, Instant};
+
+    use crate::async_pipeline::async_pipeline::{
+        read_pipeline, write_pipeline, LazyStream, PipelineFd,
+    };
+    use crate::http::http::{read_head, BodyDecoder, ResponseHead};
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const LONGPOLL_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "longpoll-entry-address",
+        "--longpoll-ea",
+        "(LongPollEntry) LongPoll Entry listen address",
+    );
+    const LONGPOLL_ENTRY_PORT: (&str, &str, &str) = (
+        "longpoll-entry-port",
+        "--longpoll-ep",
+        "(LongPollEntry) LongPoll Entry listen port",
+    );
+    const LONGPOLL_ENTRY_HOLD: (&str, &str, &str) = (
+        "longpoll-entry-hold",
+        "--longpoll-ehold",
+        "(LongPollEntry) Seconds a poll waits for data before it is answered empty, 0 answers at once",
+    );
+
+    const LONGPOLL_STEP_ADDRESS: (&str, &str, &str) = (
+        "longpoll-step-address",
+        "--longpoll-sa",
+        "(LongPollStep) LongPoll step endpoint address",
+    );
+    const LONGPOLL_STEP_PORT: (&str, &str, &str) = (
+        "longpoll-step-port",
+        "--longpoll-sp",
+        "(LongPollStep) LongPoll step endpoint port",
+    );
+    const LONGPOLL_STEP_PATH: (&str, &str, &str) = (
+        "longpoll-step-path",
+        "--longpoll-spath",
+        "(LongPollStep) Path of the requests",
+    );
+    const LONGPOLL_STEP_HEADER: (&str, &str, &str) = (
+        "longpoll-step-header",
+        "--longpoll-sheader",
+        "(LongPollStep) Extra request header as \"Name: value\", may be repeated",
+    );
+    const LONGPOLL_STEP_INTERVAL: (&str, &str, &str) = (
+        "longpoll-step-interval",
+        "--longpoll-sinterval",
+        "(LongPollStep) Milliseconds between a poll answered empty and the next one",
+    );
+
+    const LONGPOLL_TIMEOUT: (&str, &str, &str) = (
+        "longpoll-timeout",
+        "--longpoll-timeout",
+        "(LongPollEntry, LongPollStep) Seconds without a successful request after which the session is given up",
+    );
+
+    // the body answering a poll starts with the sequence number and flags of
+    // the downstream chunk it carries
+    const CHUNK_HEADER_SIZE: usize = 5;
+    const FIN: u8 = 1;
+    /// Data read from either end per request, and waiting to be polled.
+    const MAX_CHUNK: usize = 64 * 1024;
+    const MAX_BODY: usize = 2 * MAX_CHUNK;
+    const MIN_RETRY: Duration = Duration::from_millis(100);
+    const MAX_RETRY: Duration = Duration::from_millis(5_000);
+
+    fn read_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn parse_timeout(args: &CliParsed) -> Result<Duration, Error> {
+        let timeout = match args.argument_values.get(LONGPOLL_TIMEOUT.0) {
+            Some(timeout) => timeout[0].clone(),
+            None => return Err(Error::RequireOption(LONGPOLL_TIMEOUT.0.to_string())),
+        };
+        match str::parse::<u64>(timeout.as_str()) {
+            Ok(timeout) => Ok(Duration::from_secs(timeout)),
+            Err(_) => Err(Error::ParseIntError),
+        }
+    }
+
+    /// What a request asks of its session, identified by the `sid` parameter.
+    enum Exchange {
+        /// Upstream chunk `seq`, answered with the sequence number of the
+        /// chunk expected next.
+        Push {
+            seq: u32,
+            fin: bool,
+            data: Vec<u8>,
+            reply: oneshot::Sender<Vec<u8>>,
+        },
+        /// Acknowledges the downstream chunks before `ack` and asks for that
+        /// one, answered with a chunk body.
+        Poll {
+            ack: u32,
+            reply: oneshot::Sender<Vec<u8>>,
+        },
+    }
+
+    struct Request {
+        sid: u64,
+        exchange: Exchange,
+        /// Only the first requests of a session may open it.
+        opening: bool,
+    }
+
+    type Sessions = Arc<Mutex<HashMap<u64, UnboundedSender<Exchange>>>>;
+
+    fn chunk_body(seq: u32, flags: u8, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::with_capacity(CHUNK_HEADER_SIZE + data.len());
+        body.extend_from_slice(&seq.to_be_bytes());
+        body.push(flags);
+        body.extend_from_slice(data);
+        body
+    }
+
+    /// Downstream state of a session, cut into chunks that are answered
+    /// again until the client acknowledges them.
+    struct Downstream {
+        pending: Vec<u8>,
+        eof: bool,
+        next: u32,
+        chunk: Option<(u32, Vec<u8>, u8)>,
+        fin_cut: bool,
+        fin_acked: bool,
+    }
+
+    impl Downstream {
+        fn acknowledge(&mut self, ack: u32) {
+            if let Some((seq, _, flags)) = &self.chunk {
+                if ack == seq.wrapping_add(1) {
+                    self.fin_acked = flags & FIN != 0;
+                    self.chunk = None;
+                }
+            }
+        }
+
+        /// The body answering a poll for `ack`, or `None` while there is
+        /// nothing to send yet.
+        fn answer(&mut self, ack: u32) -> Option<Vec<u8>> {
+            let waiting = !self.pending.is_empty() || (self.eof && !self.fin_cut);
+            if self.chunk.is_none() && ack == self.next && waiting {
+                let size = self.pending.len().min(MAX_CHUNK);
+                let data: Vec<u8> = self.pending.drain(..size).collect();
+                let mut flags = 0;
+                if self.eof && self.pending.is_empty() {
+                    flags |= FIN;
+                    self.fin_cut = true;
+                }
+                self.chunk = Some((self.next, data, flags));
+                self.next = self.next.wrapping_add(1);
+            }
+            match &self.chunk {
+                Some((seq, data, flags)) if *seq == ack => Some(chunk_body(*seq, *flags, data)),
+                // a poll for a chunk that is not the next one, e.g. a late
+                // retry, gets nothing
+                _ if ack != self.next => Some(chunk_body(ack, 0, &[])),
+                _ => None,
+            }
+        }
+    }
+
+    /// Owns the pipeline of one client and serves the requests of its
+    /// session until both directions ended or the client went silent.
+    async fn run_session(
+        mut exchanges: UnboundedReceiver<Exchange>,
+        mut pipeline: Pipeline,
+        hold: Duration,
+        idle_timeout: Duration,
+    ) -> Result<(), Error> {
+        let pipeline_fd = PipelineFd::readable(&pipeline)?;
+        let mut up_next: u32 = 0;
+        let mut forward_closed = false;
+        let mut downstream = Downstream {
+            pending: Vec::new(),
+            eof: false,
+            next: 0,
+            chunk: None,
+            fin_cut: false,
+            fin_acked: false,
+        };
+        let mut parked: Option<(u32, oneshot::Sender<Vec<u8>>, Instant)> = None;
+        let mut last_heard = Instant::now();
+
+        while !(forward_closed && downstream.fin_acked) {
+            let hold_until = parked.as_ref().map(|(_, _, until)| *until);
+            tokio::select! {
+                exchange = exchanges.recv() => {
+                    last_heard = Instant::now();
+                    match exchange {
+                        Some(Exchange::Push { seq, fin, data, reply }) => {
+                            if seq == up_next && !forward_closed {
+                                if !data.is_empty() {
+                                    write_pipeline(&mut pipeline, data)?;
+                                }
+                                if fin {
+                                    pipeline.close_forward()?;
+                                    forward_closed = true;
+                                }
+                                up_next = up_next.wrapping_add(1);
+                            }
+                            let _ = reply.send(up_next.to_be_bytes().to_vec());
+                        }
+                        Some(Exchange::Poll { ack, reply }) => {
+                            // a client that gave up on its previous poll
+                            // asks again
+                            if let Some((ack, reply, _)) = parked.take() {
+                                let _ = reply.send(chunk_body(ack, 0, &[]));
+                            }
+                            downstream.acknowledge(ack);
+                            match downstream.answer(ack) {
+                                Some(body) => {
+                                    let _ = reply.send(body);
+                                }
+                                None if hold.is_zero() => {
+                                    let _ = reply.send(chunk_body(ack, 0, &[]));
+                                }
+                                None => parked = Some((ack, reply, Instant::now() + hold)),
+                            }
+                        }
+                        None => return Ok(()),
+                    }
+                }
+                guard = pipeline_fd.readable(), if !downstream.eof && downstream.pending.len() < MAX_CHUNK => {
+                    let mut guard = guard?;
+                    match read_pipeline(&mut pipeline) {
+                        Ok(Some(data)) => downstream.pending.extend(data),
+                        Ok(None) => guard.clear_ready(),
+                        Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                            downstream.eof = true
+                        }
+                        Err(e) => return Err(e),
+                    }
+                    if let Some((ack, reply, until)) = parked.take() {
+                        match downstream.answer(ack) {
+                            Some(body) => {
+                                let _ = reply.send(body);
+                            }
+                            None => parked = Some((ack, reply, until)),
+                        }
+                    }
+                }
+                _ = sleep_until(hold_until.unwrap_or_else(Instant::now)), if hold_until.is_some() => {
+                    if let Some((ack, reply, _)) = parked.take() {
+                        let _ = reply.send(chunk_body(ack, 0, &[]));
+                    }
+                    last_heard = Instant::now();
+                }
+                // a held poll is a client still there
+                _ = sleep_until(last_heard + idle_timeout), if parked.is_none() => {
+                    return Err(Error::Msg("client stopped polling".to_string()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers the requests of `LongPollStep` clients, every session with its
+    /// own copy of the pipeline. Each request and response is complete on its
+    /// own, so proxies buffering them whole do not stall the tunnel.
+    pub struct LongPollEntry {
+        address: String,
+        port: u16,
+        hold: Duration,
+        timeout: Duration,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for LongPollEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(self.serve())
+        }
+    }
+
+    impl LongPollEntry {
+        async fn serve(&mut self) -> Result<(), Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let server = TcpListener::bind(addr).await?;
+            let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+
+            loop {
+                let (connection, peer) = server.accept().await?;
+                let context = SessionContext {
+                    sessions: sessions.clone(),
+                    pipeline_template: self.pipeline_template.clone(),
+                    hold: self.hold,
+                    timeout: self.timeout,
+                    debug_level: self.debug_level,
+                    buffer_size: self.buffer_size,
+                };
+                let debug_level = self.debug_level;
+                tokio::spawn(async move {
+                    if let Err(e) = context.serve_connection(connection, peer).await {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving client {}: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    struct SessionContext {
+        sessions: Sessions,
+        pipeline_template: Arc<Pipeline>,
+        hold: Duration,
+        timeout: Duration,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl SessionContext {
+        /// Answers requests on a connection one at a time while the client
+        /// keeps it alive.
+        async fn serve_connection(
+            &self,
+            mut connection: TcpStream,
+            peer: SocketAddr,
+        ) -> Result<(), Error> {
+            let local = connection.local_addr().ok();
+            let mut buffer = vec![0u8; self.buffer_size];
+            loop {
+                let (head, leftover) = match read_head(&mut connection).await? {
+                    Some(request) => request,
+                    None => return Ok(()),
+                };
+
+                let mut headers = [httparse::EMPTY_HEADER; 64];
+                let mut request = httparse::Request::new(&mut headers);
+                match request.parse(&head) {
+                    Ok(httparse::Status::Complete(_)) => {}
+                    _ => {
+                        respond(&mut connection, "400 Bad Request", &[], false).await?;
+                        return Err(Error::Msg("invalid request head".to_string()));
+                    }
+                }
+                let keep_alive = request.version == Some(1)
+                    && !request.headers.iter().any(|header| {
+                        header.name.eq_ignore_ascii_case("connection")
+                            && String::from_utf8_lossy(header.value)
+                                .trim()
+                                .eq_ignore_ascii_case("close")
+                    });
+                let target = request.path.unwrap_or("/").to_string();
+                // a request without framing headers has no body
+                let mut decoder =
+                    BodyDecoder::from_headers(request.headers, BodyDecoder::Length(0))?;
+
+                let mut body = decoder.decode(&leftover)?;
+                while !decoder.is_done() {
+                    let size = connection.read(&mut buffer).await?;
+                    if size == 0 {
+                        return Err(Error::IoError(ErrorKind::UnexpectedEof.into()));
+                    }
+                    body.extend(decoder.decode(&buffer[0..size])?);
+                    if body.len() > MAX_BODY {
+                        respond(&mut connection, "413 Payload Too Large", &[], false).await?;
+                        return Err(Error::Msg("request body too large".to_string()));
+                    }
+                }
+
+                let (status, body) = match parse_request(&target, body) {
+                    Some((request, reply)) => match self.dispatch(request, peer, local) {
+                        Ok(true) => match reply.await {
+                            Ok(body) => ("200 OK", body),
+                            // the session ended before answering
+                            Err(_) => ("404 Not Found", Vec::new()),
+                        },
+                        Ok(false) => ("404 Not Found", Vec::new()),
+                        Err(e) => {
+                            respond(&mut connection, "500 Internal Server Error", &[], false)
+                                .await?;
+                            return Err(e);
+                        }
+                    },
+                    None => ("400 Bad Request", Vec::new()),
+                };
+                respond(&mut connection, status, &body, keep_alive).await?;
+                if !keep_alive {
+                    return Ok(());
+                }
+            }
+        }
+
+        /// Passes the request to the client's session, if it has one, and
+        /// returns whether the session took it. The request is given back
+        /// when there is no session.
+        fn hand_over(
+            sessions: &HashMap<u64, UnboundedSender<Exchange>>,
+            request: Request,
+        ) -> Result<bool, Request> {
+            match sessions.get(&request.sid) {
+                // the session is ending when it refuses, its late requests
+                // get nothing
+                Some(session) => Ok(session.send(request.exchange).is_ok()),
+                None => Err(request),
+            }
+        }
+
+        /// Hands the request to its session, opening one for the first
+        /// requests of a new client. Returns whether the session exists.
+        fn dispatch(
+            &self,
+            request: Request,
+            peer: SocketAddr,
+            local: Option<SocketAddr>,
+        ) -> Result<bool, Error> {
+            let lock = || match self.sessions.lock() {
+                Ok(sessions) => Ok(sessions),
+                Err(_) => Err(Error::Msg("session table poisoned".to_string())),
+            };
+            // the table is not held while the pipeline is cloned, which may
+            // dial out and would stall every other client
+            let request = match Self::hand_over(&*lock()?, request) {
+                Err(request) if request.opening => request,
+                Err(_) => return Ok(false),
+                Ok(handed) => return Ok(handed),
+            };
+
+            let info = ConnectionInfo {
+                peer: Some(peer),
+                local,
+                destination: None,
+            };
+            let pipeline =
+                tokio::task::block_in_place(|| self.pipeline_template.clone_with(&info))?;
+            let mut sessions = lock()?;
+            // another request of the client may have opened it meanwhile
+            let request = match Self::hand_over(&sessions, request) {
+                Err(request) => request,
+                Ok(handed) => return Ok(handed),
+            };
+            let (sender, receiver) = unbounded_channel();
+            let _ = sender.send(request.exchange);
+            sessions.insert(request.sid, sender);
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("new client: {:016x} via {}", request.sid, peer);
+                }
+            }
+
+            let sid = request.sid;
+            let sessions = self.sessions.clone();
+            let hold = self.hold;
+            let timeout = self.timeout;
+            let debug_level = self.debug_level;
+            tokio::spawn(async move {
+                if let Err(e) = run_session(receiver, pipeline, hold, timeout).await {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on session {:016x}: {}", sid, e);
+                    }
+                }
+                if let Ok(mut sessions) = sessions.lock() {
+                    sessions.remove(&sid);
+                }
+
+                {
+                    // debug
+                    if debug_level >= 2 {
+                        println!("client {:016x} closed", sid);
+                    }
+                }
+            });
+            Ok(true)
+        }
+    }
+
+    /// Reads the session id and sequence numbers from the query of `target`,
+    /// returning the request and where its answer body arrives.
+    fn parse_request(target: &str, body: Vec<u8>) -> Option<(Request, oneshot::Receiver<Vec<u8>>)> {
+        let query = target.split_once('?')?.1;
+        let mut sid = None;
+        let mut seq = None;
+        let mut ack = None;
+        let mut fin = false;
+        for parameter in query.split('&') {
+            match parameter.split_once('=') {
+                Some(("sid", value)) => sid = u64::from_str_radix(value, 16).ok(),
+                Some(("seq", value)) => seq = str::parse::<u32>(value).ok(),
+                Some(("ack", value)) => ack = str::parse::<u32>(value).ok(),
+                Some(("fin", "1")) => fin = true,
+                _ => {}
+            }
+        }
+
+        let (reply, receiver) = oneshot::channel();
+        let (exchange, opening) = match (seq, ack) {
+            (Some(seq), None) => (
+                Exchange::Push {
+                    seq,
+                    fin,
+                    data: body,
+                    reply,
+                },
+                seq == 0,
+            ),
+            (None, Some(ack)) => (Exchange::Poll { ack, reply }, ack == 0),
+            _ => return None,
+        };
+        Some((
+            Request {
+                sid: sid?,
+                exchange,
+                opening,
+            },
+            receiver,
+        ))
+    }
+
+    async fn respond(
+        connection: &mut TcpStream,
+        status: &str,
+        body: &[u8],
+        keep_alive: bool,
+    ) -> Result<(), Error> {
+        let mut head = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nCache-Control: no-store\r\n",
+            status,
+            body.len()
+        );
+        if !keep_alive {
+            head.push_str("Connection: close\r\n");
+        }
+        head.push_str("\r\n");
+        connection.write_all(head.as_bytes()).await?;
+        connection.write_all(body).await?;
+        Ok(())
+    }
+
+    impl EntryStatic<LongPollEntry> for LongPollEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<LongPollEntry, Error> {
+            let address = match args.argument_values.get(LONGPOLL_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(LONGPOLL_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(LONGPOLL_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(LONGPOLL_ENTRY_PORT.0.to_string())),
+            };
+            let hold = match args.argument_values.get(LONGPOLL_ENTRY_HOLD.0) {
+                Some(hold) => hold[0].clone(),
+                None => return Err(Error::RequireOption(LONGPOLL_ENTRY_HOLD.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let hold = match str::parse::<u64>(hold.as_str()) {
+                Ok(hold) => Duration::from_secs(hold),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(LongPollEntry {
+                address,
+                port,
+                hold,
+                timeout: parse_timeout(&args)?,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_ENTRY_ADDRESS.0.to_string(),
+                key: vec![LONGPOLL_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_ENTRY_PORT.0.to_string(),
+                key: vec![LONGPOLL_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("80".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_ENTRY_HOLD.0.to_string(),
+                key: vec![LONGPOLL_ENTRY_HOLD.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("10".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_ENTRY_HOLD.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_TIMEOUT.0.to_string(),
+                key: vec![LONGPOLL_TIMEOUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("60".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_TIMEOUT.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    // drives the sessions of every LongPollStep, the steps themselves are
+    // called synchronously by their entry
+    lazy_static! {
+        static ref RUNTIME: Runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+    }
+
+    #[derive(Clone)]
+    struct LongPollTarget {
+        address: String,
+        port: u16,
+        path: String,
+        headers: Vec<(String, String)>,
+        interval: Duration,
+        timeout: Duration,
+    }
+
+    /// A keep-alive connection carrying one request at a time, dialed again
+    /// whenever it fails.
+    struct Lane {
+        target: Arc<LongPollTarget>,
+        connection: Option<TcpStream>,
+    }
+
+    impl Lane {
+        /// Sends the request until it gets an answer, returning `None` when
+        /// the entry does not know the session.
+        async fn request(&mut self, query: &str, body: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+            let started = Instant::now();
+            let mut delay = MIN_RETRY;
+            loop {
+                let result = match timeout(self.target.timeout, self.exchange(query, body)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(Error::IoError(ErrorKind::TimedOut.into())),
+                };
+                match result {
+                    Ok(answer) => return Ok(answer),
+                    Err(e) => {
+                        self.connection = None;
+                        if started.elapsed() >= self.target.timeout {
+                            return Err(e);
+                        }
+                        // requests are numbered, so sending one again is safe
+                        sleep(delay).await;
+                        delay = (delay * 2).min(MAX_RETRY);
+                    }
+                }
+            }
+        }
+
+        async fn exchange(&mut self, query: &str, body: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+            let target = self.target.clone();
+            let connection = match self.connection.as_mut() {
+                Some(connection) => connection,
+                None => {
+                    let addr = create_socket_addr(target.address.as_str(), target.port)?;
+                    self.connection.insert(TcpStream::connect(addr).await?)
+                }
+            };
+
+            let separator = if target.path.contains('?') { '&' } else { '?' };
+            let mut head = format!(
+                "POST {}{}{} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nCache-Control: no-store\r\n",
+                target.path,
+                separator,
+                query,
+                target.address,
+                target.port,
+                body.len()
+            );
+            for (name, value) in target.headers.iter() {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str("\r\n");
+            connection.write_all(head.as_bytes()).await?;
+            connection.write_all(body).await?;
+
+            let (head, leftover) = match read_head(connection).await? {
+                Some(response) => response,
+                None => return Err(Error::IoError(ErrorKind::UnexpectedEof.into())),
+            };
+            let head = ResponseHead::parse(&head)?;
+            let mut decoder = head.body;
+            let mut answer = decoder.decode(&leftover)?;
+            let mut buffer = vec![0u8; 16 * 1024];
+            let mut closed = head.close;
+            while !decoder.is_done() {
+                let size = connection.read(&mut buffer).await?;
+                if size == 0 {
+                    if let BodyDecoder::UntilClose = decoder {
+                        closed = true;
+                        break;
+                    }
+                    return Err(Error::IoError(ErrorKind::UnexpectedEof.into()));
+                }
+                answer.extend(decoder.decode(&buffer[0..size])?);
+                if answer.len() > MAX_BODY {
+                    return Err(Error::Msg("response body too large".to_string()));
+                }
+            }
+            if closed {
+                self.connection = None;
+            }
+
+            match head.status {
+                200..=299 => Ok(Some(answer)),
+                404 => Ok(None),
+                status => Err(Error::Msg(format!("unexpected response status {}", status))),
+            }
+        }
+    }
+
+    /// Carries the step's end of a session: one lane pushes what its client
+    /// sends, the other polls for what comes back.
+    async fn run_client(
+        target: Arc<LongPollTarget>,
+        sid: u64,
+        tunnel_end: UnixStream,
+    ) -> Result<(), Error> {
+        let tunnel_end = tokio::net::UnixStream::from_std(tunnel_end)?;
+        let (mut reader, mut writer) = tunnel_end.into_split();
+        let gone = || Error::Msg("entry does not know the session".to_string());
+
+        let upstream = async {
+            let mut lane = Lane {
+                target: target.clone(),
+                connection: None,
+            };
+            let mut seq: u32 = 0;
+            let mut buffer = vec![0u8; MAX_CHUNK];
+            loop {
+                let size = reader.read(&mut buffer).await?;
+                let fin = size == 0;
+                let query = format!(
+                    "sid={:016x}&seq={}{}",
+                    sid,
+                    seq,
+                    if fin { "&fin=1" } else { "" }
+                );
+                let answer = lane
+                    .request(&query, &buffer[0..size])
+                    .await?
+                    .ok_or_else(gone)?;
+                if answer.len() < 4 || read_u32(&answer) != seq.wrapping_add(1) {
+                    return Err(Error::Msg("entry lost an upstream chunk".to_string()));
+                }
+                seq = seq.wrapping_add(1);
+                if fin {
+                    return Ok::<(), Error>(());
+                }
+            }
+        };
+
+        let downstream = async {
+            let mut lane = Lane {
+                target: target.clone(),
+                connection: None,
+            };
+            let mut ack: u32 = 0;
+            loop {
+                let query = format!("sid={:016x}&ack={}", sid, ack);
+                let answer = lane.request(&query, &[]).await?.ok_or_else(gone)?;
+                if answer.len() < CHUNK_HEADER_SIZE {
+                    return Err(Error::Msg("invalid poll answer".to_string()));
+                }
+                let flags = answer[4];
+                let data = &answer[CHUNK_HEADER_SIZE..];
+                if read_u32(&answer) != ack || (data.is_empty() && flags & FIN == 0) {
+                    sleep(target.interval).await;
+                    continue;
+                }
+                writer.write_all(data).await?;
+                ack = ack.wrapping_add(1);
+                if flags & FIN != 0 {
+                    // acknowledges the end before our side sees it and perhaps
+                    // exits, an entry not hearing it lets the session time out
+                    let query = format!("sid={:016x}&ack={}", sid, ack);
+                    let _ = timeout(target.timeout, lane.exchange(&query, &[])).await;
+                    writer.shutdown().await?;
+                    return Ok::<(), Error>(());
+                }
+            }
+        };
+
+        tokio::try_join!(upstream, downstream)?;
+        Ok(())
+    }
+
+    /// Carries its client as a session of short HTTP/1.1 requests to a
+    /// `LongPollEntry`.
+    pub struct LongPollStep {
+        target: Arc<LongPollTarget>,
+        /// The local end of the session, which draws its id when opened.
+        stream: LazyStream,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl LongPollStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let mut sid = [0u8; 8];
+            SystemRandom::new()
+                .fill(&mut sid)
+                .map_err(|_| Error::Msg("no randomness available for a session".to_string()))?;
+            let sid = u64::from_be_bytes(sid);
+
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let target = self.target.clone();
+            let debug_level = self.debug_level;
+            RUNTIME.spawn(async move {
+                if let Err(e) = run_client(target, sid, inner).await {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on longpoll session: {}", e);
+                    }
+                }
+            });
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("longpoll session", self.debug_level, || self.open())
+        }
+    }
+
+    impl Step for LongPollStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_all(&mut self.stream()?, data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.stream()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for LongPollStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl StepStatic for LongPollStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(LONGPOLL_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(LONGPOLL_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(LONGPOLL_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(LONGPOLL_STEP_PORT.0.to_string())),
+            };
+            let path = match args.argument_values.get(LONGPOLL_STEP_PATH.0) {
+                Some(path) => path[0].clone(),
+                None => return Err(Error::RequireOption(LONGPOLL_STEP_PATH.0.to_string())),
+            };
+            let interval = match args.argument_values.get(LONGPOLL_STEP_INTERVAL.0) {
+                Some(interval) => interval[0].clone(),
+                None => return Err(Error::RequireOption(LONGPOLL_STEP_INTERVAL.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let mut headers = Vec::new();
+            if let Some(values) = args.argument_values.get(LONGPOLL_STEP_HEADER.0) {
+                for header in values {
+                    match header.split_once(':') {
+                        Some((name, value)) => {
+                            headers.push((name.trim().to_string(), value.trim().to_string()))
+                        }
+                        None => return Err(Error::Msg(format!("invalid header: {}", header))),
+                    }
+                }
+            }
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let interval = match str::parse::<u64>(interval.as_str()) {
+                Ok(interval) => Duration::from_millis(interval),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Self {
+                target: Arc::new(LongPollTarget {
+                    address,
+                    port,
+                    path,
+                    headers,
+                    interval,
+                    timeout: parse_timeout(&args)?,
+                }),
+                stream: LazyStream::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_STEP_ADDRESS.0.to_string(),
+                key: vec![LONGPOLL_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_STEP_PORT.0.to_string(),
+                key: vec![LONGPOLL_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("80".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_STEP_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_STEP_PATH.0.to_string(),
+                key: vec![LONGPOLL_STEP_PATH.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("/".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_STEP_PATH.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_STEP_HEADER.0.to_string(),
+                key: vec![LONGPOLL_STEP_HEADER.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Multiple,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(LONGPOLL_STEP_HEADER.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: LONGPOLL_STEP_INTERVAL.0.to_string(),
+                key: vec![LONGPOLL_STEP_INTERVAL.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("200".to_string()),
+                help: Some(ArgumentHelp::Text(LONGPOLL_STEP_INTERVAL.2.to_string())),
+            });
+            // the timeout is shared with LongPollEntry, which adds it
+            argument
+        }
+    }
+
+    impl Clone for LongPollStep {
+        fn clone(&self) -> Self {
+            Self {
+                target: self.target.clone(),
+                stream: LazyStream::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for LongPollStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+}
@@ -3,8 +3,12 @@ use std::io::Read;
 use std::process::exit;
 
 use kproxy::{
-    DebugLevel, Entry, EntryStatic, HttpEntry, Pipeline, StdioEntry, StdioStep, StepStatic,
-    TcpEntry, TcpStep, BUFFER_SIZE,
+    AgentEntry, ConnectEntry, DebugLevel, DemuxEntry, DnsEntry, DnsStep, Entry, EntryStatic,
+    ExecEntry, ExecStep, FecEntry, FecStep, FileEntry, FileStep, GrpcEntry, GrpcStep,
+    HttpConnectStep, HttpEntry, HttpStep, KcpEntry, KcpStep, LongPollEntry, LongPollStep, MuxStep,
+    Pipeline, QuicEntry, QuicStep, RelayEntry, ResumeEntry, ResumeStep, Socks5Entry, Socks5Step,
+    SshStep, StdioEntry, StdioStep, StepStatic, Tcp2UdpStep, TcpEntry, TcpStep, Udp2TcpStep,
+    UdpEntry, WsEntry, WsStep, BUFFER_SIZE,
 };
 
 use cliparser::types::{
@@ -92,6 +96,39 @@ fn main() {
     cli_spec = TcpEntry::get_cmd(cli_spec);
     cli_spec = TcpStep::get_cmd(cli_spec);
     cli_spec = HttpEntry::get_cmd(cli_spec);
+    cli_spec = HttpStep::get_cmd(cli_spec);
+    cli_spec = WsEntry::get_cmd(cli_spec);
+    cli_spec = WsStep::get_cmd(cli_spec);
+    cli_spec = ConnectEntry::get_cmd(cli_spec);
+    cli_spec = Socks5Entry::get_cmd(cli_spec);
+    cli_spec = Socks5Step::get_cmd(cli_spec);
+    cli_spec = HttpConnectStep::get_cmd(cli_spec);
+    cli_spec = ExecStep::get_cmd(cli_spec);
+    cli_spec = ExecEntry::get_cmd(cli_spec);
+    cli_spec = FileEntry::get_cmd(cli_spec);
+    cli_spec = FileStep::get_cmd(cli_spec);
+    cli_spec = QuicEntry::get_cmd(cli_spec);
+    cli_spec = QuicStep::get_cmd(cli_spec);
+    cli_spec = DemuxEntry::get_cmd(cli_spec);
+    cli_spec = MuxStep::get_cmd(cli_spec);
+    cli_spec = RelayEntry::get_cmd(cli_spec);
+    cli_spec = AgentEntry::get_cmd(cli_spec);
+    cli_spec = ResumeEntry::get_cmd(cli_spec);
+    cli_spec = ResumeStep::get_cmd(cli_spec);
+    cli_spec = KcpEntry::get_cmd(cli_spec);
+    cli_spec = KcpStep::get_cmd(cli_spec);
+    cli_spec = FecEntry::get_cmd(cli_spec);
+    cli_spec = FecStep::get_cmd(cli_spec);
+    cli_spec = UdpEntry::get_cmd(cli_spec);
+    cli_spec = Udp2TcpStep::get_cmd(cli_spec);
+    cli_spec = Tcp2UdpStep::get_cmd(cli_spec);
+    cli_spec = DnsEntry::get_cmd(cli_spec);
+    cli_spec = DnsStep::get_cmd(cli_spec);
+    cli_spec = LongPollEntry::get_cmd(cli_spec);
+    cli_spec = LongPollStep::get_cmd(cli_spec);
+    cli_spec = GrpcEntry::get_cmd(cli_spec);
+    cli_spec = GrpcStep::get_cmd(cli_spec);
+    cli_spec = SshStep::get_cmd(cli_spec);
 
     let args = Vec::from_iter(env::args());
     let args = args
@@ -159,6 +196,9 @@ fn run(cli_parsed: CliParsed, debug_level: DebugLevel) {
 
     let steps = match cli_parsed.argument_values.get(STEP.0) {
         Some(steps) => steps.iter().map(|x| x.as_str()).collect::<Vec<&str>>(),
+        // the exec entry wires its clients straight to the program and the
+        // relay entry hands them to its agents
+        None if entry == "exec" || entry == "relay" => Vec::new(),
         None => {
             eprintln!("No Step Was Found");
             exit(1);
@@ -174,6 +214,57 @@ fn run(cli_parsed: CliParsed, debug_level: DebugLevel) {
             Some("tcp") => pipeline.add_step(Box::new(
                 TcpStep::new(cli_parsed.clone(), debug_level).unwrap(),
             )),
+            Some("http") => pipeline.add_step(Box::new(
+                HttpStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("ws") => pipeline.add_step(Box::new(
+                WsStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("socks5-client") => pipeline.add_step(Box::new(
+                Socks5Step::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("http-connect") => pipeline.add_step(Box::new(
+                HttpConnectStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("exec") => pipeline.add_step(Box::new(
+                ExecStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("file") => pipeline.add_step(Box::new(
+                FileStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("quic") => pipeline.add_step(Box::new(
+                QuicStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("mux") => pipeline.add_step(Box::new(
+                MuxStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("resume") => pipeline.add_step(Box::new(
+                ResumeStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("kcp") => pipeline.add_step(Box::new(
+                KcpStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("fec") => pipeline.add_step(Box::new(
+                FecStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("udp2tcp") => pipeline.add_step(Box::new(
+                Udp2TcpStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("tcp2udp") => pipeline.add_step(Box::new(
+                Tcp2UdpStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("dns") => pipeline.add_step(Box::new(
+                DnsStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("longpoll") => pipeline.add_step(Box::new(
+                LongPollStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("grpc") => pipeline.add_step(Box::new(
+                GrpcStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
+            Some("ssh") => pipeline.add_step(Box::new(
+                SshStep::new(cli_parsed.clone(), debug_level).unwrap(),
+            )),
             Some(_) => {
                 eprintln!("Unknown step");
                 exit(1);
@@ -185,7 +276,11 @@ fn run(cli_parsed: CliParsed, debug_level: DebugLevel) {
     match Some(entry) {
         Some("stdio") => {
             let mut entry = StdioEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
-            entry.listen().unwrap();
+            // the exit code tells scripts whether the upstream ended cleanly
+            if let Err(e) = entry.listen() {
+                eprintln!("{}", e);
+                exit(1);
+            }
         }
         Some("tcp") => {
             let mut entry = TcpEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
@@ -195,6 +290,70 @@ fn run(cli_parsed: CliParsed, debug_level: DebugLevel) {
             let mut entry = HttpEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
             entry.listen().unwrap();
         }
+        Some("ws") => {
+            let mut entry = WsEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("connect") => {
+            let mut entry = ConnectEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("socks5") => {
+            let mut entry = Socks5Entry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("exec") => {
+            let mut entry = ExecEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("file") => {
+            let mut entry = FileEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("quic") => {
+            let mut entry = QuicEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("demux") => {
+            let mut entry = DemuxEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("relay") => {
+            let mut entry = RelayEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("resume") => {
+            let mut entry = ResumeEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("kcp") => {
+            let mut entry = KcpEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("fec") => {
+            let mut entry = FecEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("udp") => {
+            let mut entry = UdpEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("dns") => {
+            let mut entry = DnsEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("longpoll") => {
+            let mut entry = LongPollEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("grpc") => {
+            let mut entry = GrpcEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
+        Some("agent") => {
+            let mut entry = AgentEntry::new(cli_parsed.clone(), pipeline, debug_level).unwrap();
+            entry.listen().unwrap();
+        }
         Some(_) => {
             eprintln!("Unknown entry");
             exit(1);
@@ -203,9 +362,6 @@ fn run(cli_parsed: CliParsed, debug_level: DebugLevel) {
     };
 }
 
-
-
-
 // use std::error::Error;
 
 // use h2::server::{self, SendResponse};
@@ -271,4 +427,4 @@ fn run(cli_parsed: CliParsed, debug_level: DebugLevel) {
 //     send.send_data(Bytes::from_static(b"world\n"), true)?;
 
 //     Ok(())
-// }
\ No newline at end of file
+// }
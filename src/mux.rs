@@ -0,0 +1,1274 @@
+pub mod mux {
+    use std::collections::HashMap;
+    use std::io::{ErrorKind, Read, Write};
+    use std::mem;
+    use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::{Arc, Condvar, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::unix::SourceFd;
+    use mio::{Events, Interest, Poll, Token, Waker};
+    use tokio::runtime::Handle;
+
+    use crate::async_pipeline::async_pipeline::{relay_stream, LazyStream};
+    use crate::tcp::tcp::{read_available, WRITE_TIMEOUT};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const DEMUX_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "demux-entry-address",
+        "--demux-ea",
+        "(DemuxEntry) Demux Entry listen address",
+    );
+    const DEMUX_ENTRY_PORT: (&str, &str, &str) = (
+        "demux-entry-port",
+        "--demux-ep",
+        "(DemuxEntry) Demux Entry listen port",
+    );
+
+    const MUX_KEEPALIVE: (&str, &str, &str) = (
+        "mux-keepalive",
+        "--mux-keepalive",
+        "(DemuxEntry, MuxStep, RelayEntry, AgentEntry) Seconds between keepalive pings on the shared link, 0 disables them",
+    );
+
+    // every frame starts with a version, a kind, the stream id and the
+    // payload length
+    const VERSION: u8 = 1;
+    const HEADER_SIZE: usize = 10;
+    const OPEN: u8 = 0;
+    const DATA: u8 = 1;
+    const FIN: u8 = 2;
+    const RST: u8 = 3;
+    const WINDOW: u8 = 4;
+    const PING: u8 = 5;
+    const PONG: u8 = 6;
+
+    /// Bytes a stream may have in flight before its receiver grants more.
+    const WINDOW_SIZE: u32 = 256 * 1024;
+    /// What a `MuxStep` keeps back before it waits, about what a loopback
+    /// TCP socket buffers, so it waits where a `TcpStep` would.
+    const BACKLOG_LIMIT: usize = 4 * 1024 * 1024;
+    const MAX_PAYLOAD: usize = 16 * 1024;
+    /// The link is given up once this many keepalive intervals pass in silence.
+    const KEEPALIVE_MISSES: u32 = 3;
+
+    pub(crate) const LINK: Token = Token(0);
+    pub(crate) const REQUESTS: Token = Token(1);
+    const FIRST_STREAM: usize = 2;
+
+    pub(crate) fn parse_keepalive(args: &CliParsed) -> Result<Option<Duration>, Error> {
+        let keepalive = match args.argument_values.get(MUX_KEEPALIVE.0) {
+            Some(keepalive) => keepalive[0].clone(),
+            None => return Err(Error::RequireOption(MUX_KEEPALIVE.0.to_string())),
+        };
+        match str::parse::<u64>(keepalive.as_str()) {
+            Ok(0) => Ok(None),
+            Ok(seconds) => Ok(Some(Duration::from_secs(seconds))),
+            Err(_) => Err(Error::ParseIntError),
+        }
+    }
+
+    fn push_frame(outbound: &mut Vec<u8>, kind: u8, id: u32, payload: &[u8]) {
+        outbound.push(VERSION);
+        outbound.push(kind);
+        outbound.extend_from_slice(&id.to_be_bytes());
+        outbound.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        outbound.extend_from_slice(payload);
+    }
+
+    /// An OPEN payload is the port and host the client asked its entry for,
+    /// or empty when it did not ask for one.
+    pub(crate) fn encode_destination(destination: &Option<(String, u16)>) -> Vec<u8> {
+        match destination {
+            Some((address, port)) => {
+                let mut payload = port.to_be_bytes().to_vec();
+                payload.extend_from_slice(address.as_bytes());
+                payload
+            }
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn decode_destination(payload: &[u8]) -> Option<(String, u16)> {
+        if payload.len() < 3 {
+            return None;
+        }
+        let port = u16::from_be_bytes([payload[0], payload[1]]);
+        String::from_utf8(payload[2..].to_vec())
+            .ok()
+            .map(|address| (address, port))
+    }
+
+    /// The connection a session runs over.
+    pub(crate) enum Link {
+        /// The steps behind a `MuxStep` or `ResumeStep`, written through like
+        /// any pipeline.
+        Pipeline(Pipeline),
+        /// A connection accepted by an entry. It is never written to
+        /// blocking, so two sessions can't end up waiting on each other.
+        Tcp {
+            connection: TcpStream,
+            unsent: Vec<u8>,
+        },
+    }
+
+    impl Link {
+        pub(crate) fn register(&mut self, poll: &Poll) -> Result<(), Error> {
+            match self {
+                Link::Pipeline(pipeline) => {
+                    poll.registry()
+                        .register(pipeline, LINK, Interest::READABLE)?
+                }
+                Link::Tcp { connection, .. } => poll.registry().register(
+                    &mut SourceFd(&connection.as_raw_fd()),
+                    LINK,
+                    Interest::READABLE | Interest::WRITABLE,
+                )?,
+            }
+            Ok(())
+        }
+
+        pub(crate) fn receive(&mut self, buffer_size: usize) -> Result<Vec<u8>, Error> {
+            let result = match self {
+                Link::Pipeline(pipeline) => pipeline.read_pipeline(),
+                Link::Tcp { connection, .. } => read_available(connection, buffer_size),
+            };
+            match result {
+                Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => Ok(Vec::new()),
+                result => result,
+            }
+        }
+
+        pub(crate) fn send(&mut self, data: Vec<u8>) -> Result<(), Error> {
+            match self {
+                Link::Pipeline(_) if data.is_empty() => Ok(()),
+                Link::Pipeline(pipeline) => pipeline.write_pipeline(data),
+                Link::Tcp { unsent, .. } => {
+                    unsent.extend_from_slice(&data);
+                    self.flush()
+                }
+            }
+        }
+
+        pub(crate) fn flush(&mut self) -> Result<(), Error> {
+            if let Link::Tcp { connection, unsent } = self {
+                while !unsent.is_empty() {
+                    match connection.write(unsent) {
+                        Ok(size) => {
+                            unsent.drain(..size);
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(Error::IoError(e)),
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    pub(crate) struct OpenRequest {
+        pub(crate) inner: UnixStream,
+        pub(crate) destination: Option<(String, u16)>,
+        /// Set by clients that write to their end without waiting for room.
+        pub(crate) backlog: Option<Arc<Backlog>>,
+    }
+
+    /// What a client handed its stream while the stream had no room. The
+    /// client only writes to its end while this is empty, and the session
+    /// takes from it only after reading everything written before.
+    #[derive(Default)]
+    pub(crate) struct Backlog {
+        unsent: Mutex<Unsent>,
+        /// Notified whenever the session takes from `unsent` or drops the
+        /// stream.
+        taken: Condvar,
+    }
+
+    #[derive(Default)]
+    struct Unsent {
+        data: Vec<u8>,
+        /// The client half-closed after `data`.
+        closed: bool,
+        /// The session dropped the stream, nothing more will be taken.
+        dropped: bool,
+    }
+
+    impl Backlog {
+        /// Writes as much of `data` to `stream` as it has room for and keeps
+        /// the rest, waking the session with `wake` to take it. Waits only
+        /// while `BACKLOG_LIMIT` is kept already.
+        fn write<F>(&self, mut stream: &UnixStream, mut data: &[u8], wake: F) -> Result<(), Error>
+        where
+            F: Fn() -> Result<(), Error>,
+        {
+            let mut unsent = self.unsent.lock().unwrap();
+            if unsent.data.is_empty() {
+                while !data.is_empty() {
+                    match stream.write(data) {
+                        Ok(size) => data = &data[size..],
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(e) => return Err(Error::IoError(e)),
+                    }
+                }
+                if data.is_empty() {
+                    return Ok(());
+                }
+            }
+            while !data.is_empty() {
+                if unsent.data.len() >= BACKLOG_LIMIT {
+                    wake()?;
+                    let waited;
+                    (unsent, waited) = self
+                        .taken
+                        .wait_timeout_while(unsent, WRITE_TIMEOUT, |unsent| {
+                            !unsent.dropped && unsent.data.len() >= BACKLOG_LIMIT
+                        })
+                        .unwrap();
+                    if waited.timed_out() {
+                        return Err(Error::IoError(ErrorKind::TimedOut.into()));
+                    }
+                }
+                if unsent.dropped {
+                    return Err(Error::IoError(ErrorKind::BrokenPipe.into()));
+                }
+                let size = data.len().min(BACKLOG_LIMIT - unsent.data.len());
+                unsent.data.extend_from_slice(&data[..size]);
+                data = &data[size..];
+            }
+            wake()
+        }
+
+        /// Half-closes `stream` once the session took everything kept.
+        fn close<F>(&self, stream: &UnixStream, wake: F) -> Result<(), Error>
+        where
+            F: Fn() -> Result<(), Error>,
+        {
+            let mut unsent = self.unsent.lock().unwrap();
+            if unsent.data.is_empty() {
+                stream.shutdown(Shutdown::Write)?;
+                return Ok(());
+            }
+            unsent.closed = true;
+            wake()
+        }
+    }
+
+    pub(crate) type OnOpen = Box<dyn FnMut(UnixStream, Option<(String, u16)>) + Send>;
+
+    /// One logical stream. Its client holds the other end of `inner`.
+    struct MuxStream {
+        inner: UnixStream,
+        backlog: Option<Arc<Backlog>>,
+        /// Bytes the peer still accepts on this stream.
+        credit: u32,
+        /// Read from the client, waiting for credit.
+        queued: Vec<u8>,
+        /// Received from the peer, waiting for room in `inner`.
+        pending: Vec<u8>,
+        /// Handed to the client since the last window update.
+        delivered: u32,
+        /// `inner` may hold data that was left unread for lack of credit.
+        readable: bool,
+        read_closed: bool,
+        fin_sent: bool,
+        remote_closed: bool,
+        write_closed: bool,
+    }
+
+    impl Drop for MuxStream {
+        fn drop(&mut self) {
+            // a client waiting for room learns that none is coming
+            if let Some(backlog) = &self.backlog {
+                backlog.unsent.lock().unwrap().dropped = true;
+                backlog.taken.notify_all();
+            }
+        }
+    }
+
+    impl MuxStream {
+        fn new(inner: UnixStream, backlog: Option<Arc<Backlog>>) -> Self {
+            Self {
+                inner,
+                backlog,
+                credit: WINDOW_SIZE,
+                queued: Vec::new(),
+                pending: Vec::new(),
+                delivered: 0,
+                readable: true,
+                read_closed: false,
+                fin_sent: false,
+                remote_closed: false,
+                write_closed: false,
+            }
+        }
+    }
+
+    /// Runs the streams of one link on its own thread. The side that opens
+    /// streams sends them through `requests`, the other is told about the
+    /// ones its peer opens through `on_open`.
+    pub(crate) struct MuxSession {
+        link: Link,
+        poll: Poll,
+        streams: HashMap<u32, MuxStream>,
+        next_id: u32,
+        requests: Option<Receiver<OpenRequest>>,
+        on_open: Option<OnOpen>,
+        keepalive: Option<Duration>,
+        inbound: Vec<u8>,
+        outbound: Vec<u8>,
+        last_heard: Instant,
+        last_ping: Instant,
+        /// How much a stream reads from its client ahead of the peer's window.
+        queue_limit: usize,
+        buffer_size: usize,
+    }
+
+    impl MuxSession {
+        pub(crate) fn new(
+            link: Link,
+            poll: Poll,
+            requests: Option<Receiver<OpenRequest>>,
+            on_open: Option<OnOpen>,
+            keepalive: Option<Duration>,
+            buffer_size: usize,
+        ) -> Self {
+            Self {
+                link,
+                poll,
+                streams: HashMap::new(),
+                next_id: 1,
+                requests,
+                on_open,
+                keepalive,
+                inbound: Vec::new(),
+                outbound: Vec::new(),
+                last_heard: Instant::now(),
+                last_ping: Instant::now(),
+                queue_limit: WINDOW_SIZE as usize,
+                buffer_size,
+            }
+        }
+
+        /// Returns once the link closes, dropping every stream with it.
+        pub(crate) fn run(&mut self) -> Result<(), Error> {
+            self.link.register(&self.poll)?;
+            let mut events = Events::with_capacity(1024);
+            loop {
+                let timeout = self
+                    .keepalive
+                    .map(|keepalive| keepalive.saturating_sub(self.last_ping.elapsed()));
+                if let Err(e) = self.poll.poll(&mut events, timeout) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+
+                for event in events.iter() {
+                    match event.token() {
+                        LINK => {
+                            if event.is_readable() {
+                                match self.link.receive(self.buffer_size) {
+                                    Ok(data) => self.receive(data)?,
+                                    Err(Error::IoError(e))
+                                        if e.kind() == ErrorKind::ConnectionAborted =>
+                                    {
+                                        return Ok(())
+                                    }
+                                    Err(e) => return Err(e),
+                                }
+                            }
+                            if event.is_read_closed() {
+                                return Ok(());
+                            }
+                            if event.is_writable() {
+                                self.link.flush()?;
+                            }
+                        }
+                        REQUESTS => {
+                            self.accept_requests()?;
+                            // clients also wake the session when they kept data back
+                            let backlogged: Vec<u32> = self
+                                .streams
+                                .iter()
+                                .filter(|(_, stream)| stream.backlog.is_some())
+                                .map(|(id, _)| *id)
+                                .collect();
+                            for id in backlogged {
+                                self.pump_forward(id);
+                            }
+                        }
+                        Token(token) => {
+                            let id = (token - FIRST_STREAM) as u32;
+                            if event.is_readable() {
+                                if let Some(stream) = self.streams.get_mut(&id) {
+                                    stream.readable = true;
+                                }
+                                self.pump_forward(id);
+                            }
+                            if event.is_writable() {
+                                self.pump_backward(id);
+                            }
+                        }
+                    }
+                }
+
+                if let Some(keepalive) = self.keepalive {
+                    if self.last_heard.elapsed() >= keepalive * KEEPALIVE_MISSES {
+                        return Err(Error::Msg("mux link timed out".to_string()));
+                    }
+                    if self.last_ping.elapsed() >= keepalive {
+                        push_frame(&mut self.outbound, PING, 0, &[]);
+                        self.last_ping = Instant::now();
+                    }
+                }
+                self.link.send(mem::take(&mut self.outbound))?;
+            }
+        }
+
+        fn accept_requests(&mut self) -> Result<(), Error> {
+            let mut opened = Vec::new();
+            if let Some(requests) = &self.requests {
+                while let Ok(request) = requests.try_recv() {
+                    opened.push(request);
+                }
+            }
+            for request in opened {
+                let id = self.next_id;
+                self.next_id = self.next_id.wrapping_add(1);
+                push_frame(
+                    &mut self.outbound,
+                    OPEN,
+                    id,
+                    &encode_destination(&request.destination),
+                );
+                self.add_stream(id, request.inner, request.backlog)?;
+                self.pump_forward(id);
+            }
+            Ok(())
+        }
+
+        fn add_stream(
+            &mut self,
+            id: u32,
+            inner: UnixStream,
+            backlog: Option<Arc<Backlog>>,
+        ) -> Result<(), Error> {
+            self.poll.registry().register(
+                &mut SourceFd(&inner.as_raw_fd()),
+                Token(FIRST_STREAM + id as usize),
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            self.streams.insert(id, MuxStream::new(inner, backlog));
+            Ok(())
+        }
+
+        fn receive(&mut self, data: Vec<u8>) -> Result<(), Error> {
+            self.last_heard = Instant::now();
+            self.inbound.extend_from_slice(&data);
+            let mut offset = 0;
+            while self.inbound.len() - offset >= HEADER_SIZE {
+                let header = &self.inbound[offset..offset + HEADER_SIZE];
+                if header[0] != VERSION {
+                    return Err(Error::Msg("unsupported mux version".to_string()));
+                }
+                let kind = header[1];
+                let id = u32::from_be_bytes([header[2], header[3], header[4], header[5]]);
+                let length =
+                    u32::from_be_bytes([header[6], header[7], header[8], header[9]]) as usize;
+                if length > MAX_PAYLOAD {
+                    return Err(Error::Msg("mux frame too large".to_string()));
+                }
+                if self.inbound.len() - offset < HEADER_SIZE + length {
+                    break;
+                }
+                let payload =
+                    self.inbound[offset + HEADER_SIZE..offset + HEADER_SIZE + length].to_vec();
+                offset += HEADER_SIZE + length;
+                self.handle_frame(kind, id, payload)?;
+            }
+            self.inbound.drain(..offset);
+            Ok(())
+        }
+
+        fn handle_frame(&mut self, kind: u8, id: u32, payload: Vec<u8>) -> Result<(), Error> {
+            match kind {
+                OPEN => self.accept_stream(id, &payload)?,
+                DATA => {
+                    let stream = match self.streams.get_mut(&id) {
+                        Some(stream) => stream,
+                        // already reset on this side
+                        None => return Ok(()),
+                    };
+                    let unacknowledged = stream.pending.len() + stream.delivered as usize;
+                    if stream.remote_closed || unacknowledged + payload.len() > WINDOW_SIZE as usize
+                    {
+                        self.reset(id);
+                        return Ok(());
+                    }
+                    stream.pending.extend_from_slice(&payload);
+                    self.pump_backward(id);
+                }
+                FIN => {
+                    if let Some(stream) = self.streams.get_mut(&id) {
+                        stream.remote_closed = true;
+                    }
+                    self.pump_backward(id);
+                }
+                RST => self.remove(id),
+                WINDOW if payload.len() == 4 => {
+                    if let Some(stream) = self.streams.get_mut(&id) {
+                        let increment =
+                            u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+                        stream.credit = stream.credit.saturating_add(increment);
+                    }
+                    self.pump_forward(id);
+                }
+                PING => push_frame(&mut self.outbound, PONG, 0, &[]),
+                PONG => {}
+                _ => return Err(Error::Msg(format!("unknown mux frame: {}", kind))),
+            }
+            Ok(())
+        }
+
+        fn accept_stream(&mut self, id: u32, payload: &[u8]) -> Result<(), Error> {
+            // only the entry side takes streams from its peer
+            if self.on_open.is_none() || self.streams.contains_key(&id) {
+                push_frame(&mut self.outbound, RST, id, &[]);
+                return Ok(());
+            }
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            self.add_stream(id, inner, None)?;
+            if let Some(on_open) = self.on_open.as_mut() {
+                on_open(outer, decode_destination(payload));
+            }
+            Ok(())
+        }
+
+        /// Moves what the client wrote to the link, as far as the peer's
+        /// window allows, and sends FIN once the client half-closed.
+        fn pump_forward(&mut self, id: u32) {
+            let mut buffer = vec![0u8; self.buffer_size];
+            let stream = match self.streams.get_mut(&id) {
+                Some(stream) => stream,
+                None => return,
+            };
+            // the client does not write to its end while this is held
+            let backlog = stream.backlog.clone();
+            let mut unsent = backlog
+                .as_ref()
+                .map(|backlog| backlog.unsent.lock().unwrap());
+            loop {
+                while stream.credit > 0 && !stream.queued.is_empty() {
+                    let size = stream
+                        .queued
+                        .len()
+                        .min(stream.credit as usize)
+                        .min(MAX_PAYLOAD);
+                    push_frame(&mut self.outbound, DATA, id, &stream.queued[..size]);
+                    stream.queued.drain(..size);
+                    stream.credit -= size as u32;
+                }
+                let backlogged = unsent
+                    .as_ref()
+                    .is_some_and(|unsent| !unsent.data.is_empty() || unsent.closed);
+                if (!stream.readable && !backlogged)
+                    || stream.read_closed
+                    || stream.queued.len() >= self.queue_limit
+                {
+                    break;
+                }
+                match stream.inner.read(&mut buffer) {
+                    Ok(0) => stream.read_closed = true,
+                    Ok(size) => stream.queued.extend_from_slice(&buffer[..size]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        stream.readable = false;
+                        // all the client wrote to its end was read, what it
+                        // kept back comes next
+                        if let (Some(unsent), Some(backlog)) = (unsent.as_mut(), &backlog) {
+                            let size = unsent
+                                .data
+                                .len()
+                                .min(self.queue_limit - stream.queued.len());
+                            stream.queued.extend(unsent.data.drain(..size));
+                            stream.read_closed = unsent.closed && unsent.data.is_empty();
+                            backlog.taken.notify_all();
+                        }
+                    }
+                    Err(_) => {
+                        // dropping the stream takes the lock
+                        drop(unsent);
+                        return self.reset(id);
+                    }
+                }
+            }
+            drop(unsent);
+            if stream.read_closed && stream.queued.is_empty() && !stream.fin_sent {
+                push_frame(&mut self.outbound, FIN, id, &[]);
+                stream.fin_sent = true;
+            }
+            self.remove_finished(id);
+        }
+
+        /// Hands what the peer sent to the client, granting the peer more
+        /// window as it goes, and half-closes the client after a FIN.
+        fn pump_backward(&mut self, id: u32) {
+            let stream = match self.streams.get_mut(&id) {
+                Some(stream) => stream,
+                None => return,
+            };
+            while !stream.pending.is_empty() {
+                match stream.inner.write(&stream.pending) {
+                    Ok(size) => {
+                        stream.pending.drain(..size);
+                        stream.delivered += size as u32;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    // the client is gone
+                    Err(_) => return self.reset(id),
+                }
+            }
+            if stream.delivered >= WINDOW_SIZE / 2 {
+                push_frame(
+                    &mut self.outbound,
+                    WINDOW,
+                    id,
+                    &stream.delivered.to_be_bytes(),
+                );
+                stream.delivered = 0;
+            }
+            if stream.remote_closed && stream.pending.is_empty() && !stream.write_closed {
+                let _ = stream.inner.shutdown(Shutdown::Write);
+                stream.write_closed = true;
+            }
+            self.remove_finished(id);
+        }
+
+        fn reset(&mut self, id: u32) {
+            push_frame(&mut self.outbound, RST, id, &[]);
+            self.remove(id);
+        }
+
+        fn remove_finished(&mut self, id: u32) {
+            if let Some(stream) = self.streams.get(&id) {
+                if stream.fin_sent && stream.write_closed {
+                    self.remove(id);
+                }
+            }
+        }
+
+        fn remove(&mut self, id: u32) {
+            if let Some(stream) = self.streams.remove(&id) {
+                let _ = self
+                    .poll
+                    .registry()
+                    .deregister(&mut SourceFd(&stream.inner.as_raw_fd()));
+            }
+        }
+    }
+
+    /// Serves a stream the peer opened with its own copy of the pipeline.
+    pub(crate) async fn serve_stream(
+        stream: UnixStream,
+        info: ConnectionInfo,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    ) -> Result<(), Error> {
+        let stream = tokio::net::UnixStream::from_std(stream)?;
+        let pipeline = tokio::task::block_in_place(|| pipeline_template.clone_with(&info))?;
+        let (reader, writer) = stream.into_split();
+        relay_stream(reader, writer, pipeline, buffer_size).await
+    }
+
+    /// Builds the `on_open` of a session that serves every stream its peer
+    /// opens on `runtime`.
+    pub(crate) fn stream_server(
+        runtime: Handle,
+        pipeline_template: Arc<Pipeline>,
+        peer: SocketAddr,
+        local: SocketAddr,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    ) -> OnOpen {
+        Box::new(move |stream, destination| {
+            let info = ConnectionInfo {
+                peer: Some(peer),
+                local: Some(local),
+                destination,
+            };
+            let pipeline_template = pipeline_template.clone();
+            runtime.spawn(async move {
+                if let Err(e) = serve_stream(stream, info, pipeline_template, buffer_size).await {
+                    if debug_level > 0 {
+                        eprintln!("an error accured serving stream of {}: {}", peer, e);
+                    }
+                }
+            });
+        })
+    }
+
+    /// Accepts links from `MuxStep`s and serves every stream opened on them
+    /// as one client with its own copy of the pipeline.
+    pub struct DemuxEntry {
+        address: String,
+        port: u16,
+        keepalive: Option<Duration>,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for DemuxEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let listener = TcpListener::bind(addr)?;
+
+            loop {
+                let (connection, peer) = match listener.accept() {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        if self.debug_level > 0 {
+                            eprintln!("an error accured accepting link: {}", e);
+                        }
+                        continue;
+                    }
+                };
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("new client: {}", peer);
+                    }
+                }
+
+                if let Err(e) = self.start_session(connection, peer, runtime.handle().clone()) {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured starting session for {}: {}", peer, e);
+                    }
+                }
+            }
+        }
+    }
+
+    impl DemuxEntry {
+        fn start_session(
+            &self,
+            connection: TcpStream,
+            peer: SocketAddr,
+            runtime: Handle,
+        ) -> Result<(), Error> {
+            let local = connection.local_addr()?;
+            connection.set_nonblocking(true)?;
+            connection.set_nodelay(true)?;
+
+            let debug_level = self.debug_level;
+            let buffer_size = self.buffer_size;
+            let on_open = stream_server(
+                runtime,
+                self.pipeline_template.clone(),
+                peer,
+                local,
+                debug_level,
+                buffer_size,
+            );
+
+            let link = Link::Tcp {
+                connection,
+                unsent: Vec::new(),
+            };
+            let mut session = MuxSession::new(
+                link,
+                Poll::new()?,
+                None,
+                Some(on_open),
+                self.keepalive,
+                buffer_size,
+            );
+            thread::spawn(move || {
+                if let Err(e) = session.run() {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on link of {}: {}", peer, e);
+                    }
+                }
+
+                {
+                    // debug
+                    if debug_level >= 2 {
+                        println!("client {} closed", peer);
+                    }
+                }
+            });
+            Ok(())
+        }
+    }
+
+    impl EntryStatic<DemuxEntry> for DemuxEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<DemuxEntry, Error> {
+            let address = match args.argument_values.get(DEMUX_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(DEMUX_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(DEMUX_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(DEMUX_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(DemuxEntry {
+                address,
+                port,
+                keepalive: parse_keepalive(&args)?,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: DEMUX_ENTRY_ADDRESS.0.to_string(),
+                key: vec![DEMUX_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(DEMUX_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: DEMUX_ENTRY_PORT.0.to_string(),
+                key: vec![DEMUX_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("8090".to_string()),
+                help: Some(ArgumentHelp::Text(DEMUX_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: MUX_KEEPALIVE.0.to_string(),
+                key: vec![MUX_KEEPALIVE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("30".to_string()),
+                help: Some(ArgumentHelp::Text(MUX_KEEPALIVE.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    struct SessionHandle {
+        requests: Sender<OpenRequest>,
+        waker: Waker,
+    }
+
+    /// The steps behind a `MuxStep` and the session running on them, shared
+    /// by all of its clones.
+    struct MuxLink {
+        /// Taken by the running session and given back when it ends.
+        steps: Option<Pipeline>,
+        /// The steps carried a session before, so the next one redials.
+        dialed: bool,
+        session: Option<SessionHandle>,
+        keepalive: Option<Duration>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl MuxLink {
+        fn start(shared: &Arc<Mutex<MuxLink>>, link: &mut MuxLink) -> Result<(), Error> {
+            let steps = match link.steps.take() {
+                Some(steps) if !steps.is_empty() => steps,
+                steps => {
+                    link.steps = steps;
+                    return Err(Error::Msg(
+                        "the mux step needs steps behind it to carry its link".to_string(),
+                    ));
+                }
+            };
+            let steps = if link.dialed { steps.clone() } else { steps };
+            link.dialed = true;
+
+            let poll = Poll::new()?;
+            let waker = Waker::new(poll.registry(), REQUESTS)?;
+            let (requests, receiver) = channel();
+            let mut session = MuxSession::new(
+                Link::Pipeline(steps),
+                poll,
+                Some(receiver),
+                None,
+                link.keepalive,
+                link.buffer_size,
+            );
+            let debug_level = link.debug_level;
+            let shared = shared.clone();
+            thread::spawn(move || {
+                if let Err(e) = session.run() {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on mux link: {}", e);
+                    }
+                }
+                let mut link = shared.lock().unwrap();
+                if let Link::Pipeline(steps) = session.link {
+                    link.steps = Some(steps);
+                }
+                link.session = None;
+            });
+
+            link.session = Some(SessionHandle { requests, waker });
+            Ok(())
+        }
+    }
+
+    /// Carries each client as one stream of a link shared by all of them,
+    /// made of the steps added behind this one.
+    pub struct MuxStep {
+        shared: Arc<Mutex<MuxLink>>,
+        destination: Option<(String, u16)>,
+        /// The stream this client gets on the shared link.
+        stream: LazyStream,
+        /// What the stream had no room for yet.
+        backlog: Arc<Backlog>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl MuxStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+
+            let mut link = self.shared.lock().unwrap();
+            if link.session.is_none() {
+                MuxLink::start(&self.shared, &mut link)?;
+            }
+            if let Some(session) = &link.session {
+                let request = OpenRequest {
+                    inner,
+                    destination: self.destination.clone(),
+                    backlog: Some(self.backlog.clone()),
+                };
+                if session.requests.send(request).is_err() {
+                    return Err(Error::Msg("mux link closed".to_string()));
+                }
+                session.waker.wake()?;
+            }
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("mux stream", self.debug_level, || self.open())
+        }
+
+        /// Tells the session to take what was kept back in the backlog.
+        fn wake(&self) -> Result<(), Error> {
+            match &self.shared.lock().unwrap().session {
+                Some(session) => Ok(session.waker.wake()?),
+                None => Err(Error::Msg("mux link closed".to_string())),
+            }
+        }
+
+        fn with_destination(&self, destination: Option<(String, u16)>) -> Self {
+            Self {
+                shared: self.shared.clone(),
+                destination,
+                stream: LazyStream::new(),
+                backlog: Arc::new(Backlog::default()),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl Step for MuxStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            // the entry's other clients, and the answers to this one, wait
+            // while it blocks, so a full stream is only waited for once the
+            // backlog is full too
+            self.backlog.write(self.stream()?, data, || self.wake())?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.backlog.close(self.stream()?, || self.wake())
+        }
+
+        fn adopt_step(&mut self, step: Box<dyn Step>) -> Option<Box<dyn Step>> {
+            let mut link = self.shared.lock().unwrap();
+            match link.steps.as_mut() {
+                Some(steps) => {
+                    steps.add_step(step);
+                    None
+                }
+                None => Some(step),
+            }
+        }
+    }
+
+    impl BoxedClone for MuxStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        /// Passes the destination the client asked the entry for to the
+        /// other side, whose steps dial it in place of their own endpoint.
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            Ok(Box::new(self.with_destination(info.destination.clone())))
+        }
+    }
+
+    impl StepStatic for MuxStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let link = MuxLink {
+                steps: Some(Pipeline::new()),
+                dialed: false,
+                session: None,
+                keepalive: parse_keepalive(&args)?,
+                debug_level,
+                buffer_size,
+            };
+            Ok(Self {
+                shared: Arc::new(Mutex::new(link)),
+                destination: None,
+                stream: LazyStream::new(),
+                backlog: Arc::new(Backlog::default()),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(argument: CliSpec) -> CliSpec {
+            // the keepalive option is shared with DemuxEntry, which adds it
+            argument
+        }
+    }
+
+    impl Clone for MuxStep {
+        fn clone(&self) -> Self {
+            self.with_destination(self.destination.clone())
+        }
+    }
+
+    impl AsRawFd for MuxStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        /// A session over a loopback connection nobody reads, frames are fed
+        /// to `receive` directly and its answers taken from `outbound`.
+        fn session(on_open: Option<OnOpen>) -> MuxSession {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let connection = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+            connection.set_nonblocking(true).unwrap();
+            let link = Link::Tcp {
+                connection,
+                unsent: Vec::new(),
+            };
+            MuxSession::new(link, Poll::new().unwrap(), None, on_open, None, 4096)
+        }
+
+        fn frame(kind: u8, id: u32, payload: &[u8]) -> Vec<u8> {
+            let mut frame = Vec::new();
+            push_frame(&mut frame, kind, id, payload);
+            frame
+        }
+
+        /// Takes the frames `session` queued for its link, as kind and payload.
+        fn sent_frames(session: &mut MuxSession) -> Vec<(u8, Vec<u8>)> {
+            let outbound = mem::take(&mut session.outbound);
+            let mut frames = Vec::new();
+            let mut offset = 0;
+            while offset < outbound.len() {
+                let header = &outbound[offset..offset + HEADER_SIZE];
+                let length = u32::from_be_bytes([header[6], header[7], header[8], header[9]]);
+                let end = offset + HEADER_SIZE + length as usize;
+                frames.push((header[1], outbound[offset + HEADER_SIZE..end].to_vec()));
+                offset = end;
+            }
+            frames
+        }
+
+        #[test]
+        fn frame_header_layout() {
+            assert_eq!(
+                frame(DATA, 0x01020304, b"ab"),
+                [VERSION, DATA, 1, 2, 3, 4, 0, 0, 0, 2, b'a', b'b']
+            );
+        }
+
+        #[test]
+        fn destination_round_trip() {
+            let destination = Some(("example.com".to_string(), 443));
+            assert_eq!(
+                decode_destination(&encode_destination(&destination)),
+                destination
+            );
+            assert_eq!(encode_destination(&None), b"");
+            assert_eq!(decode_destination(b""), None);
+        }
+
+        #[test]
+        fn frames_split_at_every_byte() {
+            let (opened, streams) = channel();
+            let on_open: OnOpen = Box::new(move |outer, destination| {
+                opened.send((outer, destination)).unwrap();
+            });
+            let mut session = session(Some(on_open));
+
+            let destination = Some(("example.com".to_string(), 443));
+            let mut data = frame(OPEN, 7, &encode_destination(&destination));
+            data.extend(frame(DATA, 7, b"hello"));
+            data.extend(frame(FIN, 7, &[]));
+            for byte in data {
+                session.receive(vec![byte]).unwrap();
+            }
+            assert!(session.inbound.is_empty());
+
+            let (mut outer, received) = streams.try_recv().unwrap();
+            assert_eq!(received, destination);
+            outer.set_nonblocking(false).unwrap();
+            let mut delivered = Vec::new();
+            outer.read_to_end(&mut delivered).unwrap();
+            assert_eq!(delivered, b"hello");
+        }
+
+        #[test]
+        fn backlog_follows_what_was_written() {
+            let mut session = session(None);
+            let (inner, outer) = UnixStream::pair().unwrap();
+            inner.set_nonblocking(true).unwrap();
+            outer.set_nonblocking(true).unwrap();
+            // a small socket buffer, so that most of the data is kept back
+            let size: libc::c_int = 4096;
+            unsafe {
+                libc::setsockopt(
+                    outer.as_raw_fd(),
+                    libc::SOL_SOCKET,
+                    libc::SO_SNDBUF,
+                    &size as *const libc::c_int as *const libc::c_void,
+                    mem::size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            let backlog = Arc::new(Backlog::default());
+            session.add_stream(1, inner, Some(backlog.clone())).unwrap();
+
+            // more than one window, so the peer has to grant more on the way
+            let data: Vec<u8> = (0..WINDOW_SIZE as usize + 65536)
+                .map(|i| (i % 251) as u8)
+                .collect();
+            let (first, second) = data.split_at(data.len() / 2);
+            let woken = AtomicUsize::new(0);
+            let wake = || {
+                woken.fetch_add(1, Ordering::Relaxed);
+                Ok(())
+            };
+            let mut received = Vec::new();
+            let mut fin = false;
+            // sends what the session has for the link, and grants it all
+            let mut pump = |session: &mut MuxSession| {
+                session.pump_forward(1);
+                let mut granted = 0u32;
+                for (kind, payload) in sent_frames(session) {
+                    match kind {
+                        DATA => {
+                            granted += payload.len() as u32;
+                            received.extend(payload);
+                        }
+                        FIN => fin = true,
+                        kind => panic!("unexpected frame {}", kind),
+                    }
+                }
+                session
+                    .receive(frame(WINDOW, 1, &granted.to_be_bytes()))
+                    .unwrap();
+                granted
+            };
+
+            backlog.write(&outer, first, wake).unwrap();
+            assert!(!backlog.unsent.lock().unwrap().data.is_empty());
+            assert!(pump(&mut session) > 0);
+            backlog.write(&outer, second, wake).unwrap();
+            backlog.close(&outer, wake).unwrap();
+            assert_eq!(woken.load(Ordering::Relaxed), 3);
+            while pump(&mut session) > 0 {}
+            assert!(fin);
+            assert_eq!(received, data);
+        }
+
+        #[test]
+        fn ping_is_answered() {
+            let mut session = session(None);
+            session.receive(frame(PING, 0, &[])).unwrap();
+            assert_eq!(session.outbound, frame(PONG, 0, &[]));
+        }
+
+        #[test]
+        fn open_without_acceptor_is_reset() {
+            let mut session = session(None);
+            session.receive(frame(OPEN, 3, &[])).unwrap();
+            assert_eq!(session.outbound, frame(RST, 3, &[]));
+        }
+
+        #[test]
+        fn bad_frames_are_rejected() {
+            let mut data = frame(PING, 0, &[]);
+            data[0] = VERSION + 1;
+            assert!(session(None).receive(data).is_err());
+
+            let mut data = frame(DATA, 1, &[]);
+            data[6..10].copy_from_slice(&(MAX_PAYLOAD as u32 + 1).to_be_bytes());
+            assert!(session(None).receive(data).is_err());
+
+            assert!(session(None).receive(frame(0xFF, 1, &[])).is_err());
+        }
+    }
+}
@@ -0,0 +1,553 @@
+pub mod proxy {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::{IpAddr, Shutdown};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::str::FromStr;
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    use base64::Engine;
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::net::TcpStream;
+
+    use crate::connect::connect::parse_authority;
+    use crate::socks5::socks5::{
+        ATYP_DOMAIN, ATYP_IPV4, ATYP_IPV6, AUTH_VERSION, CMD_CONNECT, METHOD_NO_AUTH,
+        METHOD_PASSWORD, REPLY_SUCCEEDED, VERSION,
+    };
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{base::base::DebugLevel, BoxedClone, ConnectionInfo, Error, Step, StepStatic};
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const SOCKS5_STEP_PROXY: (&str, &str, &str) = (
+        "socks5-step-proxy",
+        "--socks5-sproxy",
+        "(Socks5Step) Parent proxy as [socks5://|http://][user:password@]host:port, repeat for a chain",
+    );
+    const SOCKS5_STEP_ADDRESS: (&str, &str, &str) = (
+        "socks5-step-address",
+        "--socks5-sa",
+        "(Socks5Step) Target address reached through the proxies",
+    );
+    const SOCKS5_STEP_PORT: (&str, &str, &str) = (
+        "socks5-step-port",
+        "--socks5-sp",
+        "(Socks5Step) Target port reached through the proxies",
+    );
+
+    const CONNECT_STEP_PROXY: (&str, &str, &str) = (
+        "connect-step-proxy",
+        "--connect-sproxy",
+        "(HttpConnectStep) Parent proxy as [http://|socks5://][user:password@]host:port, repeat for a chain",
+    );
+    const CONNECT_STEP_ADDRESS: (&str, &str, &str) = (
+        "connect-step-address",
+        "--connect-sa",
+        "(HttpConnectStep) Target address reached through the proxies",
+    );
+    const CONNECT_STEP_PORT: (&str, &str, &str) = (
+        "connect-step-port",
+        "--connect-sp",
+        "(HttpConnectStep) Target port reached through the proxies",
+    );
+
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum ProxyProtocol {
+        Socks5,
+        HttpConnect,
+    }
+
+    #[derive(Clone)]
+    struct Hop {
+        protocol: ProxyProtocol,
+        address: String,
+        port: u16,
+        credentials: Option<(String, String)>,
+    }
+
+    impl Hop {
+        /// Parses `[scheme://][user:password@]host:port`, hops without a
+        /// scheme speak the protocol of the step they were given to.
+        fn parse(value: &str, protocol: ProxyProtocol) -> Result<Hop, Error> {
+            let invalid = || Error::Msg(format!("invalid proxy: {}", value));
+            let (protocol, rest) = match value.split_once("://") {
+                Some(("socks5", rest)) => (ProxyProtocol::Socks5, rest),
+                Some(("http", rest)) => (ProxyProtocol::HttpConnect, rest),
+                Some(_) => return Err(invalid()),
+                None => (protocol, value),
+            };
+            let (credentials, authority) = match rest.rsplit_once('@') {
+                Some((credentials, authority)) => match credentials.split_once(':') {
+                    Some((user, password)) => {
+                        (Some((user.to_string(), password.to_string())), authority)
+                    }
+                    None => return Err(invalid()),
+                },
+                None => (None, rest),
+            };
+            let (address, port) = parse_authority(authority).ok_or_else(invalid)?;
+            Ok(Hop {
+                protocol,
+                address,
+                port,
+                credentials,
+            })
+        }
+
+        /// Asks this proxy, reached over `connection`, to open a tunnel to
+        /// `address:port`.
+        fn handshake(
+            &self,
+            connection: &mut std::net::TcpStream,
+            address: &str,
+            port: u16,
+        ) -> Result<(), Error> {
+            match self.protocol {
+                ProxyProtocol::Socks5 => self.socks5_handshake(connection, address, port),
+                ProxyProtocol::HttpConnect => self.http_handshake(connection, address, port),
+            }
+        }
+
+        fn socks5_handshake(
+            &self,
+            connection: &mut std::net::TcpStream,
+            address: &str,
+            port: u16,
+        ) -> Result<(), Error> {
+            match &self.credentials {
+                Some(_) => connection.write_all(&[VERSION, 2, METHOD_NO_AUTH, METHOD_PASSWORD])?,
+                None => connection.write_all(&[VERSION, 1, METHOD_NO_AUTH])?,
+            }
+            let mut choice = [0u8; 2];
+            connection.read_exact(&mut choice)?;
+            if choice[0] != VERSION {
+                return Err(self.error("is not a socks5 proxy"));
+            }
+            match (choice[1], &self.credentials) {
+                (METHOD_NO_AUTH, _) => {}
+                (METHOD_PASSWORD, Some((user, password))) => {
+                    let mut request = vec![AUTH_VERSION];
+                    request.extend(string_field(user.as_bytes())?);
+                    request.extend(string_field(password.as_bytes())?);
+                    connection.write_all(&request)?;
+                    let mut status = [0u8; 2];
+                    connection.read_exact(&mut status)?;
+                    if status[0] != AUTH_VERSION {
+                        return Err(self.error("replied an unknown auth version"));
+                    }
+                    if status[1] != 0 {
+                        return Err(self.error("rejected the credentials"));
+                    }
+                }
+                _ => return Err(self.error("accepts none of the offered auth methods")),
+            }
+
+            let mut request = vec![VERSION, CMD_CONNECT, 0x00];
+            match IpAddr::from_str(address) {
+                Ok(IpAddr::V4(ip)) => {
+                    request.push(ATYP_IPV4);
+                    request.extend_from_slice(&ip.octets());
+                }
+                Ok(IpAddr::V6(ip)) => {
+                    request.push(ATYP_IPV6);
+                    request.extend_from_slice(&ip.octets());
+                }
+                Err(_) => {
+                    request.push(ATYP_DOMAIN);
+                    request.extend(string_field(address.as_bytes())?);
+                }
+            }
+            request.extend_from_slice(&port.to_be_bytes());
+            connection.write_all(&request)?;
+
+            let mut reply = [0u8; 4];
+            connection.read_exact(&mut reply)?;
+            if reply[0] != VERSION {
+                return Err(self.error("replied an unknown socks version"));
+            }
+            if reply[1] != REPLY_SUCCEEDED {
+                return Err(self.error(&format!("replied {:#04x}", reply[1])));
+            }
+            // skip the bound address
+            let length = match reply[3] {
+                ATYP_IPV4 => 4,
+                ATYP_IPV6 => 16,
+                ATYP_DOMAIN => {
+                    let mut length = [0u8; 1];
+                    connection.read_exact(&mut length)?;
+                    length[0] as usize
+                }
+                _ => return Err(self.error("replied an unknown address type")),
+            };
+            let mut bound = vec![0u8; length + 2];
+            connection.read_exact(&mut bound)?;
+            Ok(())
+        }
+
+        fn http_handshake(
+            &self,
+            connection: &mut std::net::TcpStream,
+            address: &str,
+            port: u16,
+        ) -> Result<(), Error> {
+            let authority = match address.contains(':') {
+                true => format!("[{}]:{}", address, port),
+                false => format!("{}:{}", address, port),
+            };
+            let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+            if let Some((user, password)) = &self.credentials {
+                let credentials = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", user, password));
+                request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+            }
+            request.push_str("\r\n");
+            connection.write_all(request.as_bytes())?;
+
+            // read byte by byte so no tunnelled bytes are consumed
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+            while !head.ends_with(b"\r\n\r\n") {
+                connection.read_exact(&mut byte)?;
+                head.push(byte[0]);
+                if head.len() > 64 * 1024 {
+                    return Err(self.error("sent a response head that is too large"));
+                }
+            }
+            let mut headers = [httparse::EMPTY_HEADER; 64];
+            let mut response = httparse::Response::new(&mut headers);
+            match response.parse(&head) {
+                Ok(httparse::Status::Complete(_)) => {}
+                _ => return Err(self.error("sent an invalid response")),
+            }
+            match response.code {
+                Some(code) if (200..300).contains(&code) => Ok(()),
+                code => Err(self.error(&format!("replied {}", code.unwrap_or(0)))),
+            }
+        }
+
+        fn error(&self, message: &str) -> Error {
+            Error::Msg(format!("proxy {}:{} {}", self.address, self.port, message))
+        }
+    }
+
+    /// `value` prefixed with its length, as socks5 sends names and credentials.
+    fn string_field(value: &[u8]) -> Result<Vec<u8>, Error> {
+        if value.len() > u8::MAX as usize {
+            return Err(Error::Msg("socks5 field longer than 255 bytes".to_string()));
+        }
+        let mut field = vec![value.len() as u8];
+        field.extend_from_slice(value);
+        Ok(field)
+    }
+
+    /// Reaches `address:port` through every hop of `proxies` in turn: each
+    /// proxy is asked to open a tunnel to the next one, the last one to the
+    /// target.
+    fn dial(proxies: &[Hop], address: &str, port: u16) -> Result<TcpStream, Error> {
+        let first = proxies
+            .first()
+            .ok_or_else(|| Error::Msg("no proxy configured".to_string()))?;
+        let addr = create_socket_addr(first.address.as_str(), first.port)?;
+        let mut connection = std::net::TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?;
+        connection.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        connection.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        for (index, hop) in proxies.iter().enumerate() {
+            match proxies.get(index + 1) {
+                Some(next) => hop.handshake(&mut connection, next.address.as_str(), next.port)?,
+                None => hop.handshake(&mut connection, address, port)?,
+            }
+        }
+        connection.set_read_timeout(None)?;
+        connection.set_write_timeout(None)?;
+        connection.set_nonblocking(true)?;
+        Ok(TcpStream::from_std(connection))
+    }
+
+    /// Connection through a chain of parent proxies, shared by the socks5
+    /// and http CONNECT steps which only differ in their default protocol.
+    struct ProxyConnection {
+        proxies: Vec<Hop>,
+        address: String,
+        port: u16,
+        /// The tunnel, opened when the step is first used.
+        connection: OnceLock<Option<TcpStream>>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl ProxyConnection {
+        fn new(
+            args: CliParsed,
+            debug_level: DebugLevel,
+            protocol: ProxyProtocol,
+            options: [(&str, &str, &str); 3],
+        ) -> Result<Self, Error> {
+            let [proxy_option, address_option, port_option] = options;
+            let proxies = match args.argument_values.get(proxy_option.0) {
+                Some(proxies) => proxies
+                    .iter()
+                    .map(|proxy| Hop::parse(proxy, protocol))
+                    .collect::<Result<Vec<Hop>, Error>>()?,
+                None => return Err(Error::RequireOption(proxy_option.0.to_string())),
+            };
+            let address = match args.argument_values.get(address_option.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(address_option.0.to_string())),
+            };
+            let port = match args.argument_values.get(port_option.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(port_option.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Self {
+                proxies,
+                address,
+                port,
+                connection: OnceLock::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        /// Dials the target, logging a failure and leaving the step
+        /// unconnected so that the entry drops the client instead of exiting.
+        fn try_dial(&self) -> Option<TcpStream> {
+            match dial(&self.proxies, self.address.as_str(), self.port) {
+                Ok(connection) => Some(connection),
+                Err(e) => {
+                    if self.debug_level > 0 {
+                        eprintln!(
+                            "an error accured connecting to {}:{}: {}",
+                            self.address, self.port, e
+                        );
+                    }
+                    None
+                }
+            }
+        }
+
+        fn tunnel(&self) -> Option<&TcpStream> {
+            self.connection.get_or_init(|| self.try_dial()).as_ref()
+        }
+
+        fn connection(&mut self) -> Result<&mut TcpStream, Error> {
+            self.tunnel();
+            self.connection
+                .get_mut()
+                .and_then(Option::as_mut)
+                .ok_or_else(|| Error::IoError(ErrorKind::NotConnected.into()))
+        }
+
+        fn forward(&mut self, data: &[u8]) -> Result<Vec<u8>, Error> {
+            write_all(self.connection()?, data)?;
+            Ok(data.to_vec())
+        }
+
+        fn backward(&mut self) -> Result<Vec<u8>, Error> {
+            let buffer_size = self.buffer_size;
+            read_available(self.connection()?, buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.connection()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+
+        /// Dials the destination the client asked the entry for, if any, in
+        /// place of the configured target.
+        fn clone_with(&self, info: &ConnectionInfo) -> Result<Self, Error> {
+            let (address, port) = match &info.destination {
+                Some((address, port)) => (address.clone(), *port),
+                None => (self.address.clone(), self.port),
+            };
+            let connection = dial(&self.proxies, address.as_str(), port)?;
+            Ok(Self {
+                proxies: self.proxies.clone(),
+                address,
+                port,
+                connection: OnceLock::from(Some(connection)),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            })
+        }
+    }
+
+    impl Clone for ProxyConnection {
+        fn clone(&self) -> Self {
+            Self {
+                proxies: self.proxies.clone(),
+                address: self.address.clone(),
+                port: self.port,
+                connection: OnceLock::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for ProxyConnection {
+        fn as_raw_fd(&self) -> RawFd {
+            match self.tunnel() {
+                Some(connection) => connection.as_raw_fd(),
+                None => -1,
+            }
+        }
+    }
+
+    fn add_arguments(
+        mut argument: CliSpec,
+        options: [(&str, &str, &str); 3],
+        default_proxy: &str,
+    ) -> CliSpec {
+        let [proxy_option, address_option, port_option] = options;
+        argument = argument.add_argument(Argument {
+            name: proxy_option.0.to_string(),
+            key: vec![proxy_option.1.to_string()],
+            argument_occurrence: ArgumentOccurrence::Multiple,
+            value_type: ArgumentValueType::Single,
+            default_value: Some(default_proxy.to_string()),
+            help: Some(ArgumentHelp::Text(proxy_option.2.to_string())),
+        });
+        argument = argument.add_argument(Argument {
+            name: address_option.0.to_string(),
+            key: vec![address_option.1.to_string()],
+            argument_occurrence: ArgumentOccurrence::Single,
+            value_type: ArgumentValueType::Single,
+            default_value: Some("127.0.0.1".to_string()),
+            help: Some(ArgumentHelp::Text(address_option.2.to_string())),
+        });
+        argument = argument.add_argument(Argument {
+            name: port_option.0.to_string(),
+            key: vec![port_option.1.to_string()],
+            argument_occurrence: ArgumentOccurrence::Single,
+            value_type: ArgumentValueType::Single,
+            default_value: Some("80".to_string()),
+            help: Some(ArgumentHelp::Text(port_option.2.to_string())),
+        });
+        argument
+    }
+
+    /// Reaches its target through a socks5 parent proxy, or a chain of them.
+    #[derive(Clone)]
+    pub struct Socks5Step(ProxyConnection);
+
+    /// Reaches its target through an http CONNECT parent proxy, or a chain of
+    /// them.
+    #[derive(Clone)]
+    pub struct HttpConnectStep(ProxyConnection);
+
+    impl StepStatic for Socks5Step {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            Ok(Socks5Step(ProxyConnection::new(
+                args,
+                debug_level,
+                ProxyProtocol::Socks5,
+                [SOCKS5_STEP_PROXY, SOCKS5_STEP_ADDRESS, SOCKS5_STEP_PORT],
+            )?))
+        }
+
+        fn get_cmd(argument: CliSpec) -> CliSpec {
+            add_arguments(
+                argument,
+                [SOCKS5_STEP_PROXY, SOCKS5_STEP_ADDRESS, SOCKS5_STEP_PORT],
+                "127.0.0.1:1080",
+            )
+        }
+    }
+
+    impl StepStatic for HttpConnectStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            Ok(HttpConnectStep(ProxyConnection::new(
+                args,
+                debug_level,
+                ProxyProtocol::HttpConnect,
+                [CONNECT_STEP_PROXY, CONNECT_STEP_ADDRESS, CONNECT_STEP_PORT],
+            )?))
+        }
+
+        fn get_cmd(argument: CliSpec) -> CliSpec {
+            add_arguments(
+                argument,
+                [CONNECT_STEP_PROXY, CONNECT_STEP_ADDRESS, CONNECT_STEP_PORT],
+                "127.0.0.1:8080",
+            )
+        }
+    }
+
+    impl Step for Socks5Step {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            self.0.forward(data)
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            self.0.backward()
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.0.close_forward()
+        }
+    }
+
+    impl Step for HttpConnectStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            self.0.forward(data)
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            self.0.backward()
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.0.close_forward()
+        }
+    }
+
+    impl BoxedClone for Socks5Step {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            Ok(Box::new(Socks5Step(self.0.clone_with(info)?)))
+        }
+    }
+
+    impl BoxedClone for HttpConnectStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            Ok(Box::new(HttpConnectStep(self.0.clone_with(info)?)))
+        }
+    }
+
+    impl AsRawFd for Socks5Step {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+
+    impl AsRawFd for HttpConnectStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0.as_raw_fd()
+        }
+    }
+}
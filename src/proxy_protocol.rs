@@ -0,0 +1,302 @@
+pub mod proxy_protocol {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::str::FromStr;
+
+    use crate::Error;
+
+    const V1_PREFIX: &[u8] = b"PROXY ";
+    /// A v1 header including its CRLF never exceeds this.
+    const V1_MAX_SIZE: usize = 107;
+    const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+    const V2_VERSION: u8 = 0x20;
+    const V2_LOCAL: u8 = 0x00;
+    const V2_PROXY: u8 = 0x01;
+    const V2_TCP4: u8 = 0x11;
+    const V2_UDP4: u8 = 0x12;
+    const V2_TCP6: u8 = 0x21;
+    const V2_UDP6: u8 = 0x22;
+
+    /// Largest header `parse` may need to see, a v2 header with all the TLVs
+    /// its length field allows.
+    pub(crate) const MAX_HEADER_SIZE: usize = 16 + u16::MAX as usize;
+
+    #[derive(Clone, Copy, PartialEq)]
+    pub(crate) enum ProxyVersion {
+        V1,
+        V2,
+    }
+
+    impl FromStr for ProxyVersion {
+        type Err = Error;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "1" | "v1" => Ok(ProxyVersion::V1),
+                "2" | "v2" => Ok(ProxyVersion::V2),
+                other => Err(Error::Msg(format!(
+                    "unknown PROXY protocol version: {}",
+                    other
+                ))),
+            }
+        }
+    }
+
+    pub(crate) struct ProxyHeader {
+        /// The original client and the address it connected to, `None` for
+        /// health checks (v2 LOCAL) and unknown protocols.
+        pub(crate) addresses: Option<(SocketAddr, SocketAddr)>,
+        /// Length of the header, the connection data starts right after it.
+        pub(crate) size: usize,
+    }
+
+    /// Parses a v1 or v2 header at the start of `data`, or returns `None`
+    /// while more data is needed to tell.
+    pub(crate) fn parse(data: &[u8]) -> Result<Option<ProxyHeader>, Error> {
+        if data.len() < V2_SIGNATURE.len() && V2_SIGNATURE.starts_with(data) {
+            return Ok(None);
+        }
+        if data.starts_with(V2_SIGNATURE) {
+            return parse_v2(data);
+        }
+        if data.len() < V1_PREFIX.len() && V1_PREFIX.starts_with(data) {
+            return Ok(None);
+        }
+        if data.starts_with(V1_PREFIX) {
+            return parse_v1(data);
+        }
+        Err(invalid("missing PROXY protocol header"))
+    }
+
+    fn invalid(reason: &str) -> Error {
+        Error::Msg(reason.to_string())
+    }
+
+    fn parse_v1(data: &[u8]) -> Result<Option<ProxyHeader>, Error> {
+        let end = match data.windows(2).position(|window| window == b"\r\n") {
+            Some(end) if end + 2 <= V1_MAX_SIZE => end,
+            Some(_) => return Err(invalid("PROXY protocol v1 header too long")),
+            None if data.len() >= V1_MAX_SIZE => {
+                return Err(invalid("PROXY protocol v1 header too long"))
+            }
+            None => return Ok(None),
+        };
+        let line = std::str::from_utf8(&data[..end])
+            .map_err(|_| invalid("invalid PROXY protocol v1 header"))?;
+        let fields: Vec<&str> = line.split(' ').collect();
+        let addresses = match fields.get(1) {
+            Some(&"UNKNOWN") => None,
+            Some(&"TCP4") | Some(&"TCP6") if fields.len() == 6 => {
+                let address = |ip: &str, port: &str| -> Option<SocketAddr> {
+                    let ip = IpAddr::from_str(ip).ok()?;
+                    let port = str::parse::<u16>(port).ok()?;
+                    Some(SocketAddr::new(ip, port))
+                };
+                match (address(fields[2], fields[4]), address(fields[3], fields[5])) {
+                    (Some(source), Some(destination)) => Some((source, destination)),
+                    _ => return Err(invalid("invalid PROXY protocol v1 address")),
+                }
+            }
+            _ => return Err(invalid("invalid PROXY protocol v1 header")),
+        };
+        Ok(Some(ProxyHeader {
+            addresses,
+            size: end + 2,
+        }))
+    }
+
+    fn parse_v2(data: &[u8]) -> Result<Option<ProxyHeader>, Error> {
+        if data.len() < 16 {
+            return Ok(None);
+        }
+        let length = u16::from_be_bytes([data[14], data[15]]) as usize;
+        if data.len() < 16 + length {
+            return Ok(None);
+        }
+        if data[12] & 0xF0 != V2_VERSION {
+            return Err(invalid("unsupported PROXY protocol version"));
+        }
+        let payload = &data[16..16 + length];
+        let addresses = match (data[12] & 0x0F, data[13]) {
+            (V2_LOCAL, _) => None,
+            (V2_PROXY, V2_TCP4) | (V2_PROXY, V2_UDP4) if length >= 12 => {
+                let ip = |at: usize| {
+                    IpAddr::V4(Ipv4Addr::new(
+                        payload[at],
+                        payload[at + 1],
+                        payload[at + 2],
+                        payload[at + 3],
+                    ))
+                };
+                let port = |at: usize| u16::from_be_bytes([payload[at], payload[at + 1]]);
+                Some((
+                    SocketAddr::new(ip(0), port(8)),
+                    SocketAddr::new(ip(4), port(10)),
+                ))
+            }
+            (V2_PROXY, V2_TCP6) | (V2_PROXY, V2_UDP6) if length >= 36 => {
+                let ip = |at: usize| {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(&payload[at..at + 16]);
+                    IpAddr::V6(Ipv6Addr::from(octets))
+                };
+                let port = |at: usize| u16::from_be_bytes([payload[at], payload[at + 1]]);
+                Some((
+                    SocketAddr::new(ip(0), port(32)),
+                    SocketAddr::new(ip(16), port(34)),
+                ))
+            }
+            // unix sockets and unspecified families carry nothing usable
+            (V2_PROXY, _) => None,
+            _ => return Err(invalid("invalid PROXY protocol v2 command")),
+        };
+        Ok(Some(ProxyHeader {
+            addresses,
+            size: 16 + length,
+        }))
+    }
+
+    /// Builds the header announcing a connection from `source` to
+    /// `destination`, or one without addresses when either is unknown or
+    /// they are of different families.
+    pub(crate) fn encode(
+        version: ProxyVersion,
+        source: Option<SocketAddr>,
+        destination: Option<SocketAddr>,
+    ) -> Vec<u8> {
+        let canonical =
+            |address: SocketAddr| SocketAddr::new(address.ip().to_canonical(), address.port());
+        let addresses = match (source.map(canonical), destination.map(canonical)) {
+            (Some(source), Some(destination)) if source.is_ipv4() == destination.is_ipv4() => {
+                Some((source, destination))
+            }
+            _ => None,
+        };
+        match version {
+            ProxyVersion::V1 => encode_v1(addresses),
+            ProxyVersion::V2 => encode_v2(addresses),
+        }
+    }
+
+    fn encode_v1(addresses: Option<(SocketAddr, SocketAddr)>) -> Vec<u8> {
+        match addresses {
+            Some((source, destination)) => format!(
+                "PROXY {} {} {} {} {}\r\n",
+                if source.is_ipv4() { "TCP4" } else { "TCP6" },
+                source.ip(),
+                destination.ip(),
+                source.port(),
+                destination.port()
+            )
+            .into_bytes(),
+            None => b"PROXY UNKNOWN\r\n".to_vec(),
+        }
+    }
+
+    fn encode_v2(addresses: Option<(SocketAddr, SocketAddr)>) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        let (command, family, mut payload) = match addresses {
+            Some((SocketAddr::V4(source), SocketAddr::V4(destination))) => {
+                let mut payload = source.ip().octets().to_vec();
+                payload.extend_from_slice(&destination.ip().octets());
+                (V2_PROXY, V2_TCP4, payload)
+            }
+            Some((SocketAddr::V6(source), SocketAddr::V6(destination))) => {
+                let mut payload = source.ip().octets().to_vec();
+                payload.extend_from_slice(&destination.ip().octets());
+                (V2_PROXY, V2_TCP6, payload)
+            }
+            _ => (V2_LOCAL, 0x00, Vec::new()),
+        };
+        if let Some((source, destination)) = addresses {
+            payload.extend_from_slice(&source.port().to_be_bytes());
+            payload.extend_from_slice(&destination.port().to_be_bytes());
+        }
+        header.push(V2_VERSION | command);
+        header.push(family);
+        header.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        header.extend_from_slice(&payload);
+        header
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn address(text: &str) -> SocketAddr {
+            SocketAddr::from_str(text).unwrap()
+        }
+
+        fn round_trip(version: ProxyVersion, source: &str, destination: &str) {
+            let (source, destination) = (address(source), address(destination));
+            let mut data = encode(version, Some(source), Some(destination));
+            let size = data.len();
+            data.extend_from_slice(b"payload");
+            let header = parse(&data).unwrap().unwrap();
+            assert_eq!(header.addresses, Some((source, destination)));
+            assert_eq!(header.size, size);
+        }
+
+        #[test]
+        fn v1_round_trip() {
+            round_trip(ProxyVersion::V1, "192.0.2.1:51000", "198.51.100.2:443");
+            round_trip(ProxyVersion::V1, "[2001:db8::1]:51000", "[2001:db8::2]:443");
+        }
+
+        #[test]
+        fn v2_round_trip() {
+            round_trip(ProxyVersion::V2, "192.0.2.1:51000", "198.51.100.2:443");
+            round_trip(ProxyVersion::V2, "[2001:db8::1]:51000", "[2001:db8::2]:443");
+        }
+
+        #[test]
+        fn truncated_header_needs_more_data() {
+            for version in [ProxyVersion::V1, ProxyVersion::V2] {
+                let data = encode(
+                    version,
+                    Some(address("192.0.2.1:51000")),
+                    Some(address("198.51.100.2:443")),
+                );
+                for end in 0..data.len() {
+                    assert!(parse(&data[..end]).unwrap().is_none(), "{} bytes", end);
+                }
+            }
+        }
+
+        #[test]
+        fn v1_header_too_long() {
+            let mut data = b"PROXY TCP4 ".to_vec();
+            data.resize(V1_MAX_SIZE, b'1');
+            assert!(parse(&data).is_err());
+            data.extend_from_slice(b"\r\n");
+            assert!(parse(&data).is_err());
+        }
+
+        #[test]
+        fn missing_header() {
+            assert!(parse(b"GET / HTTP/1.1\r\n").is_err());
+        }
+
+        #[test]
+        fn local_carries_no_addresses() {
+            let data = encode(ProxyVersion::V2, None, None);
+            assert_eq!(data[12], V2_VERSION | V2_LOCAL);
+            let header = parse(&data).unwrap().unwrap();
+            assert_eq!(header.addresses, None);
+            assert_eq!(header.size, 16);
+
+            let data = encode(ProxyVersion::V1, Some(address("192.0.2.1:1")), None);
+            assert_eq!(data, b"PROXY UNKNOWN\r\n");
+            assert_eq!(parse(&data).unwrap().unwrap().addresses, None);
+        }
+
+        #[test]
+        fn mixed_families_are_unknown() {
+            let data = encode(
+                ProxyVersion::V2,
+                Some(address("192.0.2.1:51000")),
+                Some(address("[2001:db8::2]:443")),
+            );
+            assert_eq!(parse(&data).unwrap().unwrap().addresses, None);
+        }
+    }
+}
@@ -0,0 +1,919 @@
+pub mod quic {
+    use std::fmt::Display;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::net::SocketAddr;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex, OnceLock};
+    use std::time::Duration;
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use lazy_static::lazy_static;
+    use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+    use quinn::{Connection, Endpoint, RecvStream, TransportConfig, VarInt};
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{
+        CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime,
+    };
+    use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+    use tokio::runtime::Runtime;
+    use tokio::sync::mpsc::{channel, Receiver, Sender};
+
+    use crate::async_pipeline::async_pipeline::relay_stream;
+    use crate::tcp::tcp::{read_available, WRITE_TIMEOUT};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const QUIC_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "quic-entry-address",
+        "--quic-ea",
+        "(QuicEntry) Quic Entry listen address",
+    );
+    const QUIC_ENTRY_PORT: (&str, &str, &str) = (
+        "quic-entry-port",
+        "--quic-ep",
+        "(QuicEntry) Quic Entry listen port",
+    );
+    const QUIC_ENTRY_CERT: (&str, &str, &str) = (
+        "quic-entry-cert",
+        "--quic-ecert",
+        "(QuicEntry) PEM certificate chain, a self-signed one is generated when omitted",
+    );
+    const QUIC_ENTRY_KEY: (&str, &str, &str) = (
+        "quic-entry-key",
+        "--quic-ekey",
+        "(QuicEntry) PEM private key of --quic-ecert",
+    );
+
+    const QUIC_STEP_ADDRESS: (&str, &str, &str) = (
+        "quic-step-address",
+        "--quic-sa",
+        "(QuicStep) Quic step endpoint address",
+    );
+    const QUIC_STEP_PORT: (&str, &str, &str) = (
+        "quic-step-port",
+        "--quic-sp",
+        "(QuicStep) Quic step endpoint port",
+    );
+    const QUIC_STEP_NAME: (&str, &str, &str) = (
+        "quic-step-name",
+        "--quic-sname",
+        "(QuicStep) Server name the certificate is checked against, defaults to --quic-sa",
+    );
+    const QUIC_STEP_CA: (&str, &str, &str) = (
+        "quic-step-ca",
+        "--quic-sca",
+        "(QuicStep) PEM certificates to trust instead of the public roots, e.g. the entry's own",
+    );
+    const QUIC_STEP_INSECURE: (&str, &str, &str) = (
+        "quic-step-insecure",
+        "--quic-sinsecure",
+        "(QuicStep) Accept any server certificate, e.g. a generated self-signed one",
+    );
+
+    const ALPN: &[u8] = b"kproxy";
+    /// Stream error code telling the step its pipeline could not be set up.
+    const STREAM_REFUSED: u32 = 1;
+    const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
+    const MAX_STREAMS: u32 = 1024;
+    /// Chunks a step queues for its stream before it waits for the tunnel.
+    const STREAM_CHUNKS: usize = 16;
+
+    fn quic_error<E: Display>(e: E) -> Error {
+        Error::Msg(e.to_string())
+    }
+
+    fn provider() -> Arc<CryptoProvider> {
+        Arc::new(rustls::crypto::ring::default_provider())
+    }
+
+    /// Every stream starts with the destination the step's client asked its
+    /// entry for, if any: a length byte, the host and the port.
+    fn encode_stream_header(destination: &Option<(String, u16)>) -> Vec<u8> {
+        match destination {
+            Some((address, port)) if address.len() <= u8::MAX as usize => {
+                let mut header = vec![address.len() as u8];
+                header.extend_from_slice(address.as_bytes());
+                header.extend_from_slice(&port.to_be_bytes());
+                header
+            }
+            _ => vec![0, 0, 0],
+        }
+    }
+
+    async fn read_stream_header(recv: &mut RecvStream) -> Result<Option<(String, u16)>, Error> {
+        let mut length = [0u8; 1];
+        recv.read_exact(&mut length).await.map_err(quic_error)?;
+        let mut header = vec![0u8; length[0] as usize + 2];
+        recv.read_exact(&mut header).await.map_err(quic_error)?;
+        let port = u16::from_be_bytes([header[header.len() - 2], header[header.len() - 1]]);
+        if length[0] == 0 {
+            return Ok(None);
+        }
+        match String::from_utf8(header[..length[0] as usize].to_vec()) {
+            Ok(address) => Ok(Some((address, port))),
+            Err(_) => Err(Error::Msg("invalid stream destination".to_string())),
+        }
+    }
+
+    /// Accepts QUIC connections and serves every bidirectional stream on them
+    /// as one client with its own copy of the pipeline.
+    pub struct QuicEntry {
+        address: String,
+        port: u16,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        server_config: quinn::ServerConfig,
+        buffer_size: usize,
+    }
+
+    impl Entry for QuicEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(self.serve())
+        }
+    }
+
+    impl QuicEntry {
+        async fn serve(&mut self) -> Result<(), Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let endpoint = Endpoint::server(self.server_config.clone(), addr)?;
+
+            while let Some(incoming) = endpoint.accept().await {
+                let pipeline_template = self.pipeline_template.clone();
+                let debug_level = self.debug_level;
+                let buffer_size = self.buffer_size;
+                tokio::spawn(async move {
+                    let connection = match incoming.await {
+                        Ok(connection) => connection,
+                        Err(e) => {
+                            if debug_level > 0 {
+                                eprintln!("an error accured accepting quic connection: {}", e);
+                            }
+                            return;
+                        }
+                    };
+
+                    {
+                        // debug
+                        if debug_level >= 2 {
+                            println!("new client: {}", connection.remote_address());
+                        }
+                    }
+
+                    QuicEntry::serve_connection(
+                        connection,
+                        pipeline_template,
+                        debug_level,
+                        buffer_size,
+                    )
+                    .await;
+                });
+            }
+            Ok(())
+        }
+
+        async fn serve_connection(
+            connection: Connection,
+            pipeline_template: Arc<Pipeline>,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) {
+            loop {
+                let (send, recv) = match connection.accept_bi().await {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        {
+                            // debug
+                            if debug_level >= 2 {
+                                println!("client {} closed: {}", connection.remote_address(), e);
+                            }
+                        }
+                        return;
+                    }
+                };
+                let peer = connection.remote_address();
+                let pipeline_template = pipeline_template.clone();
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        QuicEntry::serve_stream(send, recv, peer, pipeline_template, buffer_size)
+                            .await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving stream of {}: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        }
+
+        async fn serve_stream(
+            mut send: quinn::SendStream,
+            mut recv: RecvStream,
+            peer: SocketAddr,
+            pipeline_template: Arc<Pipeline>,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            let info = ConnectionInfo {
+                peer: Some(peer),
+                local: None,
+                destination: read_stream_header(&mut recv).await?,
+            };
+            let pipeline = match tokio::task::block_in_place(|| pipeline_template.clone_with(&info))
+            {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    let _ = send.reset(VarInt::from_u32(STREAM_REFUSED));
+                    return Err(e);
+                }
+            };
+            relay_stream(recv, send, pipeline, buffer_size).await
+        }
+
+        /// Loads the configured certificate, or generates a self-signed one
+        /// for `localhost` when there is none.
+        fn server_config(
+            cert: Option<&String>,
+            key: Option<&String>,
+        ) -> Result<quinn::ServerConfig, Error> {
+            let (certs, key) = match (cert, key) {
+                (Some(cert), Some(key)) => {
+                    let certs = CertificateDer::pem_slice_iter(&fs::read(cert)?)
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(quic_error)?;
+                    let key = PrivateKeyDer::from_pem_slice(&fs::read(key)?).map_err(quic_error)?;
+                    (certs, key)
+                }
+                (None, None) => {
+                    let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()])
+                        .map_err(quic_error)?;
+                    let key = PrivatePkcs8KeyDer::from(generated.key_pair.serialize_der());
+                    (vec![generated.cert.der().clone()], key.into())
+                }
+                (Some(_), None) => return Err(Error::RequireOption(QUIC_ENTRY_KEY.0.to_string())),
+                (None, Some(_)) => return Err(Error::RequireOption(QUIC_ENTRY_CERT.0.to_string())),
+            };
+
+            let mut crypto = rustls::ServerConfig::builder_with_provider(provider())
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .map_err(quic_error)?
+                .with_no_client_auth()
+                .with_single_cert(certs, key)
+                .map_err(quic_error)?;
+            crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+            let crypto = QuicServerConfig::try_from(crypto).map_err(quic_error)?;
+            let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+            let mut transport = TransportConfig::default();
+            transport.max_concurrent_bidi_streams(VarInt::from_u32(MAX_STREAMS));
+            server_config.transport_config(Arc::new(transport));
+            Ok(server_config)
+        }
+    }
+
+    impl EntryStatic<QuicEntry> for QuicEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<QuicEntry, Error> {
+            let address = match args.argument_values.get(QUIC_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(QUIC_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(QUIC_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(QUIC_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let server_config = QuicEntry::server_config(
+                args.argument_values
+                    .get(QUIC_ENTRY_CERT.0)
+                    .map(|cert| &cert[0]),
+                args.argument_values
+                    .get(QUIC_ENTRY_KEY.0)
+                    .map(|key| &key[0]),
+            )?;
+
+            Ok(QuicEntry {
+                address,
+                port,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                server_config,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: QUIC_ENTRY_ADDRESS.0.to_string(),
+                key: vec![QUIC_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(QUIC_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: QUIC_ENTRY_PORT.0.to_string(),
+                key: vec![QUIC_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("4433".to_string()),
+                help: Some(ArgumentHelp::Text(QUIC_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: QUIC_ENTRY_CERT.0.to_string(),
+                key: vec![QUIC_ENTRY_CERT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(QUIC_ENTRY_CERT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: QUIC_ENTRY_KEY.0.to_string(),
+                key: vec![QUIC_ENTRY_KEY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(QUIC_ENTRY_KEY.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Skips certificate checks for `--quic-sinsecure`, handshake signatures
+    /// are still verified.
+    #[derive(Debug)]
+    struct InsecureVerifier(Arc<CryptoProvider>);
+
+    impl ServerCertVerifier for InsecureVerifier {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &CertificateDer<'_>,
+            _intermediates: &[CertificateDer<'_>],
+            _server_name: &ServerName<'_>,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer<'_>,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(
+                message,
+                cert,
+                dss,
+                &self.0.signature_verification_algorithms,
+            )
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.0.signature_verification_algorithms.supported_schemes()
+        }
+    }
+
+    // drives the connections of every QuicStep, the steps themselves are
+    // called synchronously by their entry
+    lazy_static! {
+        static ref RUNTIME: Runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+    }
+
+    /// Tunnels pipeline bytes through a QUIC stream, on a connection that
+    /// all copies of the step share.
+    pub struct QuicStep {
+        target: QuicTarget,
+        destination: Option<(String, u16)>,
+        /// Opened when the step is first used, `None` if that failed.
+        stream: OnceLock<Option<QuicStream>>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    #[derive(Clone)]
+    struct QuicTarget {
+        address: String,
+        port: u16,
+        server_name: String,
+        client_config: quinn::ClientConfig,
+        connection: Arc<tokio::sync::Mutex<Option<(Endpoint, Connection)>>>,
+    }
+
+    impl QuicTarget {
+        /// Returns the shared connection, dialing a new one when there is
+        /// none yet or the previous one has closed.
+        async fn connection(&self) -> Result<Connection, Error> {
+            let mut connection = self.connection.lock().await;
+            if let Some((_, current)) = connection.as_ref() {
+                if current.close_reason().is_none() {
+                    return Ok(current.clone());
+                }
+            }
+
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let bind: SocketAddr = match addr {
+                SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+                SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+            };
+            let mut endpoint = Endpoint::client(bind)?;
+            endpoint.set_default_client_config(self.client_config.clone());
+            let current = endpoint
+                .connect(addr, self.server_name.as_str())
+                .map_err(quic_error)?
+                .await
+                .map_err(quic_error)?;
+            *connection = Some((endpoint, current.clone()));
+            Ok(current)
+        }
+
+        fn client_config(
+            ca: Option<&String>,
+            insecure: bool,
+        ) -> Result<quinn::ClientConfig, Error> {
+            let builder = rustls::ClientConfig::builder_with_provider(provider())
+                .with_protocol_versions(&[&rustls::version::TLS13])
+                .map_err(quic_error)?;
+            let mut crypto = match (insecure, ca) {
+                (true, _) => builder
+                    .dangerous()
+                    .with_custom_certificate_verifier(Arc::new(InsecureVerifier(provider())))
+                    .with_no_client_auth(),
+                (false, Some(ca)) => {
+                    let mut roots = RootCertStore::empty();
+                    for cert in CertificateDer::pem_slice_iter(&fs::read(ca)?) {
+                        roots.add(cert.map_err(quic_error)?).map_err(quic_error)?;
+                    }
+                    builder.with_root_certificates(roots).with_no_client_auth()
+                }
+                (false, None) => {
+                    let roots = RootCertStore {
+                        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+                    };
+                    builder.with_root_certificates(roots).with_no_client_auth()
+                }
+            };
+            crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+            let crypto = QuicClientConfig::try_from(crypto).map_err(quic_error)?;
+            let mut client_config = quinn::ClientConfig::new(Arc::new(crypto));
+            let mut transport = TransportConfig::default();
+            // idle tunnels would otherwise time out
+            transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+            client_config.transport_config(Arc::new(transport));
+            Ok(client_config)
+        }
+    }
+
+    impl QuicStep {
+        fn open(
+            target: &QuicTarget,
+            destination: Option<(String, u16)>,
+            debug_level: DebugLevel,
+        ) -> Result<QuicStream, Error> {
+            let (response_body, tunnel_end) = UnixStream::pair()?;
+            response_body.set_nonblocking(true)?;
+            tunnel_end.set_nonblocking(true)?;
+            let (request_body, receiver) = channel(STREAM_CHUNKS);
+            let failure = Arc::new(Mutex::new(None));
+
+            let tunnel = QuicTunnel {
+                target: target.clone(),
+                header: encode_stream_header(&destination),
+                request_body: receiver,
+            };
+            let tunnel_failure = failure.clone();
+            RUNTIME.spawn(async move {
+                let result = match tokio::net::UnixStream::from_std(tunnel_end) {
+                    Ok(mut tunnel_end) => tunnel.run(&mut tunnel_end).await,
+                    Err(e) => Err(Error::IoError(e)),
+                };
+                if let Err(e) = result {
+                    if debug_level > 0 {
+                        eprintln!("an error accured in quic tunnel: {}", e);
+                    }
+                    // recorded before `tunnel_end` closes so the step sees it
+                    *tunnel_failure.lock().unwrap() = Some(e.to_string());
+                }
+            });
+
+            Ok(QuicStream {
+                request_body: Some(request_body),
+                response_body,
+                failure,
+            })
+        }
+
+        fn stream(&self) -> Option<&QuicStream> {
+            self.stream
+                .get_or_init(|| {
+                    match QuicStep::open(&self.target, self.destination.clone(), self.debug_level) {
+                        Ok(stream) => Some(stream),
+                        Err(e) => {
+                            if self.debug_level > 0 {
+                                eprintln!("an error accured opening quic stream: {}", e);
+                            }
+                            None
+                        }
+                    }
+                })
+                .as_ref()
+        }
+
+        fn stream_mut(&mut self) -> Result<&mut QuicStream, Error> {
+            self.stream();
+            self.stream
+                .get_mut()
+                .and_then(Option::as_mut)
+                .ok_or_else(|| Error::IoError(ErrorKind::NotConnected.into()))
+        }
+
+        fn with_destination(&self, destination: Option<(String, u16)>) -> Self {
+            Self {
+                target: self.target.clone(),
+                destination,
+                stream: OnceLock::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    struct QuicStream {
+        /// Dropped once the client half-closed, which finishes the stream.
+        request_body: Option<Sender<Vec<u8>>>,
+        response_body: UnixStream,
+        /// Why the tunnel closed, when it was not the peer ending the stream.
+        failure: Arc<Mutex<Option<String>>>,
+    }
+
+    struct QuicTunnel {
+        target: QuicTarget,
+        header: Vec<u8>,
+        request_body: Receiver<Vec<u8>>,
+    }
+
+    impl QuicTunnel {
+        async fn run(mut self, tunnel_end: &mut tokio::net::UnixStream) -> Result<(), Error> {
+            let connection = self.target.connection().await?;
+            let (mut send, mut recv) = connection.open_bi().await.map_err(quic_error)?;
+            // also announces the stream, the peer only sees it once data arrives
+            send.write_all(&self.header).await.map_err(quic_error)?;
+
+            let backward = async move {
+                match tokio::io::copy(&mut recv, tunnel_end).await {
+                    // the step was dropped
+                    Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+                    Err(e) => Err(Error::IoError(e)),
+                    Ok(_) => Ok(()),
+                }
+            };
+            tokio::pin!(backward);
+
+            let forward = async {
+                while let Some(data) = self.request_body.recv().await {
+                    send.write_all(&data).await.map_err(quic_error)?;
+                }
+                // the step was dropped or half-closed
+                send.finish().map_err(quic_error)?;
+                Ok::<(), Error>(())
+            };
+
+            tokio::select! {
+                result = &mut backward => result,
+                result = forward => {
+                    result?;
+                    backward.await
+                }
+            }
+        }
+    }
+
+    impl Step for QuicStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            let request_body = match &self.stream_mut()?.request_body {
+                Some(request_body) => request_body,
+                None => return Err(Error::IoError(ErrorKind::BrokenPipe.into())),
+            };
+            // waits while the connection is behind on sending
+            let sent = RUNTIME.block_on(async {
+                tokio::time::timeout(WRITE_TIMEOUT, request_body.send(data.clone())).await
+            });
+            match sent {
+                Ok(Ok(())) => Ok(data.clone()),
+                Ok(Err(_)) => Err(Error::IoError(ErrorKind::BrokenPipe.into())),
+                Err(_) => Err(Error::IoError(ErrorKind::TimedOut.into())),
+            }
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            let buffer_size = self.buffer_size;
+            let stream = self.stream_mut()?;
+            match read_available(&mut stream.response_body, buffer_size) {
+                // a tunnel that failed closes like one the peer ended
+                Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                    match stream.failure.lock().unwrap().take() {
+                        Some(failure) => Err(Error::Msg(failure)),
+                        None => Err(Error::IoError(e)),
+                    }
+                }
+                result => result,
+            }
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            // a stream that was never opened has nothing to finish
+            if let Some(Some(stream)) = self.stream.get_mut() {
+                stream.request_body.take();
+            }
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for QuicStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        /// Passes the destination the client asked the entry for to the
+        /// other side, whose steps dial it in place of their own endpoint.
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            Ok(Box::new(self.with_destination(info.destination.clone())))
+        }
+    }
+
+    impl StepStatic for QuicStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(QUIC_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(QUIC_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(QUIC_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(QUIC_STEP_PORT.0.to_string())),
+            };
+            let server_name = match args.argument_values.get(QUIC_STEP_NAME.0) {
+                Some(server_name) => server_name[0].clone(),
+                None => address.clone(),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let client_config = QuicTarget::client_config(
+                args.argument_values.get(QUIC_STEP_CA.0).map(|ca| &ca[0]),
+                args.arguments.contains(QUIC_STEP_INSECURE.0),
+            )?;
+            let target = QuicTarget {
+                address,
+                port,
+                server_name,
+                client_config,
+                connection: Arc::new(tokio::sync::Mutex::new(None)),
+            };
+            // fail on a wrong endpoint or certificate right away, the
+            // connection carries no stream until a client comes
+            RUNTIME.block_on(target.connection())?;
+
+            Ok(Self {
+                target,
+                destination: None,
+                stream: OnceLock::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: QUIC_STEP_ADDRESS.0.to_string(),
+                key: vec![QUIC_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(QUIC_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: QUIC_STEP_PORT.0.to_string(),
+                key: vec![QUIC_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("4433".to_string()),
+                help: Some(ArgumentHelp::Text(QUIC_STEP_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: QUIC_STEP_NAME.0.to_string(),
+                key: vec![QUIC_STEP_NAME.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(QUIC_STEP_NAME.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: QUIC_STEP_CA.0.to_string(),
+                key: vec![QUIC_STEP_CA.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(QUIC_STEP_CA.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: QUIC_STEP_INSECURE.0.to_string(),
+                key: vec![QUIC_STEP_INSECURE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::None,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(QUIC_STEP_INSECURE.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    impl Clone for QuicStep {
+        fn clone(&self) -> Self {
+            self.with_destination(self.destination.clone())
+        }
+    }
+
+    impl AsRawFd for QuicStep {
+        fn as_raw_fd(&self) -> RawFd {
+            match self.stream() {
+                Some(stream) => stream.response_body.as_raw_fd(),
+                None => -1,
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::collections::HashMap;
+        use std::io::{Read, Write};
+        use std::net::{TcpListener, UdpSocket};
+        use std::thread;
+        use std::time::Instant;
+
+        use super::*;
+        use crate::TcpStep;
+
+        fn args(values: &[(&str, &str)]) -> CliParsed {
+            let mut argument_values: HashMap<String, Vec<String>> = values
+                .iter()
+                .map(|(name, value)| (name.to_string(), vec![value.to_string()]))
+                .collect();
+            argument_values.insert(BUFFER_SIZE.0.to_string(), vec!["4096".to_string()]);
+            CliParsed {
+                arguments: Default::default(),
+                argument_values,
+            }
+        }
+
+        /// Runs a server echoing every connection until the client ends it.
+        fn echo_server() -> u16 {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let port = listener.local_addr().unwrap().port();
+            thread::spawn(move || {
+                for connection in listener.incoming() {
+                    let mut connection = connection.unwrap();
+                    thread::spawn(move || {
+                        let mut buffer = [0u8; 4096];
+                        loop {
+                            match connection.read(&mut buffer) {
+                                Ok(0) | Err(_) => return,
+                                Ok(size) => connection.write_all(&buffer[..size]).unwrap(),
+                            }
+                        }
+                    });
+                }
+            });
+            port
+        }
+
+        /// Runs an entry with a self-signed certificate whose streams reach
+        /// `upstream` through a `TcpStep`.
+        fn quic_entry(upstream: u16) -> u16 {
+            let port = UdpSocket::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let mut pipeline = Pipeline::new();
+            pipeline.add_step(Box::new(
+                TcpStep::new(
+                    args(&[
+                        ("tcp-step-address", "127.0.0.1"),
+                        ("tcp-step-port", &upstream.to_string()),
+                    ]),
+                    DebugLevel::None,
+                )
+                .unwrap(),
+            ));
+            let mut entry = QuicEntry::new(
+                args(&[
+                    (QUIC_ENTRY_ADDRESS.0, "127.0.0.1"),
+                    (QUIC_ENTRY_PORT.0, &port.to_string()),
+                ]),
+                pipeline,
+                DebugLevel::None,
+            )
+            .unwrap();
+            thread::spawn(move || entry.listen());
+            port
+        }
+
+        #[test]
+        fn stream_through_an_entry() {
+            let port = quic_entry(echo_server());
+            let mut step_args = args(&[
+                (QUIC_STEP_ADDRESS.0, "127.0.0.1"),
+                (QUIC_STEP_PORT.0, &port.to_string()),
+                (QUIC_STEP_NAME.0, "localhost"),
+            ]);
+            step_args.arguments.insert(QUIC_STEP_INSECURE.0.to_string());
+            // the entry binds its socket on its own thread
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let template = loop {
+                match QuicStep::new(step_args.clone(), DebugLevel::None) {
+                    Ok(step) => break step,
+                    Err(e) if Instant::now() > deadline => panic!("{}", e),
+                    Err(_) => thread::sleep(Duration::from_millis(50)),
+                }
+            };
+
+            let data: Vec<u8> = (0..100_000).map(|n| (n % 251) as u8).collect();
+            let mut step = template.bclone();
+            for chunk in data.chunks(4096) {
+                step.process_data_forward(&mut chunk.to_vec()).unwrap();
+            }
+            step.close_forward().unwrap();
+
+            let deadline = Instant::now() + Duration::from_secs(20);
+            let mut echoed = Vec::new();
+            loop {
+                match step.process_data_backward(&mut Vec::new()) {
+                    Ok(data) => echoed.extend(data),
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                        assert!(Instant::now() < deadline, "echo timed out");
+                        thread::sleep(Duration::from_millis(10));
+                    }
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => break,
+                    Err(e) => panic!("{}", e),
+                }
+            }
+            assert_eq!(echoed, data);
+        }
+    }
+}
@@ -0,0 +1,975 @@
+pub mod resume {
+    use std::collections::HashMap;
+    use std::io::{ErrorKind, Read, Write};
+    use std::mem;
+    use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::unix::SourceFd;
+    use mio::{Events, Interest, Poll, Token, Waker};
+    use ring::rand::{SecureRandom, SystemRandom};
+    use tokio::runtime::Handle;
+
+    use crate::async_pipeline::async_pipeline::LazyStream;
+    use crate::mux::mux::{decode_destination, encode_destination, serve_stream, Link, LINK};
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const RESUME_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "resume-entry-address",
+        "--resume-ea",
+        "(ResumeEntry) Resume Entry listen address",
+    );
+    const RESUME_ENTRY_PORT: (&str, &str, &str) = (
+        "resume-entry-port",
+        "--resume-ep",
+        "(ResumeEntry) Resume Entry listen port",
+    );
+
+    const RESUME_TIMEOUT: (&str, &str, &str) = (
+        "resume-timeout",
+        "--resume-timeout",
+        "(ResumeEntry, ResumeStep) Seconds a session waits for its link to come back",
+    );
+    const RESUME_KEEPALIVE: (&str, &str, &str) = (
+        "resume-keepalive",
+        "--resume-keepalive",
+        "(ResumeEntry, ResumeStep) Seconds between acknowledgements on an idle link",
+    );
+    const RESUME_BUFFER: (&str, &str, &str) = (
+        "resume-buffer",
+        "--resume-buffer",
+        "(ResumeEntry, ResumeStep) Bytes kept for replay until the other side acknowledges them",
+    );
+
+    // a link starts with a hello of the magic, the version, whether the
+    // session is new, its id, how much the step received of it and the
+    // length of the destination that follows, which the entry welcomes with
+    // a status and how much it received
+    const MAGIC: &[u8] = b"KPRS";
+    const VERSION: u8 = 1;
+    const ID_SIZE: usize = 16;
+    const HELLO_SIZE: usize = 4 + 1 + 1 + ID_SIZE + 8 + 2;
+    const NEW: u8 = 0;
+    const RESUME: u8 = 1;
+    const WELCOME_SIZE: usize = 1 + 8;
+    const ACCEPTED: u8 = 0;
+    const UNKNOWN_SESSION: u8 = 1;
+
+    // then both sides send frames of a kind and a payload length; data is
+    // numbered by its offset in the stream and the FIN takes one more
+    const HEADER_SIZE: usize = 5;
+    const DATA: u8 = 0;
+    const ACK: u8 = 1;
+    const FIN: u8 = 2;
+    const RST: u8 = 3;
+    const MAX_PAYLOAD: usize = 16 * 1024;
+
+    const LOCAL: Token = Token(1);
+    const ATTACH: Token = Token(2);
+
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+    const FIRST_RETRY: Duration = Duration::from_millis(200);
+    const LAST_RETRY: Duration = Duration::from_secs(5);
+    /// The link is given up once this many keepalive intervals pass in silence.
+    const KEEPALIVE_MISSES: u32 = 3;
+
+    type SessionId = [u8; ID_SIZE];
+
+    #[derive(Clone, Copy)]
+    struct SessionOptions {
+        timeout: Duration,
+        keepalive: Duration,
+        buffer_limit: usize,
+    }
+
+    fn parse_options(args: &CliParsed) -> Result<SessionOptions, Error> {
+        let timeout = match args.argument_values.get(RESUME_TIMEOUT.0) {
+            Some(timeout) => timeout[0].clone(),
+            None => return Err(Error::RequireOption(RESUME_TIMEOUT.0.to_string())),
+        };
+        let keepalive = match args.argument_values.get(RESUME_KEEPALIVE.0) {
+            Some(keepalive) => keepalive[0].clone(),
+            None => return Err(Error::RequireOption(RESUME_KEEPALIVE.0.to_string())),
+        };
+        let buffer_limit = match args.argument_values.get(RESUME_BUFFER.0) {
+            Some(buffer_limit) => buffer_limit[0].clone(),
+            None => return Err(Error::RequireOption(RESUME_BUFFER.0.to_string())),
+        };
+
+        let timeout = match str::parse::<u64>(timeout.as_str()) {
+            Ok(timeout) => Duration::from_secs(timeout),
+            Err(_) => return Err(Error::ParseIntError),
+        };
+        // without acknowledgements a dead link is never noticed
+        let keepalive = match str::parse::<u64>(keepalive.as_str()) {
+            Ok(keepalive) => Duration::from_secs(keepalive.max(1)),
+            Err(_) => return Err(Error::ParseIntError),
+        };
+        let buffer_limit = match str::parse::<usize>(buffer_limit.as_str()) {
+            Ok(buffer_limit) => buffer_limit,
+            Err(_) => return Err(Error::ParseIntError),
+        };
+
+        Ok(SessionOptions {
+            timeout,
+            keepalive,
+            buffer_limit,
+        })
+    }
+
+    fn push_frame(outbound: &mut Vec<u8>, kind: u8, payload: &[u8]) {
+        outbound.push(kind);
+        outbound.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        outbound.extend_from_slice(payload);
+    }
+
+    /// How a link stopped carrying its session.
+    enum LinkEnd {
+        Finished,
+        Lost,
+        /// The step came back on another link, with how much it received.
+        Replaced(Link, u64),
+    }
+
+    /// One client's byte streams, kept across the links that carry them.
+    /// The client or upstream holds the other end of `local`.
+    struct ResumeSession {
+        local: UnixStream,
+        poll: Poll,
+        /// Sent but not acknowledged yet, starting at offset `acked`.
+        unacked: Vec<u8>,
+        acked: u64,
+        fin_queued: bool,
+        fin_acked: bool,
+        /// Offset reached in the peer's stream, counting its FIN.
+        received: u64,
+        /// Received from the peer, waiting for room in `local`.
+        pending: Vec<u8>,
+        /// Acknowledge data still in `pending`, so the peer never waits on
+        /// `local` being read.
+        ack_pending: bool,
+        reported: u64,
+        readable: bool,
+        remote_closed: bool,
+        write_closed: bool,
+        awaiting_welcome: bool,
+        inbound: Vec<u8>,
+        outbound: Vec<u8>,
+        options: SessionOptions,
+        buffer_size: usize,
+    }
+
+    impl ResumeSession {
+        fn new(
+            local: UnixStream,
+            options: SessionOptions,
+            buffer_size: usize,
+        ) -> Result<Self, Error> {
+            let poll = Poll::new()?;
+            poll.registry().register(
+                &mut SourceFd(&local.as_raw_fd()),
+                LOCAL,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            Ok(Self {
+                local,
+                poll,
+                unacked: Vec::new(),
+                acked: 0,
+                fin_queued: false,
+                fin_acked: false,
+                received: 0,
+                pending: Vec::new(),
+                ack_pending: false,
+                reported: 0,
+                readable: true,
+                remote_closed: false,
+                write_closed: false,
+                awaiting_welcome: false,
+                inbound: Vec::new(),
+                outbound: Vec::new(),
+                options,
+                buffer_size,
+            })
+        }
+
+        fn sent(&self) -> u64 {
+            self.acked + self.unacked.len() as u64 + self.fin_queued as u64
+        }
+
+        fn acknowledgement(&self) -> u64 {
+            match self.ack_pending {
+                true => self.received,
+                false => self.received - self.pending.len() as u64,
+            }
+        }
+
+        fn finished(&self) -> bool {
+            self.fin_acked && self.write_closed
+        }
+
+        /// Starts a link from the step's side. Nothing is sent past the
+        /// hello until the entry says where to resume from.
+        fn connect(&mut self, id: &SessionId, kind: u8, destination: &Option<(String, u16)>) {
+            let destination = encode_destination(destination);
+            self.inbound.clear();
+            self.outbound.clear();
+            self.outbound.extend_from_slice(MAGIC);
+            self.outbound.push(VERSION);
+            self.outbound.push(kind);
+            self.outbound.extend_from_slice(id);
+            self.outbound
+                .extend_from_slice(&self.received.to_be_bytes());
+            self.outbound
+                .extend_from_slice(&(destination.len() as u16).to_be_bytes());
+            self.outbound.extend_from_slice(&destination);
+            self.awaiting_welcome = true;
+        }
+
+        /// Starts a link from the entry's side, whose step received
+        /// `peer_received` so far.
+        fn accept(&mut self, peer_received: u64) -> Result<(), Error> {
+            self.inbound.clear();
+            self.outbound.clear();
+            self.outbound.push(ACCEPTED);
+            self.outbound
+                .extend_from_slice(&self.received.to_be_bytes());
+            self.resume(peer_received)
+        }
+
+        /// Replays everything the peer is missing on a fresh link.
+        fn resume(&mut self, peer_received: u64) -> Result<(), Error> {
+            self.acknowledge(peer_received)?;
+            for chunk in self.unacked.chunks(MAX_PAYLOAD) {
+                push_frame(&mut self.outbound, DATA, chunk);
+            }
+            if self.fin_queued && !self.fin_acked {
+                push_frame(&mut self.outbound, FIN, &[]);
+            }
+            Ok(())
+        }
+
+        fn acknowledge(&mut self, offset: u64) -> Result<(), Error> {
+            if offset < self.acked || offset > self.sent() {
+                return Err(Error::Msg(
+                    "peer resumed from data that is no longer kept".to_string(),
+                ));
+            }
+            let size = ((offset - self.acked) as usize).min(self.unacked.len());
+            self.unacked.drain(..size);
+            self.acked += size as u64;
+            if offset > self.acked {
+                self.fin_acked = true;
+            }
+            Ok(())
+        }
+
+        fn run_link(
+            &mut self,
+            link: &mut Link,
+            attachments: Option<&Receiver<(Link, u64)>>,
+        ) -> Result<LinkEnd, Error> {
+            let result = self.pump(link, attachments);
+            if result.is_err() {
+                let mut reset = Vec::new();
+                push_frame(&mut reset, RST, &[]);
+                let _ = link.send(reset);
+            }
+            result
+        }
+
+        fn pump(
+            &mut self,
+            link: &mut Link,
+            attachments: Option<&Receiver<(Link, u64)>>,
+        ) -> Result<LinkEnd, Error> {
+            // steps that failed to dial leave nothing to register
+            if link.register(&self.poll).is_err() {
+                return Ok(LinkEnd::Lost);
+            }
+            let keepalive = self.options.keepalive;
+            let mut last_heard = Instant::now();
+            let mut last_ack = Instant::now();
+            let mut events = Events::with_capacity(64);
+            loop {
+                if link.send(mem::take(&mut self.outbound)).is_err() {
+                    return Ok(LinkEnd::Lost);
+                }
+                if self.finished() {
+                    return Ok(LinkEnd::Finished);
+                }
+
+                let timeout = keepalive.saturating_sub(last_ack.elapsed());
+                if let Err(e) = self.poll.poll(&mut events, Some(timeout)) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+
+                for event in events.iter() {
+                    match event.token() {
+                        LINK => {
+                            if event.is_readable() {
+                                match link.receive(self.buffer_size) {
+                                    Ok(data) if data.is_empty() => {}
+                                    Ok(data) => {
+                                        last_heard = Instant::now();
+                                        self.receive(data)?;
+                                    }
+                                    Err(_) => return Ok(LinkEnd::Lost),
+                                }
+                            }
+                            if event.is_read_closed() || event.is_error() {
+                                return Ok(LinkEnd::Lost);
+                            }
+                            if event.is_writable() && link.flush().is_err() {
+                                return Ok(LinkEnd::Lost);
+                            }
+                        }
+                        LOCAL if event.is_readable() => self.readable = true,
+                        ATTACH => {
+                            // only the newest link of the step is worth keeping
+                            if let Some(latest) = attachments.and_then(|a| a.try_iter().last()) {
+                                return Ok(LinkEnd::Replaced(latest.0, latest.1));
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                if self.awaiting_welcome {
+                    if last_heard.elapsed() >= keepalive * KEEPALIVE_MISSES {
+                        return Ok(LinkEnd::Lost);
+                    }
+                    continue;
+                }
+                self.read_local()?;
+                self.write_local()?;
+
+                let acknowledgement = self.acknowledgement();
+                if acknowledgement != self.reported || last_ack.elapsed() >= keepalive {
+                    push_frame(&mut self.outbound, ACK, &acknowledgement.to_be_bytes());
+                    self.reported = acknowledgement;
+                    last_ack = Instant::now();
+                }
+                // the peer hears its FIN was taken before our side can see
+                // the end and let the process exit
+                if link.send(mem::take(&mut self.outbound)).is_err() {
+                    return Ok(LinkEnd::Lost);
+                }
+                if self.pending.is_empty() && self.remote_closed && !self.write_closed {
+                    let _ = self.local.shutdown(Shutdown::Write);
+                    self.write_closed = true;
+                }
+                if last_heard.elapsed() >= keepalive * KEEPALIVE_MISSES {
+                    return Ok(LinkEnd::Lost);
+                }
+            }
+        }
+
+        fn receive(&mut self, data: Vec<u8>) -> Result<(), Error> {
+            self.inbound.extend_from_slice(&data);
+            let inbound = mem::take(&mut self.inbound);
+            let mut offset = 0;
+
+            if self.awaiting_welcome {
+                if inbound.len() < WELCOME_SIZE {
+                    self.inbound = inbound;
+                    return Ok(());
+                }
+                if inbound[0] != ACCEPTED {
+                    return Err(Error::Msg(
+                        "the resume entry no longer knows this session".to_string(),
+                    ));
+                }
+                let peer_received =
+                    u64::from_be_bytes(inbound[1..WELCOME_SIZE].try_into().unwrap());
+                self.awaiting_welcome = false;
+                self.resume(peer_received)?;
+                offset = WELCOME_SIZE;
+            }
+
+            while inbound.len() - offset >= HEADER_SIZE {
+                let kind = inbound[offset];
+                let length = u32::from_be_bytes(
+                    inbound[offset + 1..offset + HEADER_SIZE]
+                        .try_into()
+                        .unwrap(),
+                ) as usize;
+                if length > MAX_PAYLOAD {
+                    return Err(Error::Msg("resume frame too large".to_string()));
+                }
+                if inbound.len() - offset - HEADER_SIZE < length {
+                    break;
+                }
+                let payload = &inbound[offset + HEADER_SIZE..offset + HEADER_SIZE + length];
+                self.handle_frame(kind, payload)?;
+                offset += HEADER_SIZE + length;
+            }
+            self.inbound = inbound[offset..].to_vec();
+            Ok(())
+        }
+
+        fn handle_frame(&mut self, kind: u8, payload: &[u8]) -> Result<(), Error> {
+            match kind {
+                DATA if !self.remote_closed => {
+                    self.pending.extend_from_slice(payload);
+                    self.received += payload.len() as u64;
+                }
+                ACK if payload.len() == 8 => {
+                    self.acknowledge(u64::from_be_bytes(payload.try_into().unwrap()))?
+                }
+                FIN if !self.remote_closed => {
+                    self.remote_closed = true;
+                    self.received += 1;
+                }
+                RST => return Err(Error::Msg("peer reset the session".to_string())),
+                _ => return Err(Error::Msg(format!("unexpected resume frame {}", kind))),
+            }
+            Ok(())
+        }
+
+        fn read_local(&mut self) -> Result<(), Error> {
+            while self.readable
+                && !self.fin_queued
+                && self.unacked.len() < self.options.buffer_limit
+            {
+                match read_available(&mut self.local, self.buffer_size) {
+                    Ok(data) => {
+                        for chunk in data.chunks(MAX_PAYLOAD) {
+                            push_frame(&mut self.outbound, DATA, chunk);
+                        }
+                        self.unacked.extend_from_slice(&data);
+                    }
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                        self.readable = false
+                    }
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                        self.fin_queued = true;
+                        push_frame(&mut self.outbound, FIN, &[]);
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Ok(())
+        }
+
+        fn write_local(&mut self) -> Result<(), Error> {
+            while !self.pending.is_empty() {
+                match self.local.write(&self.pending) {
+                    Ok(size) => {
+                        self.pending.drain(..size);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+            Ok(())
+        }
+    }
+
+    struct SessionHandle {
+        attach: Sender<(Link, u64)>,
+        waker: Waker,
+    }
+
+    struct ResumeServer {
+        sessions: Mutex<HashMap<SessionId, SessionHandle>>,
+        pipeline_template: Arc<Pipeline>,
+        options: SessionOptions,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl ResumeServer {
+        /// Starts a session for a new step, or hands the link to the
+        /// session of a returning one.
+        fn serve_link(
+            &self,
+            mut connection: TcpStream,
+            peer: SocketAddr,
+            runtime: Handle,
+        ) -> Result<(), Error> {
+            connection.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            let mut hello = [0u8; HELLO_SIZE];
+            connection.read_exact(&mut hello)?;
+            if &hello[..4] != MAGIC || hello[4] != VERSION {
+                return Err(Error::Msg("peer is not a resume step".to_string()));
+            }
+            let kind = hello[5];
+            let id: SessionId = hello[6..6 + ID_SIZE].try_into().unwrap();
+            let received =
+                u64::from_be_bytes(hello[6 + ID_SIZE..HELLO_SIZE - 2].try_into().unwrap());
+            let mut destination = vec![
+                0u8;
+                u16::from_be_bytes([hello[HELLO_SIZE - 2], hello[HELLO_SIZE - 1]])
+                    as usize
+            ];
+            connection.read_exact(&mut destination)?;
+
+            if kind == RESUME && !self.sessions.lock().unwrap().contains_key(&id) {
+                connection.write_all(&[UNKNOWN_SESSION; WELCOME_SIZE])?;
+                return Err(Error::Msg(
+                    "link for an unknown or expired session".to_string(),
+                ));
+            }
+            let local = connection.local_addr()?;
+            connection.set_read_timeout(None)?;
+            connection.set_nonblocking(true)?;
+            connection.set_nodelay(true)?;
+            let link = Link::Tcp {
+                connection,
+                unsent: Vec::new(),
+            };
+
+            if kind == RESUME {
+                // a session that ended meanwhile drops the link, its step
+                // redials and is told then
+                if let Some(handle) = self.sessions.lock().unwrap().get(&id) {
+                    if handle.attach.send((link, received)).is_ok() {
+                        handle.waker.wake()?;
+                    }
+                }
+                return Ok(());
+            }
+
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+            let mut session = ResumeSession::new(inner, self.options, self.buffer_size)?;
+            let (attach, attachments) = channel();
+            let waker = Waker::new(session.poll.registry(), ATTACH)?;
+            self.sessions
+                .lock()
+                .unwrap()
+                .insert(id, SessionHandle { attach, waker });
+
+            let info = ConnectionInfo {
+                peer: Some(peer),
+                local: Some(local),
+                destination: decode_destination(&destination),
+            };
+            let pipeline_template = self.pipeline_template.clone();
+            let debug_level = self.debug_level;
+            let buffer_size = self.buffer_size;
+            runtime.spawn(async move {
+                if let Err(e) = serve_stream(outer, info, pipeline_template, buffer_size).await {
+                    if debug_level > 0 {
+                        eprintln!("an error accured serving session of {}: {}", peer, e);
+                    }
+                }
+            });
+
+            let result = self.run_session(&mut session, link, received, &attachments);
+            self.sessions.lock().unwrap().remove(&id);
+            result
+        }
+
+        fn run_session(
+            &self,
+            session: &mut ResumeSession,
+            mut link: Link,
+            mut received: u64,
+            attachments: &Receiver<(Link, u64)>,
+        ) -> Result<(), Error> {
+            loop {
+                session.accept(received)?;
+                match session.run_link(&mut link, Some(attachments))? {
+                    LinkEnd::Finished => return Ok(()),
+                    LinkEnd::Replaced(replacement, peer_received) => {
+                        link = replacement;
+                        received = peer_received;
+                    }
+                    LinkEnd::Lost => {
+                        {
+                            // debug
+                            if self.debug_level >= 2 {
+                                println!("session link lost, waiting for the step");
+                            }
+                        }
+                        (link, received) = match attachments.recv_timeout(self.options.timeout) {
+                            Ok(attachment) => attachment,
+                            Err(_) => {
+                                return Err(Error::Msg(
+                                    "the step did not come back in time".to_string(),
+                                ))
+                            }
+                        };
+                    }
+                }
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("session resumed");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Accepts links from `ResumeStep`s and serves each session with its own
+    /// copy of the pipeline, which outlives the links until the session
+    /// finishes or its step stays away too long.
+    pub struct ResumeEntry {
+        address: String,
+        port: u16,
+        server: Arc<ResumeServer>,
+    }
+
+    impl Entry for ResumeEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let listener = TcpListener::bind(addr)?;
+            let debug_level = self.server.debug_level;
+
+            loop {
+                let (connection, peer) = match listener.accept() {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        if debug_level > 0 {
+                            eprintln!("an error accured accepting link: {}", e);
+                        }
+                        continue;
+                    }
+                };
+
+                {
+                    // debug
+                    if debug_level >= 2 {
+                        println!("new link: {}", peer);
+                    }
+                }
+
+                let server = self.server.clone();
+                let runtime = runtime.handle().clone();
+                thread::spawn(move || {
+                    if let Err(e) = server.serve_link(connection, peer, runtime) {
+                        if debug_level > 0 {
+                            eprintln!("an error accured on link of {}: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    impl EntryStatic<ResumeEntry> for ResumeEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<ResumeEntry, Error> {
+            let address = match args.argument_values.get(RESUME_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(RESUME_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(RESUME_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(RESUME_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(ResumeEntry {
+                address,
+                port,
+                server: Arc::new(ResumeServer {
+                    sessions: Mutex::new(HashMap::new()),
+                    pipeline_template: Arc::new(pipeline),
+                    options: parse_options(&args)?,
+                    debug_level,
+                    buffer_size,
+                }),
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: RESUME_ENTRY_ADDRESS.0.to_string(),
+                key: vec![RESUME_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(RESUME_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: RESUME_ENTRY_PORT.0.to_string(),
+                key: vec![RESUME_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("8095".to_string()),
+                help: Some(ArgumentHelp::Text(RESUME_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: RESUME_TIMEOUT.0.to_string(),
+                key: vec![RESUME_TIMEOUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("60".to_string()),
+                help: Some(ArgumentHelp::Text(RESUME_TIMEOUT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: RESUME_KEEPALIVE.0.to_string(),
+                key: vec![RESUME_KEEPALIVE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("5".to_string()),
+                help: Some(ArgumentHelp::Text(RESUME_KEEPALIVE.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: RESUME_BUFFER.0.to_string(),
+                key: vec![RESUME_BUFFER.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("1048576".to_string()),
+                help: Some(ArgumentHelp::Text(RESUME_BUFFER.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Redials the steps behind the session whenever its link drops, until
+    /// the link is back or the timeout passes.
+    fn run_client(
+        session: &mut ResumeSession,
+        steps: Pipeline,
+        info: ConnectionInfo,
+        destination: Option<(String, u16)>,
+        debug_level: DebugLevel,
+    ) -> Result<(), Error> {
+        let mut id: SessionId = [0u8; ID_SIZE];
+        SystemRandom::new()
+            .fill(&mut id)
+            .map_err(|_| Error::Msg("no randomness available for a session id".to_string()))?;
+
+        let mut link = Link::Pipeline(steps);
+        let mut kind = NEW;
+        let mut lost = Instant::now();
+        loop {
+            session.connect(&id, kind, &destination);
+            if let LinkEnd::Finished = session.run_link(&mut link, None)? {
+                return Ok(());
+            }
+            // a link that never got its welcome leaves the session where it was
+            if !session.awaiting_welcome {
+                kind = RESUME;
+                lost = Instant::now();
+            }
+
+            {
+                // debug
+                if debug_level >= 2 {
+                    eprintln!("session link lost, redialing");
+                }
+            }
+
+            let mut delay = FIRST_RETRY;
+            link = loop {
+                if lost.elapsed() >= session.options.timeout {
+                    return Err(Error::Msg(
+                        "could not get the session link back in time".to_string(),
+                    ));
+                }
+                thread::sleep(delay);
+                delay = (delay * 2).min(LAST_RETRY);
+                if let Link::Pipeline(steps) = &link {
+                    match steps.clone_with(&info) {
+                        Ok(steps) => break Link::Pipeline(steps),
+                        Err(e) => {
+                            if debug_level > 0 {
+                                eprintln!("an error accured redialing session link: {}", e);
+                            }
+                        }
+                    }
+                }
+            };
+        }
+    }
+
+    /// Carries its client over the steps added behind it as a session that
+    /// survives those steps failing: they are redialed and whatever the
+    /// `ResumeEntry` on the other side missed is sent again.
+    pub struct ResumeStep {
+        /// Taken by the session when the client first uses the step.
+        steps: Mutex<Option<Pipeline>>,
+        /// What the steps are dialed with, the destination goes in the hello.
+        info: ConnectionInfo,
+        destination: Option<(String, u16)>,
+        /// The local end of the session, a template never runs one.
+        stream: LazyStream,
+        options: SessionOptions,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl ResumeStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let steps = match self.steps.lock().unwrap().take() {
+                Some(steps) if !steps.is_empty() => steps,
+                _ => {
+                    return Err(Error::Msg(
+                        "the resume step needs steps behind it to carry its link".to_string(),
+                    ))
+                }
+            };
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+
+            let mut session = ResumeSession::new(inner, self.options, self.buffer_size)?;
+            // the entry's thread may be busy writing to this very client,
+            // so its data is acknowledged once queued rather than read
+            session.ack_pending = true;
+            let info = self.info.clone();
+            let destination = self.destination.clone();
+            let debug_level = self.debug_level;
+            thread::spawn(move || {
+                // the client sees the session end once it is dropped, so
+                // report first
+                if let Err(e) = run_client(&mut session, steps, info, destination, debug_level) {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on resumed session: {}", e);
+                    }
+                }
+                drop(session);
+            });
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("resumed session", self.debug_level, || self.open())
+        }
+    }
+
+    impl Step for ResumeStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_all(&mut self.stream()?, data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.stream()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+
+        fn adopt_step(&mut self, step: Box<dyn Step>) -> Option<Box<dyn Step>> {
+            match self.steps.get_mut().unwrap().as_mut() {
+                Some(steps) => {
+                    steps.add_step(step);
+                    None
+                }
+                None => Some(step),
+            }
+        }
+    }
+
+    impl BoxedClone for ResumeStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        /// Dials the steps behind for the client, passing the destination it
+        /// asked the entry for to the other side instead of dialing it here.
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            let info_without_destination = ConnectionInfo {
+                destination: None,
+                ..info.clone()
+            };
+            let steps = match self.steps.lock().unwrap().as_ref() {
+                Some(steps) => Some(steps.clone_with(&info_without_destination)?),
+                None => None,
+            };
+            Ok(Box::new(Self {
+                steps: Mutex::new(steps),
+                info: info_without_destination,
+                destination: info.destination.clone(),
+                stream: LazyStream::new(),
+                options: self.options,
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }))
+        }
+    }
+
+    impl StepStatic for ResumeStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Self {
+                steps: Mutex::new(Some(Pipeline::new())),
+                info: ConnectionInfo::default(),
+                destination: None,
+                stream: LazyStream::new(),
+                options: parse_options(&args)?,
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(argument: CliSpec) -> CliSpec {
+            // the session options are shared with ResumeEntry, which adds them
+            argument
+        }
+    }
+
+    impl Clone for ResumeStep {
+        fn clone(&self) -> Self {
+            Self {
+                steps: Mutex::new(self.steps.lock().unwrap().clone()),
+                info: self.info.clone(),
+                destination: self.destination.clone(),
+                stream: LazyStream::new(),
+                options: self.options,
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl AsRawFd for ResumeStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+}
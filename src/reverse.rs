@@ -0,0 +1,702 @@
+pub mod reverse {
+    use std::io::{self, ErrorKind, Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::os::unix::net::UnixStream;
+    use std::sync::mpsc::{channel, Sender};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::{Poll, Waker};
+    use ring::hmac;
+    use ring::rand::{SecureRandom, SystemRandom};
+    use tokio::runtime::Handle;
+    use tokio::sync::oneshot;
+
+    use crate::mux::mux::{
+        parse_keepalive, stream_server, Link, MuxSession, OpenRequest, REQUESTS,
+    };
+    use crate::{base::base::DebugLevel, Entry, EntryStatic, Error, Pipeline};
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const RELAY_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "relay-entry-address",
+        "--relay-ea",
+        "(RelayEntry) Address agents connect to",
+    );
+    const RELAY_ENTRY_PORT: (&str, &str, &str) = (
+        "relay-entry-port",
+        "--relay-ep",
+        "(RelayEntry) Port agents connect to",
+    );
+    const RELAY_ENTRY_PUBLIC: (&str, &str, &str) = (
+        "relay-entry-public",
+        "--relay-epublic",
+        "(RelayEntry) Address the ports opened for agents listen on",
+    );
+    const RELAY_ENTRY_PORTS: (&str, &str, &str) = (
+        "relay-entry-ports",
+        "--relay-eports",
+        "(RelayEntry) Range of ports agents may open, e.g. 8000-8100",
+    );
+
+    const AGENT_ENTRY_RELAY: (&str, &str, &str) = (
+        "agent-entry-relay",
+        "--agent-erelay",
+        "(AgentEntry) Address of the relay to dial",
+    );
+    const AGENT_ENTRY_PORT: (&str, &str, &str) = (
+        "agent-entry-port",
+        "--agent-ep",
+        "(AgentEntry) Port of the relay to dial",
+    );
+    const AGENT_ENTRY_REMOTE: (&str, &str, &str) = (
+        "agent-entry-remote",
+        "--agent-eremote",
+        "(AgentEntry) Port to open on the relay, 0 lets the relay pick one (shown with debug level 2)",
+    );
+    const AGENT_ENTRY_RETRY: (&str, &str, &str) = (
+        "agent-entry-retry",
+        "--agent-eretry",
+        "(AgentEntry) Longest wait in seconds between attempts to reach the relay",
+    );
+
+    const REVERSE_SECRET: (&str, &str, &str) = (
+        "reverse-secret",
+        "--reverse-secret",
+        "(RelayEntry, AgentEntry) Secret the relay and its agents prove to each other",
+    );
+
+    // the relay greets with a magic, a version and its nonce, the agent
+    // answers with its nonce and the port it wants, and the relay replies
+    // with a status and the port it opened; both messages end with an HMAC
+    // over both nonces so neither side can be impersonated or replayed
+    const MAGIC: &[u8] = b"KPRV";
+    const VERSION: u8 = 1;
+    const NONCE_SIZE: usize = 32;
+    const TAG_SIZE: usize = 32;
+    const ACCEPTED: u8 = 0;
+    const UNAUTHORIZED: u8 = 1;
+    const PORT_UNAVAILABLE: u8 = 2;
+
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+    const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+    fn nonce() -> Result<[u8; NONCE_SIZE], Error> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| Error::Msg("no randomness available for a nonce".to_string()))?;
+        Ok(nonce)
+    }
+
+    fn transcript(role: &[u8], relay_nonce: &[u8], agent_nonce: &[u8], message: &[u8]) -> Vec<u8> {
+        [role, relay_nonce, agent_nonce, message].concat()
+    }
+
+    fn unauthorized(message: &str) -> Error {
+        Error::IoError(io::Error::new(ErrorKind::PermissionDenied, message))
+    }
+
+    fn parse_secret(args: &CliParsed) -> Result<hmac::Key, Error> {
+        match args.argument_values.get(REVERSE_SECRET.0) {
+            Some(secret) => Ok(hmac::Key::new(hmac::HMAC_SHA256, secret[0].as_bytes())),
+            None => Err(Error::RequireOption(REVERSE_SECRET.0.to_string())),
+        }
+    }
+
+    /// Proves the secret to the relay and asks it for `remote_port`,
+    /// returning the port it opened.
+    fn register(
+        connection: &mut TcpStream,
+        secret: &hmac::Key,
+        remote_port: u16,
+    ) -> Result<u16, Error> {
+        let mut greeting = [0u8; 5 + NONCE_SIZE];
+        connection.read_exact(&mut greeting)?;
+        if &greeting[..4] != MAGIC || greeting[4] != VERSION {
+            return Err(Error::Msg("peer is not a kproxy relay".to_string()));
+        }
+        let relay_nonce = &greeting[5..];
+
+        let agent_nonce = nonce()?;
+        let request = remote_port.to_be_bytes();
+        let tag = hmac::sign(
+            secret,
+            &transcript(b"agent", relay_nonce, &agent_nonce, &request),
+        );
+        connection.write_all(&[&agent_nonce[..], &request, tag.as_ref()].concat())?;
+
+        let mut reply = [0u8; 3 + TAG_SIZE];
+        connection.read_exact(&mut reply)?;
+        // anyone on the way can send a rejection, so an unsigned one only
+        // means this attempt failed, whether the secrets differ or not
+        let signed = transcript(b"relay", relay_nonce, &agent_nonce, &reply[..3]);
+        if hmac::verify(secret, &signed, &reply[3..]).is_err() {
+            return Err(Error::Msg(
+                "the relay's reply is not signed with the secret".to_string(),
+            ));
+        }
+
+        match reply[0] {
+            ACCEPTED => Ok(u16::from_be_bytes([reply[1], reply[2]])),
+            UNAUTHORIZED => Err(Error::Msg("the relay rejected the request".to_string())),
+            PORT_UNAVAILABLE => Err(Error::Msg(format!(
+                "port {} is not available on the relay",
+                remote_port
+            ))),
+            status => Err(Error::Msg(format!("relay replied with status {}", status))),
+        }
+    }
+
+    struct Relay {
+        secret: hmac::Key,
+        public: String,
+        ports: (u16, u16),
+        keepalive: Option<Duration>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl Relay {
+        /// Checks the agent knows the secret and opens the port it asks for.
+        fn admit(&self, connection: &mut TcpStream) -> Result<TcpListener, Error> {
+            let relay_nonce = nonce()?;
+            connection.write_all(&[MAGIC, &[VERSION], &relay_nonce[..]].concat())?;
+
+            let mut request = [0u8; NONCE_SIZE + 2 + TAG_SIZE];
+            connection.read_exact(&mut request)?;
+            let (agent_nonce, rest) = request.split_at(NONCE_SIZE);
+            let (port, tag) = rest.split_at(2);
+            let signed = transcript(b"agent", &relay_nonce, agent_nonce, port);
+            if hmac::verify(&self.secret, &signed, tag).is_err() {
+                let reply = [UNAUTHORIZED, port[0], port[1]];
+                let tag = hmac::sign(
+                    &self.secret,
+                    &transcript(b"relay", &relay_nonce, agent_nonce, &reply),
+                );
+                connection.write_all(&[&reply[..], tag.as_ref()].concat())?;
+                return Err(unauthorized("agent does not know the secret"));
+            }
+
+            let requested = u16::from_be_bytes([port[0], port[1]]);
+            let listener = self.open_port(requested);
+            let reply = match &listener {
+                Some(listener) => {
+                    let mut reply = vec![ACCEPTED];
+                    reply.extend(listener.local_addr()?.port().to_be_bytes());
+                    reply
+                }
+                None => vec![PORT_UNAVAILABLE, port[0], port[1]],
+            };
+            let tag = hmac::sign(
+                &self.secret,
+                &transcript(b"relay", &relay_nonce, agent_nonce, &reply),
+            );
+            connection.write_all(&[&reply[..], tag.as_ref()].concat())?;
+
+            listener.ok_or_else(|| Error::Msg(format!("port {} is not available", requested)))
+        }
+
+        /// Binds `requested`, or the first free port of the range for 0.
+        fn open_port(&self, requested: u16) -> Option<TcpListener> {
+            let (first, last) = self.ports;
+            let bind = |port| {
+                create_socket_addr(self.public.as_str(), port)
+                    .ok()
+                    .and_then(|addr| TcpListener::bind(addr).ok())
+            };
+            match requested {
+                0 => (first..=last).find_map(bind),
+                port if (first..=last).contains(&port) => bind(port),
+                _ => None,
+            }
+        }
+
+        fn serve_agent(
+            &self,
+            mut connection: TcpStream,
+            peer: SocketAddr,
+            runtime: Handle,
+        ) -> Result<(), Error> {
+            connection.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            let listener = self.admit(&mut connection)?;
+            connection.set_read_timeout(None)?;
+            connection.set_nonblocking(true)?;
+            connection.set_nodelay(true)?;
+            listener.set_nonblocking(true)?;
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!(
+                        "agent {} opened port {}",
+                        peer,
+                        listener.local_addr()?.port()
+                    );
+                }
+            }
+
+            let poll = Poll::new()?;
+            let waker = Waker::new(poll.registry(), REQUESTS)?;
+            let (requests, receiver) = channel();
+            let link = Link::Tcp {
+                connection,
+                unsent: Vec::new(),
+            };
+            let mut session = MuxSession::new(
+                link,
+                poll,
+                Some(receiver),
+                None,
+                self.keepalive,
+                self.buffer_size,
+            );
+
+            // dropping `closed` stops the listener so the agent can have its
+            // port back when it reconnects
+            let (closed, closing) = oneshot::channel::<()>();
+            let debug_level = self.debug_level;
+            let guard = runtime.enter();
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            drop(guard);
+            runtime.spawn(accept_clients(
+                listener,
+                requests,
+                waker,
+                closing,
+                debug_level,
+            ));
+
+            let result = session.run();
+            drop(closed);
+            result
+        }
+    }
+
+    /// Carries every client of an agent's port as a stream of its link.
+    async fn accept_clients(
+        listener: tokio::net::TcpListener,
+        requests: Sender<OpenRequest>,
+        waker: Waker,
+        mut closing: oneshot::Receiver<()>,
+        debug_level: DebugLevel,
+    ) {
+        loop {
+            let (client, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        if debug_level > 0 {
+                            eprintln!("an error accured accepting client: {}", e);
+                        }
+                        continue;
+                    }
+                },
+                _ = &mut closing => return,
+            };
+
+            {
+                // debug
+                if debug_level >= 2 {
+                    println!("new client: {}", peer);
+                }
+            }
+
+            let stream = match open_stream(&requests, &waker) {
+                Ok(stream) => stream,
+                Err(e) => {
+                    if debug_level > 0 {
+                        eprintln!("an error accured opening stream for {}: {}", peer, e);
+                    }
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                if let Err(e) = relay_client(client, stream).await {
+                    if debug_level > 0 {
+                        eprintln!("an error accured relaying {}: {}", peer, e);
+                    }
+                }
+            });
+        }
+    }
+
+    fn open_stream(requests: &Sender<OpenRequest>, waker: &Waker) -> Result<UnixStream, Error> {
+        let (inner, outer) = UnixStream::pair()?;
+        inner.set_nonblocking(true)?;
+        outer.set_nonblocking(true)?;
+        requests
+            .send(OpenRequest {
+                inner,
+                destination: None,
+                backlog: None,
+            })
+            .map_err(|_| Error::Msg("agent link is closed".to_string()))?;
+        waker.wake()?;
+        Ok(outer)
+    }
+
+    async fn relay_client(
+        mut client: tokio::net::TcpStream,
+        stream: UnixStream,
+    ) -> Result<(), Error> {
+        let mut stream = tokio::net::UnixStream::from_std(stream)?;
+        tokio::io::copy_bidirectional(&mut client, &mut stream).await?;
+        Ok(())
+    }
+
+    /// Accepts agents and opens a public port for each of them, carrying
+    /// every client of that port back over the agent's own connection.
+    pub struct RelayEntry {
+        address: String,
+        port: u16,
+        relay: Arc<Relay>,
+    }
+
+    impl Entry for RelayEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let listener = TcpListener::bind(addr)?;
+            let debug_level = self.relay.debug_level;
+
+            loop {
+                let (connection, peer) = match listener.accept() {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        if debug_level > 0 {
+                            eprintln!("an error accured accepting agent: {}", e);
+                        }
+                        continue;
+                    }
+                };
+
+                {
+                    // debug
+                    if debug_level >= 2 {
+                        println!("new agent: {}", peer);
+                    }
+                }
+
+                let relay = self.relay.clone();
+                let runtime = runtime.handle().clone();
+                thread::spawn(move || {
+                    if let Err(e) = relay.serve_agent(connection, peer, runtime) {
+                        if debug_level > 0 {
+                            eprintln!("an error accured on link of agent {}: {}", peer, e);
+                        }
+                    }
+
+                    {
+                        // debug
+                        if debug_level >= 2 {
+                            println!("agent {} closed", peer);
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    impl EntryStatic<RelayEntry> for RelayEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<RelayEntry, Error> {
+            if !pipeline.is_empty() {
+                return Err(Error::Msg(
+                    "the relay entry forwards to its agents and takes no steps".to_string(),
+                ));
+            }
+
+            let address = match args.argument_values.get(RELAY_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(RELAY_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(RELAY_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(RELAY_ENTRY_PORT.0.to_string())),
+            };
+            let public = match args.argument_values.get(RELAY_ENTRY_PUBLIC.0) {
+                Some(public) => public[0].clone(),
+                None => return Err(Error::RequireOption(RELAY_ENTRY_PUBLIC.0.to_string())),
+            };
+            let ports = match args.argument_values.get(RELAY_ENTRY_PORTS.0) {
+                Some(ports) => ports[0].clone(),
+                None => return Err(Error::RequireOption(RELAY_ENTRY_PORTS.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let ports = match ports.split_once('-') {
+                Some((first, last)) => (first.parse::<u16>(), last.parse::<u16>()),
+                None => (ports.parse::<u16>(), ports.parse::<u16>()),
+            };
+            let ports = match ports {
+                (Ok(first), Ok(last)) if first > 0 && first <= last => (first, last),
+                _ => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(RelayEntry {
+                address,
+                port,
+                relay: Arc::new(Relay {
+                    secret: parse_secret(&args)?,
+                    public,
+                    ports,
+                    keepalive: parse_keepalive(&args)?,
+                    debug_level,
+                    buffer_size,
+                }),
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: RELAY_ENTRY_ADDRESS.0.to_string(),
+                key: vec![RELAY_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(RELAY_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: RELAY_ENTRY_PORT.0.to_string(),
+                key: vec![RELAY_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("7000".to_string()),
+                help: Some(ArgumentHelp::Text(RELAY_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: RELAY_ENTRY_PUBLIC.0.to_string(),
+                key: vec![RELAY_ENTRY_PUBLIC.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(RELAY_ENTRY_PUBLIC.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: RELAY_ENTRY_PORTS.0.to_string(),
+                key: vec![RELAY_ENTRY_PORTS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("1024-65535".to_string()),
+                help: Some(ArgumentHelp::Text(RELAY_ENTRY_PORTS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: REVERSE_SECRET.0.to_string(),
+                key: vec![REVERSE_SECRET.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(REVERSE_SECRET.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Dials a relay, has it open a public port and serves every client of
+    /// that port with its own copy of the pipeline, redialing whenever the
+    /// link drops.
+    pub struct AgentEntry {
+        relay: String,
+        port: u16,
+        remote_port: u16,
+        retry: Duration,
+        secret: hmac::Key,
+        keepalive: Option<Duration>,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for AgentEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let mut delay = FIRST_RETRY;
+
+            loop {
+                match self.connect() {
+                    Ok(connection) => {
+                        delay = FIRST_RETRY;
+                        if let Err(e) = self.serve(connection, runtime.handle().clone()) {
+                            if self.debug_level > 0 {
+                                eprintln!("an error accured on link to relay: {}", e);
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        if self.debug_level > 0 {
+                            eprintln!("an error accured reaching relay: {}", e);
+                        }
+                    }
+                }
+
+                thread::sleep(delay);
+                delay = (delay * 2).min(self.retry);
+            }
+        }
+    }
+
+    impl AgentEntry {
+        fn connect(&self) -> Result<TcpStream, Error> {
+            let addr = create_socket_addr(self.relay.as_str(), self.port)?;
+            let mut connection = TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?;
+            connection.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            let port = register(&mut connection, &self.secret, self.remote_port)?;
+            connection.set_read_timeout(None)?;
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("relay {} opened port {}", addr, port);
+                }
+            }
+            Ok(connection)
+        }
+
+        fn serve(&self, connection: TcpStream, runtime: Handle) -> Result<(), Error> {
+            let peer = connection.peer_addr()?;
+            let local = connection.local_addr()?;
+            connection.set_nonblocking(true)?;
+            connection.set_nodelay(true)?;
+
+            let on_open = stream_server(
+                runtime,
+                self.pipeline_template.clone(),
+                peer,
+                local,
+                self.debug_level,
+                self.buffer_size,
+            );
+            let link = Link::Tcp {
+                connection,
+                unsent: Vec::new(),
+            };
+            MuxSession::new(
+                link,
+                Poll::new()?,
+                None,
+                Some(on_open),
+                self.keepalive,
+                self.buffer_size,
+            )
+            .run()
+        }
+    }
+
+    impl EntryStatic<AgentEntry> for AgentEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<AgentEntry, Error> {
+            let relay = match args.argument_values.get(AGENT_ENTRY_RELAY.0) {
+                Some(relay) => relay[0].clone(),
+                None => return Err(Error::RequireOption(AGENT_ENTRY_RELAY.0.to_string())),
+            };
+            let port = match args.argument_values.get(AGENT_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(AGENT_ENTRY_PORT.0.to_string())),
+            };
+            let remote_port = match args.argument_values.get(AGENT_ENTRY_REMOTE.0) {
+                Some(remote_port) => remote_port[0].clone(),
+                None => return Err(Error::RequireOption(AGENT_ENTRY_REMOTE.0.to_string())),
+            };
+            let retry = match args.argument_values.get(AGENT_ENTRY_RETRY.0) {
+                Some(retry) => retry[0].clone(),
+                None => return Err(Error::RequireOption(AGENT_ENTRY_RETRY.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let remote_port = match str::parse::<u16>(remote_port.as_str()) {
+                Ok(remote_port) => remote_port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let retry = match str::parse::<u64>(retry.as_str()) {
+                Ok(retry) => Duration::from_secs(retry).max(FIRST_RETRY),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(AgentEntry {
+                relay,
+                port,
+                remote_port,
+                retry,
+                secret: parse_secret(&args)?,
+                keepalive: parse_keepalive(&args)?,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: AGENT_ENTRY_RELAY.0.to_string(),
+                key: vec![AGENT_ENTRY_RELAY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(AGENT_ENTRY_RELAY.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: AGENT_ENTRY_PORT.0.to_string(),
+                key: vec![AGENT_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("7000".to_string()),
+                help: Some(ArgumentHelp::Text(AGENT_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: AGENT_ENTRY_REMOTE.0.to_string(),
+                key: vec![AGENT_ENTRY_REMOTE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0".to_string()),
+                help: Some(ArgumentHelp::Text(AGENT_ENTRY_REMOTE.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: AGENT_ENTRY_RETRY.0.to_string(),
+                key: vec![AGENT_ENTRY_RETRY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("30".to_string()),
+                help: Some(ArgumentHelp::Text(AGENT_ENTRY_RETRY.2.to_string())),
+            });
+            // the secret is registered by RelayEntry::get_cmd
+            argument
+        }
+    }
+}
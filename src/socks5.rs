@@ -0,0 +1,535 @@
+pub mod socks5 {
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream, UdpSocket};
+    use tokio::sync::mpsc::{channel, Receiver, Sender};
+    use tokio::task::JoinSet;
+
+    use crate::async_pipeline::async_pipeline::{read_pipeline, relay, write_pipeline, PipelineFd};
+    use crate::{base::base::DebugLevel, ConnectionInfo, Entry, EntryStatic, Error, Pipeline};
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const SOCKS5_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "socks5-entry-address",
+        "--socks5-ea",
+        "(Socks5Entry) Socks5 server listen address",
+    );
+    const SOCKS5_ENTRY_PORT: (&str, &str, &str) = (
+        "socks5-entry-port",
+        "--socks5-ep",
+        "(Socks5Entry) Socks5 server listen port",
+    );
+    const SOCKS5_ENTRY_AUTH: (&str, &str, &str) = (
+        "socks5-entry-auth",
+        "--socks5-eauth",
+        "(Socks5Entry) Credentials as \"user:password\" clients must authenticate with",
+    );
+
+    pub(crate) const VERSION: u8 = 0x05;
+    pub(crate) const AUTH_VERSION: u8 = 0x01;
+
+    pub(crate) const METHOD_NO_AUTH: u8 = 0x00;
+    pub(crate) const METHOD_PASSWORD: u8 = 0x02;
+    pub(crate) const METHOD_NONE_ACCEPTABLE: u8 = 0xff;
+
+    pub(crate) const CMD_CONNECT: u8 = 0x01;
+    const CMD_UDP_ASSOCIATE: u8 = 0x03;
+
+    pub(crate) const ATYP_IPV4: u8 = 0x01;
+    pub(crate) const ATYP_DOMAIN: u8 = 0x03;
+    pub(crate) const ATYP_IPV6: u8 = 0x04;
+
+    pub(crate) const REPLY_SUCCEEDED: u8 = 0x00;
+    const REPLY_FAILURE: u8 = 0x01;
+    const REPLY_NETWORK_UNREACHABLE: u8 = 0x03;
+    const REPLY_HOST_UNREACHABLE: u8 = 0x04;
+    const REPLY_CONNECTION_REFUSED: u8 = 0x05;
+    const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+    const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+    /// Largest datagram a client can send.
+    const MAX_DATAGRAM: usize = u16::MAX as usize;
+    /// Datagrams waiting for a destination's pipeline, later ones are dropped.
+    const DATAGRAM_QUEUE: usize = 64;
+
+    /// A socks5 server (RFC 1928). CONNECT requests hand their destination to
+    /// the pipeline, where a `TcpStep` dials it in place of its configured
+    /// endpoint. UDP ASSOCIATE clones the pipeline for every destination the
+    /// client sends datagrams to, where a `Tcp2UdpStep` sends to it; as with
+    /// `UdpEntry` each datagram is one write and each read is one reply, so
+    /// a `udp2tcp` step keeps the boundaries across a stream.
+    pub struct Socks5Entry {
+        address: String,
+        port: u16,
+        /// Username and password of RFC 1929 auth, `None` accepts every client.
+        credentials: Option<(String, String)>,
+        debug_level: DebugLevel,
+        pipeline_template: Arc<Pipeline>,
+        buffer_size: usize,
+    }
+
+    impl Entry for Socks5Entry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            runtime.block_on(self.serve())
+        }
+    }
+
+    impl Socks5Entry {
+        async fn serve(&mut self) -> Result<(), Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let server = TcpListener::bind(addr).await?;
+
+            loop {
+                let (connection, peer) = server.accept().await?;
+
+                {
+                    // debug
+                    if self.debug_level >= 2 {
+                        println!("new client: {}", peer);
+                    }
+                }
+
+                let pipeline_template = self.pipeline_template.clone();
+                let credentials = self.credentials.clone();
+                let debug_level = self.debug_level;
+                let buffer_size = self.buffer_size;
+                tokio::spawn(async move {
+                    if let Err(e) = Socks5Entry::serve_client(
+                        connection,
+                        peer,
+                        pipeline_template,
+                        credentials,
+                        debug_level,
+                        buffer_size,
+                    )
+                    .await
+                    {
+                        if debug_level > 0 {
+                            eprintln!("an error accured serving {}: {}", peer, e);
+                        }
+                    }
+                });
+            }
+        }
+
+        async fn serve_client(
+            mut connection: TcpStream,
+            peer: SocketAddr,
+            pipeline_template: Arc<Pipeline>,
+            credentials: Option<(String, String)>,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) -> Result<(), Error> {
+            if !Socks5Entry::authenticate(&mut connection, credentials).await? {
+                return Ok(());
+            }
+
+            let mut request = [0u8; 3];
+            connection.read_exact(&mut request).await?;
+            if request[0] != VERSION {
+                return Err(Error::Msg("unsupported socks version".to_string()));
+            }
+            let destination = match read_address(&mut connection).await? {
+                Some(destination) => destination,
+                None => {
+                    reply(&mut connection, REPLY_ADDRESS_NOT_SUPPORTED, None).await?;
+                    return Ok(());
+                }
+            };
+
+            match request[1] {
+                CMD_CONNECT => {
+                    let info = ConnectionInfo {
+                        peer: Some(peer),
+                        local: connection.local_addr().ok(),
+                        destination: Some(destination),
+                    };
+                    let pipeline =
+                        match tokio::task::block_in_place(|| pipeline_template.clone_with(&info)) {
+                            Ok(pipeline) => pipeline,
+                            Err(e) => {
+                                reply(&mut connection, reply_code(&e), None).await?;
+                                return Err(e);
+                            }
+                        };
+                    reply(&mut connection, REPLY_SUCCEEDED, None).await?;
+                    relay(connection, pipeline, buffer_size).await
+                }
+                CMD_UDP_ASSOCIATE => {
+                    let socket =
+                        UdpSocket::bind(SocketAddr::new(connection.local_addr()?.ip(), 0)).await?;
+                    reply(&mut connection, REPLY_SUCCEEDED, Some(socket.local_addr()?)).await?;
+                    Socks5Entry::associate(connection, peer, socket, pipeline_template, debug_level)
+                        .await
+                }
+                _ => reply(&mut connection, REPLY_COMMAND_NOT_SUPPORTED, None).await,
+            }
+        }
+
+        /// Negotiates the auth method and checks the client's credentials,
+        /// returns `false` when the client was turned away.
+        async fn authenticate(
+            connection: &mut TcpStream,
+            credentials: Option<(String, String)>,
+        ) -> Result<bool, Error> {
+            let mut greeting = [0u8; 2];
+            connection.read_exact(&mut greeting).await?;
+            if greeting[0] != VERSION {
+                return Err(Error::Msg("unsupported socks version".to_string()));
+            }
+            let mut methods = vec![0u8; greeting[1] as usize];
+            connection.read_exact(&mut methods).await?;
+
+            let method = match credentials {
+                Some(_) => METHOD_PASSWORD,
+                None => METHOD_NO_AUTH,
+            };
+            if !methods.contains(&method) {
+                connection
+                    .write_all(&[VERSION, METHOD_NONE_ACCEPTABLE])
+                    .await?;
+                return Ok(false);
+            }
+            connection.write_all(&[VERSION, method]).await?;
+
+            let (user, password) = match credentials {
+                Some(credentials) => credentials,
+                None => return Ok(true),
+            };
+            let mut version = [0u8; 1];
+            connection.read_exact(&mut version).await?;
+            if version[0] != AUTH_VERSION {
+                return Err(Error::Msg("unsupported socks auth version".to_string()));
+            }
+            let client_user = read_string(connection).await?;
+            let client_password = read_string(connection).await?;
+            if client_user != user.as_bytes() || client_password != password.as_bytes() {
+                connection.write_all(&[AUTH_VERSION, 0x01]).await?;
+                return Ok(false);
+            }
+            connection.write_all(&[AUTH_VERSION, 0x00]).await?;
+            Ok(true)
+        }
+
+        /// Relays the client's datagrams for as long as the control connection
+        /// stays open, each destination through its own copy of the pipeline.
+        async fn associate(
+            mut connection: TcpStream,
+            peer: SocketAddr,
+            socket: UdpSocket,
+            pipeline_template: Arc<Pipeline>,
+            debug_level: DebugLevel,
+        ) -> Result<(), Error> {
+            let socket = Arc::new(socket);
+            let mut client: Option<SocketAddr> = None;
+            let mut destinations: HashMap<(String, u16), Sender<Vec<u8>>> = HashMap::new();
+            // dropped with the association, which aborts what is left
+            let mut tasks = JoinSet::new();
+            let mut control = [0u8; 1];
+            let mut buffer = vec![0u8; MAX_DATAGRAM];
+            loop {
+                tokio::select! {
+                    size = connection.read(&mut control) => {
+                        // the association ends with the tcp connection
+                        if size? == 0 {
+                            return Ok(());
+                        }
+                    }
+                    received = socket.recv_from(&mut buffer) => {
+                        let (size, from) = received?;
+                        // only the client that asked for the association may use it
+                        if from.ip() != peer.ip() || client.is_some_and(|client| client != from) {
+                            continue;
+                        }
+                        client = Some(from);
+                        let (destination, offset) = match parse_datagram(&buffer[0..size]) {
+                            Some(header) => header,
+                            // fragments are not supported and silently dropped
+                            None => continue,
+                        };
+                        while tasks.try_join_next().is_some() {}
+
+                        // a destination whose pipeline ended gets a new one
+                        if destinations.get(&destination).is_none_or(Sender::is_closed) {
+                            let info = ConnectionInfo {
+                                peer: Some(from),
+                                local: connection.local_addr().ok(),
+                                destination: Some(destination.clone()),
+                            };
+                            let pipeline = match tokio::task::block_in_place(|| {
+                                pipeline_template.clone_with(&info)
+                            }) {
+                                Ok(pipeline) => pipeline,
+                                Err(e) => {
+                                    if debug_level > 0 {
+                                        eprintln!("an error accured cloning pipeline: {}", e);
+                                    }
+                                    continue;
+                                }
+                            };
+                            let (sender, datagrams) = channel(DATAGRAM_QUEUE);
+                            let header = datagram_header(&destination);
+                            let socket = socket.clone();
+                            tasks.spawn(async move {
+                                if let Err(e) =
+                                    relay_datagrams(pipeline, datagrams, socket, from, header).await
+                                {
+                                    if debug_level > 0 {
+                                        eprintln!("an error accured relaying datagrams: {}", e);
+                                    }
+                                }
+                            });
+                            destinations.insert(destination.clone(), sender);
+                        }
+                        // datagrams may be lost, a full queue is no different
+                        let _ = destinations[&destination].try_send(buffer[offset..size].to_vec());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Writes the datagrams for one destination to its pipeline, and sends
+    /// each read of the pipeline back to the client as a datagram from it.
+    async fn relay_datagrams(
+        mut pipeline: Pipeline,
+        mut datagrams: Receiver<Vec<u8>>,
+        socket: Arc<UdpSocket>,
+        client: SocketAddr,
+        header: Vec<u8>,
+    ) -> Result<(), Error> {
+        let pipeline_fd = PipelineFd::readable(&pipeline)?;
+        loop {
+            tokio::select! {
+                datagram = datagrams.recv() => match datagram {
+                    Some(datagram) => write_pipeline(&mut pipeline, datagram)?,
+                    // the association ended
+                    None => return Ok(()),
+                },
+                guard = pipeline_fd.readable() => {
+                    let mut guard = guard?;
+                    match read_pipeline(&mut pipeline) {
+                        Ok(Some(data)) => {
+                            let mut datagram = header.clone();
+                            datagram.extend(data);
+                            socket.send_to(&datagram, client).await?;
+                        }
+                        Ok(None) => guard.clear_ready(),
+                        Err(Error::IoError(e)) if e.kind() == ErrorKind::ConnectionAborted => {
+                            return Ok(())
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+    }
+
+    async fn read_string(connection: &mut TcpStream) -> Result<Vec<u8>, Error> {
+        let mut length = [0u8; 1];
+        connection.read_exact(&mut length).await?;
+        let mut value = vec![0u8; length[0] as usize];
+        connection.read_exact(&mut value).await?;
+        Ok(value)
+    }
+
+    /// Reads `ATYP DST.ADDR DST.PORT`, `None` for an unknown address type.
+    async fn read_address(connection: &mut TcpStream) -> Result<Option<(String, u16)>, Error> {
+        let mut atyp = [0u8; 1];
+        connection.read_exact(&mut atyp).await?;
+        let host = match atyp[0] {
+            ATYP_IPV4 => {
+                let mut octets = [0u8; 4];
+                connection.read_exact(&mut octets).await?;
+                Ipv4Addr::from(octets).to_string()
+            }
+            ATYP_IPV6 => {
+                let mut octets = [0u8; 16];
+                connection.read_exact(&mut octets).await?;
+                Ipv6Addr::from(octets).to_string()
+            }
+            ATYP_DOMAIN => String::from_utf8_lossy(&read_string(connection).await?).to_string(),
+            _ => return Ok(None),
+        };
+        let mut port = [0u8; 2];
+        connection.read_exact(&mut port).await?;
+        Ok(Some((host, u16::from_be_bytes(port))))
+    }
+
+    /// Parses the header of a client datagram, returning its destination and
+    /// where the payload starts.
+    fn parse_datagram(datagram: &[u8]) -> Option<((String, u16), usize)> {
+        // RSV(2) FRAG(1) ATYP(1)
+        if datagram.len() < 4 || datagram[2] != 0 {
+            return None;
+        }
+        let (host, offset) = match datagram[3] {
+            ATYP_IPV4 => {
+                let octets: [u8; 4] = datagram.get(4..8)?.try_into().ok()?;
+                (Ipv4Addr::from(octets).to_string(), 8)
+            }
+            ATYP_IPV6 => {
+                let octets: [u8; 16] = datagram.get(4..20)?.try_into().ok()?;
+                (Ipv6Addr::from(octets).to_string(), 20)
+            }
+            ATYP_DOMAIN => {
+                let length = *datagram.get(4)? as usize;
+                let host = datagram.get(5..5 + length)?;
+                (String::from_utf8_lossy(host).to_string(), 5 + length)
+            }
+            _ => return None,
+        };
+        let port = u16::from_be_bytes(datagram.get(offset..offset + 2)?.try_into().ok()?);
+        Some(((host, port), offset + 2))
+    }
+
+    /// The header of datagrams sent back to the client from `destination`,
+    /// which keeps the form the client named it in.
+    fn datagram_header(destination: &(String, u16)) -> Vec<u8> {
+        let (host, port) = destination;
+        let mut header = vec![0u8, 0u8, 0u8];
+        match IpAddr::from_str(host) {
+            Ok(ip) => header.extend(encode_address(SocketAddr::new(ip, *port))),
+            Err(_) => {
+                header.push(ATYP_DOMAIN);
+                header.push(host.len() as u8);
+                header.extend_from_slice(host.as_bytes());
+                header.extend_from_slice(&port.to_be_bytes());
+            }
+        }
+        header
+    }
+
+    fn encode_address(addr: SocketAddr) -> Vec<u8> {
+        let mut result = Vec::new();
+        match addr.ip() {
+            IpAddr::V4(ip) => {
+                result.push(ATYP_IPV4);
+                result.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                result.push(ATYP_IPV6);
+                result.extend_from_slice(&ip.octets());
+            }
+        }
+        result.extend_from_slice(&addr.port().to_be_bytes());
+        result
+    }
+
+    async fn reply(
+        connection: &mut TcpStream,
+        code: u8,
+        bound: Option<SocketAddr>,
+    ) -> Result<(), Error> {
+        // the address TcpStep dialed from is not known here
+        let bound = bound.unwrap_or(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0));
+        let mut response = vec![VERSION, code, 0x00];
+        response.extend(encode_address(bound));
+        connection.write_all(&response).await?;
+        Ok(())
+    }
+
+    fn reply_code(error: &Error) -> u8 {
+        match error {
+            Error::IoError(e) => match e.kind() {
+                ErrorKind::ConnectionRefused => REPLY_CONNECTION_REFUSED,
+                ErrorKind::NetworkUnreachable => REPLY_NETWORK_UNREACHABLE,
+                ErrorKind::HostUnreachable | ErrorKind::TimedOut => REPLY_HOST_UNREACHABLE,
+                _ => REPLY_FAILURE,
+            },
+            // names that do not resolve
+            Error::Msg(_) => REPLY_HOST_UNREACHABLE,
+            _ => REPLY_FAILURE,
+        }
+    }
+
+    impl EntryStatic<Socks5Entry> for Socks5Entry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<Socks5Entry, Error> {
+            let address = match args.argument_values.get(SOCKS5_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(SOCKS5_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(SOCKS5_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(SOCKS5_ENTRY_PORT.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+            let credentials = match args.argument_values.get(SOCKS5_ENTRY_AUTH.0) {
+                Some(credentials) => match credentials[0].split_once(':') {
+                    Some((user, password)) => Some((user.to_string(), password.to_string())),
+                    None => {
+                        return Err(Error::Msg(format!(
+                            "{} must be \"user:password\"",
+                            SOCKS5_ENTRY_AUTH.1
+                        )))
+                    }
+                },
+                None => None,
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Socks5Entry {
+                address,
+                port,
+                credentials,
+                debug_level,
+                pipeline_template: Arc::new(pipeline),
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: SOCKS5_ENTRY_ADDRESS.0.to_string(),
+                key: vec![SOCKS5_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(SOCKS5_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SOCKS5_ENTRY_PORT.0.to_string(),
+                key: vec![SOCKS5_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("1080".to_string()),
+                help: Some(ArgumentHelp::Text(SOCKS5_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SOCKS5_ENTRY_AUTH.0.to_string(),
+                key: vec![SOCKS5_ENTRY_AUTH.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(SOCKS5_ENTRY_AUTH.2.to_string())),
+            });
+            argument
+        }
+    }
+}
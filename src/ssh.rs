@@ -0,0 +1,713 @@
+pub mod ssh {
+    use std::collections::{HashMap, VecDeque};
+    use std::fmt::Display;
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::Shutdown;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::path::PathBuf;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::unix::SourceFd;
+    use mio::{Events, Interest, Poll, Token, Waker};
+    use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, Session};
+
+    use crate::async_pipeline::async_pipeline::LazyStream;
+    use crate::connect::connect::parse_authority;
+    use crate::mux::mux::OpenRequest;
+    use crate::tcp::tcp::{read_available, write_all};
+    use crate::{base::base::DebugLevel, BoxedClone, ConnectionInfo, Error, Step, StepStatic};
+    use crate::{create_socket_addr, BUFFER_SIZE};
+
+    const SSH_STEP_BASTION: (&str, &str, &str) = (
+        "ssh-step-bastion",
+        "--ssh-sbastion",
+        "(SshStep) Bastion to hop through as [user@]host[:port], the user defaults to $USER",
+    );
+    const SSH_STEP_ADDRESS: (&str, &str, &str) = (
+        "ssh-step-address",
+        "--ssh-sa",
+        "(SshStep) Target address the bastion connects to",
+    );
+    const SSH_STEP_PORT: (&str, &str, &str) = (
+        "ssh-step-port",
+        "--ssh-sp",
+        "(SshStep) Target port the bastion connects to",
+    );
+    const SSH_STEP_KEY: (&str, &str, &str) = (
+        "ssh-step-key",
+        "--ssh-skey",
+        "(SshStep) Private key file to authenticate with",
+    );
+    const SSH_STEP_PASSPHRASE: (&str, &str, &str) = (
+        "ssh-step-passphrase",
+        "--ssh-spassphrase",
+        "(SshStep) Passphrase of the private key",
+    );
+    const SSH_STEP_PASSWORD: (&str, &str, &str) = (
+        "ssh-step-password",
+        "--ssh-spassword",
+        "(SshStep) Password to authenticate with when there is no key or it is refused",
+    );
+    const SSH_STEP_KNOWN_HOSTS: (&str, &str, &str) = (
+        "ssh-step-known-hosts",
+        "--ssh-sknown-hosts",
+        "(SshStep) known_hosts file the bastion's host key must be listed in",
+    );
+
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+    /// Seconds of silence after which the session sends a keepalive, so the
+    /// bastion and anything in between keep an idle session open.
+    const KEEPALIVE_INTERVAL: u32 = 30;
+    /// libssh2's error for a channel the bastion refused to open, which
+    /// leaves the session usable.
+    const CHANNEL_FAILURE: i32 = -21;
+    const EAGAIN: i32 = -37;
+
+    const BASTION: Token = Token(0);
+    const REQUESTS: Token = Token(1);
+    const FIRST_TUNNEL: usize = 2;
+
+    fn expand_home(path: &str) -> PathBuf {
+        match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(path),
+        }
+    }
+
+    #[derive(Clone)]
+    struct Bastion {
+        address: String,
+        port: u16,
+        user: String,
+        key: Option<PathBuf>,
+        passphrase: Option<String>,
+        password: Option<String>,
+        known_hosts: PathBuf,
+    }
+
+    impl Display for Bastion {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{}@{}:{}", self.user, self.address, self.port)
+        }
+    }
+
+    impl Bastion {
+        /// Splits `[user@]host[:port]`, ipv6 hosts come in brackets.
+        fn parse(value: &str) -> Result<(String, String, u16), Error> {
+            let invalid = || Error::Msg(format!("invalid bastion: {}", value));
+            let (user, authority) = match value.split_once('@') {
+                Some((user, authority)) => (user.to_string(), authority),
+                None => match std::env::var("USER") {
+                    Ok(user) => (user, value),
+                    Err(_) => return Err(invalid()),
+                },
+            };
+            let (address, port) = match parse_authority(authority) {
+                Some(authority) => authority,
+                None if !authority.is_empty() && !authority.contains(':') => {
+                    (authority.to_string(), 22)
+                }
+                None => match authority
+                    .strip_prefix('[')
+                    .and_then(|host| host.strip_suffix(']'))
+                {
+                    Some(host) if !host.is_empty() => (host.to_string(), 22),
+                    _ => return Err(invalid()),
+                },
+            };
+            if user.is_empty() {
+                return Err(invalid());
+            }
+            Ok((user, address, port))
+        }
+
+        /// Dials the bastion and returns a session that is verified,
+        /// authenticated and non-blocking.
+        fn connect(&self) -> Result<Session, Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let connection = std::net::TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?;
+            let mut session = Session::new()?;
+            session.set_timeout(HANDSHAKE_TIMEOUT.as_millis() as u32);
+            session.set_tcp_stream(connection);
+            session.handshake()?;
+            self.verify_host_key(&session)?;
+            self.authenticate(&session)?;
+            session.set_keepalive(false, KEEPALIVE_INTERVAL);
+            session.set_blocking(false);
+            Ok(session)
+        }
+
+        fn verify_host_key(&self, session: &Session) -> Result<(), Error> {
+            let (key, _) = match session.host_key() {
+                Some(key) => key,
+                None => return Err(Error::Msg(format!("{} sent no host key", self))),
+            };
+            let mut known_hosts = session.known_hosts()?;
+            if let Err(e) = known_hosts.read_file(&self.known_hosts, KnownHostFileKind::OpenSSH) {
+                return Err(Error::Msg(format!(
+                    "failed to read {}: {}",
+                    self.known_hosts.display(),
+                    e
+                )));
+            }
+            match known_hosts.check_port(self.address.as_str(), self.port, key) {
+                CheckResult::Match => Ok(()),
+                CheckResult::NotFound => Err(Error::Msg(format!(
+                    "host key of {} is not listed in {}",
+                    self,
+                    self.known_hosts.display()
+                ))),
+                CheckResult::Mismatch => Err(Error::Msg(format!(
+                    "host key of {} does not match {}, refusing to connect",
+                    self,
+                    self.known_hosts.display()
+                ))),
+                CheckResult::Failure => Err(Error::Msg(format!(
+                    "failed to check the host key of {}",
+                    self
+                ))),
+            }
+        }
+
+        /// Tries the key first, a password is the fallback for a refused
+        /// key.
+        fn authenticate(&self, session: &Session) -> Result<(), Error> {
+            if let Some(key) = &self.key {
+                let result = session.userauth_pubkey_file(
+                    self.user.as_str(),
+                    None,
+                    key,
+                    self.passphrase.as_deref(),
+                );
+                match result {
+                    Ok(()) => return Ok(()),
+                    Err(e) if self.password.is_none() => {
+                        return Err(Error::Msg(format!(
+                            "key authentication to {} failed: {}",
+                            self, e
+                        )))
+                    }
+                    Err(_) => {}
+                }
+            }
+            if let Some(password) = &self.password {
+                if let Err(e) = session.userauth_password(self.user.as_str(), password) {
+                    return Err(Error::Msg(format!(
+                        "password authentication to {} failed: {}",
+                        self, e
+                    )));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// One client, carried by a direct-tcpip channel. The client holds the
+    /// other end of `inner`.
+    struct Tunnel {
+        inner: UnixStream,
+        channel: ssh2::Channel,
+        /// Read from the client, waiting for the channel's window. libssh2
+        /// expects a write it refused with EAGAIN to be repeated, so these
+        /// bytes are never dropped while the session lives.
+        queued: Vec<u8>,
+        /// Read from the channel, waiting for room in `inner`.
+        pending: Vec<u8>,
+        read_closed: bool,
+        eof_sent: bool,
+        remote_closed: bool,
+        write_closed: bool,
+    }
+
+    /// Runs the channels of one session on its own thread, the steps send
+    /// their clients through `requests`.
+    struct SshSession {
+        session: Session,
+        poll: Poll,
+        requests: Receiver<OpenRequest>,
+        /// Waiting for their channel, libssh2 opens one at a time.
+        opening: VecDeque<OpenRequest>,
+        tunnels: HashMap<usize, Tunnel>,
+        /// Finished channels whose close has not gone out yet.
+        closing: Vec<ssh2::Channel>,
+        next_token: usize,
+        target: (String, u16),
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl SshSession {
+        /// Returns once the bastion closes the connection, dropping every
+        /// client with it. Every wakeup moves whatever can be moved on all
+        /// channels, as libssh2 may have read data for any of them off the
+        /// socket.
+        fn run(&mut self) -> Result<(), Error> {
+            let fd = self.session.as_raw_fd();
+            self.poll.registry().register(
+                &mut SourceFd(&fd),
+                BASTION,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            let mut events = Events::with_capacity(1024);
+            let mut bastion_closed = false;
+            loop {
+                while let Ok(request) = self.requests.try_recv() {
+                    self.opening.push_back(request);
+                }
+                self.open_channels()?;
+                let tokens: Vec<usize> = self.tunnels.keys().copied().collect();
+                for token in tokens {
+                    self.pump(token);
+                }
+                self.closing
+                    .retain_mut(|channel| would_block(&channel.close()));
+                if bastion_closed {
+                    return Err(Error::Msg("the bastion closed the session".to_string()));
+                }
+
+                let keepalive = match self.session.keepalive_send() {
+                    Ok(seconds) => seconds.max(1),
+                    Err(e) if e.code() == ErrorCode::Session(EAGAIN) => 1,
+                    Err(e) => return Err(Error::from(e)),
+                };
+                let timeout = Duration::from_secs(keepalive as u64);
+                if let Err(e) = self.poll.poll(&mut events, Some(timeout)) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+                for event in events.iter() {
+                    if event.token() == BASTION && event.is_read_closed() {
+                        bastion_closed = true;
+                    }
+                }
+            }
+        }
+
+        /// Opens the channel of the oldest waiting client, a target the
+        /// bastion cannot reach only costs that client.
+        fn open_channels(&mut self) -> Result<(), Error> {
+            while let Some(request) = self.opening.front() {
+                let (host, port) = request.destination.as_ref().unwrap_or(&self.target);
+                match self
+                    .session
+                    .channel_direct_tcpip(host.as_str(), *port, None)
+                {
+                    Ok(channel) => {
+                        let request = self.opening.pop_front().unwrap();
+                        let token = self.next_token;
+                        self.next_token += 1;
+                        self.poll.registry().register(
+                            &mut SourceFd(&request.inner.as_raw_fd()),
+                            Token(token),
+                            Interest::READABLE | Interest::WRITABLE,
+                        )?;
+                        self.tunnels.insert(
+                            token,
+                            Tunnel {
+                                inner: request.inner,
+                                channel,
+                                queued: Vec::new(),
+                                pending: Vec::new(),
+                                read_closed: false,
+                                eof_sent: false,
+                                remote_closed: false,
+                                write_closed: false,
+                            },
+                        );
+                    }
+                    Err(e) if e.code() == ErrorCode::Session(CHANNEL_FAILURE) => {
+                        if self.debug_level > 0 {
+                            eprintln!(
+                                "an error accured opening channel to {}:{}: {}",
+                                host, port, e
+                            );
+                        }
+                        // dropping the client's end closes it
+                        self.opening.pop_front();
+                    }
+                    Err(e) if e.code() == ErrorCode::Session(EAGAIN) => return Ok(()),
+                    Err(e) => return Err(Error::from(e)),
+                }
+            }
+            Ok(())
+        }
+
+        /// Moves what the client wrote to the channel and what the channel
+        /// returned to the client, passing on half-closes both ways.
+        fn pump(&mut self, token: usize) {
+            let mut buffer = vec![0u8; self.buffer_size];
+            let tunnel = match self.tunnels.get_mut(&token) {
+                Some(tunnel) => tunnel,
+                None => return,
+            };
+
+            while !tunnel.eof_sent {
+                if !tunnel.queued.is_empty() {
+                    match tunnel.channel.write(&tunnel.queued) {
+                        Ok(size) => {
+                            tunnel.queued.drain(..size);
+                            continue;
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        // the target is gone, nothing more can be sent
+                        Err(_) => {
+                            tunnel.queued.clear();
+                            tunnel.read_closed = true;
+                            tunnel.eof_sent = true;
+                            break;
+                        }
+                    }
+                }
+                if tunnel.read_closed {
+                    if would_block(&tunnel.channel.send_eof()) {
+                        break;
+                    }
+                    tunnel.eof_sent = true;
+                    break;
+                }
+                match tunnel.inner.read(&mut buffer) {
+                    Ok(0) => tunnel.read_closed = true,
+                    Ok(size) => tunnel.queued.extend_from_slice(&buffer[..size]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    // the client is gone
+                    Err(_) => tunnel.read_closed = true,
+                }
+            }
+
+            while !tunnel.write_closed {
+                if !tunnel.pending.is_empty() {
+                    match tunnel.inner.write(&tunnel.pending) {
+                        Ok(size) => {
+                            tunnel.pending.drain(..size);
+                            continue;
+                        }
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                        Err(_) => {
+                            tunnel.pending.clear();
+                            tunnel.remote_closed = true;
+                        }
+                    }
+                }
+                if tunnel.remote_closed {
+                    let _ = tunnel.inner.shutdown(Shutdown::Write);
+                    tunnel.write_closed = true;
+                    break;
+                }
+                match tunnel.channel.read(&mut buffer) {
+                    Ok(0) => tunnel.remote_closed = true,
+                    Ok(size) => tunnel.pending.extend_from_slice(&buffer[..size]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => tunnel.remote_closed = true,
+                }
+            }
+
+            if tunnel.eof_sent && tunnel.write_closed {
+                let tunnel = self.tunnels.remove(&token).unwrap();
+                let _ = self
+                    .poll
+                    .registry()
+                    .deregister(&mut SourceFd(&tunnel.inner.as_raw_fd()));
+                self.closing.push(tunnel.channel);
+            }
+        }
+    }
+
+    fn would_block(result: &Result<(), ssh2::Error>) -> bool {
+        matches!(result, Err(e) if e.code() == ErrorCode::Session(EAGAIN))
+    }
+
+    struct SessionHandle {
+        requests: Sender<OpenRequest>,
+        waker: Waker,
+    }
+
+    /// The bastion and the session to it, shared by all clones of an
+    /// `SshStep`.
+    struct SshLink {
+        bastion: Bastion,
+        target: (String, u16),
+        session: Option<SessionHandle>,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl SshLink {
+        /// Starts a session thread, which dials and authenticates before it
+        /// serves the clients queued up meanwhile.
+        fn start(shared: &Arc<Mutex<SshLink>>, link: &mut SshLink) -> Result<(), Error> {
+            let poll = Poll::new()?;
+            let waker = Waker::new(poll.registry(), REQUESTS)?;
+            let (requests, receiver) = channel();
+
+            let bastion = link.bastion.clone();
+            let target = link.target.clone();
+            let debug_level = link.debug_level;
+            let buffer_size = link.buffer_size;
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let result = bastion.connect().and_then(|session| {
+                    SshSession {
+                        session,
+                        poll,
+                        requests: receiver,
+                        opening: VecDeque::new(),
+                        tunnels: HashMap::new(),
+                        closing: Vec::new(),
+                        next_token: FIRST_TUNNEL,
+                        target,
+                        debug_level,
+                        buffer_size,
+                    }
+                    .run()
+                });
+                if let Err(e) = result {
+                    if debug_level > 0 {
+                        eprintln!("an error accured on ssh session to {}: {}", bastion, e);
+                    }
+                }
+                shared.lock().unwrap().session = None;
+            });
+
+            link.session = Some(SessionHandle { requests, waker });
+            Ok(())
+        }
+    }
+
+    /// Carries each client through a direct-tcpip channel of an SSH session
+    /// to a bastion, like `ssh -L`. All clients share the session, which is
+    /// dialed again once it is lost.
+    pub struct SshStep {
+        shared: Arc<Mutex<SshLink>>,
+        destination: Option<(String, u16)>,
+        /// A channel of the shared bastion session.
+        stream: LazyStream,
+        debug_level: DebugLevel,
+        buffer_size: usize,
+    }
+
+    impl SshStep {
+        fn open(&self) -> Result<UnixStream, Error> {
+            let (inner, outer) = UnixStream::pair()?;
+            inner.set_nonblocking(true)?;
+            outer.set_nonblocking(true)?;
+
+            let mut link = self.shared.lock().unwrap();
+            if link.session.is_none() {
+                SshLink::start(&self.shared, &mut link)?;
+            }
+            if let Some(session) = &link.session {
+                let request = OpenRequest {
+                    inner,
+                    destination: self.destination.clone(),
+                    backlog: None,
+                };
+                if session.requests.send(request).is_err() {
+                    return Err(Error::Msg("ssh session closed".to_string()));
+                }
+                session.waker.wake()?;
+            }
+            Ok(outer)
+        }
+
+        fn stream(&self) -> Result<&UnixStream, Error> {
+            self.stream
+                .get("ssh channel", self.debug_level, || self.open())
+        }
+
+        fn with_destination(&self, destination: Option<(String, u16)>) -> Self {
+            Self {
+                shared: self.shared.clone(),
+                destination,
+                stream: LazyStream::new(),
+                debug_level: self.debug_level,
+                buffer_size: self.buffer_size,
+            }
+        }
+    }
+
+    impl Step for SshStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_all(&mut self.stream()?, data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            read_available(&mut self.stream()?, self.buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.stream()?.shutdown(Shutdown::Write)?;
+            Ok(())
+        }
+    }
+
+    impl BoxedClone for SshStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        /// Asks the bastion for the destination the client asked the entry
+        /// for, if any, in place of the configured target.
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            let destination = info.destination.clone().or(self.destination.clone());
+            Ok(Box::new(self.with_destination(destination)))
+        }
+    }
+
+    impl StepStatic for SshStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let bastion = match args.argument_values.get(SSH_STEP_BASTION.0) {
+                Some(bastion) => bastion[0].clone(),
+                None => return Err(Error::RequireOption(SSH_STEP_BASTION.0.to_string())),
+            };
+            let address = match args.argument_values.get(SSH_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(SSH_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(SSH_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(SSH_STEP_PORT.0.to_string())),
+            };
+            let known_hosts = match args.argument_values.get(SSH_STEP_KNOWN_HOSTS.0) {
+                Some(known_hosts) => known_hosts[0].clone(),
+                None => return Err(Error::RequireOption(SSH_STEP_KNOWN_HOSTS.0.to_string())),
+            };
+            let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
+                Some(buffer_size) => buffer_size[0].clone(),
+                None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
+            };
+            let key = args
+                .argument_values
+                .get(SSH_STEP_KEY.0)
+                .map(|key| expand_home(&key[0]));
+            let passphrase = args
+                .argument_values
+                .get(SSH_STEP_PASSPHRASE.0)
+                .map(|passphrase| passphrase[0].clone());
+            let password = args
+                .argument_values
+                .get(SSH_STEP_PASSWORD.0)
+                .map(|password| password[0].clone());
+            if key.is_none() && password.is_none() {
+                return Err(Error::Msg(format!(
+                    "the ssh step needs {} or {}",
+                    SSH_STEP_KEY.1, SSH_STEP_PASSWORD.1
+                )));
+            }
+
+            let (user, bastion_address, bastion_port) = Bastion::parse(&bastion)?;
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
+                Ok(buffer_size) => buffer_size,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let link = SshLink {
+                bastion: Bastion {
+                    address: bastion_address,
+                    port: bastion_port,
+                    user,
+                    key,
+                    passphrase,
+                    password,
+                    known_hosts: expand_home(&known_hosts),
+                },
+                target: (address, port),
+                session: None,
+                debug_level,
+                buffer_size,
+            };
+            Ok(Self {
+                shared: Arc::new(Mutex::new(link)),
+                destination: None,
+                stream: LazyStream::new(),
+                debug_level,
+                buffer_size,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: SSH_STEP_BASTION.0.to_string(),
+                key: vec![SSH_STEP_BASTION.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(SSH_STEP_BASTION.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SSH_STEP_ADDRESS.0.to_string(),
+                key: vec![SSH_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(SSH_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SSH_STEP_PORT.0.to_string(),
+                key: vec![SSH_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("80".to_string()),
+                help: Some(ArgumentHelp::Text(SSH_STEP_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SSH_STEP_KEY.0.to_string(),
+                key: vec![SSH_STEP_KEY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(SSH_STEP_KEY.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SSH_STEP_PASSPHRASE.0.to_string(),
+                key: vec![SSH_STEP_PASSPHRASE.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(SSH_STEP_PASSPHRASE.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SSH_STEP_PASSWORD.0.to_string(),
+                key: vec![SSH_STEP_PASSWORD.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(SSH_STEP_PASSWORD.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: SSH_STEP_KNOWN_HOSTS.0.to_string(),
+                key: vec![SSH_STEP_KNOWN_HOSTS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("~/.ssh/known_hosts".to_string()),
+                help: Some(ArgumentHelp::Text(SSH_STEP_KNOWN_HOSTS.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    impl Clone for SshStep {
+        fn clone(&self) -> Self {
+            self.with_destination(self.destination.clone())
+        }
+    }
+
+    impl AsRawFd for SshStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.stream().map_or(-1, |stream| stream.as_raw_fd())
+        }
+    }
+}
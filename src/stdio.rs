@@ -1,10 +1,17 @@
 pub mod stdio {
     use std::{
+        collections::HashMap,
         fmt::Display,
-        io::{stdin, stdout, Read, Stdin, Stdout, Write},
+        fs::{self, File, OpenOptions},
+        io::{self, stdin, stdout, BufRead, BufReader, ErrorKind, Read, Stdin, Stdout, Write},
         ops::{BitAnd, BitOr},
-        os::{fd::AsRawFd, unix::net::UnixStream},
-        sync::Mutex,
+        os::{
+            fd::{AsRawFd, FromRawFd},
+            unix::net::UnixStream,
+        },
+        sync::{Arc, Mutex},
+        thread,
+        time::{Duration, Instant},
     };
     extern crate lazy_static;
 
@@ -15,6 +22,9 @@ pub mod stdio {
     use mio::{unix::SourceFd, Events, Interest, Poll, Token};
     // use mio::{Events, Interest, Poll, Token};
 
+    use crate::async_pipeline::async_pipeline::LazyStream;
+    use crate::exec::exec::deliver;
+    use crate::tcp::tcp::read_available;
     use crate::{
         base::base::DebugLevel, BoxedClone, Entry, EntryStatic, Error, Pipeline, Step, StepStatic,
         BUFFER_SIZE,
@@ -31,6 +41,30 @@ pub mod stdio {
         "(StdioStep) print into stdout when pipeline direction is backward",
     );
 
+    const STDIO_STEP_INPUT: (&str, &str, &str) = (
+        "stdio-step-input",
+        "--stdio-sin",
+        "(StdioStep) File descriptor or path read for backward data, regular files need --stdio-snonblock",
+    );
+    const STDIO_STEP_OUTPUT: (&str, &str, &str) = (
+        "stdio-step-output",
+        "--stdio-sout",
+        "(StdioStep) File descriptor or path printed into by --forward-stdout and --backward-stdout",
+    );
+    const STDIO_STEP_NONBLOCK: (&str, &str, &str) = (
+        "stdio-step-nonblock",
+        "--stdio-snonblock",
+        "(StdioStep) Read the input on a thread and copy it to every client instead of blocking the entry",
+    );
+    const STDIO_STEP_DEMUX: (&str, &str, &str) = (
+        "stdio-step-demux",
+        "--stdio-sdemux",
+        "(StdioStep) Prefix printed lines with the client id and send input lines `id: payload` to that client only, implies --stdio-snonblock",
+    );
+
+    /// Gives up on a client whose socket stays full this long, in milliseconds.
+    const CLIENT_TIMEOUT: i32 = 1000;
+
     lazy_static! {
         static ref STDOUT: Mutex<Stdout> = Mutex::new(stdout());
     }
@@ -39,11 +73,48 @@ pub mod stdio {
         static ref STDIN: Mutex<Stdin> = Mutex::new(stdin());
     }
 
+    const CLOSE_ON_EOF_OPTION: (&str, &str, &str) = (
+        "close-on-eof",
+        "--close-on-eof",
+        "(StdioEntry) exit as soon as stdin ends instead of half-closing the pipeline and waiting for it to end",
+    );
+    const QUIT_AFTER_OPTION: (&str, &str, &str) = (
+        "quit-after",
+        "--quit-after",
+        "(StdioEntry) exit this many seconds after stdin ends even if the pipeline has not ended",
+    );
+
     const STDIN_TOKEN: Token = Token(0);
     const PIPELINE_TOKEN: Token = Token(1);
 
+    /// Copies stdin into the returned socket from a thread. Unlike stdin
+    /// itself the socket can always be polled, and its EOF is reported once
+    /// rather than on every wakeup.
+    fn read_stdin(buffer_size: usize) -> Result<UnixStream, Error> {
+        let (local, mut remote) = UnixStream::pair()?;
+        local.set_nonblocking(true)?;
+        thread::spawn(move || {
+            let mut buffer = vec![0u8; buffer_size];
+            loop {
+                let size = match STDIN.lock().unwrap().read(&mut buffer) {
+                    Ok(0) | Err(_) => return,
+                    Ok(size) => size,
+                };
+                if remote.write_all(&buffer[..size]).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(local)
+    }
+
+    /// Connects stdin and stdout to the pipeline, netcat style: once stdin
+    /// ends the pipeline is half-closed, and `listen` returns when the
+    /// pipeline ends, `Ok` on a clean close and the error otherwise.
     pub struct StdioEntry {
         pipeline: Pipeline,
+        close_on_eof: bool,
+        quit_after: Option<Duration>,
         debug_level: DebugLevel,
         buffer_size: usize,
     }
@@ -52,7 +123,12 @@ pub mod stdio {
         fn listen(&mut self) -> Result<(), Error> {
             let mut poll = Poll::new()?;
             let mut events = Events::with_capacity(128);
-            let fd = STDIN.lock().unwrap().as_raw_fd();
+            // a step whose upstream could not be reached has no fd to poll
+            if self.pipeline.as_raw_fd() < 0 {
+                return Err(Error::IoError(ErrorKind::NotConnected.into()));
+            }
+            let mut input = read_stdin(self.buffer_size)?;
+            let fd = input.as_raw_fd();
             let mut fd = SourceFd(&fd);
             poll.registry()
                 .register(&mut fd, STDIN_TOKEN, Interest::READABLE)?;
@@ -62,24 +138,79 @@ pub mod stdio {
                 Interest::READABLE | Interest::WRITABLE,
             )?;
 
-            let mut buf = vec![0u8; self.buffer_size];
-            let mut buf_fill_size = 0;
+            // set once stdin has ended
+            let mut deadline: Option<Option<Instant>> = None;
             loop {
-                poll.poll(&mut events, None)?;
+                let timeout = match deadline {
+                    Some(Some(deadline)) => {
+                        Some(deadline.saturating_duration_since(Instant::now()))
+                    }
+                    _ => None,
+                };
+                poll.poll(&mut events, timeout)?;
+                if let Some(Some(deadline)) = deadline {
+                    if Instant::now() >= deadline {
+                        return Ok(());
+                    }
+                }
                 for event in events.iter() {
                     match event.token() {
                         STDIN_TOKEN => {
-                            buf_fill_size = STDIN.lock().unwrap().read(buf.as_mut_slice())?;
-                            if buf_fill_size > 0 {
-                                self.pipeline
-                                    .write_pipeline(buf[0..buf_fill_size].to_vec())?;
-                                buf_fill_size = 0;
+                            if deadline.is_some() {
+                                continue;
+                            }
+                            let ended = match read_available(&mut input, self.buffer_size) {
+                                Ok(data) => {
+                                    self.pipeline.write_pipeline(data)?;
+                                    // data and the hangup may share one edge
+                                    event.is_read_closed()
+                                }
+                                Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                                    false
+                                }
+                                Err(Error::IoError(e))
+                                    if e.kind() == ErrorKind::ConnectionAborted =>
+                                {
+                                    true
+                                }
+                                Err(e) => return Err(e),
+                            };
+                            if ended {
+                                {
+                                    // debug
+                                    if self.debug_level >= 2 {
+                                        eprintln!("stdin ended");
+                                    }
+                                }
+                                if self.close_on_eof {
+                                    return Ok(());
+                                }
+                                poll.registry().deregister(&mut fd)?;
+                                self.pipeline.close_forward()?;
+                                deadline =
+                                    Some(self.quit_after.map(|after| Instant::now() + after));
                             }
                         }
                         PIPELINE_TOKEN => {
                             if event.is_readable() {
-                                let data = self.pipeline.read_pipeline()?;
-                                STDOUT.lock().unwrap().write(&data)?;
+                                match self.pipeline.read_pipeline() {
+                                    Ok(data) => {
+                                        let mut stdout = STDOUT.lock().unwrap();
+                                        stdout.write_all(&data)?;
+                                        stdout.flush()?;
+                                        if event.is_read_closed() {
+                                            return Ok(());
+                                        }
+                                    }
+                                    Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => {
+                                    }
+                                    Err(Error::IoError(e))
+                                        if e.kind() == ErrorKind::ConnectionAborted =>
+                                    {
+                                        return Ok(())
+                                    }
+                                    Err(e) => return Err(e),
+                                }
                             }
                         }
                         _ => unreachable!(),
@@ -90,12 +221,19 @@ pub mod stdio {
     }
 
     impl EntryStatic<StdioEntry> for StdioEntry {
-        #[allow(unused_variables)]
         fn new(
             args: CliParsed,
             pipeline: Pipeline,
             debug_level: DebugLevel,
         ) -> Result<StdioEntry, Error> {
+            let close_on_eof = args.arguments.contains(CLOSE_ON_EOF_OPTION.0);
+            let quit_after = match args.argument_values.get(QUIT_AFTER_OPTION.0) {
+                Some(quit_after) => match str::parse::<u64>(quit_after[0].as_str()) {
+                    Ok(quit_after) => Some(Duration::from_secs(quit_after)),
+                    Err(_) => return Err(Error::ParseIntError),
+                },
+                None => None,
+            };
             let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
                 Some(buffer_size) => buffer_size[0].clone(),
                 None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
@@ -103,17 +241,36 @@ pub mod stdio {
 
             let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
                 Ok(buffer_size) => buffer_size,
-                Err(e) => return Err(Error::ParseIntError),
+                Err(_) => return Err(Error::ParseIntError),
             };
 
             Ok(Self {
                 pipeline,
+                close_on_eof,
+                quit_after,
                 debug_level,
                 buffer_size,
             })
         }
 
-        fn get_cmd(argument: CliSpec) -> CliSpec {
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: CLOSE_ON_EOF_OPTION.0.to_string(),
+                key: vec![CLOSE_ON_EOF_OPTION.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::None,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(CLOSE_ON_EOF_OPTION.2.to_string())),
+            });
+
+            argument = argument.add_argument(Argument {
+                name: QUIT_AFTER_OPTION.0.to_string(),
+                key: vec![QUIT_AFTER_OPTION.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(QUIT_AFTER_OPTION.2.to_string())),
+            });
             argument
         }
     }
@@ -185,80 +342,260 @@ pub mod stdio {
         }
     }
 
+    /// Opens `--stdio-sin` or `--stdio-sout`, given either as a file
+    /// descriptor number or as a path.
+    fn open_stdio(value: &str, write: bool) -> Result<File, Error> {
+        if let Ok(fd) = str::parse::<i32>(value) {
+            let fd = unsafe { libc::dup(fd) };
+            if fd < 0 {
+                return Err(Error::IoError(io::Error::last_os_error()));
+            }
+            return Ok(unsafe { File::from_raw_fd(fd) });
+        }
+        Ok(OpenOptions::new()
+            .read(!write)
+            .append(write)
+            .create(write)
+            .open(value)?)
+    }
+
+    /// Reads the step input on a thread and hands it to the subscribed
+    /// clients: all of them, or in demux mode the one named by each line.
+    struct StdioInput {
+        clients: Mutex<HashMap<usize, UnixStream>>,
+        next_id: Mutex<usize>,
+        ended: Mutex<bool>,
+    }
+
+    impl StdioInput {
+        /// Opens `input` on the thread too, as opening a named pipe blocks
+        /// until it has a writer.
+        fn start(
+            input: String,
+            demux: bool,
+            debug_level: DebugLevel,
+            buffer_size: usize,
+        ) -> Arc<StdioInput> {
+            let hub = Arc::new(StdioInput {
+                clients: Mutex::new(HashMap::new()),
+                next_id: Mutex::new(0),
+                ended: Mutex::new(false),
+            });
+            let shared = hub.clone();
+            thread::spawn(move || {
+                match open_stdio(&input, false) {
+                    Ok(input) if demux => shared.route(input, debug_level),
+                    Ok(input) => shared.broadcast(input, buffer_size),
+                    Err(e) => {
+                        if debug_level > 0 {
+                            eprintln!("an error accured opening {}: {}", input, e);
+                        }
+                    }
+                }
+                *shared.ended.lock().unwrap() = true;
+                // closing the sockets ends every client
+                shared.clients.lock().unwrap().clear();
+            });
+            hub
+        }
+
+        fn broadcast(&self, mut input: File, buffer_size: usize) {
+            let mut buffer = vec![0u8; buffer_size];
+            loop {
+                let size = match input.read(&mut buffer) {
+                    Ok(0) | Err(_) => return,
+                    Ok(size) => size,
+                };
+                self.clients
+                    .lock()
+                    .unwrap()
+                    .retain(|_, client| deliver(client, &buffer[..size], CLIENT_TIMEOUT));
+            }
+        }
+
+        fn route(&self, input: File, debug_level: DebugLevel) {
+            for line in BufReader::new(input).split(b'\n') {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => return,
+                };
+                let (id, payload) = match parse_demux_line(&line) {
+                    Some(parsed) => parsed,
+                    None => {
+                        if debug_level > 0 {
+                            eprintln!(
+                                "expected `id: payload`, got {:?}",
+                                String::from_utf8_lossy(&line)
+                            );
+                        }
+                        continue;
+                    }
+                };
+                let mut clients = self.clients.lock().unwrap();
+                let client = match clients.get_mut(&id) {
+                    Some(client) => client,
+                    None => {
+                        if debug_level > 0 {
+                            eprintln!("No client found with this id {}", id);
+                        }
+                        continue;
+                    }
+                };
+                let mut data = payload.to_vec();
+                data.push(b'\n');
+                if !deliver(client, &data, CLIENT_TIMEOUT) {
+                    clients.remove(&id);
+                }
+            }
+        }
+
+        fn subscribe(&self) -> Result<(usize, UnixStream), Error> {
+            let (local, remote) = UnixStream::pair()?;
+            local.set_nonblocking(true)?;
+            remote.set_nonblocking(true)?;
+            let mut next_id = self.next_id.lock().unwrap();
+            let id = *next_id;
+            *next_id += 1;
+            // after the input ended, dropping `remote` ends the client at once
+            if !*self.ended.lock().unwrap() {
+                self.clients.lock().unwrap().insert(id, remote);
+            }
+            Ok((id, local))
+        }
+
+        fn unsubscribe(&self, id: usize) {
+            self.clients.lock().unwrap().remove(&id);
+        }
+    }
+
+    /// Splits an input line `id: payload` in demux mode.
+    fn parse_demux_line(line: &[u8]) -> Option<(usize, &[u8])> {
+        let colon = line.iter().position(|byte| *byte == b':')?;
+        let id = std::str::from_utf8(&line[..colon]).ok()?.trim();
+        let id = str::parse::<usize>(id).ok()?;
+        let payload = &line[colon + 1..];
+        Some((id, payload.strip_prefix(b" ").unwrap_or(payload)))
+    }
+
+    enum StdioStepInput {
+        /// Reads the input directly, blocking the entry until it has data.
+        Blocking(Arc<File>),
+        /// Reads what the `StdioInput` thread handed to this client. The
+        /// client subscribes on first use, so a template takes no id.
+        Shared {
+            hub: Arc<StdioInput>,
+            subscription: LazyStream<(usize, UnixStream)>,
+        },
+    }
+
+    impl StdioStepInput {
+        fn clone_input(&self) -> StdioStepInput {
+            match self {
+                StdioStepInput::Blocking(input) => StdioStepInput::Blocking(input.clone()),
+                StdioStepInput::Shared { hub, .. } => StdioStepInput::Shared {
+                    hub: hub.clone(),
+                    subscription: LazyStream::new(),
+                },
+            }
+        }
+    }
+
+    impl Drop for StdioStepInput {
+        fn drop(&mut self) {
+            if let StdioStepInput::Shared { hub, subscription } = self {
+                if let Some((id, _)) = subscription.opened() {
+                    hub.unsubscribe(*id);
+                }
+            }
+        }
+    }
+
     pub struct StdioStep {
         stdout_mode: StdoutMode,
+        input: StdioStepInput,
+        output: Arc<Mutex<File>>,
+        demux: bool,
         debug_level: DebugLevel,
         buffer_size: usize,
-        // streams: (UnixStream, UnixStream),
+    }
+
+    impl StdioStep {
+        /// The client's id on `hub` and the stream the hub hands it the
+        /// input on, subscribing on first use.
+        fn subscribed<'a>(
+            &self,
+            hub: &StdioInput,
+            subscription: &'a LazyStream<(usize, UnixStream)>,
+        ) -> Result<&'a (usize, UnixStream), Error> {
+            subscription.get("stdin subscription", self.debug_level, || hub.subscribe())
+        }
+
+        /// Prints `data`, line by line behind the client id in demux mode.
+        fn print(&self, data: &[u8]) -> Result<(), Error> {
+            let mut output = self.output.lock().unwrap();
+            match &self.input {
+                StdioStepInput::Shared { hub, subscription } if self.demux => {
+                    let (id, _) = self.subscribed(hub, subscription)?;
+                    for line in data.split_inclusive(|byte| *byte == b'\n') {
+                        write!(output, "{}: ", id)?;
+                        output.write_all(line)?;
+                    }
+                    if !data.is_empty() && !data.ends_with(b"\n") {
+                        output.write_all(b"\n")?;
+                    }
+                }
+                _ => output.write_all(data)?,
+            }
+            output.flush()?;
+            Ok(())
+        }
     }
 
     impl Step for StdioStep {
         fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
             if self.debug_level as usize > 2 {
-                STDOUT
-                    .lock()
-                    .unwrap()
-                    .write("\n++++++++++++++++++++++++++++++++++".as_bytes())?;
-                STDOUT
-                    .lock()
-                    .unwrap()
-                    .write("\nstdio forward : \n".as_bytes())?;
-                STDOUT.lock().unwrap().write(data.as_slice())?;
-                STDOUT
-                    .lock()
-                    .unwrap()
-                    .write("++++++++++++++++++++++++++++++++++\n".as_bytes())?;
-                STDOUT.lock().unwrap().flush()?;
+                self.print("\n++++++++++++++++++++++++++++++++++".as_bytes())?;
+                self.print("\nstdio forward : \n".as_bytes())?;
+                self.print(data.as_slice())?;
+                self.print("++++++++++++++++++++++++++++++++++\n".as_bytes())?;
             }
             if self.stdout_mode & StdoutMode::Forward == StdoutMode::Forward
                 && self.debug_level as usize <= 2
             {
-                STDOUT.lock().unwrap().write(data.as_slice())?;
-                STDOUT.lock().unwrap().flush()?;
+                self.print(data.as_slice())?;
             }
             Ok(data.clone())
         }
 
         fn process_data_backward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
             if self.debug_level as usize > 2 {
-                STDOUT
-                    .lock()
-                    .unwrap()
-                    .write("\n++++++++++++++++++++++++++++++++++\n".as_bytes())?;
-                STDOUT
-                    .lock()
-                    .unwrap()
-                    .write("\nstdio backward : \n".as_bytes())?;
-                STDOUT.lock().unwrap().write(data.as_slice())?;
-                STDOUT
-                    .lock()
-                    .unwrap()
-                    .write("++++++++++++++++++++++++++++++++++\n".as_bytes())?;
-                STDOUT.lock().unwrap().flush()?;
+                self.print("\n++++++++++++++++++++++++++++++++++\n".as_bytes())?;
+                self.print("\nstdio backward : \n".as_bytes())?;
+                self.print(data.as_slice())?;
+                self.print("++++++++++++++++++++++++++++++++++\n".as_bytes())?;
             }
             if self.stdout_mode & StdoutMode::Backward == StdoutMode::Backward
                 && self.debug_level as usize <= 2
             {
-                STDOUT.lock().unwrap().write(data.as_slice())?;
-                STDOUT.lock().unwrap().flush()?;
+                self.print(data.as_slice())?;
+            }
+            match &self.input {
+                StdioStepInput::Blocking(input) => {
+                    let mut read_buffer = vec![0u8; self.buffer_size];
+                    let read_size = input.as_ref().read(&mut read_buffer)?;
+                    Ok(read_buffer[0..read_size].to_vec())
+                }
+                StdioStepInput::Shared { hub, subscription } => {
+                    let (_, stream) = self.subscribed(hub, subscription)?;
+                    read_available(&mut &*stream, self.buffer_size)
+                }
             }
-            let mut read_buffer = vec![0u8; self.buffer_size];
-            let read_size = STDIN.lock().unwrap().read(&mut read_buffer)?;
-            Ok(read_buffer[0..read_size].to_vec())
         }
     }
 
     impl BoxedClone for StdioStep {
         fn bclone(&self) -> Box<dyn Step> {
-            Box::new(Self {
-                debug_level: self.debug_level.clone(),
-                stdout_mode: self.stdout_mode.clone(),
-                buffer_size: self.buffer_size,
-                // streams: (
-                //     self.streams.0.try_clone().unwrap(),
-                //     self.streams.1.try_clone().unwrap(),
-                // ),
-            })
+            Box::new(self.clone())
         }
     }
 
@@ -271,6 +608,16 @@ pub mod stdio {
             if args.arguments.contains(BACKWARD_STDOUT_OPTION.0) {
                 stdout_mode = stdout_mode | StdoutMode::Backward;
             }
+            let demux = args.arguments.contains(STDIO_STEP_DEMUX.0);
+            let nonblock = demux || args.arguments.contains(STDIO_STEP_NONBLOCK.0);
+            let input = match args.argument_values.get(STDIO_STEP_INPUT.0) {
+                Some(input) => input[0].clone(),
+                None => return Err(Error::RequireOption(STDIO_STEP_INPUT.0.to_string())),
+            };
+            let output = match args.argument_values.get(STDIO_STEP_OUTPUT.0) {
+                Some(output) => output[0].clone(),
+                None => return Err(Error::RequireOption(STDIO_STEP_OUTPUT.0.to_string())),
+            };
             let buffer_size = match args.argument_values.get(BUFFER_SIZE.0) {
                 Some(buffer_size) => buffer_size[0].clone(),
                 None => return Err(Error::RequireOption(BUFFER_SIZE.0.to_string())),
@@ -278,14 +625,30 @@ pub mod stdio {
 
             let buffer_size = match str::parse::<usize>(buffer_size.as_str()) {
                 Ok(buffer_size) => buffer_size,
-                Err(e) => return Err(Error::ParseIntError),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            let input = match nonblock {
+                true => {
+                    // report missing paths now
+                    if str::parse::<i32>(&input).is_err() {
+                        fs::metadata(&input)?;
+                    }
+                    StdioStepInput::Shared {
+                        hub: StdioInput::start(input, demux, debug_level, buffer_size),
+                        subscription: LazyStream::new(),
+                    }
+                }
+                false => StdioStepInput::Blocking(Arc::new(open_stdio(&input, false)?)),
             };
 
             Ok(Self {
                 stdout_mode,
+                input,
+                output: Arc::new(Mutex::new(open_stdio(&output, true)?)),
+                demux,
                 debug_level,
                 buffer_size,
-                // streams: (stream1, stream2),
             })
         }
 
@@ -307,37 +670,67 @@ pub mod stdio {
                 default_value: None,
                 help: Some(ArgumentHelp::Text(BACKWARD_STDOUT_OPTION.2.to_string())),
             });
+
+            argument = argument.add_argument(Argument {
+                name: STDIO_STEP_INPUT.0.to_string(),
+                key: vec![STDIO_STEP_INPUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0".to_string()),
+                help: Some(ArgumentHelp::Text(STDIO_STEP_INPUT.2.to_string())),
+            });
+
+            argument = argument.add_argument(Argument {
+                name: STDIO_STEP_OUTPUT.0.to_string(),
+                key: vec![STDIO_STEP_OUTPUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("1".to_string()),
+                help: Some(ArgumentHelp::Text(STDIO_STEP_OUTPUT.2.to_string())),
+            });
+
+            argument = argument.add_argument(Argument {
+                name: STDIO_STEP_NONBLOCK.0.to_string(),
+                key: vec![STDIO_STEP_NONBLOCK.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::None,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(STDIO_STEP_NONBLOCK.2.to_string())),
+            });
+
+            argument = argument.add_argument(Argument {
+                name: STDIO_STEP_DEMUX.0.to_string(),
+                key: vec![STDIO_STEP_DEMUX.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::None,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(STDIO_STEP_DEMUX.2.to_string())),
+            });
             argument
         }
     }
 
-    // impl Copy for StdioStep {}
-
     impl Clone for StdioStep {
         fn clone(&self) -> Self {
             Self {
-                debug_level: self.debug_level.clone(),
-                stdout_mode: self.stdout_mode.clone(),
+                stdout_mode: self.stdout_mode,
+                input: self.input.clone_input(),
+                output: self.output.clone(),
+                demux: self.demux,
+                debug_level: self.debug_level,
                 buffer_size: self.buffer_size,
-                // streams: (
-                //     self.streams.0.try_clone().unwrap(),
-                //     self.streams.1.try_clone().unwrap(),
-                // ),
             }
         }
     }
 
     impl AsRawFd for StdioStep {
         fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
-            // let fd = unsafe {
-            //     libc::dup2(
-            //         STDIN.lock().unwrap().as_raw_fd(),
-            //         STDOUT.lock().unwrap().as_raw_fd(),
-            //     )
-            // };
-            STDIN.lock().unwrap().as_raw_fd()
-            // return fd as RawFd;
-            // self.streams.0.as_raw_fd()
+            match &self.input {
+                StdioStepInput::Blocking(input) => input.as_raw_fd(),
+                StdioStepInput::Shared { hub, subscription } => self
+                    .subscribed(hub, subscription)
+                    .map_or(-1, |(_, stream)| stream.as_raw_fd()),
+            }
         }
     }
 }
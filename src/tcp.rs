@@ -1,9 +1,14 @@
 pub mod tcp {
     // use polling::{Event, Events, PollMode, Poller};
+    use std::collections::HashMap;
     use std::io::{ErrorKind, Read, Write};
+    use std::net::{Shutdown, SocketAddr};
     // use std::net::{TcpListener, TcpStream};
-    use std::os::fd::{AsRawFd, FromRawFd};
-    use std::{result, string, vec};
+    use std::os::fd::{AsFd, AsRawFd};
+    use std::str::FromStr;
+    use std::sync::OnceLock;
+    use std::time::{Duration, Instant};
+    use std::{mem, result, string, vec};
 
     use cliparser::types::{
         Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
@@ -12,8 +17,10 @@ pub mod tcp {
     use mio::net::{TcpListener, TcpStream};
     use mio::{Events, Interest, Poll, Token};
 
+    use crate::proxy_protocol::proxy_protocol::{self, ProxyVersion, MAX_HEADER_SIZE};
     use crate::{
-        base::base::DebugLevel, BoxedClone, Entry, EntryStatic, Error, Pipeline, Step, StepStatic,
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
     };
     use crate::{create_socket_addr, MultiMap, Ref, BUFFER_SIZE};
 
@@ -28,6 +35,12 @@ pub mod tcp {
         "(TcpEntry) Tcp Entry listen port",
     );
 
+    const TCP_ENTRY_PROXY: (&str, &str, &str) = (
+        "tcp-entry-proxy",
+        "--tcp-eproxy",
+        "(TcpEntry) Expect a PROXY protocol v1 or v2 header from every client, e.g. behind a load balancer",
+    );
+
     const TCP_STEP_ADDRESS: (&str, &str, &str) = (
         "tcp-step-address",
         "--tcp-sa",
@@ -39,7 +52,15 @@ pub mod tcp {
         "(TcpStep) Tcp step endpoint port",
     );
 
+    const TCP_STEP_PROXY: (&str, &str, &str) = (
+        "tcp-step-proxy",
+        "--tcp-sproxy",
+        "(TcpStep) Send a PROXY protocol header of this version (v1 or v2) with the client address before any data",
+    );
+
     const SERVER_TOKEN: Token = Token(0);
+    const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
 
     pub struct TcpEntry {
         address: String,
@@ -47,6 +68,8 @@ pub mod tcp {
         debug_level: DebugLevel,
         pipeline_template: Pipeline,
         connections: MultiMap<Token, Ref<TcpEntryContext>>,
+        proxy_protocol: bool,
+        pending: HashMap<Token, PendingClient>,
         buffer_size: usize,
     }
 
@@ -55,6 +78,18 @@ pub mod tcp {
         pipeline: Pipeline,
         connection_buf: Vec<u8>,
         pipeline_buf: Vec<u8>,
+        /// The pipeline has ended, close once `pipeline_buf` is flushed.
+        closing: bool,
+        /// The client is done sending and the pipeline was half-closed.
+        client_done: bool,
+    }
+
+    /// A client whose PROXY protocol header has not been read completely.
+    struct PendingClient {
+        connection: TcpStream,
+        peer: SocketAddr,
+        header: Vec<u8>,
+        started: Instant,
     }
 
     impl Entry for TcpEntry {
@@ -69,49 +104,51 @@ pub mod tcp {
             let mut connection_counter = 0;
 
             loop {
-                poll.poll(&mut events, None)?;
+                let timeout = match self.pending.is_empty() {
+                    true => None,
+                    false => Some(HEADER_TIMEOUT),
+                };
+                poll.poll(&mut events, timeout)?;
                 for event in events.iter() {
                     match event.token() {
-                        SERVER_TOKEN => {
-                            let connection = server.accept()?;
-
-                            let mut client = Ref::new(TcpEntryContext {
-                                connection: connection.0,
-                                pipeline: self.pipeline_template.clone(),
-                                connection_buf: vec![0u8; 0],
-                                pipeline_buf: vec![0u8; 0],
-                            });
-
-                            {
-                                // debug
-                                if self.debug_level >= 2 {
-                                    println!("new client: {}", connection.1);
-                                }
-                            }
+                        // one edge can stand for several pending connections
+                        SERVER_TOKEN => loop {
+                            let (mut connection, peer) = match server.accept() {
+                                Ok(connection) => connection,
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                                Err(e) => return Err(Error::IoError(e)),
+                            };
+                            connection_counter += 2;
+                            let token = Token(connection_counter - 1);
 
-                            connection_counter += 1;
-                            let token = Token(connection_counter);
-                            poll.registry()
-                                .register(
-                                    &mut client.connection,
+                            if self.proxy_protocol {
+                                // the pipeline is cloned once the header names the client
+                                poll.registry().register(
+                                    &mut connection,
+                                    token,
+                                    Interest::READABLE,
+                                )?;
+                                self.pending.insert(
                                     token,
-                                    Interest::READABLE | Interest::WRITABLE,
-                                )
-                                .unwrap();
-
-                            connection_counter += 1;
-                            let pipeline_token = Token(connection_counter);
-                            poll.registry()
-                                .register(
-                                    &mut client.pipeline,
-                                    pipeline_token,
-                                    Interest::READABLE | Interest::WRITABLE,
-                                )
-                                .unwrap();
-
-                            self.connections.insert(token, client.clone());
-                            self.connections.insert(pipeline_token, client);
-                            // drop(connection);
+                                    PendingClient {
+                                        connection,
+                                        peer,
+                                        header: Vec::new(),
+                                        started: Instant::now(),
+                                    },
+                                );
+                                continue;
+                            }
+
+                            let info = ConnectionInfo {
+                                peer: Some(peer),
+                                local: connection.local_addr().ok(),
+                                destination: None,
+                            };
+                            self.start_client(&poll, connection, info, token, Vec::new())?;
+                        },
+                        other if self.pending.contains_key(&other) => {
+                            self.read_header(&poll, other)?;
                         }
                         other => {
                             let client = match self.connections.get_mut(&other) {
@@ -124,16 +161,25 @@ pub mod tcp {
                                 }
                             };
 
+                            let mut close = false;
                             match other.0 % 2 {
                                 0 => {
                                     // pipeline has io event
                                     if event.is_readable() {
+                                        // data and the hangup may share one edge, in which
+                                        // case no read reports the end
+                                        client.closing |= event.is_read_closed();
                                         if let Err(e) = TcpEntry::read_pipeline(client) {
                                             match e {
-                                                Error::IoError(e) => {
-                                                    if e.kind() == ErrorKind::WouldBlock {
-                                                        continue;
-                                                    }
+                                                Error::IoError(e)
+                                                    if e.kind() == ErrorKind::WouldBlock =>
+                                                {
+                                                    continue;
+                                                }
+                                                Error::IoError(e)
+                                                    if e.kind() == ErrorKind::ConnectionAborted =>
+                                                {
+                                                    client.closing = true;
                                                 }
                                                 _ => {
                                                     if self.debug_level > 0 {
@@ -142,6 +188,7 @@ pub mod tcp {
                                                             e
                                                         );
                                                     }
+                                                    client.closing = true;
                                                 }
                                             }
                                         }
@@ -150,75 +197,310 @@ pub mod tcp {
                                             if self.debug_level > 0 {
                                                 eprintln!("an error accured writing client: {}", e);
                                             }
+                                            close = true;
                                         }
+                                        close |= client.closing && client.pipeline_buf.is_empty();
                                     }
                                 }
                                 1 => {
                                     // client has io event
-                                    if event.is_readable() {
-                                        if let Err(e) =
-                                            TcpEntry::read_client(self.buffer_size, client)
-                                        {
+                                    if event.is_writable() {
+                                        if let Err(e) = TcpEntry::write_client(client) {
                                             if self.debug_level > 0 {
-                                                eprintln!("an error accured reading client: {}", e);
+                                                eprintln!("an error accured writing client: {}", e);
+                                            }
+                                            close = true;
+                                        }
+                                        close |= client.closing && client.pipeline_buf.is_empty();
+                                    }
+                                    if event.is_readable() && !client.client_done {
+                                        let mut ended = false;
+                                        match TcpEntry::read_client(self.buffer_size, client) {
+                                            Ok(closed) => ended = closed,
+                                            Err(e) => {
+                                                if self.debug_level > 0 {
+                                                    eprintln!(
+                                                        "an error accured reading client: {}",
+                                                        e
+                                                    );
+                                                }
+                                                close = true;
                                             }
                                         }
 
                                         if let Err(e) = TcpEntry::write_pipeline(client) {
+                                            if self.debug_level > 0 {
+                                                eprintln!(
+                                                    "an error accured writing pipeline: {}",
+                                                    e
+                                                );
+                                            }
+                                            // a step that would block keeps the client going
                                             match e {
-                                                Error::IoError(e) => {
-                                                    if e.kind() == ErrorKind::WouldBlock {
-                                                        continue;
-                                                    }
-                                                }
-                                                _ => {
-                                                    if self.debug_level > 0 {
-                                                        eprintln!(
-                                                            "an error accured writing pipeline: {}",
-                                                            e
-                                                        );
-                                                    }
+                                                Error::IoError(e)
+                                                    if e.kind() == ErrorKind::WouldBlock => {}
+                                                _ => close = true,
+                                            }
+                                        }
+
+                                        // the answer may still be on its way, so only
+                                        // the pipeline is told that the client is done
+                                        if ended && !close {
+                                            client.client_done = true;
+                                            if let Err(e) = client.pipeline.close_forward() {
+                                                if self.debug_level > 0 {
+                                                    eprintln!(
+                                                        "an error accured closing pipeline: {}",
+                                                        e
+                                                    );
                                                 }
+                                                close = true;
                                             }
                                         }
                                     }
                                 }
                                 _ => unreachable!(),
                             }
+                            if close {
+                                self.close(&poll, other);
+                            }
                         }
                     }
                 }
+                self.expire_headers(&poll);
             }
         }
     }
 
     impl TcpEntry {
-        fn read_client(buffer_size: usize, client: &mut TcpEntryContext) -> Result<(), Error> {
-            let mut buffer = vec![0u8; buffer_size];
-            let size = client.connection.read(&mut buffer)?;
-            client.connection_buf.extend(buffer[0..size].to_vec());
+        /// Entry for another entry that listens on its own options and only
+        /// builds the pipeline.
+        pub(crate) fn with_pipeline(
+            address: String,
+            port: u16,
+            pipeline: Pipeline,
+            buffer_size: usize,
+            debug_level: DebugLevel,
+        ) -> TcpEntry {
+            TcpEntry {
+                address,
+                port,
+                debug_level,
+                pipeline_template: pipeline,
+                connections: MultiMap::new(),
+                proxy_protocol: false,
+                pending: HashMap::new(),
+                buffer_size,
+            }
+        }
+
+        /// Clones the pipeline for a client and registers both, the client
+        /// under `token` and the pipeline under the token after it. `data`
+        /// already came from the client and is passed on right away.
+        fn start_client(
+            &mut self,
+            poll: &Poll,
+            connection: TcpStream,
+            info: ConnectionInfo,
+            token: Token,
+            data: Vec<u8>,
+        ) -> Result<(), Error> {
+            let pipeline = match self.pipeline_template.clone_with(&info) {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured cloning pipeline: {}", e);
+                    }
+                    let _ = connection.shutdown(Shutdown::Both);
+                    return Ok(());
+                }
+            };
+
+            let mut client = Ref::new(TcpEntryContext {
+                connection,
+                pipeline,
+                connection_buf: data,
+                pipeline_buf: vec![0u8; 0],
+                closing: false,
+                client_done: false,
+            });
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    if let Some(peer) = info.peer {
+                        println!("new client: {}", peer);
+                    }
+                }
+            }
+
+            if let Err(e) = poll.registry().register(
+                &mut client.connection,
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            ) {
+                if self.debug_level > 0 {
+                    eprintln!("an error accured registering client: {}", e);
+                }
+                let _ = client.connection.shutdown(Shutdown::Both);
+                // the ref was never stored, so this is its only copy
+                unsafe { client.free() };
+                return Ok(());
+            }
+
+            let pipeline_token = Token(token.0 + 1);
+            if let Err(e) = poll.registry().register(
+                &mut client.pipeline,
+                pipeline_token,
+                Interest::READABLE | Interest::WRITABLE,
+            ) {
+                if self.debug_level > 0 {
+                    eprintln!("an error accured registering pipeline: {}", e);
+                }
+                let _ = poll.registry().deregister(&mut client.connection);
+                let _ = client.connection.shutdown(Shutdown::Both);
+                // the ref was never stored, so this is its only copy
+                unsafe { client.free() };
+                return Ok(());
+            }
+
+            if let Err(e) = TcpEntry::write_pipeline(&mut client) {
+                if self.debug_level > 0 {
+                    eprintln!("an error accured writing pipeline: {}", e);
+                }
+            }
+
+            self.connections.insert(token, client.clone());
+            self.connections.insert(pipeline_token, client);
+            Ok(())
+        }
+
+        /// Reads what a client sent so far and starts it once the PROXY
+        /// protocol header is complete, or drops it if the header is invalid.
+        fn read_header(&mut self, poll: &Poll, token: Token) -> Result<(), Error> {
+            let mut pending = match self.pending.remove(&token) {
+                Some(pending) => pending,
+                None => return Ok(()),
+            };
+            let mut buffer = vec![0u8; self.buffer_size];
+            let closed = loop {
+                match pending.connection.read(&mut buffer) {
+                    Ok(0) => break true,
+                    Ok(size) => pending.header.extend_from_slice(&buffer[0..size]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break false,
+                    Err(_) => break true,
+                }
+            };
+
+            let error = match proxy_protocol::parse(&pending.header) {
+                Ok(Some(header)) => {
+                    poll.registry().deregister(&mut pending.connection)?;
+                    let info = match header.addresses {
+                        Some((source, destination)) => ConnectionInfo {
+                            peer: Some(source),
+                            local: Some(destination),
+                            destination: None,
+                        },
+                        None => ConnectionInfo {
+                            peer: Some(pending.peer),
+                            local: pending.connection.local_addr().ok(),
+                            destination: None,
+                        },
+                    };
+                    let data = pending.header.split_off(header.size);
+                    return self.start_client(poll, pending.connection, info, token, data);
+                }
+                Ok(None) if !closed && pending.header.len() < MAX_HEADER_SIZE => {
+                    self.pending.insert(token, pending);
+                    return Ok(());
+                }
+                Ok(None) => Error::IoError(ErrorKind::UnexpectedEof.into()),
+                Err(e) => e,
+            };
+            if self.debug_level > 0 {
+                eprintln!(
+                    "an error accured reading PROXY header from {}: {}",
+                    pending.peer, error
+                );
+            }
+            poll.registry().deregister(&mut pending.connection)?;
+            let _ = pending.connection.shutdown(Shutdown::Both);
             Ok(())
         }
 
+        /// Drops the clients that have not sent their PROXY header in time.
+        fn expire_headers(&mut self, poll: &Poll) {
+            let expired: Vec<Token> = self
+                .pending
+                .iter()
+                .filter(|(_, pending)| pending.started.elapsed() >= HEADER_TIMEOUT)
+                .map(|(token, _)| *token)
+                .collect();
+            for token in expired {
+                let mut pending = self.pending.remove(&token).unwrap();
+                let _ = poll.registry().deregister(&mut pending.connection);
+                let _ = pending.connection.shutdown(Shutdown::Both);
+                if self.debug_level > 0 {
+                    eprintln!("PROXY header from {} timed out", pending.peer);
+                }
+            }
+        }
+
+        /// Returns `true` once the client has closed the connection.
+        fn read_client(buffer_size: usize, client: &mut TcpEntryContext) -> Result<bool, Error> {
+            let mut buffer = vec![0u8; buffer_size];
+            // events are edge triggered, so read until the socket is drained
+            loop {
+                match client.connection.read(&mut buffer) {
+                    Ok(0) => return Ok(true),
+                    Ok(size) => client.connection_buf.extend(buffer[0..size].to_vec()),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+        }
+
+        /// Closes the client owning `token` and drops its pipeline.
+        fn close(&mut self, poll: &Poll, token: Token) {
+            let token = Token(token.0 - (1 - token.0 % 2));
+            let mut client = match self.connections.remove(&token) {
+                Some(client) => client,
+                None => return,
+            };
+            self.connections.remove(&Token(token.0 + 1));
+            let _ = poll.registry().deregister(&mut client.connection);
+            let _ = poll.registry().deregister(&mut client.pipeline);
+            let _ = client.connection.shutdown(Shutdown::Both);
+            // both tokens were removed, so no copy of the ref is left
+            unsafe { client.free() };
+        }
+
         fn read_pipeline(client: &mut TcpEntryContext) -> Result<(), Error> {
             client.pipeline_buf.extend(client.pipeline.read_pipeline()?);
             Ok(())
         }
 
+        /// Writes as much as the client accepts, the rest is kept until the
+        /// connection becomes writable again.
         fn write_client(client: &mut TcpEntryContext) -> Result<(), Error> {
-            if client.pipeline_buf.len() > 0 {
-                client.connection.write(client.pipeline_buf.as_slice())?;
-                client.pipeline_buf.clear();
+            while !client.pipeline_buf.is_empty() {
+                match client.connection.write(client.pipeline_buf.as_slice()) {
+                    Ok(size) => {
+                        client.pipeline_buf.drain(0..size);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => return Err(Error::IoError(e)),
+                }
             }
             Ok(())
         }
 
         fn write_pipeline(client: &mut TcpEntryContext) -> Result<(), Error> {
             if client.connection_buf.len() > 0 {
-                client
-                    .pipeline
-                    .write_pipeline(client.connection_buf.clone())?;
-                client.connection_buf.clear();
+                // a step that fails may have passed some of it on already, so
+                // it is never written again
+                let data = mem::take(&mut client.connection_buf);
+                client.pipeline.write_pipeline(data)?;
             }
             Ok(())
         }
@@ -258,6 +540,8 @@ pub mod tcp {
                 debug_level,
                 pipeline_template: pipeline,
                 connections: MultiMap::new(),
+                proxy_protocol: args.arguments.contains(TCP_ENTRY_PROXY.0),
+                pending: HashMap::new(),
                 buffer_size,
             })
         }
@@ -279,6 +563,14 @@ pub mod tcp {
                 default_value: Some("80".to_string()),
                 help: Some(ArgumentHelp::Text(TCP_ENTRY_PORT.2.to_string())),
             });
+            argument = argument.add_argument(Argument {
+                name: TCP_ENTRY_PROXY.0.to_string(),
+                key: vec![TCP_ENTRY_PROXY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::None,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(TCP_ENTRY_PROXY.2.to_string())),
+            });
             argument
         }
     }
@@ -286,35 +578,169 @@ pub mod tcp {
     pub struct TcpStep {
         address: String,
         port: u16,
-        connection: TcpStream,
+        /// Dialed when first used, `None` if that failed.
+        connection: OnceLock<Option<TcpStream>>,
+        proxy_version: Option<ProxyVersion>,
         debug_level: DebugLevel,
         buffer_size: usize,
     }
 
-    impl TcpStep {}
+    impl TcpStep {
+        fn connect(address: &str, port: u16, header: Option<Vec<u8>>) -> Result<TcpStream, Error> {
+            let addr = create_socket_addr(address, port)?;
+            let mut connection = std::net::TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT)?;
+            if let Some(header) = header {
+                connection.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+                connection.write_all(&header)?;
+                connection.set_write_timeout(None)?;
+            }
+            connection.set_nonblocking(true)?;
+            Ok(TcpStream::from_std(connection))
+        }
+
+        /// Dials `address:port`, logging a failure and leaving the step
+        /// unconnected so that the entry drops the client instead of exiting.
+        fn try_connect(
+            address: &str,
+            port: u16,
+            header: Option<Vec<u8>>,
+            debug_level: DebugLevel,
+        ) -> Option<TcpStream> {
+            match TcpStep::connect(address, port, header) {
+                Ok(connection) => Some(connection),
+                Err(e) => {
+                    if debug_level > 0 {
+                        eprintln!("an error accured connecting to {}:{}: {}", address, port, e);
+                    }
+                    None
+                }
+            }
+        }
+
+        /// The PROXY protocol header announcing `info`'s client, if enabled.
+        fn proxy_header(version: Option<ProxyVersion>, info: &ConnectionInfo) -> Option<Vec<u8>> {
+            version.map(|version| proxy_protocol::encode(version, info.peer, info.local))
+        }
+
+        fn dial(&self) -> Option<&TcpStream> {
+            self.connection
+                .get_or_init(|| {
+                    TcpStep::try_connect(
+                        self.address.as_str(),
+                        self.port,
+                        TcpStep::proxy_header(self.proxy_version, &ConnectionInfo::default()),
+                        self.debug_level,
+                    )
+                })
+                .as_ref()
+        }
+
+        fn connection(&mut self) -> Result<&mut TcpStream, Error> {
+            self.dial();
+            self.connection
+                .get_mut()
+                .and_then(Option::as_mut)
+                .ok_or_else(|| Error::IoError(ErrorKind::NotConnected.into()))
+        }
+
+        fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+            write_all(self.connection()?, data)
+        }
+    }
 
     impl Step for TcpStep {
         fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
-            self.connection.write(&data)?;
+            self.write_all(data)?;
             Ok(data.clone())
         }
 
         fn process_data_backward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
-            let mut read_buffer = vec![0u8; self.buffer_size];
-            let read_size = self.connection.read(&mut read_buffer)?;
-            Ok(read_buffer[0..read_size].to_vec())
+            let buffer_size = self.buffer_size;
+            read_available(self.connection()?, buffer_size)
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.connection()?.shutdown(Shutdown::Write)?;
+            Ok(())
         }
     }
 
+    /// How long a step waits for its upstream to accept more data before
+    /// giving up on the client.
+    pub(crate) const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Writes all of `data` to a nonblocking fd, failing with `TimedOut` if
+    /// the fd does not become writable within `WRITE_TIMEOUT`.
+    pub(crate) fn write_all<W: Write + AsFd>(
+        connection: &mut W,
+        mut data: &[u8],
+    ) -> Result<(), Error> {
+        while !data.is_empty() {
+            match connection.write(data) {
+                Ok(size) => data = &data[size..],
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    // entries only poll steps for readability, so wait here
+                    let mut fd = libc::pollfd {
+                        fd: connection.as_fd().as_raw_fd(),
+                        events: libc::POLLOUT,
+                        revents: 0,
+                    };
+                    let ready = unsafe { libc::poll(&mut fd, 1, WRITE_TIMEOUT.as_millis() as i32) };
+                    if ready == 0 {
+                        return Err(Error::IoError(ErrorKind::TimedOut.into()));
+                    }
+                }
+                Err(e) => return Err(Error::IoError(e)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Drains a nonblocking fd, entries are notified once per edge.
+    /// A closed fd is reported as `ConnectionAborted`.
+    pub(crate) fn read_available<R: Read>(
+        connection: &mut R,
+        buffer_size: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let mut read_buffer = vec![0u8; buffer_size];
+        let mut result = Vec::new();
+        loop {
+            match connection.read(&mut read_buffer) {
+                Ok(0) if result.is_empty() => {
+                    return Err(Error::IoError(ErrorKind::ConnectionAborted.into()))
+                }
+                Ok(0) => break,
+                Ok(read_size) => result.extend_from_slice(&read_buffer[0..read_size]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock && !result.is_empty() => break,
+                Err(e) => return Err(Error::IoError(e)),
+            }
+        }
+        Ok(result)
+    }
+
     impl BoxedClone for TcpStep {
         fn bclone(&self) -> Box<dyn Step> {
-            Box::new(Self {
-                address: self.address.clone(),
-                port: self.port.clone(),
-                connection: unsafe { TcpStream::from_raw_fd(self.connection.as_raw_fd()) },
+            Box::new(self.clone())
+        }
+
+        /// Dials the destination the client asked the entry for, if any, in
+        /// place of the configured endpoint. Unlike `bclone` a failed dial is
+        /// reported so the entry can tell the client.
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            let (address, port) = match &info.destination {
+                Some((address, port)) => (address.clone(), *port),
+                None => (self.address.clone(), self.port),
+            };
+            let header = TcpStep::proxy_header(self.proxy_version, info);
+            let connection = TcpStep::connect(address.as_str(), port, header)?;
+            Ok(Box::new(Self {
+                address,
+                port,
+                connection: OnceLock::from(Some(connection)),
+                proxy_version: self.proxy_version,
                 debug_level: self.debug_level,
                 buffer_size: self.buffer_size,
-            })
+            }))
         }
     }
 
@@ -342,13 +768,16 @@ pub mod tcp {
                 Err(e) => return Err(Error::ParseIntError),
             };
 
-            let addr = create_socket_addr(address.as_str(), port)?;
-            let connection = TcpStream::connect(addr)?;
+            let proxy_version = match args.argument_values.get(TCP_STEP_PROXY.0) {
+                Some(version) => Some(ProxyVersion::from_str(version[0].as_str())?),
+                None => None,
+            };
 
             Ok(Self {
                 address: address,
                 port: port,
-                connection: connection,
+                connection: OnceLock::new(),
+                proxy_version,
                 debug_level: debug_level,
                 buffer_size: buffer_size,
             })
@@ -371,6 +800,14 @@ pub mod tcp {
                 default_value: Some("80".to_string()),
                 help: Some(ArgumentHelp::Text(TCP_STEP_PORT.2.to_string())),
             });
+            argument = argument.add_argument(Argument {
+                name: TCP_STEP_PROXY.0.to_string(),
+                key: vec![TCP_STEP_PROXY.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(TCP_STEP_PROXY.2.to_string())),
+            });
             argument
         }
     }
@@ -379,8 +816,9 @@ pub mod tcp {
         fn clone(&self) -> Self {
             Self {
                 address: self.address.clone(),
-                port: self.port.clone(),
-                connection: unsafe { TcpStream::from_raw_fd(self.connection.as_raw_fd()) },
+                port: self.port,
+                connection: OnceLock::new(),
+                proxy_version: self.proxy_version,
                 debug_level: self.debug_level,
                 buffer_size: self.buffer_size,
             }
@@ -389,7 +827,10 @@ pub mod tcp {
 
     impl AsRawFd for TcpStep {
         fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
-            self.connection.as_raw_fd()
+            match self.dial() {
+                Some(connection) => connection.as_raw_fd(),
+                None => -1,
+            }
         }
     }
 }
@@ -0,0 +1,724 @@
+pub mod udp {
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::net::SocketAddr;
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::time::{Duration, Instant};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::net::UdpSocket;
+    use mio::{Events, Interest, Poll, Token};
+
+    use crate::async_pipeline::async_pipeline::LazyStream;
+    use crate::create_socket_addr;
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+
+    const UDP_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "udp-entry-address",
+        "--udp-ea",
+        "(UdpEntry) Udp Entry listen address",
+    );
+    const UDP_ENTRY_PORT: (&str, &str, &str) = (
+        "udp-entry-port",
+        "--udp-ep",
+        "(UdpEntry) Udp Entry listen port",
+    );
+    const UDP_ENTRY_TIMEOUT: (&str, &str, &str) = (
+        "udp-entry-timeout",
+        "--udp-etimeout",
+        "(UdpEntry) Seconds without a datagram either way after which a client's pipeline is dropped",
+    );
+
+    const TCP2UDP_STEP_ADDRESS: (&str, &str, &str) = (
+        "tcp2udp-step-address",
+        "--tcp2udp-sa",
+        "(Tcp2UdpStep) Tcp2Udp step endpoint address",
+    );
+    const TCP2UDP_STEP_PORT: (&str, &str, &str) = (
+        "tcp2udp-step-port",
+        "--tcp2udp-sp",
+        "(Tcp2UdpStep) Tcp2Udp step endpoint port",
+    );
+
+    const SOCKET_TOKEN: Token = Token(0);
+    /// Largest datagram, and how much a frame's length prefix can express.
+    const MAX_DATAGRAM: usize = u16::MAX as usize;
+    /// How often idle clients are looked for.
+    const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// Puts a datagram in a stream as its length and its bytes.
+    fn push_frame(datagram: &[u8], stream: &mut Vec<u8>) {
+        stream.extend_from_slice(&(datagram.len() as u16).to_be_bytes());
+        stream.extend_from_slice(datagram);
+    }
+
+    /// Takes the first complete datagram off a stream of frames.
+    fn pop_frame(stream: &mut Vec<u8>) -> Option<Vec<u8>> {
+        if stream.len() < 2 {
+            return None;
+        }
+        let size = u16::from_be_bytes([stream[0], stream[1]]) as usize;
+        if stream.len() < 2 + size {
+            return None;
+        }
+        let datagram = stream[2..2 + size].to_vec();
+        stream.drain(..2 + size);
+        Some(datagram)
+    }
+
+    /// Serves every client address that sends a datagram with its own copy
+    /// of the pipeline. Each datagram is written to the pipeline on its own,
+    /// and each read from it is sent back as one datagram, so a `udp2tcp`
+    /// step keeps the boundaries across a stream.
+    pub struct UdpEntry {
+        address: String,
+        port: u16,
+        timeout: Duration,
+        debug_level: DebugLevel,
+        pipeline_template: Pipeline,
+        clients: HashMap<Token, UdpClient>,
+        peers: HashMap<SocketAddr, Token>,
+    }
+
+    struct UdpClient {
+        peer: SocketAddr,
+        pipeline: Pipeline,
+        last_active: Instant,
+    }
+
+    impl Entry for UdpEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let mut socket = UdpSocket::bind(addr)?;
+            let local = socket.local_addr()?;
+
+            let mut poll = Poll::new()?;
+            let mut events = Events::with_capacity(128);
+            poll.registry()
+                .register(&mut socket, SOCKET_TOKEN, Interest::READABLE)?;
+            let mut buffer = vec![0u8; MAX_DATAGRAM];
+            let mut client_counter = 0;
+            let mut last_sweep = Instant::now();
+
+            loop {
+                if let Err(e) = poll.poll(&mut events, Some(SWEEP_INTERVAL)) {
+                    if e.kind() == ErrorKind::Interrupted {
+                        continue;
+                    }
+                    return Err(Error::IoError(e));
+                }
+                for event in events.iter() {
+                    match event.token() {
+                        // one edge can stand for several datagrams
+                        SOCKET_TOKEN => loop {
+                            let (size, peer) = match socket.recv_from(&mut buffer) {
+                                Ok(received) => received,
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                                Err(e) => return Err(Error::IoError(e)),
+                            };
+                            let token = match self.peers.get(&peer) {
+                                Some(token) => *token,
+                                None => {
+                                    client_counter += 1;
+                                    let token = Token(client_counter);
+                                    let info = ConnectionInfo {
+                                        peer: Some(peer),
+                                        local: Some(local),
+                                        destination: None,
+                                    };
+                                    if !self.start_client(&poll, info, token) {
+                                        continue;
+                                    }
+                                    token
+                                }
+                            };
+                            self.write_pipeline(&poll, token, &buffer[0..size]);
+                        },
+                        token => {
+                            if event.is_readable() {
+                                self.read_pipeline(&poll, &socket, token);
+                            }
+                        }
+                    }
+                }
+
+                if last_sweep.elapsed() >= SWEEP_INTERVAL {
+                    last_sweep = Instant::now();
+                    let idle: Vec<Token> = self
+                        .clients
+                        .iter()
+                        .filter(|(_, client)| client.last_active.elapsed() >= self.timeout)
+                        .map(|(token, _)| *token)
+                        .collect();
+                    for token in idle {
+                        self.close(&poll, token);
+                    }
+                }
+            }
+        }
+    }
+
+    impl UdpEntry {
+        /// Clones the pipeline for a new client address, returning whether
+        /// it could be started.
+        fn start_client(&mut self, poll: &Poll, info: ConnectionInfo, token: Token) -> bool {
+            let peer = match info.peer {
+                Some(peer) => peer,
+                None => return false,
+            };
+            let mut pipeline = match self.pipeline_template.clone_with(&info) {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured cloning pipeline: {}", e);
+                    }
+                    return false;
+                }
+            };
+            if let Err(e) = poll
+                .registry()
+                .register(&mut pipeline, token, Interest::READABLE)
+            {
+                if self.debug_level > 0 {
+                    eprintln!("an error accured registering pipeline: {}", e);
+                }
+                return false;
+            }
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("new client: {}", peer);
+                }
+            }
+
+            self.clients.insert(
+                token,
+                UdpClient {
+                    peer,
+                    pipeline,
+                    last_active: Instant::now(),
+                },
+            );
+            self.peers.insert(peer, token);
+            true
+        }
+
+        fn write_pipeline(&mut self, poll: &Poll, token: Token, datagram: &[u8]) {
+            let client = match self.clients.get_mut(&token) {
+                Some(client) => client,
+                None => return,
+            };
+            client.last_active = Instant::now();
+            if let Err(e) = client.pipeline.write_pipeline(datagram.to_vec()) {
+                if self.debug_level > 0 {
+                    eprintln!("an error accured writing pipeline: {}", e);
+                }
+                self.close(poll, token);
+            }
+        }
+
+        /// Sends every read of the pipeline to the client as a datagram,
+        /// until nothing is left to read.
+        fn read_pipeline(&mut self, poll: &Poll, socket: &UdpSocket, token: Token) {
+            let client = match self.clients.get_mut(&token) {
+                Some(client) => client,
+                None => return,
+            };
+            let error = loop {
+                match client.pipeline.read_pipeline() {
+                    Ok(datagram) => {
+                        client.last_active = Instant::now();
+                        match socket.send_to(&datagram, client.peer) {
+                            // datagrams may be lost, a full socket buffer is no different
+                            Err(e) if e.kind() != ErrorKind::WouldBlock && self.debug_level > 0 => {
+                                eprintln!("an error accured writing client: {}", e)
+                            }
+                            _ => {}
+                        }
+                    }
+                    Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => return,
+                    Err(e) => break e,
+                }
+            };
+            match error {
+                Error::IoError(e) if e.kind() == ErrorKind::ConnectionAborted => {}
+                e => {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured reading pipeline: {}", e);
+                    }
+                }
+            }
+            self.close(poll, token);
+        }
+
+        /// Drops the pipeline of a client, a later datagram from it starts a
+        /// new one.
+        fn close(&mut self, poll: &Poll, token: Token) {
+            let mut client = match self.clients.remove(&token) {
+                Some(client) => client,
+                None => return,
+            };
+            self.peers.remove(&client.peer);
+            let _ = poll.registry().deregister(&mut client.pipeline);
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("client {} closed", client.peer);
+                }
+            }
+        }
+    }
+
+    impl EntryStatic<UdpEntry> for UdpEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<UdpEntry, Error> {
+            let address = match args.argument_values.get(UDP_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(UDP_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(UDP_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(UDP_ENTRY_PORT.0.to_string())),
+            };
+            let timeout = match args.argument_values.get(UDP_ENTRY_TIMEOUT.0) {
+                Some(timeout) => timeout[0].clone(),
+                None => return Err(Error::RequireOption(UDP_ENTRY_TIMEOUT.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let timeout = match str::parse::<u64>(timeout.as_str()) {
+                Ok(timeout) => Duration::from_secs(timeout),
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(UdpEntry {
+                address,
+                port,
+                timeout,
+                debug_level,
+                pipeline_template: pipeline,
+                clients: HashMap::new(),
+                peers: HashMap::new(),
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: UDP_ENTRY_ADDRESS.0.to_string(),
+                key: vec![UDP_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(UDP_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: UDP_ENTRY_PORT.0.to_string(),
+                key: vec![UDP_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("53".to_string()),
+                help: Some(ArgumentHelp::Text(UDP_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: UDP_ENTRY_TIMEOUT.0.to_string(),
+                key: vec![UDP_ENTRY_TIMEOUT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("60".to_string()),
+                help: Some(ArgumentHelp::Text(UDP_ENTRY_TIMEOUT.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    /// Carries datagrams over the stream of the steps behind it, each with a
+    /// two byte length in front. Every forward write is one datagram, and
+    /// every backward read returns one, for a `UdpEntry` to send.
+    pub struct Udp2TcpStep {
+        steps: Pipeline,
+        /// Frames read from the steps behind that were not returned yet.
+        received: Vec<u8>,
+    }
+
+    impl Step for Udp2TcpStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            if self.steps.is_empty() {
+                return Err(Error::Msg(
+                    "the udp2tcp step needs steps behind it to carry its stream".to_string(),
+                ));
+            }
+            let mut stream = Vec::new();
+            // a stream entry may hand on more than a datagram holds, an empty
+            // datagram still is one
+            for datagram in data.chunks(MAX_DATAGRAM) {
+                push_frame(datagram, &mut stream);
+            }
+            if data.is_empty() {
+                push_frame(&[], &mut stream);
+            }
+            self.steps.write_pipeline(stream)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            loop {
+                if let Some(datagram) = pop_frame(&mut self.received) {
+                    return Ok(datagram);
+                }
+                let data = self.steps.read_pipeline()?;
+                self.received.extend_from_slice(&data);
+            }
+        }
+
+        fn close_forward(&mut self) -> Result<(), Error> {
+            self.steps.close_forward()
+        }
+
+        fn adopt_step(&mut self, step: Box<dyn Step>) -> Option<Box<dyn Step>> {
+            self.steps.add_step(step);
+            None
+        }
+    }
+
+    impl BoxedClone for Udp2TcpStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            Ok(Box::new(Self {
+                steps: self.steps.clone_with(info)?,
+                received: Vec::new(),
+            }))
+        }
+    }
+
+    impl StepStatic for Udp2TcpStep {
+        fn new(_args: CliParsed, _debug_level: DebugLevel) -> Result<Self, Error> {
+            Ok(Self {
+                steps: Pipeline::new(),
+                received: Vec::new(),
+            })
+        }
+
+        fn get_cmd(argument: CliSpec) -> CliSpec {
+            argument
+        }
+    }
+
+    impl Clone for Udp2TcpStep {
+        fn clone(&self) -> Self {
+            Self {
+                steps: self.steps.clone(),
+                received: Vec::new(),
+            }
+        }
+    }
+
+    impl AsRawFd for Udp2TcpStep {
+        fn as_raw_fd(&self) -> RawFd {
+            match self.steps.is_empty() {
+                true => -1,
+                false => self.steps.as_raw_fd(),
+            }
+        }
+    }
+
+    /// Splits the stream written to it into the datagrams a `udp2tcp` step
+    /// framed and sends them to its endpoint, framing the replies the same
+    /// way. Every clone binds its own socket when first used, so the endpoint
+    /// tells clients apart by their source port.
+    pub struct Tcp2UdpStep {
+        address: String,
+        port: u16,
+        socket: LazyStream<UdpSocket>,
+        /// Bytes of a frame that did not arrive completely yet.
+        received: Vec<u8>,
+        debug_level: DebugLevel,
+    }
+
+    impl Tcp2UdpStep {
+        /// Binds a socket for the endpoint.
+        fn connect(&self) -> Result<UdpSocket, Error> {
+            let peer = create_socket_addr(self.address.as_str(), self.port)?;
+            let bind = match peer {
+                SocketAddr::V4(_) => "0.0.0.0:0",
+                SocketAddr::V6(_) => "[::]:0",
+            };
+            let socket = UdpSocket::bind(bind.parse()?)?;
+            socket.connect(peer)?;
+            Ok(socket)
+        }
+
+        fn socket(&self) -> Result<&UdpSocket, Error> {
+            self.socket
+                .get("udp socket", self.debug_level, || self.connect())
+        }
+    }
+
+    impl Step for Tcp2UdpStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            self.received.extend_from_slice(data);
+            while let Some(datagram) = pop_frame(&mut self.received) {
+                match self.socket()?.send(&datagram) {
+                    Ok(_) => {}
+                    // lost like any datagram, or refused by an endpoint not
+                    // listening yet
+                    Err(e)
+                        if e.kind() == ErrorKind::WouldBlock
+                            || e.kind() == ErrorKind::ConnectionRefused => {}
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            let socket = self.socket()?;
+            let mut buffer = vec![0u8; MAX_DATAGRAM];
+            let mut stream = Vec::new();
+            // events are edge triggered, so read until the socket is drained
+            loop {
+                match socket.recv(&mut buffer) {
+                    Ok(size) => push_frame(&buffer[0..size], &mut stream),
+                    Err(e) if e.kind() == ErrorKind::ConnectionRefused => continue,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock && !stream.is_empty() => break,
+                    Err(e) => return Err(Error::IoError(e)),
+                }
+            }
+            Ok(stream)
+        }
+    }
+
+    impl BoxedClone for Tcp2UdpStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+
+        /// Sends to the destination the client asked the entry for, if any, in
+        /// place of the configured endpoint.
+        fn bclone_with(&self, info: &ConnectionInfo) -> Result<Box<dyn Step>, Error> {
+            let (address, port) = match &info.destination {
+                Some((address, port)) => (address.clone(), *port),
+                None => (self.address.clone(), self.port),
+            };
+            Ok(Box::new(Self {
+                address,
+                port,
+                socket: LazyStream::new(),
+                received: Vec::new(),
+                debug_level: self.debug_level,
+            }))
+        }
+    }
+
+    impl StepStatic for Tcp2UdpStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let address = match args.argument_values.get(TCP2UDP_STEP_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(TCP2UDP_STEP_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(TCP2UDP_STEP_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(TCP2UDP_STEP_PORT.0.to_string())),
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+
+            Ok(Self {
+                address,
+                port,
+                socket: LazyStream::new(),
+                received: Vec::new(),
+                debug_level,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: TCP2UDP_STEP_ADDRESS.0.to_string(),
+                key: vec![TCP2UDP_STEP_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("127.0.0.1".to_string()),
+                help: Some(ArgumentHelp::Text(TCP2UDP_STEP_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: TCP2UDP_STEP_PORT.0.to_string(),
+                key: vec![TCP2UDP_STEP_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("53".to_string()),
+                help: Some(ArgumentHelp::Text(TCP2UDP_STEP_PORT.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    impl Clone for Tcp2UdpStep {
+        fn clone(&self) -> Self {
+            Self {
+                address: self.address.clone(),
+                port: self.port,
+                socket: LazyStream::new(),
+                received: Vec::new(),
+                debug_level: self.debug_level,
+            }
+        }
+    }
+
+    impl AsRawFd for Tcp2UdpStep {
+        fn as_raw_fd(&self) -> RawFd {
+            self.socket().map_or(-1, |socket| socket.as_raw_fd())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::thread::sleep;
+
+        fn frames(datagrams: &[&[u8]]) -> Vec<u8> {
+            let mut stream = Vec::new();
+            for datagram in datagrams {
+                push_frame(datagram, &mut stream);
+            }
+            stream
+        }
+
+        /// A socket standing in for the endpoint, and a step sending to it.
+        fn endpoint() -> (std::net::UdpSocket, Tcp2UdpStep) {
+            let endpoint = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            endpoint
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let step = Tcp2UdpStep {
+                address: "127.0.0.1".to_string(),
+                port: endpoint.local_addr().unwrap().port(),
+                socket: LazyStream::new(),
+                received: Vec::new(),
+                debug_level: DebugLevel::None,
+            };
+            (endpoint, step)
+        }
+
+        /// Reads a step backward, waiting for the endpoint's replies to arrive.
+        fn read(step: &mut dyn Step) -> Vec<u8> {
+            let started = Instant::now();
+            loop {
+                match step.process_data_backward(&mut Vec::new()) {
+                    Ok(data) => return data,
+                    Err(Error::IoError(e))
+                        if e.kind() == ErrorKind::WouldBlock
+                            && started.elapsed() < Duration::from_secs(5) =>
+                    {
+                        sleep(Duration::from_millis(10))
+                    }
+                    Err(e) => panic!("{}", e),
+                }
+            }
+        }
+
+        #[test]
+        fn joined_frames_pop_in_order() {
+            let long = vec![7u8; MAX_DATAGRAM];
+            let datagrams: [&[u8]; 4] = [b"first", b"", &long, b"last"];
+            let mut stream = frames(&datagrams);
+            for datagram in datagrams {
+                assert_eq!(pop_frame(&mut stream).unwrap(), datagram);
+            }
+            assert!(pop_frame(&mut stream).is_none());
+            assert!(stream.is_empty());
+        }
+
+        #[test]
+        fn split_frames_wait_for_their_last_byte() {
+            let datagrams: [&[u8]; 3] = [b"first", b"", b"second"];
+            let stream = frames(&datagrams);
+            let mut received = Vec::new();
+            let mut popped = Vec::new();
+            for byte in stream {
+                received.push(byte);
+                while let Some(datagram) = pop_frame(&mut received) {
+                    popped.push(datagram);
+                }
+            }
+            assert_eq!(popped, datagrams);
+        }
+
+        #[test]
+        fn empty_datagrams_are_frames_of_their_own() {
+            let mut stream = frames(&[b"", b""]);
+            assert_eq!(stream, [0, 0, 0, 0]);
+            assert_eq!(pop_frame(&mut stream).unwrap(), b"");
+            assert_eq!(pop_frame(&mut stream).unwrap(), b"");
+            assert!(pop_frame(&mut stream).is_none());
+        }
+
+        #[test]
+        fn tcp2udp_sends_each_frame_as_a_datagram() {
+            let (endpoint, mut step) = endpoint();
+            let datagrams: [&[u8]; 3] = [b"one", b"", b"three"];
+            let stream = frames(&datagrams);
+            // the first write ends inside a frame, the second holds its rest,
+            // the whole empty frame and the start of the last one
+            step.process_data_forward(&mut stream[0..3].to_vec())
+                .unwrap();
+            step.process_data_forward(&mut stream[3..9].to_vec())
+                .unwrap();
+            step.process_data_forward(&mut stream[9..].to_vec())
+                .unwrap();
+
+            let mut buffer = [0u8; 64];
+            for datagram in datagrams {
+                let (size, from) = endpoint.recv_from(&mut buffer).unwrap();
+                assert_eq!(&buffer[0..size], datagram);
+                endpoint.send_to(&buffer[0..size], from).unwrap();
+            }
+            let mut replies = Vec::new();
+            while replies.len() < frames(&datagrams).len() {
+                replies.extend(read(&mut step));
+            }
+            assert_eq!(replies, frames(&datagrams));
+        }
+
+        #[test]
+        fn datagrams_keep_their_boundaries_across_the_stream() {
+            let (endpoint, tcp2udp) = endpoint();
+            let mut step = Udp2TcpStep {
+                steps: Pipeline::new(),
+                received: Vec::new(),
+            };
+            assert!(step.adopt_step(Box::new(tcp2udp)).is_none());
+
+            let long = vec![7u8; 4000];
+            let datagrams: [&[u8]; 3] = [b"first", b"", &long];
+            let mut buffer = vec![0u8; MAX_DATAGRAM];
+            for datagram in datagrams {
+                step.process_data_forward(&mut datagram.to_vec()).unwrap();
+                let (size, from) = endpoint.recv_from(&mut buffer).unwrap();
+                assert_eq!(&buffer[0..size], datagram);
+                endpoint.send_to(&buffer[0..size], from).unwrap();
+            }
+            for datagram in datagrams {
+                assert_eq!(read(&mut step), datagram);
+            }
+        }
+    }
+}
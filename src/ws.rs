@@ -0,0 +1,729 @@
+pub mod ws {
+    use std::collections::HashMap;
+    use std::io::ErrorKind;
+    use std::net::{SocketAddr, TcpStream};
+    use std::os::fd::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+    use std::sync::{Arc, Mutex, OnceLock, Weak};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use cliparser::types::{
+        Argument, ArgumentHelp, ArgumentOccurrence, ArgumentValueType, CliParsed, CliSpec,
+    };
+    use mio::net::TcpListener;
+    use mio::unix::SourceFd;
+    use mio::{Events, Interest, Poll, Token};
+    use tungstenite::client::ClientRequestBuilder;
+    use tungstenite::handshake::server::{
+        Callback, ErrorResponse, Request, Response, ServerHandshake,
+    };
+    use tungstenite::handshake::MidHandshake;
+    use tungstenite::http::{HeaderValue, StatusCode, Uri};
+    use tungstenite::stream::MaybeTlsStream;
+    use tungstenite::{HandshakeError, Message, WebSocket};
+
+    use crate::tcp::tcp::WRITE_TIMEOUT;
+    use crate::{
+        base::base::DebugLevel, BoxedClone, ConnectionInfo, Entry, EntryStatic, Error, Pipeline,
+        Step, StepStatic,
+    };
+    use crate::{create_socket_addr, MultiMap, Ref};
+
+    const WS_ENTRY_ADDRESS: (&str, &str, &str) = (
+        "ws-entry-address",
+        "--ws-ea",
+        "(WsEntry) WebSocket Entry listen address",
+    );
+    const WS_ENTRY_PORT: (&str, &str, &str) = (
+        "ws-entry-port",
+        "--ws-ep",
+        "(WsEntry) WebSocket Entry listen port",
+    );
+    const WS_ENTRY_PATH: (&str, &str, &str) = (
+        "ws-entry-path",
+        "--ws-epath",
+        "(WsEntry) Path that accepts WebSocket upgrades",
+    );
+    const WS_ENTRY_PROTOCOL: (&str, &str, &str) = (
+        "ws-entry-protocol",
+        "--ws-eprotocol",
+        "(WsEntry) Subprotocol that clients must offer",
+    );
+
+    const WS_STEP_URL: (&str, &str, &str) = (
+        "ws-step-url",
+        "--ws-surl",
+        "(WsStep) WebSocket step endpoint url (ws:// or wss://)",
+    );
+    const WS_STEP_PROTOCOL: (&str, &str, &str) = (
+        "ws-step-protocol",
+        "--ws-sprotocol",
+        "(WsStep) Subprotocol to request from the endpoint",
+    );
+
+    const WS_PING_INTERVAL: (&str, &str, &str) = (
+        "ws-ping-interval",
+        "--ws-ping",
+        "(WsEntry, WsStep) Seconds between keepalive pings, 0 disables them",
+    );
+
+    const SERVER_TOKEN: Token = Token(0);
+    const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    type WsSocket = WebSocket<MaybeTlsStream<TcpStream>>;
+    type WsHandshake = MidHandshake<ServerHandshake<MaybeTlsStream<TcpStream>, UpgradeCheck>>;
+
+    fn tcp_stream(socket: &WsSocket) -> &TcpStream {
+        match socket.get_ref() {
+            MaybeTlsStream::Plain(stream) => stream,
+            MaybeTlsStream::Rustls(stream) => stream.get_ref(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reads every frame that is already available on the socket and returns
+    /// the concatenated payload of data frames. The second value is true once
+    /// the peer has closed the connection.
+    fn read_frames(socket: &mut WsSocket) -> Result<(Vec<u8>, bool), Error> {
+        let mut data = Vec::new();
+        tcp_stream(socket).set_nonblocking(true)?;
+        let closed = loop {
+            match socket.read() {
+                Ok(Message::Binary(payload)) => data.extend(payload),
+                Ok(Message::Text(payload)) => data.extend(payload.as_bytes()),
+                Ok(Message::Close(_)) => break true,
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => break false,
+                Err(tungstenite::Error::ConnectionClosed)
+                | Err(tungstenite::Error::AlreadyClosed) => break true,
+                Err(e) => {
+                    tcp_stream(socket).set_nonblocking(false)?;
+                    return Err(e.into());
+                }
+            }
+        };
+        tcp_stream(socket).set_nonblocking(false)?;
+        if !closed {
+            // pongs for received pings are queued by tungstenite until the next flush
+            socket.flush()?;
+        }
+        Ok((data, closed))
+    }
+
+    fn write_frame(socket: &mut WsSocket, data: &[u8]) -> Result<(), Error> {
+        if !data.is_empty() {
+            socket.send(Message::Binary(data.to_vec()))?;
+        }
+        Ok(())
+    }
+
+    fn parse_ping_interval(args: &CliParsed) -> Result<Option<Duration>, Error> {
+        let ping_interval = match args.argument_values.get(WS_PING_INTERVAL.0) {
+            Some(ping_interval) => ping_interval[0].clone(),
+            None => return Err(Error::RequireOption(WS_PING_INTERVAL.0.to_string())),
+        };
+        match str::parse::<u64>(ping_interval.as_str()) {
+            Ok(0) => Ok(None),
+            Ok(seconds) => Ok(Some(Duration::from_secs(seconds))),
+            Err(_) => Err(Error::ParseIntError),
+        }
+    }
+
+    pub struct WsEntry {
+        address: String,
+        port: u16,
+        path: String,
+        protocol: Option<HeaderValue>,
+        ping_interval: Option<Duration>,
+        debug_level: DebugLevel,
+        pipeline_template: Pipeline,
+        connections: MultiMap<Token, Ref<WsEntryContext>>,
+        handshakes: HashMap<Token, PendingHandshake>,
+    }
+
+    /// An upgrade request that has not fully arrived yet, keyed by the token
+    /// the client keeps once it is accepted.
+    struct PendingHandshake {
+        handshake: WsHandshake,
+        peer: SocketAddr,
+        started: Instant,
+    }
+
+    /// Accepts upgrades of `path` that offer `protocol`, if one is required.
+    struct UpgradeCheck {
+        path: String,
+        protocol: Option<HeaderValue>,
+    }
+
+    impl Callback for UpgradeCheck {
+        fn on_request(
+            self,
+            request: &Request,
+            mut response: Response,
+        ) -> Result<Response, ErrorResponse> {
+            if request.uri().path() != self.path {
+                let mut error = ErrorResponse::new(Some("Not Found".to_string()));
+                *error.status_mut() = StatusCode::NOT_FOUND;
+                return Err(error);
+            }
+            if let Some(protocol) = self.protocol {
+                let offered = request
+                    .headers()
+                    .get_all("Sec-WebSocket-Protocol")
+                    .iter()
+                    .filter_map(|value| value.to_str().ok())
+                    .flat_map(|value| value.split(','))
+                    .any(|offered| offered.trim().as_bytes() == protocol.as_bytes());
+                if !offered {
+                    let mut error = ErrorResponse::new(Some("Unsupported subprotocol".to_string()));
+                    *error.status_mut() = StatusCode::BAD_REQUEST;
+                    return Err(error);
+                }
+                response
+                    .headers_mut()
+                    .insert("Sec-WebSocket-Protocol", protocol);
+            }
+            Ok(response)
+        }
+    }
+
+    struct WsEntryContext {
+        socket: WsSocket,
+        pipeline: Pipeline,
+        last_ping: Instant,
+    }
+
+    impl Entry for WsEntry {
+        fn listen(&mut self) -> Result<(), Error> {
+            let addr = create_socket_addr(self.address.as_str(), self.port)?;
+            let mut server = TcpListener::bind(addr)?;
+
+            let mut poll = Poll::new()?;
+            let mut events = Events::with_capacity(128);
+            poll.registry()
+                .register(&mut server, SERVER_TOKEN, Interest::READABLE)?;
+            let mut connection_counter = 0;
+
+            loop {
+                let timeout = match (self.handshakes.is_empty(), self.ping_interval) {
+                    (true, ping_interval) => ping_interval,
+                    (false, Some(ping_interval)) => Some(ping_interval.min(HANDSHAKE_TIMEOUT)),
+                    (false, None) => Some(HANDSHAKE_TIMEOUT),
+                };
+                poll.poll(&mut events, timeout)?;
+                for event in events.iter() {
+                    match event.token() {
+                        SERVER_TOKEN => loop {
+                            let (connection, peer) = match server.accept() {
+                                Ok(connection) => connection,
+                                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                                Err(e) => return Err(Error::IoError(e)),
+                            };
+
+                            // the pipeline gets the even token after the client's
+                            connection_counter += 2;
+                            let token = Token(connection_counter - 1);
+                            let stream =
+                                unsafe { TcpStream::from_raw_fd(connection.into_raw_fd()) };
+                            poll.registry().register(
+                                &mut SourceFd(&stream.as_raw_fd()),
+                                token,
+                                Interest::READABLE | Interest::WRITABLE,
+                            )?;
+                            let result = tungstenite::accept_hdr(
+                                MaybeTlsStream::Plain(stream),
+                                self.upgrade_check(),
+                            );
+                            self.handshake(&poll, token, peer, Instant::now(), result)?;
+                        },
+                        other if self.handshakes.contains_key(&other) => {
+                            let pending = self.handshakes.remove(&other).unwrap();
+                            let result = pending.handshake.handshake();
+                            self.handshake(&poll, other, pending.peer, pending.started, result)?;
+                        }
+                        other => {
+                            let mut client = match self.connections.get_mut(&other) {
+                                Some(client) => *client,
+                                None => {
+                                    if self.debug_level > 0 {
+                                        eprintln!("No client found with this token {}", other.0);
+                                    }
+                                    continue;
+                                }
+                            };
+
+                            let result = match other.0 % 2 {
+                                // pipeline has io event
+                                0 => WsEntry::pipeline_to_client(&mut client),
+                                // client has io event
+                                1 => WsEntry::client_to_pipeline(&mut client),
+                                _ => unreachable!(),
+                            };
+
+                            // clients own odd tokens, their pipelines the following even one
+                            let token = match other.0 % 2 {
+                                0 => Token(other.0 - 1),
+                                _ => other,
+                            };
+                            match result {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                    if self.debug_level >= 2 {
+                                        println!("client closed connection");
+                                    }
+                                    self.close(&poll, token, client)?;
+                                }
+                                Err(e) => {
+                                    if self.debug_level > 0 {
+                                        eprintln!("an error accured serving client: {}", e);
+                                    }
+                                    self.close(&poll, token, client)?;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if let Some(ping_interval) = self.ping_interval {
+                    self.ping_clients(ping_interval);
+                }
+                self.expire_handshakes(&poll);
+            }
+        }
+    }
+
+    impl WsEntry {
+        fn upgrade_check(&self) -> UpgradeCheck {
+            UpgradeCheck {
+                path: self.path.clone(),
+                protocol: self.protocol.clone(),
+            }
+        }
+
+        /// Parks a handshake that is waiting for more of the request, or
+        /// starts serving the client once it is upgraded.
+        fn handshake(
+            &mut self,
+            poll: &Poll,
+            token: Token,
+            peer: SocketAddr,
+            started: Instant,
+            result: Result<
+                WsSocket,
+                HandshakeError<ServerHandshake<MaybeTlsStream<TcpStream>, UpgradeCheck>>,
+            >,
+        ) -> Result<(), Error> {
+            let socket = match result {
+                Ok(socket) => socket,
+                Err(HandshakeError::Interrupted(handshake)) => {
+                    self.handshakes.insert(
+                        token,
+                        PendingHandshake {
+                            handshake,
+                            peer,
+                            started,
+                        },
+                    );
+                    return Ok(());
+                }
+                Err(HandshakeError::Failure(e)) => {
+                    // dropping the socket also removes it from the poll
+                    if self.debug_level > 0 {
+                        eprintln!("websocket handshake with {} failed: {}", peer, e);
+                    }
+                    return Ok(());
+                }
+            };
+
+            {
+                // debug
+                if self.debug_level >= 2 {
+                    println!("new client: {}", peer);
+                }
+            }
+
+            // frames are read nonblocking and written blocking, see read_frames,
+            // and a client that stops reading must not hold up the others
+            tcp_stream(&socket).set_nonblocking(false)?;
+            tcp_stream(&socket).set_write_timeout(Some(WRITE_TIMEOUT))?;
+            let fd = tcp_stream(&socket).as_raw_fd();
+            poll.registry()
+                .reregister(&mut SourceFd(&fd), token, Interest::READABLE)?;
+
+            let info = ConnectionInfo {
+                peer: Some(peer),
+                local: tcp_stream(&socket).local_addr().ok(),
+                destination: None,
+            };
+            let pipeline = match self.pipeline_template.clone_with(&info) {
+                Ok(pipeline) => pipeline,
+                Err(e) => {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured cloning pipeline: {}", e);
+                    }
+                    let _ = poll.registry().deregister(&mut SourceFd(&fd));
+                    return Ok(());
+                }
+            };
+
+            let mut client = Ref::new(WsEntryContext {
+                socket,
+                pipeline,
+                last_ping: Instant::now(),
+            });
+            let pipeline_token = Token(token.0 + 1);
+            if let Err(e) =
+                poll.registry()
+                    .register(&mut client.pipeline, pipeline_token, Interest::READABLE)
+            {
+                if self.debug_level > 0 {
+                    eprintln!("an error accured registering pipeline: {}", e);
+                }
+                let _ = poll.registry().deregister(&mut SourceFd(&fd));
+                // the ref was never stored, so this is its only copy
+                unsafe { client.free() };
+                return Ok(());
+            }
+
+            self.connections.insert(token, client);
+            self.connections.insert(pipeline_token, client);
+            Ok(())
+        }
+
+        /// Drops clients that have not finished their upgrade request in time.
+        fn expire_handshakes(&mut self, poll: &Poll) {
+            let expired: Vec<Token> = self
+                .handshakes
+                .iter()
+                .filter(|(_, pending)| pending.started.elapsed() >= HANDSHAKE_TIMEOUT)
+                .map(|(token, _)| *token)
+                .collect();
+            for token in expired {
+                let pending = self.handshakes.remove(&token).unwrap();
+                let fd = match pending.handshake.get_ref().get_ref() {
+                    MaybeTlsStream::Plain(stream) => stream.as_raw_fd(),
+                    _ => unreachable!(),
+                };
+                let _ = poll.registry().deregister(&mut SourceFd(&fd));
+                if self.debug_level > 0 {
+                    eprintln!("websocket handshake with {} timed out", pending.peer);
+                }
+            }
+        }
+
+        /// Returns false once the client has closed the connection.
+        fn client_to_pipeline(client: &mut WsEntryContext) -> Result<bool, Error> {
+            let (data, closed) = read_frames(&mut client.socket)?;
+            if !data.is_empty() {
+                client.pipeline.write_pipeline(data)?;
+            }
+            Ok(!closed)
+        }
+
+        fn pipeline_to_client(client: &mut WsEntryContext) -> Result<bool, Error> {
+            let data = match client.pipeline.read_pipeline() {
+                Ok(data) => data,
+                Err(Error::IoError(e)) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e),
+            };
+            write_frame(&mut client.socket, &data)?;
+            Ok(true)
+        }
+
+        fn ping_clients(&mut self, ping_interval: Duration) {
+            for (token, client) in &mut self.connections {
+                if token.0 % 2 == 0 || client.last_ping.elapsed() < ping_interval {
+                    continue;
+                }
+                client.last_ping = Instant::now();
+                if let Err(e) = client.socket.send(Message::Ping(Vec::new())) {
+                    if self.debug_level > 0 {
+                        eprintln!("an error accured pinging client: {}", e);
+                    }
+                }
+            }
+        }
+
+        fn close(
+            &mut self,
+            poll: &Poll,
+            token: Token,
+            mut client: Ref<WsEntryContext>,
+        ) -> Result<(), Error> {
+            let fd = tcp_stream(&client.socket).as_raw_fd();
+            poll.registry().deregister(&mut SourceFd(&fd))?;
+            poll.registry().deregister(&mut client.pipeline)?;
+            let _ = client.socket.close(None);
+            let _ = client.socket.flush();
+            self.connections.remove(&token);
+            self.connections.remove(&Token(token.0 + 1));
+            // both tokens were removed, so no copy of the ref is left
+            unsafe { client.free() };
+            Ok(())
+        }
+    }
+
+    impl EntryStatic<WsEntry> for WsEntry {
+        fn new(
+            args: CliParsed,
+            pipeline: Pipeline,
+            debug_level: DebugLevel,
+        ) -> Result<WsEntry, Error> {
+            let address = match args.argument_values.get(WS_ENTRY_ADDRESS.0) {
+                Some(address) => address[0].clone(),
+                None => return Err(Error::RequireOption(WS_ENTRY_ADDRESS.0.to_string())),
+            };
+            let port = match args.argument_values.get(WS_ENTRY_PORT.0) {
+                Some(port) => port[0].clone(),
+                None => return Err(Error::RequireOption(WS_ENTRY_PORT.0.to_string())),
+            };
+            let path = match args.argument_values.get(WS_ENTRY_PATH.0) {
+                Some(path) => path[0].clone(),
+                None => return Err(Error::RequireOption(WS_ENTRY_PATH.0.to_string())),
+            };
+            let protocol = match args.argument_values.get(WS_ENTRY_PROTOCOL.0) {
+                Some(protocol) => match HeaderValue::from_str(protocol[0].as_str()) {
+                    Ok(protocol) => Some(protocol),
+                    Err(e) => {
+                        return Err(Error::Msg(format!(
+                            "invalid websocket subprotocol {}: {}",
+                            protocol[0], e
+                        )))
+                    }
+                },
+                None => None,
+            };
+
+            let port = match str::parse::<u16>(port.as_str()) {
+                Ok(port) => port,
+                Err(_) => return Err(Error::ParseIntError),
+            };
+            let ping_interval = parse_ping_interval(&args)?;
+
+            Ok(WsEntry {
+                address,
+                port,
+                path,
+                protocol,
+                ping_interval,
+                debug_level,
+                pipeline_template: pipeline,
+                connections: MultiMap::new(),
+                handshakes: HashMap::new(),
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: WS_ENTRY_ADDRESS.0.to_string(),
+                key: vec![WS_ENTRY_ADDRESS.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("0.0.0.0".to_string()),
+                help: Some(ArgumentHelp::Text(WS_ENTRY_ADDRESS.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: WS_ENTRY_PORT.0.to_string(),
+                key: vec![WS_ENTRY_PORT.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("80".to_string()),
+                help: Some(ArgumentHelp::Text(WS_ENTRY_PORT.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: WS_ENTRY_PATH.0.to_string(),
+                key: vec![WS_ENTRY_PATH.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("/".to_string()),
+                help: Some(ArgumentHelp::Text(WS_ENTRY_PATH.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: WS_ENTRY_PROTOCOL.0.to_string(),
+                key: vec![WS_ENTRY_PROTOCOL.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(WS_ENTRY_PROTOCOL.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: WS_PING_INTERVAL.0.to_string(),
+                key: vec![WS_PING_INTERVAL.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("30".to_string()),
+                help: Some(ArgumentHelp::Text(WS_PING_INTERVAL.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    pub struct WsStep {
+        url: String,
+        protocol: Option<String>,
+        ping_interval: Option<Duration>,
+        /// Connected when the step is first used, a clone starts without one.
+        socket: OnceLock<Option<Arc<Mutex<WsSocket>>>>,
+        debug_level: DebugLevel,
+    }
+
+    impl WsStep {
+        fn connect(
+            url: &str,
+            protocol: &Option<String>,
+            ping_interval: Option<Duration>,
+            debug_level: DebugLevel,
+        ) -> Result<Arc<Mutex<WsSocket>>, Error> {
+            let uri = match url.parse::<Uri>() {
+                Ok(uri) => uri,
+                Err(e) => return Err(Error::Msg(format!("invalid websocket url {}: {}", url, e))),
+            };
+            let mut request = ClientRequestBuilder::new(uri);
+            if let Some(protocol) = protocol {
+                request = request.with_sub_protocol(protocol.clone());
+            }
+            let (socket, _) = tungstenite::connect(request)?;
+            // a stalled endpoint fails the write instead of the entry's thread
+            tcp_stream(&socket).set_write_timeout(Some(WRITE_TIMEOUT))?;
+            let socket = Arc::new(Mutex::new(socket));
+
+            if let Some(ping_interval) = ping_interval {
+                WsStep::keepalive(Arc::downgrade(&socket), ping_interval, debug_level);
+            }
+            Ok(socket)
+        }
+
+        fn socket(&self) -> Result<&Arc<Mutex<WsSocket>>, Error> {
+            let socket = self.socket.get_or_init(|| {
+                match WsStep::connect(
+                    &self.url,
+                    &self.protocol,
+                    self.ping_interval,
+                    self.debug_level,
+                ) {
+                    Ok(socket) => Some(socket),
+                    Err(e) => {
+                        if self.debug_level > 0 {
+                            eprintln!("an error accured connecting to {}: {}", self.url, e);
+                        }
+                        None
+                    }
+                }
+            });
+            match socket {
+                Some(socket) => Ok(socket),
+                None => Err(Error::Msg(format!("not connected to {}", self.url))),
+            }
+        }
+
+        /// Pings the endpoint until the step owning the socket is dropped.
+        fn keepalive(
+            socket: Weak<Mutex<WsSocket>>,
+            ping_interval: Duration,
+            debug_level: DebugLevel,
+        ) {
+            thread::spawn(move || loop {
+                thread::sleep(ping_interval);
+                let socket = match socket.upgrade() {
+                    Some(socket) => socket,
+                    None => break,
+                };
+                let mut socket = socket.lock().unwrap();
+                if let Err(e) = socket.send(Message::Ping(Vec::new())) {
+                    if debug_level > 0 {
+                        eprintln!("an error accured pinging websocket endpoint: {}", e);
+                    }
+                    break;
+                }
+            });
+        }
+    }
+
+    impl Step for WsStep {
+        fn process_data_forward(&mut self, data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            write_frame(&mut self.socket()?.lock().unwrap(), data)?;
+            Ok(data.clone())
+        }
+
+        fn process_data_backward(&mut self, _data: &mut Vec<u8>) -> Result<Vec<u8>, Error> {
+            let (data, closed) = read_frames(&mut self.socket()?.lock().unwrap())?;
+            if closed {
+                return Err(Error::IoError(ErrorKind::ConnectionAborted.into()));
+            }
+            Ok(data)
+        }
+    }
+
+    impl BoxedClone for WsStep {
+        fn bclone(&self) -> Box<dyn Step> {
+            Box::new(self.clone())
+        }
+    }
+
+    impl StepStatic for WsStep {
+        fn new(args: CliParsed, debug_level: DebugLevel) -> Result<Self, Error> {
+            let url = match args.argument_values.get(WS_STEP_URL.0) {
+                Some(url) => url[0].clone(),
+                None => return Err(Error::RequireOption(WS_STEP_URL.0.to_string())),
+            };
+            let protocol = args
+                .argument_values
+                .get(WS_STEP_PROTOCOL.0)
+                .map(|protocol| protocol[0].clone());
+            let ping_interval = parse_ping_interval(&args)?;
+
+            if let Err(e) = url.parse::<Uri>() {
+                return Err(Error::Msg(format!("invalid websocket url {}: {}", url, e)));
+            }
+
+            Ok(Self {
+                url,
+                protocol,
+                ping_interval,
+                socket: OnceLock::new(),
+                debug_level,
+            })
+        }
+
+        fn get_cmd(mut argument: CliSpec) -> CliSpec {
+            argument = argument.add_argument(Argument {
+                name: WS_STEP_URL.0.to_string(),
+                key: vec![WS_STEP_URL.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: Some("ws://127.0.0.1:80/".to_string()),
+                help: Some(ArgumentHelp::Text(WS_STEP_URL.2.to_string())),
+            });
+            argument = argument.add_argument(Argument {
+                name: WS_STEP_PROTOCOL.0.to_string(),
+                key: vec![WS_STEP_PROTOCOL.1.to_string()],
+                argument_occurrence: ArgumentOccurrence::Single,
+                value_type: ArgumentValueType::Single,
+                default_value: None,
+                help: Some(ArgumentHelp::Text(WS_STEP_PROTOCOL.2.to_string())),
+            });
+            argument
+        }
+    }
+
+    impl Clone for WsStep {
+        fn clone(&self) -> Self {
+            Self {
+                url: self.url.clone(),
+                protocol: self.protocol.clone(),
+                ping_interval: self.ping_interval,
+                socket: OnceLock::new(),
+                debug_level: self.debug_level,
+            }
+        }
+    }
+
+    impl AsRawFd for WsStep {
+        fn as_raw_fd(&self) -> RawFd {
+            match self.socket() {
+                Ok(socket) => tcp_stream(&socket.lock().unwrap()).as_raw_fd(),
+                Err(_) => -1,
+            }
+        }
+    }
+}